@@ -0,0 +1,16 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec4 fragColor;
+layout(location = 1) in vec2 fragTextureCoord;
+
+layout(location = 0) out vec4 outColor;
+
+// Material parameters, in the order they are declared by `ColoredMaterial`.
+layout(set = 0, binding = 0) uniform Parameters {
+    vec4 color;
+} parameters;
+
+void main() {
+    outColor = fragColor * parameters.color;
+}
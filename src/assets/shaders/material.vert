@@ -0,0 +1,21 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// Matches the layout of `Vertex`.
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec4 inColor;
+layout(location = 2) in vec2 inTextureCoord;
+
+layout(location = 0) out vec4 fragColor;
+layout(location = 1) out vec2 fragTextureCoord;
+
+// Per-object transform, pushed before each draw.
+layout(push_constant) uniform Transform {
+    mat4 model;
+} transform;
+
+void main() {
+    gl_Position = transform.model * vec4(inPosition, 1.0);
+    fragColor = inColor;
+    fragTextureCoord = inTextureCoord;
+}
@@ -0,0 +1,19 @@
+#version 450
+
+struct Particle {
+    vec2 position;
+    vec2 velocity;
+};
+
+layout(std430, set = 0, binding = 0) readonly buffer Particles {
+    Particle particles[];
+};
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    Particle particle = particles[gl_VertexIndex];
+    gl_Position = vec4(particle.position, 0.0, 1.0);
+    gl_PointSize = 2.0;
+    outColor = vec4(1.0, 0.6 + 0.2 * length(particle.velocity), 0.2, 0.5);
+}
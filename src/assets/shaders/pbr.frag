@@ -0,0 +1,172 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec3 fragPosition;
+layout(location = 1) in vec3 fragNormal;
+layout(location = 2) in vec4 fragTangent;
+layout(location = 3) in vec2 fragTextureCoord;
+
+layout(location = 0) out vec4 outColor;
+
+// Material parameters, in the order they are declared by `PbrMaterial`.
+layout(set = 0, binding = 0) uniform Parameters {
+    vec4 baseColor;
+    vec4 emissive;
+    float metallic;
+    float roughness;
+    float normalScale;
+    float occlusionStrength;
+} parameters;
+
+layout(set = 0, binding = 1) uniform texture2D baseColorMap;
+layout(set = 0, binding = 2) uniform sampler baseColorSampler;
+layout(set = 0, binding = 3) uniform texture2D metallicRoughnessMap;
+layout(set = 0, binding = 4) uniform sampler metallicRoughnessSampler;
+layout(set = 0, binding = 5) uniform texture2D normalMap;
+layout(set = 0, binding = 6) uniform sampler normalSampler;
+layout(set = 0, binding = 7) uniform texture2D occlusionMap;
+layout(set = 0, binding = 8) uniform sampler occlusionSampler;
+layout(set = 0, binding = 9) uniform texture2D emissiveMap;
+layout(set = 0, binding = 10) uniform sampler emissiveSampler;
+
+// The light's position and type, direction and range, color premultiplied by intensity, and the cosine of the outer
+// cone angle and the reciprocal of the difference between the inner and outer cosines.
+struct Light {
+    vec4 position;
+    vec4 direction;
+    vec4 color;
+    vec4 cone;
+};
+
+// Matches the layout written by `Lighting::write`. The camera position's w is the exposure, and the counts are the
+// number of lights and the number of mip levels of the prefiltered environment.
+layout(set = 1, binding = 0) uniform Scene {
+    mat4 viewProjection;
+    vec4 cameraPosition;
+    uvec4 counts;
+    Light lights[16];
+} scene;
+
+layout(set = 1, binding = 1) uniform textureCube irradianceMap;
+layout(set = 1, binding = 2) uniform textureCube prefilteredMap;
+layout(set = 1, binding = 3) uniform texture2D brdfLut;
+layout(set = 1, binding = 4) uniform sampler environmentSampler;
+
+const float PI = 3.14159265359;
+const uint LIGHT_DIRECTIONAL = 0u;
+const uint LIGHT_POINT = 1u;
+const uint LIGHT_SPOT = 2u;
+
+float distributionGgx(float nDotH, float alpha) {
+    float alpha2 = alpha * alpha;
+    float denominator = nDotH * nDotH * (alpha2 - 1.0) + 1.0;
+    return alpha2 / (PI * denominator * denominator);
+}
+
+// The height-correlated Smith visibility term, which includes the 1 / (4 n.l n.v) of the specular BRDF.
+float visibilitySmithGgx(float nDotL, float nDotV, float alpha) {
+    float alpha2 = alpha * alpha;
+    float ggxV = nDotL * sqrt(nDotV * nDotV * (1.0 - alpha2) + alpha2);
+    float ggxL = nDotV * sqrt(nDotL * nDotL * (1.0 - alpha2) + alpha2);
+    float ggx = ggxV + ggxL;
+    return ggx > 0.0 ? 0.5 / ggx : 0.0;
+}
+
+vec3 fresnelSchlick(float cosTheta, vec3 f0) {
+    return f0 + (1.0 - f0) * pow(1.0 - cosTheta, 5.0);
+}
+
+vec3 fresnelSchlickRoughness(float cosTheta, vec3 f0, float roughness) {
+    return f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(1.0 - cosTheta, 5.0);
+}
+
+// Returns the light's radiance arriving at the fragment, and the direction towards the light.
+vec3 incomingRadiance(Light light, vec3 position, out vec3 toLight) {
+    uint lightType = uint(light.position.w);
+    if (lightType == LIGHT_DIRECTIONAL) {
+        toLight = -normalize(light.direction.xyz);
+        return light.color.rgb;
+    }
+    vec3 offset = light.position.xyz - position;
+    float distanceSquared = max(dot(offset, offset), 0.0001);
+    toLight = offset * inversesqrt(distanceSquared);
+    // Inverse square falloff, windowed to reach zero at the light's range.
+    float range = light.direction.w;
+    float window = 1.0;
+    if (range > 0.0) {
+        float ratio = distanceSquared / (range * range);
+        window = clamp(1.0 - ratio * ratio, 0.0, 1.0);
+        window = window * window;
+    }
+    float attenuation = window / distanceSquared;
+    if (lightType == LIGHT_SPOT) {
+        float cosAngle = dot(normalize(light.direction.xyz), -toLight);
+        float cone = clamp((cosAngle - light.cone.x) * light.cone.y, 0.0, 1.0);
+        attenuation *= cone * cone;
+    }
+    return light.color.rgb * attenuation;
+}
+
+// A fit of the ACES filmic tone curve by Krzysztof Narkowicz.
+vec3 toneMap(vec3 color) {
+    return clamp((color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14), 0.0, 1.0);
+}
+
+void main() {
+    vec4 baseColor = parameters.baseColor
+        * texture(sampler2D(baseColorMap, baseColorSampler), fragTextureCoord);
+    // Roughness is read from the green channel and metalness from the blue channel.
+    vec4 metallicRoughness = texture(sampler2D(metallicRoughnessMap, metallicRoughnessSampler), fragTextureCoord);
+    float metallic = clamp(parameters.metallic * metallicRoughness.b, 0.0, 1.0);
+    float roughness = clamp(parameters.roughness * metallicRoughness.g, 0.04, 1.0);
+    float alpha = roughness * roughness;
+    float occlusion = texture(sampler2D(occlusionMap, occlusionSampler), fragTextureCoord).r;
+    occlusion = 1.0 + parameters.occlusionStrength * (occlusion - 1.0);
+    vec3 emissive = parameters.emissive.rgb
+        * texture(sampler2D(emissiveMap, emissiveSampler), fragTextureCoord).rgb;
+
+    vec3 normal = normalize(fragNormal);
+    vec3 tangent = fragTangent.xyz - normal * dot(normal, fragTangent.xyz);
+    if (dot(tangent, tangent) > 0.0) {
+        tangent = normalize(tangent);
+        vec3 bitangent = cross(normal, tangent) * fragTangent.w;
+        vec3 tangentNormal = texture(sampler2D(normalMap, normalSampler), fragTextureCoord).xyz * 2.0 - 1.0;
+        tangentNormal.xy *= parameters.normalScale;
+        normal = normalize(mat3(tangent, bitangent, normal) * tangentNormal);
+    }
+
+    vec3 view = normalize(scene.cameraPosition.xyz - fragPosition);
+    float nDotV = clamp(dot(normal, view), 0.0001, 1.0);
+    vec3 f0 = mix(vec3(0.04), baseColor.rgb, metallic);
+    vec3 diffuseColor = baseColor.rgb * (1.0 - metallic);
+
+    vec3 color = vec3(0.0);
+    uint lightCount = min(scene.counts.x, 16u);
+    for (uint index = 0u; index < lightCount; index++) {
+        vec3 toLight;
+        vec3 radiance = incomingRadiance(scene.lights[index], fragPosition, toLight);
+        float nDotL = clamp(dot(normal, toLight), 0.0, 1.0);
+        if (nDotL <= 0.0) {
+            continue;
+        }
+        vec3 halfway = normalize(toLight + view);
+        float nDotH = clamp(dot(normal, halfway), 0.0, 1.0);
+        float vDotH = clamp(dot(view, halfway), 0.0, 1.0);
+        vec3 fresnel = fresnelSchlick(vDotH, f0);
+        vec3 specular = fresnel * distributionGgx(nDotH, alpha) * visibilitySmithGgx(nDotL, nDotV, alpha);
+        vec3 diffuse = (1.0 - fresnel) * diffuseColor / PI;
+        color += (diffuse + specular) * radiance * nDotL;
+    }
+
+    // Image based lighting, using the split sum approximation for the specular term.
+    vec3 fresnel = fresnelSchlickRoughness(nDotV, f0, roughness);
+    vec3 irradiance = texture(samplerCube(irradianceMap, environmentSampler), normal).rgb;
+    vec3 reflection = reflect(-view, normal);
+    float maxLod = float(max(scene.counts.y, 1u) - 1u);
+    vec3 prefiltered = textureLod(samplerCube(prefilteredMap, environmentSampler), reflection, roughness * maxLod).rgb;
+    vec2 brdf = texture(sampler2D(brdfLut, environmentSampler), vec2(nDotV, roughness)).rg;
+    vec3 ambient = (1.0 - fresnel) * irradiance * diffuseColor + prefiltered * (f0 * brdf.x + brdf.y);
+    color += ambient * occlusion + emissive;
+
+    outColor = vec4(toneMap(color * scene.cameraPosition.w), baseColor.a);
+}
@@ -0,0 +1,43 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// Matches the layout of `PbrVertex`.
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inNormal;
+layout(location = 2) in vec4 inTangent;
+layout(location = 3) in vec2 inTextureCoord;
+
+layout(location = 0) out vec3 fragPosition;
+layout(location = 1) out vec3 fragNormal;
+layout(location = 2) out vec4 fragTangent;
+layout(location = 3) out vec2 fragTextureCoord;
+
+struct Light {
+    vec4 position;
+    vec4 direction;
+    vec4 color;
+    vec4 cone;
+};
+
+// Matches the layout written by `Lighting::write`.
+layout(set = 1, binding = 0) uniform Scene {
+    mat4 viewProjection;
+    vec4 cameraPosition;
+    uvec4 counts;
+    Light lights[16];
+} scene;
+
+// Per-object transforms, pushed before each draw. The normal matrix is the inverse transpose of the model matrix.
+layout(push_constant) uniform Transform {
+    mat4 model;
+    mat4 normal;
+} transform;
+
+void main() {
+    vec4 position = transform.model * vec4(inPosition, 1.0);
+    gl_Position = scene.viewProjection * position;
+    fragPosition = position.xyz;
+    fragNormal = mat3(transform.normal) * inNormal;
+    fragTangent = vec4(mat3(transform.model) * inTangent.xyz, inTangent.w);
+    fragTextureCoord = inTextureCoord;
+}
@@ -0,0 +1,20 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec4 fragColor;
+layout(location = 1) in vec2 fragTextureCoord;
+
+layout(location = 0) out vec4 outColor;
+
+// Material parameters, in the order they are declared by `TexturedMaterial`.
+layout(set = 0, binding = 0) uniform Parameters {
+    vec4 tint;
+} parameters;
+
+// Each texture parameter is bound as an image followed by its sampler.
+layout(set = 0, binding = 1) uniform texture2D albedo;
+layout(set = 0, binding = 2) uniform sampler albedoSampler;
+
+void main() {
+    outColor = fragColor * parameters.tint * texture(sampler2D(albedo, albedoSampler), fragTextureCoord);
+}
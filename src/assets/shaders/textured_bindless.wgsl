@@ -0,0 +1,27 @@
+// The bindless variant of textured.frag, which reads its texture from the `BindlessHeap` bound at set 1 rather than
+// from the material's set.
+
+// Material parameters, in the order they are declared by `TexturedMaterial`.
+struct Parameters {
+    tint : vec4<f32>,
+}
+
+// The per-object transform read by material.vert, followed by the heap index of each texture parameter.
+struct Handles {
+    model : mat4x4<f32>,
+    albedo : u32,
+}
+
+@group(0) @binding(0) var<uniform> parameters : Parameters;
+
+// The heap must hold at least as many textures as these arrays, see `BINDLESS_TEXTURES` in the renderer.
+@group(1) @binding(0) var textures : binding_array<texture_2d<f32>, 4096>;
+@group(1) @binding(2) var samplers : binding_array<sampler, 4096>;
+
+var<push_constant> handles : Handles;
+
+@fragment
+fn main(@location(0) color : vec4<f32>, @location(1) texture_coord : vec2<f32>) -> @location(0) vec4<f32> {
+    let albedo = textureSample(textures[handles.albedo], samplers[handles.albedo], texture_coord);
+    return color * parameters.tint * albedo;
+}
@@ -0,0 +1,201 @@
+#!/usr/bin/env python3
+"""Generates the glTF fixtures used by the importer tests. Run from this directory.
+
+Box.gltf is a cube with two faces, stored in Box.bin with interleaved positions and normals. Box.glb and
+BoxTextured.gltf add a blended, double-sided material whose texture is ../images/rgba8.png, embedded in the binary
+chunk or referred to by a percent-encoded URI. Animated.gltf uses quantized joints, a sparse accessor, a skin, cameras
+and animations, all stored in a data URI. The Invalid* files are broken copies of Animated.gltf.
+"""
+
+import base64
+import json
+import struct
+
+FLOAT, UNSIGNED_BYTE, UNSIGNED_SHORT = 5126, 5121, 5123
+
+
+def write(name, data):
+    with open(name, "wb") as f:
+        f.write(data)
+
+
+def write_json(name, root):
+    write(name, json.dumps(root, indent=1).encode())
+
+
+def pack(format, rows):
+    return b"".join(struct.pack(format, *row) for row in rows)
+
+
+def pad(data, fill=b"\0"):
+    return data + fill * (-len(data) % 4)
+
+
+def data_uri(data):
+    return "data:application/octet-stream;base64," + base64.b64encode(data).decode()
+
+
+def box_buffer():
+    """Returns the binary data, buffer views and accessors of two faces of a unit cube."""
+    faces = [((0, 0, 1), [(-0.5, -0.5, 0.5), (0.5, -0.5, 0.5), (0.5, 0.5, 0.5), (-0.5, 0.5, 0.5)]),
+             ((0, 0, -1), [(0.5, -0.5, -0.5), (-0.5, -0.5, -0.5), (-0.5, 0.5, -0.5), (0.5, 0.5, -0.5)])]
+    vertices, texture_coords, indices = [], [], []
+    for normal, corners in faces:
+        first = len(vertices)
+        vertices += [corner + normal for corner in corners]
+        texture_coords += [(0, 0), (1, 0), (1, 1), (0, 1)]
+        indices += [first, first + 1, first + 2, first, first + 2, first + 3]
+    index_data = pad(struct.pack("<%dH" % len(indices), *indices))
+    vertex_data = pack("<6f", vertices)
+    texture_coord_data = pack("<2f", texture_coords)
+    data = index_data + vertex_data + texture_coord_data
+    views = [
+        {"buffer": 0, "byteLength": len(indices) * 2, "target": 34963},
+        {"buffer": 0, "byteOffset": len(index_data), "byteLength": len(vertex_data), "byteStride": 24},
+        {"buffer": 0, "byteOffset": len(index_data) + len(vertex_data), "byteLength": len(texture_coord_data)},
+    ]
+    accessors = [
+        {"bufferView": 0, "componentType": UNSIGNED_SHORT, "count": len(indices), "type": "SCALAR"},
+        {"bufferView": 1, "componentType": FLOAT, "count": len(vertices), "type": "VEC3",
+         "min": [-0.5, -0.5, -0.5], "max": [0.5, 0.5, 0.5]},
+        {"bufferView": 1, "byteOffset": 12, "componentType": FLOAT, "count": len(vertices), "type": "VEC3"},
+        {"bufferView": 2, "componentType": FLOAT, "count": len(vertices), "type": "VEC2"},
+    ]
+    return data, views, accessors
+
+
+def box(buffer, views, accessors, image=None):
+    """Returns a box under a rotated parent node, with a red material which is textured if there is an image."""
+    material = {"name": "Red", "pbrMetallicRoughness": {"baseColorFactor": [0.8, 0, 0, 1], "metallicFactor": 0}}
+    root = {
+        "asset": {"version": "2.0"},
+        "scene": 0,
+        "scenes": [{"name": "Scene", "nodes": [0]}],
+        "nodes": [{"name": "parent", "children": [1], "matrix": [1, 0, 0, 0, 0, 0, -1, 0, 0, 1, 0, 0, 0, 2, 0, 1]},
+                  {"name": "box", "mesh": 0}],
+        "meshes": [{"name": "Box", "primitives": [
+            {"attributes": {"POSITION": 1, "NORMAL": 2, "TEXCOORD_0": 3}, "indices": 0, "material": 0}]}],
+        "materials": [material],
+        "accessors": accessors,
+        "bufferViews": views,
+        "buffers": [buffer],
+    }
+    if image is not None:
+        material["pbrMetallicRoughness"]["baseColorTexture"] = {"index": 0}
+        material["normalTexture"] = {"index": 0, "scale": 0.5}
+        material["alphaMode"] = "BLEND"
+        material["doubleSided"] = True
+        root["textures"] = [{"sampler": 0, "source": 0}]
+        root["samplers"] = [{"magFilter": 9728, "minFilter": 9984, "wrapS": 33071}]
+        root["images"] = [image]
+    return root
+
+
+def glb(root, binary):
+    json_chunk = pad(json.dumps(root).encode(), b" ")
+    length = 12 + 8 + len(json_chunk) + 8 + len(binary)
+    return (struct.pack("<4sII", b"glTF", 2, length)
+            + struct.pack("<II", len(json_chunk), 0x4e4f534a) + json_chunk
+            + struct.pack("<II", len(binary), 0x004e4942) + binary)
+
+
+def animated():
+    """Returns a skinned quad with a sparse copy, a camera and animations of its nodes."""
+    positions = [(0, 0, 0), (1, 0, 0), (0, 1, 0), (1, 1, 0)]
+    identity = [1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1]
+    parts = [
+        pack("<3f", positions),
+        pack("<f", [(0,), (1,), (2,)]),
+        pack("<4f", [(0, 0, 0, 1), (0, 0, 0.7071068, 0.7071068), (0, 0, 1, 0)]),
+        # An in-tangent, value and out-tangent for each of the two keyframes.
+        pack("<3f", [(0, 0, 0), (0, 0, 0), (0, 0, 0), (0, 0, 0), (10, 0, 0), (0, 0, 0)]),
+        pack("<f", [(0,), (1,)]),
+        pack("<4B", [(0, 1, 0, 0)] * 4),
+        pack("<4f", [(0.5, 0.5, 0, 0)] * 4),
+        struct.pack("<32f", *identity, *identity[:12], -1, 0, 0, 1),
+        pad(struct.pack("<B", 1)),
+        pack("<3f", [(5, 5, 5)]),
+    ]
+    views, data = [], b""
+    for part in parts:
+        views.append({"buffer": 0, "byteOffset": len(data), "byteLength": len(part)})
+        data += part
+    accessors = [
+        {"bufferView": 0, "componentType": FLOAT, "count": 4, "type": "VEC3"},
+        {"bufferView": 1, "componentType": FLOAT, "count": 3, "type": "SCALAR"},
+        {"bufferView": 2, "componentType": FLOAT, "count": 3, "type": "VEC4"},
+        {"bufferView": 3, "componentType": FLOAT, "count": 6, "type": "VEC3"},
+        {"bufferView": 4, "componentType": FLOAT, "count": 2, "type": "SCALAR"},
+        {"bufferView": 5, "componentType": UNSIGNED_BYTE, "count": 4, "type": "VEC4"},
+        {"bufferView": 6, "componentType": FLOAT, "count": 4, "type": "VEC4"},
+        {"bufferView": 7, "componentType": FLOAT, "count": 2, "type": "MAT4"},
+        {"bufferView": 0, "componentType": FLOAT, "count": 4, "type": "VEC3", "sparse": {
+            "count": 1, "indices": {"bufferView": 8, "componentType": UNSIGNED_BYTE}, "values": {"bufferView": 9}}},
+        {"componentType": FLOAT, "count": 4, "type": "VEC3", "sparse": {
+            "count": 1, "indices": {"bufferView": 8, "componentType": UNSIGNED_BYTE}, "values": {"bufferView": 9}}},
+    ]
+    return {
+        "asset": {"version": "2.0"},
+        "extensionsRequired": ["KHR_mesh_quantization"],
+        "extensionsUsed": ["KHR_mesh_quantization"],
+        "nodes": [{"name": "root", "children": [1, 2], "translation": [0, 0, 1]},
+                  {"name": "skinned", "mesh": 0, "skin": 0},
+                  {"name": "joint", "children": [3]},
+                  {"name": "camera", "camera": 0, "translation": [0, 0, 5]}],
+        "meshes": [{"name": "Quads", "primitives": [
+            {"attributes": {"POSITION": 0, "JOINTS_0": 5, "WEIGHTS_0": 6}, "mode": 5},
+            {"attributes": {"POSITION": 8}},
+            {"attributes": {"POSITION": 9}, "mode": 6},
+            {"attributes": {"POSITION": 0}, "mode": 1}]}],
+        "skins": [{"name": "Skin", "joints": [0, 2], "inverseBindMatrices": 7}],
+        "cameras": [{"type": "perspective", "perspective": {"yfov": 1.0, "znear": 0.1}},
+                    {"type": "orthographic", "orthographic": {"xmag": 1, "ymag": 1, "znear": 0, "zfar": 10}}],
+        "animations": [{"name": "Move", "channels": [
+            {"sampler": 0, "target": {"node": 2, "path": "rotation"}},
+            {"sampler": 1, "target": {"node": 1, "path": "translation"}},
+            {"sampler": 2, "target": {"node": 1, "path": "weights"}}],
+            "samplers": [{"input": 1, "output": 2},
+                         {"input": 4, "output": 3, "interpolation": "CUBICSPLINE"},
+                         {"input": 1, "output": 1, "interpolation": "STEP"}]}],
+        "accessors": accessors,
+        "bufferViews": views,
+        "buffers": [{"uri": data_uri(data), "byteLength": len(data)}],
+    }
+
+
+def main():
+    with open("../images/rgba8.png", "rb") as f:
+        png = f.read()
+
+    data, views, accessors = box_buffer()
+    write("Box.bin", data)
+    write_json("Box.gltf", box({"uri": "Box.bin", "byteLength": len(data)}, views, accessors))
+
+    write("my tex.png", png)
+    write_json("BoxTextured.gltf", box({"uri": data_uri(data), "byteLength": len(data)}, views, accessors,
+                                       {"uri": "my%20tex.png"}))
+
+    binary = data + pad(png)
+    image_view = {"buffer": 0, "byteOffset": len(data), "byteLength": len(png)}
+    root = box({"byteLength": len(binary)}, views + [image_view], accessors,
+               {"bufferView": len(views), "mimeType": "image/png"})
+    write("Box.glb", glb(root, binary))
+
+    write_json("Animated.gltf", animated())
+    invalid = animated()
+    invalid["extensionsRequired"].append("KHR_draco_mesh_compression")
+    write_json("InvalidExtension.gltf", invalid)
+    invalid = animated()
+    invalid["nodes"][3]["children"] = [0]
+    write_json("InvalidCycle.gltf", invalid)
+    invalid = animated()
+    invalid["accessors"][0]["count"] = 100
+    write_json("InvalidView.gltf", invalid)
+    # Without a buffer view, only the count limits the size of the accessor.
+    invalid = animated()
+    invalid["accessors"][9]["count"] = 1 << 40
+    write_json("InvalidCount.gltf", invalid)
+
+
+if __name__ == "__main__":
+    main()
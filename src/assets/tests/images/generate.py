@@ -0,0 +1,1156 @@
+#!/usr/bin/env python3
+"""Generates the PNG and JPEG fixtures used by the image decoder tests.
+
+Every PNG has a matching .rgba file with the pixels it should decode to. The JPEGs are encodings of gradient.rgba,
+which they should decode to within the error introduced by quantization and chroma subsampling.
+
+The KTX2 and DDS textures store 8x8 images with their mip levels. Those which can be decompressed on the CPU have a
+matching .rgba file with every level decompressed, largest first. The BC7 and ASTC files hold random blocks, decoded
+by the reference decoders below, which follow the BC7 and ASTC specifications independently of the Rust decoders.
+bc7_blocks.bin and astc_blocks.bin hold separate blocks, the ASTC ones for every footprint in ASTC_FOOTPRINTS, with
+their texels in the matching .rgba files.
+
+Files written by other encoders are kept in external/, which is described in its README.
+"""
+
+import heapq
+import math
+import random
+import struct
+import zlib
+
+WIDTH, HEIGHT = 7, 5
+ADAM7 = [(0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2)]
+
+
+def noise(x, y, channel, maximum):
+    return ((x * 37 + y * 91 + channel * 53 + 11) * 2654435761 >> 7) % (maximum + 1)
+
+
+def chunk(kind, data):
+    return struct.pack(">I", len(data)) + kind + data + struct.pack(">I", zlib.crc32(kind + data) & 0xffffffff)
+
+
+def paeth(a, b, c):
+    p = a + b - c
+    pa, pb, pc = abs(p - a), abs(p - b), abs(p - c)
+    if pa <= pb and pa <= pc:
+        return a
+    return b if pb <= pc else c
+
+
+def filter_rows(rows, stride):
+    """Filters each row, cycling through every filter type."""
+    out = bytearray()
+    previous = bytes(len(rows[0])) if rows else b""
+    for y, row in enumerate(rows):
+        kind = y % 5
+        out.append(kind)
+        for x, value in enumerate(row):
+            a = row[x - stride] if x >= stride else 0
+            b = previous[x]
+            c = previous[x - stride] if x >= stride else 0
+            predictor = [0, a, b, (a + b) // 2, paeth(a, b, c)][kind]
+            out.append((value - predictor) & 0xff)
+        previous = row
+    return bytes(out)
+
+
+def pack_row(samples, depth):
+    if depth == 16:
+        return b"".join(struct.pack(">H", sample) for sample in samples)
+    if depth == 8:
+        return bytes(samples)
+    out = bytearray()
+    bits, count = 0, 0
+    for sample in samples:
+        bits = (bits << depth) | sample
+        count += depth
+        if count == 8:
+            out.append(bits)
+            bits, count = 0, 0
+    if count:
+        out.append(bits << (8 - count))
+    return bytes(out)
+
+
+def png(color_type, depth, pixels, interlaced=False, palette=None, transparency=None):
+    """Encodes `pixels`, a row-major list of sample tuples."""
+    channels = {0: 1, 2: 3, 3: 1, 4: 2, 6: 4}[color_type]
+    stride = max(1, channels * depth // 8)
+
+    def rows_of(xs, ys):
+        return [pack_row([sample for x in xs for sample in pixels[y * WIDTH + x]], depth) for y in ys]
+
+    if interlaced:
+        data = b""
+        for x0, y0, dx, dy in ADAM7:
+            xs, ys = range(x0, WIDTH, dx), range(y0, HEIGHT, dy)
+            if len(xs) and len(ys):
+                data += filter_rows(rows_of(xs, ys), stride)
+    else:
+        data = filter_rows(rows_of(range(WIDTH), range(HEIGHT)), stride)
+
+    out = b"\x89PNG\r\n\x1a\n"
+    out += chunk(b"IHDR", struct.pack(">IIBBBBB", WIDTH, HEIGHT, depth, color_type, 0, 0, int(interlaced)))
+    if palette is not None:
+        out += chunk(b"PLTE", bytes(value for color in palette for value in color))
+    if transparency is not None:
+        out += chunk(b"tRNS", transparency)
+    compressed = zlib.compress(data)
+    # Split the data over two chunks, which the decoder has to join.
+    out += chunk(b"IDAT", compressed[:len(compressed) // 2]) + chunk(b"IDAT", compressed[len(compressed) // 2:])
+    return out + chunk(b"IEND", b"")
+
+
+def scale(value, depth):
+    if depth == 16:
+        return value >> 8
+    return value * 255 // ((1 << depth) - 1)
+
+
+def write(name, data):
+    with open(name, "wb") as f:
+        f.write(data)
+
+
+def generate_png(name, color_type, depth, interlaced=False, transparent=False):
+    maximum = (1 << depth) - 1
+    positions = [(x, y) for y in range(HEIGHT) for x in range(WIDTH)]
+    palette = trns = None
+    if color_type == 3:
+        entries = min(1 << depth, 40)
+        samples = [(noise(x, y, 0, entries - 1),) for x, y in positions]
+        palette = [(noise(i, 0, 1, 255), noise(i, 0, 2, 255), noise(i, 0, 3, 255)) for i in range(entries)]
+        # Shorter than the palette, so the remaining entries are opaque.
+        trns = bytes(noise(i, 0, 4, 255) for i in range(entries // 2 + 1))
+        expected = [palette[s[0]] + ((trns[s[0]],) if s[0] < len(trns) else (255,)) for s in samples]
+    else:
+        channels = {0: 1, 2: 3, 4: 2, 6: 4}[color_type]
+        samples = [tuple(noise(x, y, c, maximum) for c in range(channels)) for x, y in positions]
+        key = None
+        if transparent:
+            # Make a few pixels match the transparent color exactly.
+            key = samples[3]
+            samples[10] = key
+            trns = b"".join(struct.pack(">H", value) for value in key)
+        expected = []
+        for s in samples:
+            values = [scale(value, depth) for value in s]
+            alpha = 0 if transparent and s == key else 255
+            if color_type == 0:
+                expected.append((values[0],) * 3 + (alpha,))
+            elif color_type == 4:
+                expected.append((values[0],) * 3 + (values[1],))
+            elif color_type == 2:
+                expected.append(tuple(values) + (alpha,))
+            else:
+                expected.append(tuple(values))
+    write(name + ".png", png(color_type, depth, samples, interlaced, palette, trns))
+    write(name + ".rgba", bytes(value for pixel in expected for value in pixel))
+
+
+class BitWriter:
+    def __init__(self):
+        self.out = bytearray()
+        self.bits = 0
+        self.count = 0
+
+    def write(self, value, length):
+        for i in reversed(range(length)):
+            self.bits = (self.bits << 1) | ((value >> i) & 1)
+            self.count += 1
+            if self.count == 8:
+                self.out.append(self.bits)
+                if self.bits == 0xff:
+                    self.out.append(0)
+                self.bits, self.count = 0, 0
+
+    def flush(self):
+        """Pads the last byte with ones."""
+        if self.count:
+            self.write((1 << (8 - self.count)) - 1, 8 - self.count)
+
+
+ZIGZAG = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20, 13, 6, 7, 14, 21,
+    28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61, 54,
+    47, 55, 62, 63]
+
+# The example tables of the JPEG specification, in natural order.
+LUMA_QUANTIZATION = [
+    16, 11, 10, 16, 24, 40, 51, 61, 12, 12, 14, 19, 26, 58, 60, 55, 14, 13, 16, 24, 40, 57, 69, 56, 14, 17, 22, 29,
+    51, 87, 80, 62, 18, 22, 37, 56, 68, 109, 103, 77, 24, 35, 55, 64, 81, 104, 113, 92, 49, 64, 78, 87, 103, 121, 120,
+    101, 72, 92, 95, 98, 112, 100, 103, 99]
+CHROMA_QUANTIZATION = [
+    17, 18, 24, 47, 99, 99, 99, 99, 18, 21, 26, 66, 99, 99, 99, 99, 24, 26, 56, 99, 99, 99, 99, 99, 47, 66, 99, 99,
+    99, 99, 99, 99] + [99] * 32
+
+
+def quality(table):
+    """Scales a table to quality 90 and reorders it into the zigzag order in which it is stored."""
+    return [max(1, min(255, (table[ZIGZAG[k]] * 20 + 50) // 100)) for k in range(64)]
+
+
+def fdct(block):
+    out = [0.0] * 64
+    for v in range(8):
+        for u in range(8):
+            cu = 1 / math.sqrt(2) if u == 0 else 1
+            cv = 1 / math.sqrt(2) if v == 0 else 1
+            total = 0.0
+            for y in range(8):
+                for x in range(8):
+                    total += block[y * 8 + x] * math.cos((2 * x + 1) * u * math.pi / 16) \
+                        * math.cos((2 * y + 1) * v * math.pi / 16)
+            out[v * 8 + u] = cu * cv * total / 4
+    return out
+
+
+def category(value):
+    return abs(value).bit_length()
+
+
+def magnitude_bits(value):
+    return value if value >= 0 else value + (1 << category(value)) - 1
+
+
+def huffman_table(frequencies):
+    """Builds code lengths for the symbols, reserving the code of all ones, and returns the DHT counts, values and
+    a map from symbol to (code, length)."""
+    heap = [(count, i, [symbol]) for i, (symbol, count) in enumerate(sorted(frequencies.items()))]
+    heap.append((0, len(heap), [None]))
+    heapq.heapify(heap)
+    lengths = {symbol: 0 for symbol in frequencies}
+    lengths[None] = 0
+    order = len(heap)
+    while len(heap) > 1:
+        a, b = heapq.heappop(heap), heapq.heappop(heap)
+        for symbol in a[2] + b[2]:
+            lengths[symbol] += 1
+        heapq.heappush(heap, (a[0] + b[0], order, a[2] + b[2]))
+        order += 1
+    assert max(lengths.values()) <= 16
+    # The reserved symbol takes the last, all ones code of the longest length.
+    values = sorted(frequencies, key=lambda symbol: (lengths[symbol], symbol))
+    counts = [0] * 16
+    for symbol in values:
+        counts[lengths[symbol] - 1] += 1
+    codes = {}
+    code, index = 0, 0
+    for length in range(1, 17):
+        for _ in range(counts[length - 1]):
+            codes[values[index]] = (code, length)
+            code += 1
+            index += 1
+        code <<= 1
+    return counts, values, codes
+
+
+def to_ycbcr(pixel):
+    r, g, b = pixel[:3]
+    y = 0.299 * r + 0.587 * g + 0.114 * b
+    cb = -0.168736 * r - 0.331264 * g + 0.5 * b + 128
+    cr = 0.5 * r - 0.418688 * g - 0.081312 * b + 128
+    return y, cb, cr
+
+
+def jpeg(pixels, width, height, subsampled, restart_interval=0):
+    factor = 2 if subsampled else 1
+    mcus_x = math.ceil(width / (8 * factor))
+    mcus_y = math.ceil(height / (8 * factor))
+
+    def plane(channel, sub):
+        """Returns a sampler of the channel, averaging `sub` by `sub` pixels and replicating the edges."""
+        def sample(x, y):
+            total = 0.0
+            for dy in range(sub):
+                for dx in range(sub):
+                    px = min(x * sub + dx, width - 1)
+                    py = min(y * sub + dy, height - 1)
+                    total += to_ycbcr(pixels[py * width + px])[channel]
+            return total / (sub * sub)
+        return sample
+
+    # (sampler, blocks per MCU horizontally and vertically, quantization table, table index)
+    components = [
+        (plane(0, 1), factor, factor, quality(LUMA_QUANTIZATION), 0),
+        (plane(1, factor), 1, 1, quality(CHROMA_QUANTIZATION), 1),
+        (plane(2, factor), 1, 1, quality(CHROMA_QUANTIZATION), 1),
+    ]
+
+    # Encodes every block first to build the Huffman tables. Each entry is a restart marker index or
+    # (component, dc symbol, dc bits, [(ac symbol, ac bits)]).
+    events = []
+    predictions = [0, 0, 0]
+    for mcu in range(mcus_x * mcus_y):
+        if restart_interval and mcu and mcu % restart_interval == 0:
+            events.append(mcu // restart_interval - 1)
+            predictions = [0, 0, 0]
+        mcu_x, mcu_y = mcu % mcus_x, mcu // mcus_x
+        for index, (sample, blocks_x, blocks_y, table, _) in enumerate(components):
+            for block_y in range(blocks_y):
+                for block_x in range(blocks_x):
+                    x0 = (mcu_x * blocks_x + block_x) * 8
+                    y0 = (mcu_y * blocks_y + block_y) * 8
+                    limit_x = math.ceil(width / (factor if index else 1)) - 1
+                    limit_y = math.ceil(height / (factor if index else 1)) - 1
+                    block = [sample(min(x0 + x, limit_x), min(y0 + y, limit_y)) - 128
+                             for y in range(8) for x in range(8)]
+                    coefficients = fdct(block)
+                    quantized = [round(coefficients[ZIGZAG[k]] / table[k]) for k in range(64)]
+                    difference = quantized[0] - predictions[index]
+                    predictions[index] = quantized[0]
+                    ac = []
+                    run = 0
+                    for k in range(1, 64):
+                        if quantized[k] == 0:
+                            run += 1
+                            continue
+                        while run > 15:
+                            ac.append((0xf0, None))
+                            run -= 16
+                        ac.append(((run << 4) | category(quantized[k]), quantized[k]))
+                        run = 0
+                    if run:
+                        ac.append((0x00, None))
+                    events.append((index, category(difference), difference, ac))
+
+    dc_frequencies = [{}, {}]
+    ac_frequencies = [{}, {}]
+    for event in events:
+        if isinstance(event, int):
+            continue
+        index, dc_symbol, _, ac = event
+        table = components[index][4]
+        dc_frequencies[table][dc_symbol] = dc_frequencies[table].get(dc_symbol, 0) + 1
+        for symbol, _ in ac:
+            ac_frequencies[table][symbol] = ac_frequencies[table].get(symbol, 0) + 1
+    dc_tables = [huffman_table(frequencies) for frequencies in dc_frequencies]
+    ac_tables = [huffman_table(frequencies) for frequencies in ac_frequencies]
+
+    writer = BitWriter()
+    for event in events:
+        if isinstance(event, int):
+            writer.flush()
+            writer.out += bytes([0xff, 0xd0 + event % 8])
+            continue
+        index, dc_symbol, difference, ac = event
+        table = components[index][4]
+        writer.write(*dc_tables[table][2][dc_symbol])
+        writer.write(magnitude_bits(difference), dc_symbol)
+        for symbol, value in ac:
+            writer.write(*ac_tables[table][2][symbol])
+            if value is not None:
+                writer.write(magnitude_bits(value), symbol & 15)
+    writer.flush()
+
+    def segment(marker, data):
+        return bytes([0xff, marker]) + struct.pack(">H", len(data) + 2) + data
+
+    out = b"\xff\xd8"
+    out += segment(0xe0, b"JFIF\x00\x01\x01\x00\x00\x01\x00\x01\x00\x00")
+    for index, table in enumerate([quality(LUMA_QUANTIZATION), quality(CHROMA_QUANTIZATION)]):
+        out += segment(0xdb, bytes([index]) + bytes(table))
+    frame = struct.pack(">BHHB", 8, height, width, 3)
+    for index, (_, blocks_x, blocks_y, _, table) in enumerate(components):
+        frame += bytes([index + 1, (blocks_x << 4) | blocks_y, table])
+    out += segment(0xc0, frame)
+    for index in range(2):
+        for kind, tables in [(0, dc_tables), (1, ac_tables)]:
+            counts, values, _ = tables[index]
+            out += segment(0xc4, bytes([(kind << 4) | index] + counts + values))
+    if restart_interval:
+        out += segment(0xdd, struct.pack(">H", restart_interval))
+    scan = bytes([3])
+    for index, (_, _, _, _, table) in enumerate(components):
+        scan += bytes([index + 1, (table << 4) | table])
+    out += segment(0xda, scan + bytes([0, 63, 0]))
+    return out + bytes(writer.out) + b"\xff\xd9"
+
+
+def bc1_block(color0, color1, indices):
+    return struct.pack("<HHI", color0, color1, sum(index << (i * 2) for i, index in enumerate(indices)))
+
+
+def expand_565(color):
+    r, g, b = color >> 11, (color >> 5) & 63, color & 31
+    return [(r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2)]
+
+
+def decode_bc1(block):
+    color0, color1, indices = struct.unpack("<HHI", block)
+    c0, c1 = expand_565(color0), expand_565(color1)
+    palette = [c0 + [255], c1 + [255]]
+    if color0 > color1:
+        palette.append([(2 * a + b) // 3 for a, b in zip(c0, c1)] + [255])
+        palette.append([(a + 2 * b) // 3 for a, b in zip(c0, c1)] + [255])
+    else:
+        palette.append([(a + b) // 2 for a, b in zip(c0, c1)] + [255])
+        palette.append([0, 0, 0, 0])
+    return [palette[(indices >> (i * 2)) & 3] for i in range(16)]
+
+
+ETC_MODIFIERS = [[2, 8], [5, 17], [9, 29], [13, 42], [18, 60], [24, 80], [33, 106], [47, 183]]
+
+
+def etc_block(differential, flip, colors, tables, indices):
+    """Packs an individual or differential mode block. `colors` are two 4-bit colors, or a 5-bit color and a 3-bit
+    signed offset for each channel. `indices` are in row-major order."""
+    bits = 0
+    if differential:
+        (r, g, b), (dr, dg, db) = colors
+        bits |= r << 59 | (dr & 7) << 56 | g << 51 | (dg & 7) << 48 | b << 43 | (db & 7) << 40
+    else:
+        (r1, g1, b1), (r2, g2, b2) = colors
+        bits |= r1 << 60 | r2 << 56 | g1 << 52 | g2 << 48 | b1 << 44 | b2 << 40
+    bits |= tables[0] << 37 | tables[1] << 34 | int(differential) << 33 | int(flip) << 32
+    for i, index in enumerate(indices):
+        x, y = i % 4, i // 4
+        bit = x * 4 + y
+        bits |= (index >> 1) << (bit + 16) | (index & 1) << bit
+    return struct.pack(">Q", bits)
+
+
+def decode_etc(block):
+    bits = struct.unpack(">Q", block)[0]
+    differential = bits >> 33 & 1
+    flip = bits >> 32 & 1
+    if differential:
+        base = [bits >> 59 & 31, bits >> 51 & 31, bits >> 43 & 31]
+        offset = [bits >> 56 & 7, bits >> 48 & 7, bits >> 40 & 7]
+        second = [value + (delta - 8 if delta >= 4 else delta) for value, delta in zip(base, offset)]
+        bases = [[value << 3 | value >> 2 for value in color] for color in [base, second]]
+    else:
+        first = [bits >> 60 & 15, bits >> 52 & 15, bits >> 44 & 15]
+        second = [bits >> 56 & 15, bits >> 48 & 15, bits >> 40 & 15]
+        bases = [[value << 4 | value for value in color] for color in [first, second]]
+    tables = [bits >> 37 & 7, bits >> 34 & 7]
+    texels = []
+    for i in range(16):
+        x, y = i % 4, i // 4
+        subblock = int(y >= 2) if flip else int(x >= 2)
+        bit = x * 4 + y
+        index = (bits >> (bit + 16) & 1) << 1 | (bits >> bit & 1)
+        small, large = ETC_MODIFIERS[tables[subblock]]
+        modifier = [small, large, -small, -large][index]
+        texels.append([max(0, min(255, value + modifier)) for value in bases[subblock]] + [255])
+    return texels
+
+
+BC7_MODES = [
+    # subsets, partition bits, rotation bits, index selection bits, color bits, alpha bits, p-bits per endpoint,
+    # p-bits per subset, index bits, secondary index bits
+    (3, 4, 0, 0, 4, 0, 1, 0, 3, 0),
+    (2, 6, 0, 0, 6, 0, 0, 1, 3, 0),
+    (3, 6, 0, 0, 5, 0, 0, 0, 2, 0),
+    (2, 6, 0, 0, 7, 0, 1, 0, 2, 0),
+    (1, 0, 2, 1, 5, 6, 0, 0, 2, 3),
+    (1, 0, 2, 0, 7, 8, 0, 0, 2, 2),
+    (1, 0, 0, 0, 7, 7, 1, 0, 4, 0),
+    (2, 6, 0, 0, 5, 5, 1, 0, 2, 0),
+]
+BC7_WEIGHTS = {2: [0, 21, 43, 64], 3: [0, 9, 18, 27, 37, 46, 55, 64],
+               4: [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64]}
+# Each partition lists the subset of every texel, in row-major order.
+BC7_PARTITIONS_2 = """
+0011001100110011 0001000100010001 0111011101110111 0001001100110111 0000000100010011 0011011101111111
+0001001101111111 0000000100110111 0000000000010011 0011011111111111 0000000101111111 0000000000010111
+0001011111111111 0000000011111111 0000111111111111 0000000000001111 0000100011101111 0111000100000000
+0000000010001110 0111001100010000 0011000100000000 0000100011001110 0000000010001100 0111001100110001
+0011000100010000 0000100010001100 0110011001100110 0011011001101100 0001011111101000 0000111111110000
+0111000110001110 0011100110011100 0101010101010101 0000111100001111 0101101001011010 0011001111001100
+0011110000111100 0101010110101010 0110100101101001 0101101010100101 0111001111001110 0001001111001000
+0011001001001100 0011101111011100 0110100110010110 0011110011000011 0110011010011001 0000011001100000
+0100111001000000 0010011100100000 0000001001110010 0000010011100100 0110110010010011 0011011011001001
+0110001110011100 0011100111000110 0110110011001001 0110001100111001 0111111010000001 0001100011100111
+0000111100110011 0011001111110000 0010001011101110 0100010001110111""".split()
+BC7_PARTITIONS_3 = """
+0011001102212222 0001001122112221 0000200122112211 0222002200110111 0000000011221122 0011001100220022
+0022002211111111 0011001122112211 0000000011112222 0000111111112222 0000111122222222 0012001200120012
+0112011201120112 0122012201220122 0011011211221222 0011200122002220 0001001101121122 0111001120012200
+0000112211221122 0022002200221111 0111011102220222 0001000122212221 0000001101220122 0000110022102210
+0122012200110000 0012001211222222 0110122112210110 0000011012211221 0022110211020022 0110011020022222
+0011012201220011 0000200022112221 0000000211221222 0222002200120011 0011001200220222 0120012001200120
+0000111122220000 0120120120120120 0120201212010120 0011220011220011 0011112222000011 0101010122222222
+0000000021212121 0022112200221122 0022001100220011 0220122102201221 0101222222220101 0000212121212121
+0101010101012222 0222011102220111 0002111200021112 0000211221122112 0222011101110222 0002111211120002
+0110011001102222 0000000021122112 0110011022222222 0022001100110022 0022112211220022 0000000000002112
+0002000100020001 0222122202221222 0101222222222222 0111201122012220""".split()
+BC7_ANCHORS_2 = [15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 2, 8, 2, 2, 8, 8, 15, 2, 8, 2, 2,
+                 8, 8, 2, 2, 15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6, 6, 2, 6, 8, 15, 15, 2, 2, 15, 15,
+                 15, 15, 15, 2, 2, 15]
+BC7_ANCHORS_3 = [
+    [3, 3, 15, 15, 8, 3, 15, 15, 8, 8, 6, 6, 6, 5, 3, 3, 3, 3, 8, 15, 3, 3, 6, 10, 5, 8, 8, 6, 8, 5, 15, 15, 8, 15, 3,
+     5, 6, 10, 8, 15, 15, 3, 15, 5, 15, 15, 15, 15, 3, 15, 5, 5, 5, 8, 5, 10, 5, 10, 8, 13, 15, 12, 3, 3],
+    [15, 8, 8, 3, 15, 15, 3, 8, 15, 15, 15, 15, 15, 15, 15, 8, 15, 8, 15, 3, 15, 8, 15, 8, 3, 15, 6, 10, 15, 15, 10, 8,
+     15, 3, 15, 10, 10, 8, 9, 10, 6, 15, 8, 15, 3, 6, 6, 8, 15, 3, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 3, 15, 15,
+     8]]
+
+
+class BitReader:
+    def __init__(self, data):
+        self.value = int.from_bytes(data, "little")
+        self.position = 0
+
+    def read(self, length):
+        result = self.value >> self.position & ((1 << length) - 1)
+        self.position += length
+        return result
+
+
+def decode_bc7(block):
+    if block[0] == 0:
+        return [[0, 0, 0, 0]] * 16
+    mode = (block[0] & -block[0]).bit_length() - 1
+    subsets, partition_bits, rotation_bits, selection_bits, color_bits, alpha_bits, endpoint_p, subset_p, \
+        index_bits, index_bits2 = BC7_MODES[mode]
+    reader = BitReader(block)
+    reader.read(mode + 1)
+    partition = reader.read(partition_bits)
+    rotation = reader.read(rotation_bits)
+    selection = reader.read(selection_bits)
+    # endpoints[subset][endpoint] is [r, g, b, a]
+    endpoints = [[[0, 0, 0, 0] for _ in range(2)] for _ in range(subsets)]
+    for channel in range(4):
+        bits = color_bits if channel < 3 else alpha_bits
+        for subset in range(subsets):
+            for endpoint in range(2):
+                endpoints[subset][endpoint][channel] = reader.read(bits)
+    p_bits = [[0, 0] for _ in range(subsets)]
+    for subset in range(subsets):
+        if subset_p:
+            p_bits[subset] = [reader.read(1)] * 2
+        elif endpoint_p:
+            p_bits[subset] = [reader.read(1), reader.read(1)]
+    has_p = endpoint_p or subset_p
+    for subset in range(subsets):
+        for endpoint in range(2):
+            for channel in range(4):
+                bits = color_bits if channel < 3 else alpha_bits
+                if bits == 0:
+                    endpoints[subset][endpoint][channel] = 255
+                    continue
+                value = endpoints[subset][endpoint][channel]
+                if has_p:
+                    value = value << 1 | p_bits[subset][endpoint]
+                    bits += 1
+                endpoints[subset][endpoint][channel] = (value << (8 - bits)) | (value >> (2 * bits - 8))
+    if subsets == 1:
+        texel_subsets = [0] * 16
+        anchors = [0]
+    elif subsets == 2:
+        texel_subsets = [int(c) for c in BC7_PARTITIONS_2[partition]]
+        anchors = [0, BC7_ANCHORS_2[partition]]
+    else:
+        texel_subsets = [int(c) for c in BC7_PARTITIONS_3[partition]]
+        anchors = [0, BC7_ANCHORS_3[0][partition], BC7_ANCHORS_3[1][partition]]
+    indices = [reader.read(index_bits - (i in anchors)) for i in range(16)]
+    indices2 = [reader.read(index_bits2 - (i == 0)) for i in range(16)] if index_bits2 else None
+    assert reader.position == 128
+    texels = []
+    for i in range(16):
+        e0, e1 = endpoints[texel_subsets[i]]
+        color_weight = alpha_weight = BC7_WEIGHTS[index_bits][indices[i]]
+        if indices2:
+            alpha_weight = BC7_WEIGHTS[index_bits2][indices2[i]]
+            if selection:
+                color_weight, alpha_weight = alpha_weight, color_weight
+        texel = [((64 - (alpha_weight if c == 3 else color_weight)) * e0[c]
+                  + (alpha_weight if c == 3 else color_weight) * e1[c] + 32) >> 6 for c in range(4)]
+        if rotation:
+            texel[3], texel[rotation - 1] = texel[rotation - 1], texel[3]
+        texels.append(texel)
+    return texels
+
+
+def bc7_random_block(rng, mode):
+    bits = rng.getrandbits(128 - mode - 1)
+    return ((bits << (mode + 1)) | (1 << mode)).to_bytes(16, "little")
+
+
+ASTC_ERROR = [255, 0, 255, 255]
+ASTC_FOOTPRINTS = [(4, 4), (5, 4), (5, 5), (6, 5), (6, 6), (8, 5), (8, 6), (8, 8), (10, 5), (10, 6), (10, 8), (10, 10),
+                   (12, 10), (12, 12)]
+# The levels of every quantization mode, with their number of trits, quints and bits.
+ASTC_RANGES = [(2, 0, 0, 1), (3, 1, 0, 0), (4, 0, 0, 2), (5, 0, 1, 0), (6, 1, 0, 1), (8, 0, 0, 3), (10, 0, 1, 1),
+               (12, 1, 0, 2), (16, 0, 0, 4), (20, 0, 1, 2), (24, 1, 0, 3), (32, 0, 0, 5), (40, 0, 1, 3),
+               (48, 1, 0, 4), (64, 0, 0, 6), (80, 0, 1, 4), (96, 1, 0, 5), (128, 0, 0, 7), (160, 0, 1, 5),
+               (192, 1, 0, 6), (256, 0, 0, 8)]
+
+
+def astc_range(levels):
+    return next(r for r in ASTC_RANGES if r[0] == levels)
+
+
+def ise_size(count, levels):
+    _, trits, quints, bits = astc_range(levels)
+    return count * bits + (math.ceil(8 * count / 5) if trits else 0) + (math.ceil(7 * count / 3) if quints else 0)
+
+
+def bits_of(value, high, low):
+    return value >> low & ((1 << (high - low + 1)) - 1)
+
+
+def unpack_trits(t):
+    if bits_of(t, 4, 2) == 7:
+        c = bits_of(t, 7, 5) << 2 | bits_of(t, 1, 0)
+        t4 = t3 = 2
+    else:
+        c = bits_of(t, 4, 0)
+        if bits_of(t, 6, 5) == 3:
+            t4, t3 = 2, bits_of(t, 7, 7)
+        else:
+            t4, t3 = bits_of(t, 7, 7), bits_of(t, 6, 5)
+    if bits_of(c, 1, 0) == 3:
+        t2, t1 = 2, bits_of(c, 4, 4)
+        t0 = bits_of(c, 3, 3) << 1 | (bits_of(c, 2, 2) & (1 - bits_of(c, 3, 3)))
+    elif bits_of(c, 3, 2) == 3:
+        t2, t1, t0 = 2, 2, bits_of(c, 1, 0)
+    else:
+        t2, t1 = bits_of(c, 4, 4), bits_of(c, 3, 2)
+        t0 = bits_of(c, 1, 1) << 1 | (bits_of(c, 0, 0) & (1 - bits_of(c, 1, 1)))
+    return [t0, t1, t2, t3, t4]
+
+
+def unpack_quints(q):
+    if bits_of(q, 2, 1) == 3 and bits_of(q, 6, 5) == 0:
+        q0_bit = bits_of(q, 0, 0)
+        return [4, 4, q0_bit << 2 | (bits_of(q, 4, 4) & (1 - q0_bit)) << 1 | (bits_of(q, 3, 3) & (1 - q0_bit))]
+    if bits_of(q, 2, 1) == 3:
+        q2 = 4
+        c = bits_of(q, 4, 3) << 3 | (3 - bits_of(q, 6, 5)) << 1 | bits_of(q, 0, 0)
+    else:
+        q2 = bits_of(q, 6, 5)
+        c = bits_of(q, 4, 0)
+    if bits_of(c, 2, 0) == 5:
+        return [bits_of(c, 4, 3), 4, q2]
+    return [bits_of(c, 2, 0), bits_of(c, 4, 3), q2]
+
+
+def decode_ise(value, count, levels):
+    """Decodes `count` values from the low bits of `value`, which must hold nothing past the sequence."""
+    _, trits, quints, bits = astc_range(levels)
+    reader = BitReader(b"")
+    reader.value = value
+    out = []
+    while len(out) < count:
+        if trits:
+            m, t = [], 0
+            for shift, length in [(0, 2), (2, 2), (4, 1), (5, 2), (7, 1)]:
+                m.append(reader.read(bits))
+                t |= reader.read(length) << shift
+            out += [digit << bits | low for digit, low in zip(unpack_trits(t), m)]
+        elif quints:
+            m, q = [], 0
+            for shift, length in [(0, 3), (3, 2), (5, 2)]:
+                m.append(reader.read(bits))
+                q |= reader.read(length) << shift
+            out += [digit << bits | low for digit, low in zip(unpack_quints(q), m)]
+        else:
+            out.append(reader.read(bits))
+    return out[:count]
+
+
+def replicate_bits(value, bits, length):
+    out = 0
+    for i in range(length):
+        out |= (value >> (bits - 1 - i % bits) & 1) << (length - 1 - i)
+    return out
+
+
+# The C and B of the color unquantization table, keyed by (trits, quints, bits), with B as a bit layout string where
+# each letter names a bit of the value, a being the lowest.
+ASTC_COLOR_UNQUANTIZE = {
+    (1, 0, 1): (204, "000000000"), (0, 1, 1): (113, "000000000"), (1, 0, 2): (93, "b000b0bb0"),
+    (0, 1, 2): (54, "b0000bb00"), (1, 0, 3): (44, "cb000cbcb"), (0, 1, 3): (26, "cb0000cbc"),
+    (1, 0, 4): (22, "dcb000dcb"), (0, 1, 4): (13, "dcb0000dc"), (1, 0, 5): (11, "edcb000ed"),
+    (0, 1, 5): (6, "edcb0000e"), (1, 0, 6): (5, "fedcb000f"),
+}
+ASTC_WEIGHT_UNQUANTIZE = {
+    (1, 0, 1): (50, "0000000"), (0, 1, 1): (28, "0000000"), (1, 0, 2): (23, "b000b0b"), (0, 1, 2): (13, "b0000b0"),
+    (1, 0, 3): (11, "cb000cb"),
+}
+
+
+def layout_value(layout, value):
+    out = 0
+    for char in layout:
+        out = out << 1 | (0 if char == "0" else value >> (ord(char) - ord("a")) & 1)
+    return out
+
+
+def unquantize(value, levels, table, width):
+    _, trits, quints, bits = astc_range(levels)
+    low = value & ((1 << bits) - 1)
+    scale, layout = table[(trits, quints, bits)]
+    a = (1 << width) - 1 if low & 1 else 0
+    t = ((value >> bits) * scale + layout_value(layout, low)) ^ a
+    return (a & (1 << (width - 2))) | (t >> 2)
+
+
+def unquantize_color(value, levels):
+    _, trits, quints, bits = astc_range(levels)
+    if not trits and not quints:
+        return replicate_bits(value, bits, 8)
+    return unquantize(value, levels, ASTC_COLOR_UNQUANTIZE, 9)
+
+
+def unquantize_weight(value, levels):
+    _, trits, quints, bits = astc_range(levels)
+    if not trits and not quints:
+        weight = replicate_bits(value, bits, 6)
+    elif bits == 0:
+        weight = ([0, 32, 63] if trits else [0, 16, 32, 47, 63])[value]
+    else:
+        weight = unquantize(value, levels, ASTC_WEIGHT_UNQUANTIZE, 7)
+    return weight + 1 if weight > 32 else weight
+
+
+def astc_block_mode(mode):
+    """Returns the weight grid width and height, whether it has two planes and its levels, or None if reserved."""
+    a, h, d = bits_of(mode, 6, 5), bits_of(mode, 9, 9), bits_of(mode, 10, 10)
+    if bits_of(mode, 1, 0):
+        r = bits_of(mode, 4, 4) | bits_of(mode, 1, 0) << 1
+        b = bits_of(mode, 8, 7)
+        kind = bits_of(mode, 3, 2)
+        if kind == 0:
+            size = (b + 4, a + 2)
+        elif kind == 1:
+            size = (b + 8, a + 2)
+        elif kind == 2:
+            size = (a + 2, b + 8)
+        elif bits_of(mode, 8, 8):
+            size = (bits_of(mode, 7, 7) + 2, a + 2)
+        else:
+            size = (a + 2, bits_of(mode, 7, 7) + 6)
+    else:
+        r = bits_of(mode, 4, 4) | bits_of(mode, 3, 2) << 1
+        if bits_of(mode, 3, 2) == 0:
+            return None
+        kind = bits_of(mode, 8, 7)
+        if kind == 0:
+            size = (12, a + 2)
+        elif kind == 1:
+            size = (a + 2, 12)
+        elif kind == 2:
+            size = (a + 6, bits_of(mode, 10, 9) + 6)
+            h = d = 0
+        elif a < 2:
+            size = [(6, 10), (10, 6)][a]
+        else:
+            return None
+    levels = [2, 3, 4, 5, 6, 8, 10, 12, 16, 20, 24, 32][r - 2 + 6 * h]
+    return size[0], size[1], bool(d), levels
+
+
+def transfer(a, b):
+    """Applies bit_transfer_signed, returning the signed offset a and the base b."""
+    b = b >> 1 | (a & 0x80)
+    a = a >> 1 & 0x3f
+    return (a - 0x40 if a & 0x20 else a), b
+
+
+def blue_contract(r, g, b, a):
+    return [(r + b) >> 1, (g + b) >> 1, b, a]
+
+
+def astc_endpoints(mode, v):
+    if mode == 0:
+        e = [[v[0]] * 3 + [255], [v[1]] * 3 + [255]]
+    elif mode == 1:
+        l0 = (v[0] >> 2) | (v[1] & 0xc0)
+        l1 = min(l0 + (v[1] & 0x3f), 255)
+        e = [[l0] * 3 + [255], [l1] * 3 + [255]]
+    elif mode == 4:
+        e = [[v[0]] * 3 + [v[2]], [v[1]] * 3 + [v[3]]]
+    elif mode == 5:
+        o1, b0 = transfer(v[1], v[0])
+        o3, b2 = transfer(v[3], v[2])
+        e = [[b0] * 3 + [b2], [b0 + o1] * 3 + [b2 + o3]]
+    elif mode in (6, 10):
+        alphas = [v[4], v[5]] if mode == 10 else [255, 255]
+        e = [[v[0] * v[3] >> 8, v[1] * v[3] >> 8, v[2] * v[3] >> 8, alphas[0]], [v[0], v[1], v[2], alphas[1]]]
+    elif mode in (8, 12):
+        alphas = [v[6], v[7]] if mode == 12 else [255, 255]
+        if v[1] + v[3] + v[5] >= v[0] + v[2] + v[4]:
+            e = [[v[0], v[2], v[4], alphas[0]], [v[1], v[3], v[5], alphas[1]]]
+        else:
+            e = [blue_contract(v[1], v[3], v[5], alphas[1]), blue_contract(v[0], v[2], v[4], alphas[0])]
+    elif mode in (9, 13):
+        pairs = [transfer(v[i + 1], v[i]) for i in range(0, 8 if mode == 13 else 6, 2)]
+        offsets = [offset for offset, _ in pairs]
+        bases = [base for _, base in pairs]
+        if mode == 9:
+            offsets.append(0)
+            bases.append(255)
+        summed = [base + offset for base, offset in zip(bases, offsets)]
+        if sum(offsets[:3]) >= 0:
+            e = [bases, summed]
+        else:
+            e = [blue_contract(*summed), blue_contract(*bases)]
+    else:
+        return None
+    return [[max(0, min(255, value)) for value in endpoint] for endpoint in e]
+
+
+def astc_hash(seed):
+    mask = 0xffffffff
+    seed ^= seed >> 15
+    seed = (seed - (seed << 17)) & mask
+    seed = (seed + (seed << 7)) & mask
+    seed = (seed + (seed << 4)) & mask
+    seed ^= seed >> 5
+    seed = (seed + (seed << 16)) & mask
+    seed ^= seed >> 7
+    seed ^= seed >> 3
+    seed = (seed ^ (seed << 6)) & mask
+    seed ^= seed >> 17
+    return seed
+
+
+def astc_partition(seed, x, y, count, small):
+    if small:
+        x, y = x * 2, y * 2
+    seed += (count - 1) * 1024
+    rnum = astc_hash(seed)
+    seeds = [(rnum >> (4 * i) & 15) ** 2 for i in range(8)]
+    sh1 = (4 if seed & 2 else 5) if seed & 1 else (6 if count == 3 else 5)
+    sh2 = (6 if count == 3 else 5) if seed & 1 else (4 if seed & 2 else 5)
+    seeds = [value >> (sh1 if i % 2 == 0 else sh2) for i, value in enumerate(seeds)]
+    distances = [(seeds[0] * x + seeds[1] * y + (rnum >> 14)) & 63, (seeds[2] * x + seeds[3] * y + (rnum >> 10)) & 63,
+                 (seeds[4] * x + seeds[5] * y + (rnum >> 6)) & 63, (seeds[6] * x + seeds[7] * y + (rnum >> 2)) & 63]
+    distances = [distance if i < count else 0 for i, distance in enumerate(distances)]
+    return distances.index(max(distances))
+
+
+def astc_infill(weights, grid_width, grid_height, width, height, x, y):
+    ds, dt = (1024 + width // 2) // (width - 1), (1024 + height // 2) // (height - 1)
+    gs, gt = (ds * x * (grid_width - 1) + 32) >> 6, (dt * y * (grid_height - 1) + 32) >> 6
+    js, fs, jt, ft = gs >> 4, gs & 15, gt >> 4, gt & 15
+
+    def weight(i, j):
+        return weights[j * grid_width + i] if i < grid_width and j < grid_height else 0
+
+    w11 = (fs * ft + 8) >> 4
+    w10, w01 = ft - w11, fs - w11
+    w00 = 16 - fs - ft + w11
+    total = weight(js, jt) * w00 + weight(js + 1, jt) * w01 + weight(js, jt + 1) * w10 + weight(js + 1, jt + 1) * w11
+    return (total + 8) >> 4
+
+
+def decode_astc_texels(block, width, height, srgb):
+    """Returns the texels of a block in row-major order, or None if it decodes to the error color."""
+    bits = int.from_bytes(block, "little")
+    mode = bits_of(bits, 10, 0)
+    if mode & 0x1ff == 0x1fc:
+        if bits_of(bits, 9, 9) or bits_of(bits, 11, 10) != 3:
+            return None
+        return [[bits_of(bits, 79 + 16 * c, 72 + 16 * c) for c in range(4)]] * (width * height)
+    grid = astc_block_mode(mode)
+    if grid is None:
+        return None
+    grid_width, grid_height, dual, weight_levels = grid
+    partitions = bits_of(bits, 12, 11) + 1
+    weight_count = grid_width * grid_height * (2 if dual else 1)
+    weight_bits = ise_size(weight_count, weight_levels)
+    if grid_width > width or grid_height > height or (dual and partitions == 4) or weight_count > 64 \
+            or not 24 <= weight_bits <= 96:
+        return None
+    top = 128 - weight_bits
+    if partitions == 1:
+        modes = [bits_of(bits, 16, 13)]
+        start = 17
+    else:
+        start = 29
+        low = bits_of(bits, 28, 23)
+        if low & 3 == 0:
+            modes = [low >> 2] * partitions
+        else:
+            extra = 3 * partitions - 4
+            top -= extra
+            encoded = low | bits_of(bits, top + extra - 1, top) << 6
+            classes = [(encoded >> (2 + i) & 1) + (encoded & 3) - 1 for i in range(partitions)]
+            modes = [classes[i] << 2 | (encoded >> (2 + partitions + 2 * i) & 3) for i in range(partitions)]
+    component = None
+    if dual:
+        top -= 2
+        component = bits_of(bits, top + 1, top)
+    count = sum(2 * ((mode >> 2) + 1) for mode in modes)
+    if count > 18 or top < start:
+        return None
+    fitting = [levels for levels, _, _, _ in ASTC_RANGES if ise_size(count, levels) <= top - start]
+    if not fitting or fitting[-1] < 6:
+        return None
+    color_levels = fitting[-1]
+    sequence = bits_of(bits, start + ise_size(count, color_levels) - 1, start)
+    values = [unquantize_color(value, color_levels) for value in decode_ise(sequence, count, color_levels)]
+    endpoints = []
+    for mode in modes:
+        length = 2 * ((mode >> 2) + 1)
+        endpoint = astc_endpoints(mode, values[:length])
+        if endpoint is None:
+            return None
+        endpoints.append(endpoint)
+        values = values[length:]
+    reversed_bits = int(format(bits, "0128b")[::-1], 2)
+    weights = [unquantize_weight(value, weight_levels)
+               for value in decode_ise(bits_of(reversed_bits, weight_bits - 1, 0), weight_count, weight_levels)]
+    planes = [weights[0::2], weights[1::2]] if dual else [weights]
+    texels = []
+    for y in range(height):
+        for x in range(width):
+            partition = astc_partition(bits_of(bits, 22, 13), x, y, partitions, width * height < 31) \
+                if partitions > 1 else 0
+            e0, e1 = endpoints[partition]
+            plane_weights = [astc_infill(plane, grid_width, grid_height, width, height, x, y) for plane in planes]
+            texel = []
+            for c in range(4):
+                weight = plane_weights[1] if c == component else plane_weights[0]
+                c0, c1 = [value << 8 | (0x80 if srgb else value) for value in (e0[c], e1[c])]
+                texel.append(((c0 * (64 - weight) + c1 * weight + 32) >> 6) >> 8)
+            texels.append(texel)
+    return texels
+
+
+def decode_astc(block, width, height, srgb=False):
+    return decode_astc_texels(block, width, height, srgb) or [ASTC_ERROR] * (width * height)
+
+
+def astc_block_features(block, width, height):
+    """Returns the features of a valid block which the fixtures should cover, or None for invalid blocks."""
+    if decode_astc_texels(block, width, height, False) is None:
+        return None
+    bits = int.from_bytes(block, "little")
+    if bits_of(bits, 8, 0) == 0x1fc:
+        return {"void extent"}
+    grid_width, grid_height, dual, levels = astc_block_mode(bits_of(bits, 10, 0))
+    _, trits, quints, _ = astc_range(levels)
+    features = {"partitions %d" % (bits_of(bits, 12, 11) + 1)}
+    if dual:
+        features.add("dual plane")
+    if trits or quints:
+        features.add("trit or quint weights")
+    if (grid_width, grid_height) != (width, height):
+        features.add("infilled weights")
+    return features
+
+
+def astc_random_block(rng, width, height):
+    """Returns random bits which form a valid block, with the features it covers."""
+    while True:
+        block = rng.getrandbits(128).to_bytes(16, "little")
+        features = astc_block_features(block, width, height)
+        if features is not None:
+            return block, features
+
+
+def astc_random_blocks(rng, width, height, count):
+    """Returns `count` valid blocks of random bits. After the first half, blocks which cover a feature the others do
+    not are preferred, so that partitions, dual planes, trits and quints, and infill are all exercised."""
+    blocks, covered = [], set()
+    while len(blocks) < count:
+        for _ in range(1000 if len(blocks) >= count // 2 else 1):
+            block, features = astc_random_block(rng, width, height)
+            if features - covered:
+                break
+        blocks.append(block)
+        covered |= features
+    return blocks, covered
+
+
+def blocks_to_rgba(blocks, width, height, decode_block, block_width=4, block_height=4):
+    blocks_x = (width + block_width - 1) // block_width
+    rgba = bytearray(width * height * 4)
+    for index, block in enumerate(blocks):
+        texels = decode_block(block)
+        for i, texel in enumerate(texels):
+            x = (index % blocks_x) * block_width + i % block_width
+            y = (index // blocks_x) * block_height + i // block_width
+            if x < width and y < height:
+                rgba[(y * width + x) * 4:(y * width + x + 1) * 4] = bytes(texel)
+    return bytes(rgba)
+
+
+def level_sizes(size, count):
+    return [max(1, size >> level) for level in range(count)]
+
+
+def bc1_levels():
+    """Returns the blocks of each level of an 8x8 BC1 texture, mixing the four and three color modes."""
+    levels = []
+    for level, size in enumerate(level_sizes(8, 4)):
+        blocks = []
+        for i in range(((size + 3) // 4) ** 2):
+            indices = [noise(i, j, level, 3) for j in range(16)]
+            if (i + level) % 2:
+                blocks.append(bc1_block(0xf800, 0x001f, indices))
+            else:
+                blocks.append(bc1_block(0x07e0, 0xffff, indices))
+        levels.append(blocks)
+    return levels
+
+
+def etc2_levels():
+    """Returns the blocks of each level of an 8x8 ETC2 texture, in both the individual and differential modes."""
+    levels = []
+    for level, size in enumerate(level_sizes(8, 4)):
+        blocks = []
+        for i in range(((size + 3) // 4) ** 2):
+            indices = [noise(i, j, level + 4, 3) for j in range(16)]
+            tables = [noise(i, level, 1, 7), noise(i, level, 2, 7)]
+            flip = (i + level) % 2 == 1
+            if i % 2:
+                colors = [(noise(i, 0, 3, 31), noise(i, 0, 4, 31), noise(i, 0, 5, 31)), (-4, 3, 1)]
+                colors[0] = tuple(max(4, min(27, value)) for value in colors[0])
+                blocks.append(etc_block(True, flip, colors, tables, indices))
+            else:
+                colors = [(noise(i, 1, c, 15),) for c in range(6)]
+                colors = [(colors[0][0], colors[1][0], colors[2][0]), (colors[3][0], colors[4][0], colors[5][0])]
+                blocks.append(etc_block(False, flip, colors, tables, indices))
+        levels.append(blocks)
+    return levels
+
+
+def bc7_levels():
+    """Returns random blocks for each level of an 8x8 BC7 texture, cycling through the modes."""
+    rng = random.Random(7)
+    return [[bc7_random_block(rng, (i + level * 4) % 8) for i in range(((size + 3) // 4) ** 2)]
+            for level, size in enumerate(level_sizes(8, 4))]
+
+
+def astc_levels(rng, block_width, block_height):
+    """Returns valid random blocks for each level of an 8x8 ASTC texture."""
+    return [[astc_random_block(rng, block_width, block_height)[0]
+             for _ in range(((size + block_width - 1) // block_width) * ((size + block_height - 1) // block_height))]
+            for size in level_sizes(8, 4)]
+
+
+def ktx2(format, color_model, block_size, levels, zlib_levels=False, block_width=4, block_height=4):
+    data = [b"".join(blocks) for blocks in levels]
+    stored = [zlib.compress(level) if zlib_levels else level for level in data]
+    # A basic data format descriptor without samples, with the color model and the size of the blocks.
+    dfd = struct.pack("<IIHHBBBB4B8B", 28, 0, 2, 24, color_model, 1, 1, 0, block_width - 1, block_height - 1, 0, 0,
+                      block_size, *bytes(7))
+    header_size = 80 + 24 * len(levels)
+    dfd_offset = header_size
+    offset = dfd_offset + len(dfd)
+    # Levels are stored smallest first, each aligned to 16 bytes.
+    offsets = [0] * len(levels)
+    body = b""
+    for level in reversed(range(len(levels))):
+        padding = (-(offset + len(body))) % 16
+        body += bytes(padding)
+        offsets[level] = offset + len(body)
+        body += stored[level]
+    out = b"\xabKTX 20\xbb\r\n\x1a\n"
+    out += struct.pack("<IIIIIIIII", format, 1, 8, 8, 0, 0, 1, len(levels), 3 if zlib_levels else 0)
+    out += struct.pack("<IIIIQQ", dfd_offset, len(dfd), 0, 0, 0, 0)
+    for level in range(len(levels)):
+        out += struct.pack("<QQQ", offsets[level], len(stored[level]), len(data[level]))
+    return out + dfd + body
+
+
+def dds(levels, four_cc=b"", dxgi_format=None, rgb_masks=None):
+    flags = 0x1 | 0x2 | 0x4 | 0x1000 | 0x20000
+    if rgb_masks:
+        pixel_format = struct.pack("<II4sIIIII", 32, 0x40, b"\0\0\0\0", 24, *rgb_masks, 0)
+    else:
+        pixel_format = struct.pack("<II4sIIIII", 32, 0x4, b"DX10" if dxgi_format else four_cc, 0, 0, 0, 0, 0)
+    header = struct.pack("<IIIIIII", 124, flags, 8, 8, 0, 0, len(levels)) + bytes(44) + pixel_format
+    header += struct.pack("<IIIII", 0x1000 | 0x400000 | 0x8, 0, 0, 0, 0)
+    out = b"DDS " + header
+    if dxgi_format:
+        out += struct.pack("<IIIII", dxgi_format, 3, 0, 1, 0)
+    return out + b"".join(b"".join(blocks) for blocks in levels)
+
+
+def rgba_levels(levels, decode_block):
+    return b"".join(blocks_to_rgba(blocks, size, size, decode_block)
+                    for blocks, size in zip(levels, level_sizes(8, len(levels))))
+
+
+def generate_textures():
+    bc1 = bc1_levels()
+    bc1_rgba = rgba_levels(bc1, decode_bc1)
+    write("bc1.ktx2", ktx2(133, 128, 8, bc1))
+    write("bc1_zlib.ktx2", ktx2(133, 128, 8, bc1, zlib_levels=True))
+    write("bc1.dds", dds(bc1, four_cc=b"DXT1"))
+    write("bc1.rgba", bc1_rgba)
+
+    etc2 = etc2_levels()
+    write("etc2.ktx2", ktx2(147, 160, 8, etc2))
+    write("etc2.rgba", rgba_levels(etc2, decode_etc))
+
+    bc7 = bc7_levels()
+    write("bc7.ktx2", ktx2(145, 135, 16, bc7))
+    write("bc7_dx10.dds", dds(bc7[:2], dxgi_format=98))
+    write("bc7.rgba", rgba_levels(bc7, decode_bc7))
+
+    # Separate blocks of every mode, followed by a block of the reserved mode 8.
+    rng = random.Random(8)
+    blocks = [bc7_random_block(rng, mode) for mode in range(8) for _ in range(16)] + [bytes(16)]
+    write("bc7_blocks.bin", b"".join(blocks))
+    write("bc7_blocks.rgba", bytes(value for block in blocks for texel in decode_bc7(block) for value in texel))
+
+    rng = random.Random(6)
+    astc = astc_levels(rng, 6, 6)
+    write("astc_6x6.ktx2", ktx2(165, 162, 16, astc, block_width=6, block_height=6))
+    write("astc_6x6.rgba", b"".join(blocks_to_rgba(blocks, size, size, lambda block: decode_astc(block, 6, 6), 6, 6)
+                                    for blocks, size in zip(astc, level_sizes(8, 4))))
+
+    # Blocks of every footprint, followed by a void extent block, an HDR void extent block and a reserved block mode.
+    rng = random.Random(12)
+    void_extent = (0xdfc | 0xfff_ffff_ffff_f000 | 0x4000_c000_8000_1234 << 64).to_bytes(16, "little")
+    hdr_void_extent = (int.from_bytes(void_extent, "little") | 0x200).to_bytes(16, "little")
+    reserved = (0x1c0).to_bytes(16, "little")
+    blocks, unorm, srgb, covered = [], [], [], set()
+    for width, height in ASTC_FOOTPRINTS:
+        footprint_blocks, features = astc_random_blocks(rng, width, height, 8)
+        covered |= features
+        footprint_blocks += [void_extent, hdr_void_extent, reserved]
+        blocks += footprint_blocks
+        for block in footprint_blocks:
+            unorm += decode_astc(block, width, height)
+            srgb += decode_astc(block, width, height, srgb=True)
+    assert covered >= {"partitions 1", "partitions 2", "partitions 3", "partitions 4", "dual plane",
+                       "trit or quint weights", "infilled weights"}, covered
+    write("astc_blocks.bin", b"".join(blocks))
+    write("astc_blocks.rgba", bytes(value for texel in unorm for value in texel))
+    write("astc_blocks_srgb.rgba", bytes(value for texel in srgb for value in texel))
+
+    # Three mip levels of BGR pixels, as written by most tools.
+    pixels = [[(noise(i, 0, level, 255), noise(i, 1, level, 255), noise(i, 2, level, 255)) for i in range(size * size)]
+              for level, size in enumerate(level_sizes(8, 3))]
+    write("bgr.dds", dds([[bytes(value for pixel in level for value in pixel)] for level in pixels],
+                         rgb_masks=(0xff0000, 0xff00, 0xff)))
+    write("bgr.rgba", bytes(value for level in pixels for b, g, r in level for value in (r, g, b, 255)))
+
+
+def main():
+    for depth in [1, 2, 4, 8, 16]:
+        generate_png("gray%d" % depth, 0, depth)
+    for depth in [1, 2, 4, 8]:
+        generate_png("palette%d" % depth, 3, depth)
+    for depth in [8, 16]:
+        generate_png("rgb%d" % depth, 2, depth)
+        generate_png("gray_alpha%d" % depth, 4, depth)
+        generate_png("rgba%d" % depth, 6, depth)
+    generate_png("gray8_trns", 0, 8, transparent=True)
+    generate_png("rgb16_trns", 2, 16, transparent=True)
+    generate_png("rgba8_adam7", 6, 8, interlaced=True)
+    generate_png("palette2_adam7", 3, 2, interlaced=True)
+    generate_png("gray16_adam7", 0, 16, interlaced=True)
+
+    width, height = 21, 13
+    gradient = [(x * 12, y * 19, 60 + (x + y) * 5) for y in range(height) for x in range(width)]
+    write("gradient.rgba", bytes(value for pixel in gradient for value in pixel + (255,)))
+    write("baseline_444.jpg", jpeg(gradient, width, height, subsampled=False))
+    write("baseline_420.jpg", jpeg(gradient, width, height, subsampled=True))
+    write("restart_420.jpg", jpeg(gradient, width, height, subsampled=True, restart_interval=1))
+
+    generate_textures()
+
+
+if __name__ == "__main__":
+    main()
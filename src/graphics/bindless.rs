@@ -0,0 +1,255 @@
+use std::sync::Arc;
+use ash::version::DeviceV1_0;
+use ash::vk;
+use super::{CmdBuffer, CmdRecordingError, DescriptorSet, Device, Pipeline};
+use super::buffer::Buffer;
+use super::deletion::DeferredObject;
+use super::descriptors::{DescriptorSetLayout, DescriptorSetLayoutBuilder, DescriptorWriter};
+
+/// The binding of the array of sampled images in the heap's set.
+pub const TEXTURE_BINDING : u32 = 0;
+/// The binding of the array of storage buffers in the heap's set.
+pub const BUFFER_BINDING : u32 = 1;
+/// The binding of the array of samplers in the heap's set, which holds the sampler of each texture at the texture's
+/// index.
+pub const SAMPLER_BINDING : u32 = 2;
+
+/// Refers to a texture in a `BindlessHeap` by its index in the texture array.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextureHandle(u32);
+
+impl TextureHandle {
+    pub fn index(self) -> u32 {
+        self.0
+    }
+}
+
+/// Refers to a storage buffer in a `BindlessHeap` by its index in the buffer array.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BufferHandle(u32);
+
+impl BufferHandle {
+    pub fn index(self) -> u32 {
+        self.0
+    }
+}
+
+/// Hands out slots of one descriptor array. Freed slots are only reused once the frame they were freed in has retired,
+/// since the GPU may still be reading the old descriptor.
+struct SlotAllocator {
+    capacity : u32,
+    next : u32,
+    free : Vec<u32>,
+    /// Slots along with the frame they were freed in.
+    pending : Vec<(u64, u32)>,
+}
+
+impl SlotAllocator {
+    fn new(capacity : u32) -> Self {
+        Self { capacity, next: 0, free: Vec::new(), pending: Vec::new() }
+    }
+
+    fn allocate(&mut self, retired : Option<u64>) -> Option<u32> {
+        let free = &mut self.free;
+        self.pending.retain(|&(frame, slot)| {
+            let reusable = retired.is_some_and(|retired| frame <= retired);
+            if reusable {
+                free.push(slot);
+            }
+            !reusable
+        });
+        if let Some(slot) = self.free.pop() {
+            return Some(slot);
+        }
+        if self.next < self.capacity {
+            self.next += 1;
+            return Some(self.next - 1);
+        }
+        None
+    }
+
+    fn release(&mut self, frame : u64, slot : u32) {
+        self.pending.push((frame, slot));
+    }
+}
+
+/// A global descriptor set holding arrays of every texture and storage buffer, which shaders index with handles passed
+/// in push constants. This lets many draws with different resources share one bind, rather than binding a set per draw.
+///
+/// Requires descriptor indexing, see `Device::supports_bindless`. Like material textures, each texture is split into a
+/// sampled image and a sampler, stored at the same index of two arrays. In WGSL, shaders declare the arrays as
+/// `@group(N) @binding(0) var textures : binding_array<texture_2d<f32>, COUNT>;` and
+/// `@group(N) @binding(2) var samplers : binding_array<sampler, COUNT>;`, where `COUNT` is at most the number of
+/// textures the heap was created for. Handles which vary within a draw must be indexed with `nonuniformEXT` in GLSL.
+pub struct BindlessHeap {
+    device : Arc<Device>,
+    set_layout : DescriptorSetLayout,
+    pool : vk::DescriptorPool,
+    set : DescriptorSet,
+    textures : SlotAllocator,
+    buffers : SlotAllocator,
+}
+
+impl Drop for BindlessHeap {
+    fn drop(&mut self) {
+        self.device.destroy_deferred(DeferredObject::DescriptorPool(self.pool));
+        info!("Dropped BindlessHeap")
+    }
+}
+
+impl BindlessHeap {
+    /// Creates a heap for up to `max_textures` textures and `max_buffers` storage buffers, clamped to the device's
+    /// limits. Returns `None` if the device does not support descriptor indexing.
+    pub fn new(device : Arc<Device>, max_textures : u32, max_buffers : u32) -> Option<Self> {
+        let limits = device.bindless_limits()?;
+        let max_textures = max_textures.min(limits.max_sampled_images).min(limits.max_samplers);
+        let max_buffers = max_buffers.min(limits.max_storage_buffers);
+
+        let flags = vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+            | vk::DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING
+            | vk::DescriptorBindingFlags::PARTIALLY_BOUND;
+        let stages = vk::ShaderStageFlags::ALL;
+        let set_layout = DescriptorSetLayoutBuilder::new(Arc::clone(&device))
+            .binding(TEXTURE_BINDING, vk::DescriptorType::SAMPLED_IMAGE, max_textures, stages)
+            .binding_flags(TEXTURE_BINDING, flags)
+            .binding(BUFFER_BINDING, vk::DescriptorType::STORAGE_BUFFER, max_buffers, stages)
+            .binding_flags(BUFFER_BINDING, flags)
+            .binding(SAMPLER_BINDING, vk::DescriptorType::SAMPLER, max_textures, stages)
+            .binding_flags(SAMPLER_BINDING, flags)
+            .build();
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize { ty: vk::DescriptorType::SAMPLED_IMAGE, descriptor_count: max_textures },
+            vk::DescriptorPoolSize { ty: vk::DescriptorType::STORAGE_BUFFER, descriptor_count: max_buffers },
+            vk::DescriptorPoolSize { ty: vk::DescriptorType::SAMPLER, descriptor_count: max_textures },
+        ];
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .flags(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND)
+            .max_sets(1)
+            .pool_sizes(&pool_sizes);
+        let (pool, set) = unsafe {
+            let pool = device
+                .ash_device()
+                .create_descriptor_pool(&pool_info, None)
+                .expect("Failed to create bindless descriptor pool");
+            let set_layouts = [set_layout.layout_raw()];
+            let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(pool)
+                .set_layouts(&set_layouts);
+            let set = device
+                .ash_device()
+                .allocate_descriptor_sets(&allocate_info)
+                .expect("Failed to allocate bindless descriptor set")
+                .remove(0);
+            (pool, DescriptorSet::from_raw(set))
+        };
+        info!("Created bindless heap for {} textures and {} buffers", max_textures, max_buffers);
+
+        Some(Self {
+            device,
+            set_layout,
+            pool,
+            set,
+            textures: SlotAllocator::new(max_textures),
+            buffers: SlotAllocator::new(max_buffers),
+        })
+    }
+
+    /// Returns the number of textures the heap holds at most, which shaders must not declare larger arrays than.
+    pub fn texture_capacity(&self) -> u32 {
+        self.textures.capacity
+    }
+
+    /// The layout of the heap's set, which pipelines using the heap include at the set number they bind it to.
+    pub fn set_layout(&self) -> &DescriptorSetLayout {
+        &self.set_layout
+    }
+
+    pub fn set(&self) -> DescriptorSet {
+        self.set
+    }
+
+    /// Adds a texture to the heap, returning `None` if the heap is full.
+    pub fn add_texture(&mut self, view : vk::ImageView, layout : vk::ImageLayout, sampler : vk::Sampler)
+        -> Option<TextureHandle> {
+        let slot = self.textures.allocate(self.device.retired_frame())?;
+        DescriptorWriter::new()
+            .image(TEXTURE_BINDING, slot, vk::DescriptorType::SAMPLED_IMAGE, vk::Sampler::null(), view, layout)
+            .image(SAMPLER_BINDING, slot, vk::DescriptorType::SAMPLER, sampler, vk::ImageView::null(),
+                   vk::ImageLayout::UNDEFINED)
+            .update(&self.device, self.set);
+        Some(TextureHandle(slot))
+    }
+
+    /// Adds `range` bytes of `buffer` from `offset` to the heap, returning `None` if the heap is full.
+    pub fn add_buffer(&mut self, buffer : &Buffer, offset : vk::DeviceSize, range : vk::DeviceSize)
+        -> Option<BufferHandle> {
+        let slot = self.buffers.allocate(self.device.retired_frame())?;
+        DescriptorWriter::new()
+            .buffer(BUFFER_BINDING, slot, vk::DescriptorType::STORAGE_BUFFER, buffer, offset, range)
+            .update(&self.device, self.set);
+        Some(BufferHandle(slot))
+    }
+
+    /// Removes a texture. Its slot is reused once the current frame has retired.
+    pub fn remove_texture(&mut self, handle : TextureHandle) {
+        self.textures.release(self.device.current_frame(), handle.0);
+    }
+
+    /// Removes a buffer. Its slot is reused once the current frame has retired.
+    pub fn remove_buffer(&mut self, handle : BufferHandle) {
+        self.buffers.release(self.device.current_frame(), handle.0);
+    }
+
+    /// Binds the heap at `set` using the layout of `pipeline`. The pipeline must already be bound.
+    pub fn bind(&self, cmd_buffer : &mut CmdBuffer, pipeline : &Pipeline, set : u32) -> Result<(), CmdRecordingError> {
+        cmd_buffer.bind_descriptor_sets(pipeline, set, &[self.set], &[])
+    }
+
+    /// Pushes the indices of handles to the stages of `pipeline` at `offset`, so a draw can select its resources.
+    pub fn push_handles(cmd_buffer : &mut CmdBuffer,
+                        pipeline : &Pipeline,
+                        stages : vk::ShaderStageFlags,
+                        offset : u32,
+                        indices : &[u32]) -> Result<(), CmdRecordingError> {
+        let bytes : Vec<u8> = indices.iter().flat_map(|index| index.to_ne_bytes().to_vec()).collect();
+        cmd_buffer.push_constant_bytes(pipeline, stages, offset, &bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocates_each_slot_once() {
+        let mut slots = SlotAllocator::new(2);
+        assert_eq!(slots.allocate(None), Some(0));
+        assert_eq!(slots.allocate(None), Some(1));
+        assert_eq!(slots.allocate(None), None);
+    }
+
+    #[test]
+    fn keeps_released_slots_until_their_frame_retires() {
+        let mut slots = SlotAllocator::new(2);
+        assert_eq!(slots.allocate(None), Some(0));
+        assert_eq!(slots.allocate(None), Some(1));
+        slots.release(5, 0);
+        assert_eq!(slots.allocate(None), None);
+        assert_eq!(slots.allocate(Some(4)), None);
+        assert_eq!(slots.allocate(Some(5)), Some(0));
+        assert_eq!(slots.allocate(Some(5)), None);
+    }
+
+    #[test]
+    fn reuses_released_slots_before_new_ones() {
+        let mut slots = SlotAllocator::new(4);
+        assert_eq!(slots.allocate(None), Some(0));
+        assert_eq!(slots.allocate(None), Some(1));
+        slots.release(1, 1);
+        slots.release(2, 0);
+        assert_eq!(slots.allocate(Some(1)), Some(1));
+        assert_eq!(slots.allocate(Some(1)), Some(2));
+        assert_eq!(slots.allocate(Some(2)), Some(0));
+    }
+}
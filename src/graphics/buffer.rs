@@ -1,25 +1,158 @@
-use std::{cell::RefCell, mem::size_of, rc::Rc};
+use std::{mem::size_of_val, slice, sync::Arc};
 use ash::version::DeviceV1_0;
 use ash::vk;
-use super::{Device, Material, util::find_memory_type_index};
+use super::{CmdBuffer, CmdPool, Device, Material, Queue, util::find_memory_type_index};
+use super::deletion::DeferredObject;
 
+#[derive(Debug)]
 pub enum BufferCreationError {
     AllocationFailed,
     UnsupportedMemoryType,
 }
 
 pub struct Buffer {
-    device : Rc<RefCell<Device>>,
+    device : Arc<Device>,
     buffer : vk::Buffer,
     buffer_memory : vk::DeviceMemory,
 }
 
-impl Drop for Buffer {
-    fn drop(&mut self) {
+impl Buffer {
+    /// Creates a buffer backed by its own allocation with the requested memory properties. When more than one distinct
+    /// queue family is given, the buffer is shared concurrently between them so no ownership transfers are needed.
+    pub fn new(device : Arc<Device>,
+               size : vk::DeviceSize,
+               usage : vk::BufferUsageFlags,
+               memory_flags : vk::MemoryPropertyFlags,
+               queue_families : &[u32]) -> Result<Self,BufferCreationError> {
+        let mut families = queue_families.to_vec();
+        families.sort_unstable();
+        families.dedup();
+        let buffer_info = vk::BufferCreateInfo::builder()
+            .size(size)
+            .usage(usage);
+        let buffer_info = if families.len() > 1 {
+            buffer_info
+                .sharing_mode(vk::SharingMode::CONCURRENT)
+                .queue_family_indices(families.as_slice())
+        } else {
+            buffer_info.sharing_mode(vk::SharingMode::EXCLUSIVE)
+        };
+
+        let (buffer, memory_requirements) = unsafe {
+            let buffer = device
+                .ash_device()
+                .create_buffer(&buffer_info, None)
+                .expect("Failed to create buffer");
+            let memory_requirements = device.ash_device().get_buffer_memory_requirements(buffer);
+            (buffer, memory_requirements)
+        };
+
+        let memory_properties = device.memory_properties();
+        let memory_index = match find_memory_type_index(&memory_requirements, &memory_properties, memory_flags) {
+            Some(i) => i,
+            None => {
+                unsafe { device.ash_device().destroy_buffer(buffer, None) };
+                return Err(BufferCreationError::UnsupportedMemoryType);
+            }
+        };
+        let allocate_info = vk::MemoryAllocateInfo::builder()
+            .memory_type_index(memory_index)
+            .allocation_size(memory_requirements.size);
+        let buffer_memory = unsafe {
+            match device.ash_device().allocate_memory(&allocate_info, None) {
+                Ok(memory) => memory,
+                Err(_) => {
+                    device.ash_device().destroy_buffer(buffer, None);
+                    return Err(BufferCreationError::AllocationFailed);
+                }
+            }
+        };
+        unsafe {
+            device
+                .ash_device()
+                .bind_buffer_memory(buffer, buffer_memory, 0)
+                .expect("Failed to bind buffer memory");
+        }
+        Ok(Self { device, buffer, buffer_memory })
+    }
+
+    /// Copies `data` into the buffer at `offset`. The buffer must have been created with host visible and coherent
+    /// memory.
+    pub fn write(&self, offset : vk::DeviceSize, data : &[u8]) {
+        unsafe {
+            let mapped = self.device
+                .ash_device()
+                .map_memory(self.buffer_memory, offset, data.len() as vk::DeviceSize, vk::MemoryMapFlags::empty())
+                .expect("Failed to map buffer memory");
+            std::ptr::copy_nonoverlapping(data.as_ptr(), mapped as *mut u8, data.len());
+            self.device
+                .ash_device()
+                .unmap_memory(self.buffer_memory);
+        }
+    }
+
+    /// Creates a device local buffer holding `data`, copied through a staging buffer on the transfer queue. The buffer
+    /// is shared with the graphics queue, so it can be used there without an ownership transfer. Blocks until the copy
+    /// is complete.
+    pub fn device_local_with_data(device : Arc<Device>,
+                                  transfer_queue : &Queue,
+                                  graphics_queue : &Queue,
+                                  usage : vk::BufferUsageFlags,
+                                  data : &[u8]) -> Result<Self, BufferCreationError> {
+        let size = (data.len() as vk::DeviceSize).max(4);
+        let staging = Buffer::new(
+            Arc::clone(&device),
+            size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            &[])?;
+        staging.write(0, data);
+        let buffer = Buffer::new(
+            Arc::clone(&device),
+            size,
+            usage | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            &[transfer_queue.family_index(), graphics_queue.family_index()])?;
+
+        let cmd_pool = Arc::new(CmdPool::new(Arc::clone(&device), transfer_queue));
+        let mut cmd_buffer = CmdBuffer::new(Arc::clone(&device), cmd_pool);
+        let region = vk::BufferCopy { src_offset: 0, dst_offset: 0, size };
+        cmd_buffer.begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)
+            .and_then(|_| cmd_buffer.copy_buffer(&staging, &buffer, &[region]))
+            .and_then(|_| cmd_buffer.end())
+            .expect("Failed to record buffer upload");
+
+        let fence_info = vk::FenceCreateInfo::builder();
+        let fence = unsafe {
+            device
+                .ash_device()
+                .create_fence(&fence_info, None)
+                .expect("Failed to create fence")
+        };
+        transfer_queue.submit_with(&[&cmd_buffer], &[], &[], Some(fence));
         unsafe {
-            self.device.borrow().ash_device().destroy_buffer(self.buffer, None);
-            self.device.borrow().ash_device().free_memory(self.buffer_memory, None);
+            device
+                .ash_device()
+                .wait_for_fences(&[fence], true, u64::MAX)
+                .expect("Failed to wait for buffer upload");
         }
+        device.destroy_deferred(DeferredObject::Fence(fence));
+        Ok(buffer)
+    }
+
+    pub fn buffer_raw(&self) -> vk::Buffer {
+        self.buffer
+    }
+
+    pub fn memory_raw(&self) -> vk::DeviceMemory {
+        self.buffer_memory
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        self.device.destroy_deferred(DeferredObject::Buffer(self.buffer));
+        self.device.destroy_deferred(DeferredObject::Memory(self.buffer_memory));
         info!("Dropped Buffer")
     }
 }
@@ -29,7 +162,7 @@ pub struct VertexBuffer {
 }
 
 impl VertexBuffer {
-    pub fn new(device : Rc<RefCell<Device>>, material : &Material) -> Result<Self,BufferCreationError> {
+    pub fn new(device : Arc<Device>, material : &Material) -> Result<Self,BufferCreationError> {
         let buffer_info = vk::BufferCreateInfo::builder()
             .size(material.vertex_buffer_size())
             .sharing_mode(vk::SharingMode::EXCLUSIVE)
@@ -38,15 +171,14 @@ impl VertexBuffer {
 
         let (buffer, memory_requirements) = unsafe {
             let buffer = device
-                .borrow()
                 .ash_device()
                 .create_buffer(&buffer_info, None)
                 .expect("Failed to create buffer");
-            let memory_requirements= device.borrow().ash_device().get_buffer_memory_requirements(buffer);
+            let memory_requirements= device.ash_device().get_buffer_memory_requirements(buffer);
             (buffer, memory_requirements)
         };
 
-        let memory_properties = device.borrow().memory_properties();
+        let memory_properties = device.memory_properties();
         let memory_index = find_memory_type_index(&memory_requirements, &memory_properties, vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE);
         match memory_index {
             Some(i) => {
@@ -56,23 +188,19 @@ impl VertexBuffer {
                     .build();
                 let buffer_memory = unsafe {
                     let buffer_memory = device
-                        .borrow()
                         .ash_device()
                         .allocate_memory(&allocate_info, None)
                         .expect("Failed to allocate memory");
                     device
-                        .borrow()
                         .ash_device()
                         .bind_buffer_memory(buffer, buffer_memory, 0)
                         .expect("Failed to bind buffer memory");
                     device
-                        .borrow()
                         .ash_device()
                         .map_memory(buffer_memory, 0, buffer_info.size, vk::MemoryMapFlags::empty())
                         .expect("Failed to map buffer memory");
                     // TODO: memcpy
                     device
-                        .borrow()
                         .ash_device()
                         .unmap_memory(buffer_memory);
                     buffer_memory
@@ -85,12 +213,60 @@ impl VertexBuffer {
             None => Err(BufferCreationError::UnsupportedMemoryType),
         }
     }
+
+    /// Uploads `vertices` into a device local vertex buffer. `V` should be `#[repr(C)]` and match the vertex input of
+    /// the pipelines drawing from the buffer. Blocks until the upload is complete.
+    pub fn from_vertices<V : Copy>(device : Arc<Device>,
+                                   transfer_queue : &Queue,
+                                   graphics_queue : &Queue,
+                                   vertices : &[V]) -> Result<Self, BufferCreationError> {
+        let bytes = unsafe { slice::from_raw_parts(vertices.as_ptr() as *const u8, size_of_val(vertices)) };
+        let buffer = Buffer::device_local_with_data(
+            device,
+            transfer_queue,
+            graphics_queue,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            bytes)?;
+        Ok(Self { buffer })
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
 }
 
+/// A buffer of 32-bit indices, bound with `vk::IndexType::UINT32`.
 pub struct IndexBuffer {
     buffer : Buffer,
 }
 
+impl IndexBuffer {
+    /// Uploads `indices` into a device local index buffer. Blocks until the upload is complete.
+    pub fn from_indices(device : Arc<Device>,
+                        transfer_queue : &Queue,
+                        graphics_queue : &Queue,
+                        indices : &[u32]) -> Result<Self, BufferCreationError> {
+        let bytes : Vec<u8> = indices.iter().flat_map(|index| index.to_le_bytes()).collect();
+        let buffer = Buffer::device_local_with_data(
+            device,
+            transfer_queue,
+            graphics_queue,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            &bytes)?;
+        Ok(Self { buffer })
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+}
+
 pub struct StagingBuffer {
     buffer : Buffer,
+}
+
+impl StagingBuffer {
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
 }
\ No newline at end of file
@@ -1,7 +1,58 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{convert::TryFrom, mem::size_of, slice, sync::{Arc, Mutex, MutexGuard}};
 use ash::version::DeviceV1_0;
 use ash::vk;
-use super::{Device, Framebuffer, Pipeline, Queue, RenderPass};
+use super::{DepthBias, Device, Framebuffer, Pipeline, Queue, RenderPass};
+use super::buffer::{Buffer, IndexBuffer, VertexBuffer};
+use super::sync::{BufferAccess, ImageAccess, ResourceTracker};
+use super::deletion::{DeferredObject, DestroyedResource, DestroyedResources};
+use super::mesh::Mesh;
+use super::texture::Texture;
+
+/// Describes how a command was recorded out of order.
+#[derive(Debug)]
+pub enum CmdRecordingError {
+    /// `begin` was called while the command buffer was already recording.
+    AlreadyRecording,
+    /// A command was recorded before `begin` or after `end`.
+    NotRecording,
+    /// The command is only valid outside of a render pass.
+    InsideRenderPass,
+    /// The command is only valid inside of a render pass.
+    OutsideRenderPass,
+    /// A draw or dispatch was recorded without a pipeline of the matching kind being bound.
+    NoPipelineBound,
+    /// The pipeline supports neither graphics nor compute.
+    IncompatiblePipeline,
+    /// Inline commands were recorded in a render pass begun for secondary command buffers, or secondary command
+    /// buffers were executed in a render pass begun for inline commands.
+    WrongSubpassContents,
+    /// The command is not valid for the level of this command buffer, i.e. executing commands from a secondary buffer.
+    WrongLevel,
+    /// Push constants end at `end` bytes, beyond the device's `max_push_constants_size` of `limit` bytes.
+    PushConstantsTooLarge { end : u32, limit : u32 },
+    /// Push constants are not a multiple of 4 bytes, or are not covered by a range of the pipeline for every stage.
+    PushConstantsOutOfRange,
+    /// The image is not in the layout the command requires, i.e. `TRANSFER_DST_OPTIMAL` for a copy into it.
+    WrongImageLayout { expected : vk::ImageLayout, actual : vk::ImageLayout },
+    /// The image was not created with the usage flags the command requires.
+    MissingImageUsage(vk::ImageUsageFlags),
+}
+
+/// A descriptor set which can be bound with `CmdBuffer::bind_descriptor_sets`. Sets are freed along with the pool they
+/// were allocated from, so this only wraps the handle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DescriptorSet(vk::DescriptorSet);
+
+impl DescriptorSet {
+    /// Wraps a set allocated from a descriptor pool.
+    pub fn from_raw(set : vk::DescriptorSet) -> Self {
+        Self(set)
+    }
+
+    pub fn set_raw(&self) -> vk::DescriptorSet {
+        self.0
+    }
+}
 
 /// Specifices the state which will be used for Command Buffers.
 pub struct CmdState {
@@ -10,119 +61,840 @@ pub struct CmdState {
 }
 
 pub struct CmdBuffer {
-    device : Rc<RefCell<Device>>,
-    cmd_pool : Rc<RefCell<CmdPool>>,
+    device : Arc<Device>,
+    cmd_pool : Arc<CmdPool>,
     cmd_buffer : vk::CommandBuffer,
+    level : vk::CommandBufferLevel,
     recording : bool,
+    render_pass_contents : Option<vk::SubpassContents>,
+    bound_bind_point : Option<vk::PipelineBindPoint>,
+    tracker : ResourceTracker,
+    /// The images and buffers destroyed since the last recording began, which the tracker forgets.
+    destroyed : DestroyedResources,
 }
 
 impl Drop for CmdBuffer {
     fn drop(&mut self) {
-        unsafe {
-            self.device.borrow().ash_device().device_wait_idle().unwrap();
-            self.device
-                .borrow()
-                .ash_device()
-                .free_command_buffers(self.cmd_pool.borrow().cmd_pool_raw(), &[self.cmd_buffer]);
-        }
+        self.device.destroy_deferred(DeferredObject::CommandBuffer(
+            self.cmd_pool.cmd_pool_raw(),
+            self.cmd_buffer,
+            Arc::clone(&self.cmd_pool.lock)));
         info!("Dropped CmdBuffer")
     }
 }
 
 /// A recorder for graphics, compute, or transfer operations.
 impl CmdBuffer {
-    pub fn new(device : Rc<RefCell<Device>>,
-               cmd_pool : Rc<RefCell<CmdPool>>) -> Self {
+    /// Allocates a primary command buffer, which can be submitted to a queue.
+    pub fn new(device : Arc<Device>,
+               cmd_pool : Arc<CmdPool>) -> Self {
+        Self::with_level(device, cmd_pool, vk::CommandBufferLevel::PRIMARY)
+    }
+
+    /// Allocates a secondary command buffer, which is executed from a primary command buffer with `execute_commands`.
+    /// Secondary command buffers let a render pass be recorded on several threads at once.
+    pub fn new_secondary(device : Arc<Device>,
+                         cmd_pool : Arc<CmdPool>) -> Self {
+        Self::with_level(device, cmd_pool, vk::CommandBufferLevel::SECONDARY)
+    }
+
+    fn with_level(device : Arc<Device>,
+                  cmd_pool : Arc<CmdPool>,
+                  level : vk::CommandBufferLevel) -> Self {
         let cmd_buffer_info = vk::CommandBufferAllocateInfo::builder()
-            .command_pool(cmd_pool.borrow().cmd_pool_raw())
+            .command_pool(cmd_pool.cmd_pool_raw())
             .command_buffer_count(1)
-            .level(vk::CommandBufferLevel::PRIMARY);
+            .level(level);
+        let lock = cmd_pool.lock();
         let cmd_buffer = unsafe {
             device
-                .borrow()
                 .ash_device()
                 .allocate_command_buffers(&cmd_buffer_info)
                 .expect("Failed to create command buffer")
                 .remove(0)
         };
 
-        Self { device, cmd_pool, cmd_buffer, recording: false }
+        drop(lock);
+
+        let destroyed = device.destroyed_resources();
+        Self { device,
+            cmd_pool,
+            cmd_buffer,
+            level,
+            recording: false,
+            render_pass_contents: None,
+            bound_bind_point: None,
+            tracker: ResourceTracker::new(),
+            destroyed,
+        }
     }
 
-    // Records graphics commands to the command buffer.
+    // Records graphics commands to the command buffer, drawing the mesh if one is given.
     pub fn record_graphics(&mut self,
                            state : CmdState,
                            render_pass : &RenderPass,
                            framebuffer : &Framebuffer,
-                           pipeline : &Pipeline) {
+                           pipeline : &Pipeline,
+                           mesh : Option<&Mesh>) {
+        // The command buffer must not be pending execution, which the caller ensures, i.e. with `reset_after`.
+        self.reset();
+        self.begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)
+            .expect("Command buffer is already recording");
+
+        let clear_values = [
+            vk::ClearValue { color: vk::ClearColorValue { float32: [0.39, 0.58, 0.94, 1.0] } }];
+        self.begin_render_pass(render_pass, framebuffer, state.extent, &clear_values)
+            .and_then(|_| self.bind_pipeline(pipeline))
+            .and_then(|_| self.set_viewport_extent(state.extent))
+            .and_then(|_| match mesh {
+                Some(mesh) => mesh.draw(self),
+                // Without a mesh, the vertex shader generates a triangle from the vertex index.
+                None => self.draw(3, 1, 0, 0),
+            })
+            .and_then(|_| self.end_render_pass())
+            .and_then(|_| self.end())
+            .expect("Failed to record graphics commands");
+    }
+
+    /// Waits for `fence`, which must be signalled by the last submission of this command buffer, and resets it to the
+    /// initial state.
+    pub fn reset_after(&mut self, fence : vk::Fence) {
         unsafe {
-            // Wait for any device operations to complete before resetting command buffer.
             self.device
-                .borrow()
                 .ash_device()
-                .device_wait_idle()
-                .unwrap();
+                .wait_for_fences(&[fence], true, u64::MAX)
+                .expect("Failed to wait for fence");
+        }
+        self.reset();
+    }
+
+    /// Resets the command buffer to the initial state. The device must have finished executing it, i.e. by waiting on
+    /// the fence of its last submission, which `reset_after` does.
+    pub fn reset(&mut self) {
+        unsafe {
             self.device
-                .borrow()
                 .ash_device()
                 .reset_command_buffer(
                     self.cmd_buffer,
                     vk::CommandBufferResetFlags::RELEASE_RESOURCES)
                 .unwrap();
         }
+        self.recording = false;
+        self.render_pass_contents = None;
+        self.bound_bind_point = None;
+        self.clear_stale_state();
+    }
+
+    /// Drops the barriers left over from an abandoned recording, and stops tracking the images and buffers destroyed
+    /// since the last recording, whose handles may have been reused.
+    fn clear_stale_state(&mut self) {
+        self.tracker.discard_pending();
+        for resource in self.destroyed.lock().unwrap().drain(..) {
+            match resource {
+                DestroyedResource::Image(image) => self.tracker.forget_image(image),
+                DestroyedResource::Buffer(buffer) => self.tracker.forget_buffer(buffer),
+            }
+        }
+    }
+
+    /// Begins recording. The command buffer must not already be recording.
+    pub fn begin(&mut self, flags : vk::CommandBufferUsageFlags) -> Result<(), CmdRecordingError> {
+        if self.recording {
+            return Err(CmdRecordingError::AlreadyRecording);
+        }
+        let begin_info = vk::CommandBufferBeginInfo::builder()
+            .flags(flags);
+        unsafe {
+            self.device
+                .ash_device()
+                .begin_command_buffer(self.cmd_buffer, &begin_info)
+                .unwrap();
+        }
+        self.clear_stale_state();
+        self.recording = true;
+        self.render_pass_contents = None;
+        self.bound_bind_point = None;
+        Ok(())
+    }
 
+    /// Begins recording a secondary command buffer which continues `subpass` of the render pass. Draws can be recorded
+    /// straight away, and the buffer is executed inside the render pass by the primary command buffer.
+    pub fn begin_secondary(&mut self,
+                           render_pass : &RenderPass,
+                           subpass : u32,
+                           framebuffer : &Framebuffer) -> Result<(), CmdRecordingError> {
+        if self.level != vk::CommandBufferLevel::SECONDARY {
+            return Err(CmdRecordingError::WrongLevel);
+        }
+        if self.recording {
+            return Err(CmdRecordingError::AlreadyRecording);
+        }
+        let inheritance_info = vk::CommandBufferInheritanceInfo::builder()
+            .render_pass(render_pass.render_pass_raw())
+            .subpass(subpass)
+            .framebuffer(framebuffer.framebuffer_raw());
         let begin_info = vk::CommandBufferBeginInfo::builder()
-            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT | vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE)
+            .inheritance_info(&inheritance_info);
         unsafe {
             self.device
-                .borrow()
                 .ash_device()
-                .begin_command_buffer(
-                    self.cmd_buffer,
-                    &begin_info)
+                .begin_command_buffer(self.cmd_buffer, &begin_info)
                 .unwrap();
         }
+        self.clear_stale_state();
         self.recording = true;
+        self.render_pass_contents = Some(vk::SubpassContents::INLINE);
+        self.bound_bind_point = None;
+        Ok(())
+    }
 
-        let clear_values = vec![
-            vk::ClearValue { color: vk::ClearColorValue { float32: [0.39, 0.58, 0.94, 1.0] } }];
+    /// Finishes recording, after which the command buffer can be submitted.
+    pub fn end(&mut self) -> Result<(), CmdRecordingError> {
+        if self.level == vk::CommandBufferLevel::SECONDARY {
+            // Secondary command buffers may continue a render pass which is ended by the primary command buffer.
+            self.ensure_recording()?;
+            self.render_pass_contents = None;
+        } else {
+            self.ensure_outside_render_pass()?;
+            self.flush_barriers();
+        }
+        unsafe {
+            self.device
+                .ash_device()
+                .end_command_buffer(self.cmd_buffer)
+                .unwrap();
+        }
+        self.recording = false;
+        Ok(())
+    }
+
+    fn ensure_recording(&self) -> Result<(), CmdRecordingError> {
+        if self.recording { Ok(()) } else { Err(CmdRecordingError::NotRecording) }
+    }
+
+    fn ensure_inside_render_pass(&self) -> Result<(), CmdRecordingError> {
+        self.ensure_recording()?;
+        if self.render_pass_contents.is_some() { Ok(()) } else { Err(CmdRecordingError::OutsideRenderPass) }
+    }
 
+    fn ensure_inline_contents(&self) -> Result<(), CmdRecordingError> {
+        self.ensure_inside_render_pass()?;
+        if self.render_pass_contents == Some(vk::SubpassContents::INLINE) {
+            Ok(())
+        } else {
+            Err(CmdRecordingError::WrongSubpassContents)
+        }
+    }
+
+    fn ensure_outside_render_pass(&self) -> Result<(), CmdRecordingError> {
+        self.ensure_recording()?;
+        if self.render_pass_contents.is_some() { Err(CmdRecordingError::InsideRenderPass) } else { Ok(()) }
+    }
+
+    fn ensure_bound(&self, bind_point : vk::PipelineBindPoint) -> Result<(), CmdRecordingError> {
+        if self.bound_bind_point == Some(bind_point) { Ok(()) } else { Err(CmdRecordingError::NoPipelineBound) }
+    }
+
+    /// Begins a render pass covering `extent` whose commands are recorded inline. Any queued barriers are recorded
+    /// first, since they cannot be recorded inside of a render pass.
+    pub fn begin_render_pass(&mut self,
+                             render_pass : &RenderPass,
+                             framebuffer : &Framebuffer,
+                             extent : vk::Extent2D,
+                             clear_values : &[vk::ClearValue]) -> Result<(), CmdRecordingError> {
+        self.begin_render_pass_with_contents(render_pass, framebuffer, extent, clear_values, vk::SubpassContents::INLINE)
+    }
+
+    /// Begins a render pass whose commands are provided by secondary command buffers through `execute_commands`.
+    pub fn begin_render_pass_secondary(&mut self,
+                                       render_pass : &RenderPass,
+                                       framebuffer : &Framebuffer,
+                                       extent : vk::Extent2D,
+                                       clear_values : &[vk::ClearValue]) -> Result<(), CmdRecordingError> {
+        self.begin_render_pass_with_contents(
+            render_pass,
+            framebuffer,
+            extent,
+            clear_values,
+            vk::SubpassContents::SECONDARY_COMMAND_BUFFERS)
+    }
+
+    fn begin_render_pass_with_contents(&mut self,
+                                       render_pass : &RenderPass,
+                                       framebuffer : &Framebuffer,
+                                       extent : vk::Extent2D,
+                                       clear_values : &[vk::ClearValue],
+                                       contents : vk::SubpassContents) -> Result<(), CmdRecordingError> {
+        if self.level != vk::CommandBufferLevel::PRIMARY {
+            return Err(CmdRecordingError::WrongLevel);
+        }
+        self.ensure_outside_render_pass()?;
+        self.flush_barriers();
         let begin_pass_info = vk::RenderPassBeginInfo::builder()
-            .clear_values(clear_values.as_slice())
+            .clear_values(clear_values)
             .framebuffer(framebuffer.framebuffer_raw())
             .render_pass(render_pass.render_pass_raw())
             .render_area(vk::Rect2D::builder()
-                .extent(state.extent)
+                .extent(extent)
                 .build());
+        unsafe {
+            self.device
+                .ash_device()
+                .cmd_begin_render_pass(self.cmd_buffer, &begin_pass_info, contents);
+        }
+        self.render_pass_contents = Some(contents);
+        Ok(())
+    }
 
+    pub fn end_render_pass(&mut self) -> Result<(), CmdRecordingError> {
+        if self.level != vk::CommandBufferLevel::PRIMARY {
+            return Err(CmdRecordingError::WrongLevel);
+        }
+        self.ensure_inside_render_pass()?;
         unsafe {
             self.device
-                .borrow()
                 .ash_device()
-                .cmd_begin_render_pass(self.cmd_buffer, &begin_pass_info, vk::SubpassContents::INLINE);
-            // Beginning of the render pass.
-            // =====================================================================================
+                .cmd_end_render_pass(self.cmd_buffer);
+        }
+        self.render_pass_contents = None;
+        Ok(())
+    }
+
+    /// Executes secondary command buffers which have finished recording, inside a render pass begun with
+    /// `begin_render_pass_secondary`.
+    pub fn execute_commands(&mut self, secondary_buffers : &[CmdBuffer]) -> Result<(), CmdRecordingError> {
+        if self.level != vk::CommandBufferLevel::PRIMARY
+            || secondary_buffers.iter().any(|buffer| buffer.level != vk::CommandBufferLevel::SECONDARY) {
+            return Err(CmdRecordingError::WrongLevel);
+        }
+        if secondary_buffers.iter().any(|buffer| buffer.recording) {
+            return Err(CmdRecordingError::AlreadyRecording);
+        }
+        self.ensure_inside_render_pass()?;
+        if self.render_pass_contents != Some(vk::SubpassContents::SECONDARY_COMMAND_BUFFERS) {
+            return Err(CmdRecordingError::WrongSubpassContents);
+        }
+        let raw_buffers : Vec<vk::CommandBuffer> = secondary_buffers
+            .iter()
+            .map(|buffer| buffer.cmd_buffer)
+            .collect();
+        unsafe {
             self.device
-                .borrow()
                 .ash_device()
-                .cmd_bind_pipeline(self.cmd_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline.pipeline_raw());
+                .cmd_execute_commands(self.cmd_buffer, raw_buffers.as_slice());
+        }
+        Ok(())
+    }
+
+    /// Binds a graphics or compute pipeline, depending on what the pipeline supports.
+    pub fn bind_pipeline(&mut self, pipeline : &Pipeline) -> Result<(), CmdRecordingError> {
+        self.ensure_recording()?;
+        let bind_point = if pipeline.supports_graphics() {
+            vk::PipelineBindPoint::GRAPHICS
+        } else if pipeline.supports_compute() {
+            vk::PipelineBindPoint::COMPUTE
+        } else {
+            return Err(CmdRecordingError::IncompatiblePipeline);
+        };
+        unsafe {
             self.device
-                .borrow()
                 .ash_device()
-                .cmd_draw(self.cmd_buffer, 3, 1, 0, 0);
-            // End of render pass.
-            // =====================================================================================
+                .cmd_bind_pipeline(self.cmd_buffer, bind_point, pipeline.pipeline_raw());
+        }
+        self.bound_bind_point = Some(bind_point);
+        Ok(())
+    }
+
+    /// Binds vertex buffers to consecutive bindings starting at `first_binding`.
+    pub fn bind_vertex_buffers(&mut self, first_binding : u32, buffers : &[&VertexBuffer]) -> Result<(), CmdRecordingError> {
+        self.ensure_recording()?;
+        let raw_buffers : Vec<vk::Buffer> = buffers.iter().map(|buffer| buffer.buffer().buffer_raw()).collect();
+        let offsets = vec![0; raw_buffers.len()];
+        unsafe {
             self.device
-                .borrow()
                 .ash_device()
-                .cmd_end_render_pass(self.cmd_buffer);
+                .cmd_bind_vertex_buffers(self.cmd_buffer, first_binding, raw_buffers.as_slice(), offsets.as_slice());
+        }
+        Ok(())
+    }
+
+    pub fn bind_index_buffer(&mut self, buffer : &IndexBuffer, index_type : vk::IndexType) -> Result<(), CmdRecordingError> {
+        self.ensure_recording()?;
+        unsafe {
             self.device
-                .borrow()
                 .ash_device()
-                .end_command_buffer(self.cmd_buffer)
-                .unwrap();
+                .cmd_bind_index_buffer(self.cmd_buffer, buffer.buffer().buffer_raw(), 0, index_type);
         }
-        self.recording = false;
+        Ok(())
+    }
+
+    /// Binds descriptor sets using the layout of `pipeline`. The pipeline must already be bound.
+    pub fn bind_descriptor_sets(&mut self,
+                                pipeline : &Pipeline,
+                                first_set : u32,
+                                descriptor_sets : &[DescriptorSet],
+                                dynamic_offsets : &[u32]) -> Result<(), CmdRecordingError> {
+        self.ensure_recording()?;
+        let bind_point = self.bound_bind_point.ok_or(CmdRecordingError::NoPipelineBound)?;
+        let descriptor_sets : Vec<vk::DescriptorSet> = descriptor_sets.iter().map(DescriptorSet::set_raw).collect();
+        unsafe {
+            self.device
+                .ash_device()
+                .cmd_bind_descriptor_sets(
+                    self.cmd_buffer,
+                    bind_point,
+                    pipeline.layout_raw(),
+                    first_set,
+                    descriptor_sets.as_slice(),
+                    dynamic_offsets);
+        }
+        Ok(())
+    }
+
+    /// Updates push constants at `offset` for `stages` with the bytes of `constants`. `T` should be `#[repr(C)]` and
+    /// match the layout of the shader's push constant block.
+    pub fn push_constants<T : Copy>(&mut self,
+                                    pipeline : &Pipeline,
+                                    stages : vk::ShaderStageFlags,
+                                    offset : u32,
+                                    constants : &T) -> Result<(), CmdRecordingError> {
+        let bytes = unsafe { slice::from_raw_parts(constants as *const T as *const u8, size_of::<T>()) };
+        self.push_constant_bytes(pipeline, stages, offset, bytes)
+    }
+
+    /// Updates push constants at `offset` for `stages`. The bytes must fit within the device's limit and within the
+    /// pipeline's push constant ranges for each stage.
+    pub fn push_constant_bytes(&mut self,
+                               pipeline : &Pipeline,
+                               stages : vk::ShaderStageFlags,
+                               offset : u32,
+                               constants : &[u8]) -> Result<(), CmdRecordingError> {
+        self.ensure_recording()?;
+        let limit = self.device.limits().max_push_constants_size;
+        // An end beyond `u32::MAX` is reported as `u32::MAX`, which is above any limit.
+        let end = u32::try_from(constants.len())
+            .ok()
+            .and_then(|size| offset.checked_add(size))
+            .unwrap_or(u32::MAX);
+        if end > limit {
+            return Err(CmdRecordingError::PushConstantsTooLarge { end, limit });
+        }
+        let covered = |stage : vk::ShaderStageFlags| pipeline
+            .push_constant_ranges()
+            .iter()
+            .any(|range| range.stage_flags.contains(stage) && range.offset <= offset && end <= range.offset + range.size);
+        let mut stage_bits = (0..32)
+            .map(|bit| vk::ShaderStageFlags::from_raw(1 << bit))
+            .filter(|&stage| stages.contains(stage));
+        if !offset.is_multiple_of(4) || !constants.len().is_multiple_of(4) || stages.is_empty()
+            || !stage_bits.all(covered) {
+            return Err(CmdRecordingError::PushConstantsOutOfRange);
+        }
+        unsafe {
+            self.device
+                .ash_device()
+                .cmd_push_constants(self.cmd_buffer, pipeline.layout_raw(), stages, offset, constants);
+        }
+        Ok(())
+    }
+
+    /// Sets the dynamic viewports, starting at `first`.
+    pub fn set_viewports(&mut self, first : u32, viewports : &[vk::Viewport]) -> Result<(), CmdRecordingError> {
+        self.ensure_recording()?;
+        unsafe {
+            self.device
+                .ash_device()
+                .cmd_set_viewport(self.cmd_buffer, first, viewports);
+        }
+        Ok(())
+    }
+
+    /// Sets the dynamic scissors, starting at `first`.
+    pub fn set_scissors(&mut self, first : u32, scissors : &[vk::Rect2D]) -> Result<(), CmdRecordingError> {
+        self.ensure_recording()?;
+        unsafe {
+            self.device
+                .ash_device()
+                .cmd_set_scissor(self.cmd_buffer, first, scissors);
+        }
+        Ok(())
+    }
+
+    /// Sets the viewport and scissor to cover the whole of `extent`. This is usually called once per frame with the
+    /// current swapchain extent, so pipelines do not need to be rebuilt when the window is resized.
+    pub fn set_viewport_extent(&mut self, extent : vk::Extent2D) -> Result<(), CmdRecordingError> {
+        let viewport = vk::Viewport::builder()
+            .width(extent.width as _)
+            .height(extent.height as _)
+            .max_depth(1.0)
+            .build();
+        let scissor = vk::Rect2D::builder()
+            .extent(extent)
+            .build();
+        self.set_viewports(0, &[viewport])?;
+        self.set_scissors(0, &[scissor])
+    }
+
+    /// Sets the line width, for pipelines with `LINE_WIDTH` as dynamic state.
+    pub fn set_line_width(&mut self, line_width : f32) -> Result<(), CmdRecordingError> {
+        self.ensure_recording()?;
+        unsafe {
+            self.device
+                .ash_device()
+                .cmd_set_line_width(self.cmd_buffer, line_width);
+        }
+        Ok(())
+    }
+
+    /// Sets the depth bias, for pipelines with `DEPTH_BIAS` as dynamic state.
+    pub fn set_depth_bias(&mut self, depth_bias : DepthBias) -> Result<(), CmdRecordingError> {
+        self.ensure_recording()?;
+        unsafe {
+            self.device
+                .ash_device()
+                .cmd_set_depth_bias(
+                    self.cmd_buffer,
+                    depth_bias.constant_factor,
+                    depth_bias.clamp,
+                    depth_bias.slope_factor);
+        }
+        Ok(())
+    }
+
+    /// Sets the blend constants, for pipelines with `BLEND_CONSTANTS` as dynamic state.
+    pub fn set_blend_constants(&mut self, constants : [f32; 4]) -> Result<(), CmdRecordingError> {
+        self.ensure_recording()?;
+        unsafe {
+            self.device
+                .ash_device()
+                .cmd_set_blend_constants(self.cmd_buffer, &constants);
+        }
+        Ok(())
+    }
+
+    /// Sets the stencil reference of the given faces, for pipelines with `STENCIL_REFERENCE` as dynamic state.
+    pub fn set_stencil_reference(&mut self,
+                                 faces : vk::StencilFaceFlags,
+                                 reference : u32) -> Result<(), CmdRecordingError> {
+        self.ensure_recording()?;
+        unsafe {
+            self.device
+                .ash_device()
+                .cmd_set_stencil_reference(self.cmd_buffer, faces, reference);
+        }
+        Ok(())
+    }
+
+    pub fn draw(&mut self,
+                vertex_count : u32,
+                instance_count : u32,
+                first_vertex : u32,
+                first_instance : u32) -> Result<(), CmdRecordingError> {
+        self.ensure_inline_contents()?;
+        self.ensure_bound(vk::PipelineBindPoint::GRAPHICS)?;
+        unsafe {
+            self.device
+                .ash_device()
+                .cmd_draw(self.cmd_buffer, vertex_count, instance_count, first_vertex, first_instance);
+        }
+        Ok(())
+    }
+
+    pub fn draw_indexed(&mut self,
+                        index_count : u32,
+                        instance_count : u32,
+                        first_index : u32,
+                        vertex_offset : i32,
+                        first_instance : u32) -> Result<(), CmdRecordingError> {
+        self.ensure_inline_contents()?;
+        self.ensure_bound(vk::PipelineBindPoint::GRAPHICS)?;
+        unsafe {
+            self.device
+                .ash_device()
+                .cmd_draw_indexed(self.cmd_buffer, index_count, instance_count, first_index, vertex_offset, first_instance);
+        }
+        Ok(())
+    }
+
+    /// Dispatches the bound compute pipeline. Any queued barriers are recorded first.
+    pub fn dispatch(&mut self, group_count_x : u32, group_count_y : u32, group_count_z : u32)
+        -> Result<(), CmdRecordingError> {
+        self.ensure_outside_render_pass()?;
+        self.ensure_bound(vk::PipelineBindPoint::COMPUTE)?;
+        self.flush_barriers();
+        unsafe {
+            self.device
+                .ash_device()
+                .cmd_dispatch(self.cmd_buffer, group_count_x, group_count_y, group_count_z);
+        }
+        Ok(())
+    }
+
+    /// Copies regions between two buffers. Any queued barriers are recorded first.
+    pub fn copy_buffer(&mut self, src : &Buffer, dst : &Buffer, regions : &[vk::BufferCopy])
+        -> Result<(), CmdRecordingError> {
+        self.ensure_outside_render_pass()?;
+        self.flush_barriers();
+        unsafe {
+            self.device
+                .ash_device()
+                .cmd_copy_buffer(self.cmd_buffer, src.buffer_raw(), dst.buffer_raw(), regions);
+        }
+        Ok(())
+    }
+
+    /// Copies regions of a buffer into an image. The image must have been readied for `ImageAccess::TransferWrite` with
+    /// `use_image`, which puts it in the `TRANSFER_DST_OPTIMAL` layout. Any queued barriers are recorded first.
+    pub fn copy_buffer_to_image(&mut self, src : &Buffer, dst : vk::Image, regions : &[vk::BufferImageCopy])
+        -> Result<(), CmdRecordingError> {
+        self.ensure_outside_render_pass()?;
+        let expected = vk::ImageLayout::TRANSFER_DST_OPTIMAL;
+        let actual = self.tracker.image_layout(dst).unwrap_or(vk::ImageLayout::UNDEFINED);
+        if actual != expected {
+            return Err(CmdRecordingError::WrongImageLayout { expected, actual });
+        }
+        self.flush_barriers();
+        unsafe {
+            self.device
+                .ash_device()
+                .cmd_copy_buffer_to_image(
+                    self.cmd_buffer,
+                    src.buffer_raw(),
+                    dst,
+                    expected,
+                    regions);
+        }
+        Ok(())
+    }
+
+    /// Copies regions of a buffer into a texture like `copy_buffer_to_image`, also checking that the texture was
+    /// created with the transfer usage.
+    pub fn copy_buffer_to_texture(&mut self, src : &Buffer, dst : &Texture, regions : &[vk::BufferImageCopy])
+        -> Result<(), CmdRecordingError> {
+        let required_usage = ImageAccess::TransferWrite.usage();
+        if !dst.usage().contains(required_usage) {
+            return Err(CmdRecordingError::MissingImageUsage(required_usage));
+        }
+        self.copy_buffer_to_image(src, dst.image_raw(), regions)
+    }
+
+    /// Fills every mip level after the first by blitting each level into the next with linear filtering, then readies
+    /// every level for `access`. The first level must already have been written, and the format must support
+    /// `BLIT_SRC`, `BLIT_DST` and `SAMPLED_IMAGE_FILTER_LINEAR` with optimal tiling. Nothing is recorded if
+    /// `mip_levels` is 0.
+    pub fn generate_mipmaps(&mut self,
+                            image : vk::Image,
+                            extent : vk::Extent2D,
+                            mip_levels : u32,
+                            access : ImageAccess) -> Result<(), CmdRecordingError> {
+        self.generate_layered_mipmaps(image, extent, mip_levels, 1, access)
+    }
+
+    /// Like `generate_mipmaps`, but fills the levels of every array layer at once, such as the six faces of a cube
+    /// map.
+    pub fn generate_layered_mipmaps(&mut self,
+                                    image : vk::Image,
+                                    extent : vk::Extent2D,
+                                    mip_levels : u32,
+                                    layer_count : u32,
+                                    access : ImageAccess) -> Result<(), CmdRecordingError> {
+        self.ensure_outside_render_pass()?;
+        // An image without levels has nothing to fill or ready.
+        if mip_levels == 0 || layer_count == 0 {
+            return Ok(());
+        }
+        let range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: mip_levels,
+            base_array_layer: 0,
+            layer_count,
+        };
+        self.tracker.use_image(image, range, ImageAccess::TransferWrite);
+        self.flush_barriers();
+
+        // Levels move to the transfer source layout one at a time, since each is written by the blit before it.
+        let level_barrier = |level : u32, old_layout, new_layout, src_access, dst_access| {
+            vk::ImageMemoryBarrier::builder()
+                .image(image)
+                .subresource_range(vk::ImageSubresourceRange { base_mip_level: level, level_count: 1, ..range })
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .src_access_mask(src_access)
+                .dst_access_mask(dst_access)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .build()
+        };
+        let device = self.device.ash_device();
+        let (mut width, mut height) = (extent.width as i32, extent.height as i32);
+        for level in 1..mip_levels {
+            let (next_width, next_height) = ((width / 2).max(1), (height / 2).max(1));
+            let blit = vk::ImageBlit {
+                src_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: level - 1,
+                    base_array_layer: 0,
+                    layer_count,
+                },
+                src_offsets: [vk::Offset3D { x: 0, y: 0, z: 0 }, vk::Offset3D { x: width, y: height, z: 1 }],
+                dst_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: level,
+                    base_array_layer: 0,
+                    layer_count,
+                },
+                dst_offsets: [vk::Offset3D { x: 0, y: 0, z: 0 }, vk::Offset3D { x: next_width, y: next_height, z: 1 }],
+            };
+            unsafe {
+                device.cmd_pipeline_barrier(
+                    self.cmd_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[level_barrier(
+                        level - 1,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        vk::AccessFlags::TRANSFER_WRITE,
+                        vk::AccessFlags::TRANSFER_READ)]);
+                device.cmd_blit_image(
+                    self.cmd_buffer,
+                    image,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[blit],
+                    vk::Filter::LINEAR);
+            }
+            width = next_width;
+            height = next_height;
+        }
+
+        // Every level but the last was only read since its barrier, while the last was written by the final blit.
+        let info = access.info();
+        let mut barriers : Vec<vk::ImageMemoryBarrier> = (0..mip_levels - 1)
+            .map(|level| level_barrier(
+                level,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                info.layout,
+                vk::AccessFlags::empty(),
+                info.access_mask))
+            .collect();
+        barriers.push(level_barrier(
+            mip_levels - 1,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            info.layout,
+            vk::AccessFlags::TRANSFER_WRITE,
+            info.access_mask));
+        unsafe {
+            device.cmd_pipeline_barrier(
+                self.cmd_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                info.stage,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                barriers.as_slice());
+        }
+        self.tracker.import_image(image, range, Some(access));
+        Ok(())
+    }
+
+    /// Returns true while commands are being recorded.
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Declares the next usage of an image, queueing the layout transition and barrier it requires.
+    /// Queued barriers are recorded together by `flush_barriers`.
+    pub fn use_image(&mut self, image : vk::Image, range : vk::ImageSubresourceRange, access : ImageAccess) {
+        self.tracker.use_image(image, range, access);
+    }
+
+    /// Declares the next usage of a buffer, queueing the barrier it requires.
+    pub fn use_buffer(&mut self, buffer : vk::Buffer, access : BufferAccess) {
+        self.tracker.use_buffer(buffer, access);
+    }
+
+    /// Transfers ownership of an image to the queue family of `queue`, i.e. from the transfer queue to the graphics
+    /// queue after an upload. Returns the layout the image was in, which has to be passed to `acquire_image`.
+    pub fn release_image(&mut self, image : vk::Image, queue : &Queue, access : ImageAccess) -> Option<vk::ImageLayout> {
+        let src_family = self.cmd_pool.family_index();
+        if src_family == queue.family_index() {
+            return None;
+        }
+        self.tracker.release_image(image, src_family, queue.family_index(), access)
+    }
+
+    /// Takes ownership of an image released from the queue family of `queue`.
+    pub fn acquire_image(&mut self,
+                         image : vk::Image,
+                         range : vk::ImageSubresourceRange,
+                         queue : &Queue,
+                         old_layout : vk::ImageLayout,
+                         access : ImageAccess) {
+        let dst_family = self.cmd_pool.family_index();
+        if dst_family == queue.family_index() {
+            self.tracker.use_image(image, range, access);
+        } else {
+            self.tracker.acquire_image(image, range, queue.family_index(), dst_family, old_layout, access);
+        }
+    }
+
+    /// Transfers ownership of a buffer to the queue family of `queue`.
+    pub fn release_buffer(&mut self, buffer : vk::Buffer, queue : &Queue) {
+        let src_family = self.cmd_pool.family_index();
+        if src_family != queue.family_index() {
+            self.tracker.release_buffer(buffer, src_family, queue.family_index());
+        }
+    }
+
+    /// Takes ownership of a buffer released from the queue family of `queue`.
+    pub fn acquire_buffer(&mut self, buffer : vk::Buffer, queue : &Queue, access : BufferAccess) {
+        let dst_family = self.cmd_pool.family_index();
+        if dst_family == queue.family_index() {
+            self.tracker.use_buffer(buffer, access);
+        } else {
+            self.tracker.acquire_buffer(buffer, queue.family_index(), dst_family, access);
+        }
+    }
+
+    /// Records every queued barrier with a single `cmd_pipeline_barrier`.
+    pub fn flush_barriers(&mut self) {
+        self.tracker.flush(&self.device, self.cmd_buffer);
+    }
+
+    /// Records barriers which were computed outside of this command buffer, such as those of a `RenderGraph`. Any
+    /// queued barriers are recorded first.
+    pub fn pipeline_barrier(&mut self,
+                            src_stage : vk::PipelineStageFlags,
+                            dst_stage : vk::PipelineStageFlags,
+                            buffer_barriers : &[vk::BufferMemoryBarrier],
+                            image_barriers : &[vk::ImageMemoryBarrier]) -> Result<(), CmdRecordingError> {
+        self.ensure_outside_render_pass()?;
+        self.flush_barriers();
+        unsafe {
+            self.device
+                .ash_device()
+                .cmd_pipeline_barrier(
+                    self.cmd_buffer,
+                    src_stage,
+                    dst_stage,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    buffer_barriers,
+                    image_barriers);
+        }
+        Ok(())
+    }
+
+    /// Returns the resource tracker, which can be used to import the state of resources used by earlier submissions.
+    pub fn tracker_mut(&mut self) -> &mut ResourceTracker {
+        &mut self.tracker
     }
 
     pub fn cmd_buffer_raw(&self) -> vk::CommandBuffer {
@@ -132,41 +904,39 @@ impl CmdBuffer {
 
 /// Allocates the command buffers into memory for reuse.
 pub struct CmdPool {
-    device : Rc<RefCell<Device>>,
+    device : Arc<Device>,
     cmd_pool : vk::CommandPool,
+    family_index : u32,
+    lock : Arc<Mutex<()>>,
 }
 
 impl Drop for CmdPool {
     fn drop(&mut self) {
-        unsafe {
-            self.device.borrow().ash_device().device_wait_idle().unwrap();
-            self.device.borrow().ash_device().destroy_command_pool(self.cmd_pool, None);
-        }
+        self.device.destroy_deferred(DeferredObject::CommandPool(self.cmd_pool));
         info!("Dropped CmdPool")
     }
 }
 
 impl CmdPool {
-    pub fn new(device : Rc<RefCell<Device>>,
+    pub fn new(device : Arc<Device>,
                queue : &Queue) -> Self {
         let cmd_pool_info = vk::CommandPoolCreateInfo::builder()
             .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
             .queue_family_index(queue.family_index());
         let cmd_pool = unsafe {
             device
-                .borrow()
                 .ash_device()
                 .create_command_pool(&cmd_pool_info, None)
                 .expect("Failed to create command pool")
         };
 
-        Self { device, cmd_pool }
+        Self { device, cmd_pool, family_index: queue.family_index(), lock: Arc::new(Mutex::new(())) }
     }
 
     pub fn reset(&self) {
+        let _lock = self.lock();
         unsafe {
             self.device
-                .borrow()
                 .ash_device()
                 .reset_command_pool(self.cmd_pool, vk::CommandPoolResetFlags::RELEASE_RESOURCES)
                 .unwrap();
@@ -176,4 +946,15 @@ impl CmdPool {
     pub fn cmd_pool_raw(&self) -> vk::CommandPool {
         self.cmd_pool
     }
+
+    /// Locks the pool. Command pools are externally synchronized, so allocating, freeing or resetting buffers holds
+    /// this lock. Command buffers from one pool should only be recorded on one thread at a time.
+    pub fn lock(&self) -> MutexGuard<'_, ()> {
+        self.lock.lock().unwrap()
+    }
+
+    /// Returns the queue family which command buffers from this pool can be submitted to.
+    pub fn family_index(&self) -> u32 {
+        self.family_index
+    }
 }
\ No newline at end of file
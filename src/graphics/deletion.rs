@@ -0,0 +1,199 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, Weak};
+use std::sync::atomic::{AtomicU64, Ordering};
+use ash::version::DeviceV1_0;
+use ash::vk;
+
+/// A Vulkan object whose destruction is deferred until the GPU is finished with it.
+pub enum DeferredObject {
+    Buffer(vk::Buffer),
+    Memory(vk::DeviceMemory),
+    Image(vk::Image),
+    ImageView(vk::ImageView),
+    Sampler(vk::Sampler),
+    Framebuffer(vk::Framebuffer),
+    RenderPass(vk::RenderPass),
+    Pipeline(vk::Pipeline),
+    PipelineLayout(vk::PipelineLayout),
+    ShaderModule(vk::ShaderModule),
+    DescriptorSetLayout(vk::DescriptorSetLayout),
+    DescriptorPool(vk::DescriptorPool),
+    Semaphore(vk::Semaphore),
+    Fence(vk::Fence),
+    CommandPool(vk::CommandPool),
+    /// A command buffer along with the pool it was allocated from and the lock of that pool.
+    CommandBuffer(vk::CommandPool, vk::CommandBuffer, Arc<Mutex<()>>),
+}
+
+/// An image or buffer which has been destroyed, whose handle may be reused by a new object.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DestroyedResource {
+    Image(vk::Image),
+    Buffer(vk::Buffer),
+}
+
+/// The resources destroyed since a subscriber last took them, i.e. so a command buffer can stop tracking them.
+pub type DestroyedResources = Arc<Mutex<Vec<DestroyedResource>>>;
+
+impl DeferredObject {
+    fn destroyed_resource(&self) -> Option<DestroyedResource> {
+        match *self {
+            DeferredObject::Image(image) => Some(DestroyedResource::Image(image)),
+            DeferredObject::Buffer(buffer) => Some(DestroyedResource::Buffer(buffer)),
+            _ => None,
+        }
+    }
+
+    unsafe fn destroy(self, device : &ash::Device) {
+        match self {
+            DeferredObject::Buffer(buffer) => device.destroy_buffer(buffer, None),
+            DeferredObject::Memory(memory) => device.free_memory(memory, None),
+            DeferredObject::Image(image) => device.destroy_image(image, None),
+            DeferredObject::ImageView(view) => device.destroy_image_view(view, None),
+            DeferredObject::Sampler(sampler) => device.destroy_sampler(sampler, None),
+            DeferredObject::Framebuffer(framebuffer) => device.destroy_framebuffer(framebuffer, None),
+            DeferredObject::RenderPass(render_pass) => device.destroy_render_pass(render_pass, None),
+            DeferredObject::Pipeline(pipeline) => device.destroy_pipeline(pipeline, None),
+            DeferredObject::PipelineLayout(layout) => device.destroy_pipeline_layout(layout, None),
+            DeferredObject::ShaderModule(module) => device.destroy_shader_module(module, None),
+            DeferredObject::DescriptorSetLayout(layout) => device.destroy_descriptor_set_layout(layout, None),
+            DeferredObject::DescriptorPool(pool) => device.destroy_descriptor_pool(pool, None),
+            DeferredObject::Semaphore(semaphore) => device.destroy_semaphore(semaphore, None),
+            DeferredObject::Fence(fence) => device.destroy_fence(fence, None),
+            DeferredObject::CommandPool(pool) => device.destroy_command_pool(pool, None),
+            DeferredObject::CommandBuffer(pool, cmd_buffer, lock) => {
+                let _lock = lock.lock().unwrap();
+                device.free_command_buffers(pool, &[cmd_buffer]);
+            }
+        }
+    }
+}
+
+/// Holds objects which were dropped while frames that may still use them are in flight. Each object is tagged with the
+/// frame that was being recorded when it was dropped, and is destroyed once that frame has retired.
+pub struct DeletionQueue {
+    frame : AtomicU64,
+    /// One more than the newest frame retired, so that 0 means no frame has retired yet.
+    retired : AtomicU64,
+    pending : Mutex<VecDeque<(u64, DeferredObject)>>,
+    subscribers : Mutex<Vec<Weak<Mutex<Vec<DestroyedResource>>>>>,
+}
+
+impl Default for DeletionQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DeletionQueue {
+    pub fn new() -> Self {
+        Self {
+            frame: AtomicU64::new(0),
+            retired: AtomicU64::new(0),
+            pending: Mutex::new(VecDeque::new()),
+            subscribers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the frame which is currently being recorded.
+    pub fn current_frame(&self) -> u64 {
+        self.frame.load(Ordering::Acquire)
+    }
+
+    /// Moves on to the next frame, returning its number.
+    pub fn advance_frame(&self) -> u64 {
+        self.frame.fetch_add(1, Ordering::AcqRel) + 1
+    }
+
+    /// Returns the newest frame which has retired, if any.
+    pub fn retired_frame(&self) -> Option<u64> {
+        self.retired.load(Ordering::Acquire).checked_sub(1)
+    }
+
+    pub fn push(&self, object : DeferredObject) {
+        let frame = self.current_frame();
+        self.pending.lock().unwrap().push_back((frame, object));
+    }
+
+    /// Returns a list which every image and buffer destroyed from now on is added to, until it is dropped.
+    pub fn subscribe(&self) -> DestroyedResources {
+        let destroyed = Arc::new(Mutex::new(Vec::new()));
+        self.subscribers.lock().unwrap().push(Arc::downgrade(&destroyed));
+        destroyed
+    }
+
+    /// Adds the images and buffers among `objects` to the list of each subscriber, dropping those which are gone.
+    fn notify(&self, objects : &[DeferredObject]) {
+        let destroyed : Vec<DestroyedResource> = objects
+            .iter()
+            .filter_map(DeferredObject::destroyed_resource)
+            .collect();
+        if destroyed.is_empty() {
+            return;
+        }
+        self.subscribers.lock().unwrap().retain(|subscriber| match subscriber.upgrade() {
+            Some(subscriber) => {
+                subscriber.lock().unwrap().extend_from_slice(&destroyed);
+                true
+            }
+            None => false,
+        });
+    }
+
+    /// Destroys every object which was dropped during or before `frame`. The lock is released before destroying, since
+    /// destroying an object may drop others which are deferred in turn.
+    pub fn retire(&self, device : &ash::Device, frame : u64) {
+        self.retired.fetch_max(frame + 1, Ordering::AcqRel);
+        let retired : Vec<DeferredObject> = {
+            let mut pending = self.pending.lock().unwrap();
+            let count = pending.iter().take_while(|(dropped, _)| *dropped <= frame).count();
+            pending.drain(..count).map(|(_, object)| object).collect()
+        };
+        self.notify(&retired);
+        for object in retired {
+            unsafe { object.destroy(device) };
+        }
+    }
+
+    /// Destroys every pending object. The device must be idle.
+    pub fn flush(&self, device : &ash::Device) {
+        loop {
+            let retired : Vec<DeferredObject> = self.pending
+                .lock()
+                .unwrap()
+                .drain(..)
+                .map(|(_, object)| object)
+                .collect();
+            if retired.is_empty() {
+                break;
+            }
+            self.notify(&retired);
+            for object in retired {
+                unsafe { object.destroy(device) };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk::Handle;
+    use super::*;
+
+    #[test]
+    fn notifies_subscribers_of_destroyed_resources() {
+        let queue = DeletionQueue::new();
+        let destroyed = queue.subscribe();
+        queue.notify(&[
+            DeferredObject::Image(vk::Image::from_raw(1)),
+            DeferredObject::Fence(vk::Fence::from_raw(2)),
+            DeferredObject::Buffer(vk::Buffer::from_raw(3))]);
+        assert_eq!(*destroyed.lock().unwrap(), [
+            DestroyedResource::Image(vk::Image::from_raw(1)),
+            DestroyedResource::Buffer(vk::Buffer::from_raw(3))]);
+
+        drop(destroyed);
+        queue.notify(&[DeferredObject::Image(vk::Image::from_raw(4))]);
+        assert!(queue.subscribers.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,409 @@
+use std::{slice, sync::Arc};
+use ash::version::DeviceV1_0;
+use ash::vk;
+use super::{DescriptorSet, Device};
+use super::buffer::Buffer;
+use super::deletion::DeferredObject;
+use super::texture::Texture;
+
+/// The number of sets in the first pool an allocator creates. Each new pool holds twice as many as the last.
+const INITIAL_SETS_PER_POOL : u32 = 64;
+/// The most sets a single pool is grown to.
+const MAX_SETS_PER_POOL : u32 = 4096;
+
+/// The number of descriptors of each type reserved per set when an allocator does not specify its own.
+const DEFAULT_POOL_SIZES : [(vk::DescriptorType, u32); 6] = [
+    (vk::DescriptorType::UNIFORM_BUFFER, 2),
+    (vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC, 1),
+    (vk::DescriptorType::STORAGE_BUFFER, 2),
+    (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 4),
+    (vk::DescriptorType::SAMPLED_IMAGE, 2),
+    (vk::DescriptorType::SAMPLER, 1),
+];
+
+/// A set layout along with the bindings it was created from.
+pub struct DescriptorSetLayout {
+    device : Arc<Device>,
+    layout : vk::DescriptorSetLayout,
+    bindings : Vec<vk::DescriptorSetLayoutBinding>,
+}
+
+// The bindings never point to immutable samplers.
+unsafe impl Send for DescriptorSetLayout {}
+unsafe impl Sync for DescriptorSetLayout {}
+
+impl Drop for DescriptorSetLayout {
+    fn drop(&mut self) {
+        self.device.destroy_deferred(DeferredObject::DescriptorSetLayout(self.layout));
+        info!("Dropped DescriptorSetLayout")
+    }
+}
+
+impl DescriptorSetLayout {
+    pub fn layout_raw(&self) -> vk::DescriptorSetLayout {
+        self.layout
+    }
+
+    pub fn bindings(&self) -> &[vk::DescriptorSetLayoutBinding] {
+        &self.bindings
+    }
+}
+
+pub struct DescriptorSetLayoutBuilder {
+    device : Arc<Device>,
+    bindings : Vec<vk::DescriptorSetLayoutBinding>,
+    binding_flags : Vec<(u32, vk::DescriptorBindingFlags)>,
+    flags : vk::DescriptorSetLayoutCreateFlags,
+}
+
+impl DescriptorSetLayoutBuilder {
+    pub fn new(device : Arc<Device>) -> Self {
+        Self {
+            device,
+            bindings: Vec::new(),
+            binding_flags: Vec::new(),
+            flags: vk::DescriptorSetLayoutCreateFlags::empty(),
+        }
+    }
+
+    /// Sets descriptor indexing flags for `binding`, such as allowing it to be updated after being bound. Sets with
+    /// such bindings must be allocated from a pool created with `UPDATE_AFTER_BIND`.
+    pub fn binding_flags(mut self, binding : u32, flags : vk::DescriptorBindingFlags) -> Self {
+        self.binding_flags.retain(|(existing, _)| *existing != binding);
+        self.binding_flags.push((binding, flags));
+        if flags.contains(vk::DescriptorBindingFlags::UPDATE_AFTER_BIND) {
+            self.flags |= vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL;
+        }
+        self
+    }
+
+    /// Adds `count` descriptors of `descriptor_type` at `binding`, replacing any binding already there.
+    pub fn binding(mut self,
+                   binding : u32,
+                   descriptor_type : vk::DescriptorType,
+                   count : u32,
+                   stages : vk::ShaderStageFlags) -> Self {
+        self.bindings.retain(|existing| existing.binding != binding);
+        self.bindings.push(vk::DescriptorSetLayoutBinding::builder()
+            .binding(binding)
+            .descriptor_type(descriptor_type)
+            .descriptor_count(count)
+            .stage_flags(stages)
+            .build());
+        self
+    }
+
+    pub fn uniform_buffer(self, binding : u32, stages : vk::ShaderStageFlags) -> Self {
+        self.binding(binding, vk::DescriptorType::UNIFORM_BUFFER, 1, stages)
+    }
+
+    pub fn storage_buffer(self, binding : u32, stages : vk::ShaderStageFlags) -> Self {
+        self.binding(binding, vk::DescriptorType::STORAGE_BUFFER, 1, stages)
+    }
+
+    pub fn sampled_image(self, binding : u32, stages : vk::ShaderStageFlags) -> Self {
+        self.binding(binding, vk::DescriptorType::SAMPLED_IMAGE, 1, stages)
+    }
+
+    pub fn sampler(self, binding : u32, stages : vk::ShaderStageFlags) -> Self {
+        self.binding(binding, vk::DescriptorType::SAMPLER, 1, stages)
+    }
+
+    pub fn combined_image_sampler(self, binding : u32, stages : vk::ShaderStageFlags) -> Self {
+        self.binding(binding, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 1, stages)
+    }
+
+    pub fn build(mut self) -> DescriptorSetLayout {
+        self.bindings.sort_by_key(|binding| binding.binding);
+        // Binding flags are given per binding in the same order as the bindings.
+        let binding_flags : Vec<vk::DescriptorBindingFlags> = self.bindings
+            .iter()
+            .map(|binding| self.binding_flags
+                .iter()
+                .find(|(flagged, _)| *flagged == binding.binding)
+                .map_or(vk::DescriptorBindingFlags::empty(), |(_, flags)| *flags))
+            .collect();
+        let mut binding_flags_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo::builder()
+            .binding_flags(binding_flags.as_slice());
+        let mut layout_info = vk::DescriptorSetLayoutCreateInfo::builder()
+            .flags(self.flags)
+            .bindings(self.bindings.as_slice());
+        if !self.binding_flags.is_empty() {
+            layout_info = layout_info.push_next(&mut binding_flags_info);
+        }
+        let layout = unsafe {
+            self.device
+                .ash_device()
+                .create_descriptor_set_layout(&layout_info, None)
+                .expect("Failed to create descriptor set layout")
+        };
+        DescriptorSetLayout { device: self.device, layout, bindings: self.bindings }
+    }
+}
+
+/// Allocates descriptor sets from a list of pools, creating a larger pool whenever the current one is exhausted. Sets
+/// are freed all at once, either by `reset` or when the allocator is dropped.
+pub struct DescriptorAllocator {
+    device : Arc<Device>,
+    /// The number of descriptors of each type reserved per set.
+    pool_sizes : Vec<(vk::DescriptorType, u32)>,
+    /// Pools in the order they were created, which is smallest first.
+    pools : Vec<vk::DescriptorPool>,
+    /// The index of the pool currently allocated from. Pools before it are full.
+    current : usize,
+    sets_per_pool : u32,
+}
+
+impl Drop for DescriptorAllocator {
+    fn drop(&mut self) {
+        for pool in self.pools.drain(..) {
+            self.device.destroy_deferred(DeferredObject::DescriptorPool(pool));
+        }
+        info!("Dropped DescriptorAllocator")
+    }
+}
+
+impl DescriptorAllocator {
+    pub fn new(device : Arc<Device>) -> Self {
+        Self::with_pool_sizes(device, DEFAULT_POOL_SIZES.to_vec())
+    }
+
+    /// Creates an allocator whose pools reserve the given number of descriptors of each type per set.
+    pub fn with_pool_sizes(device : Arc<Device>, pool_sizes : Vec<(vk::DescriptorType, u32)>) -> Self {
+        Self { device, pool_sizes, pools: Vec::new(), current: 0, sets_per_pool: INITIAL_SETS_PER_POOL }
+    }
+
+    /// Allocates a set with the given layout, moving on to the next pool, or creating one, whenever the current pool has
+    /// run out of space.
+    pub fn allocate(&mut self, layout : &DescriptorSetLayout) -> DescriptorSet {
+        while let Some(&pool) = self.pools.get(self.current) {
+            match self.allocate_from(pool, layout.layout_raw()) {
+                Ok(set) => return DescriptorSet::from_raw(set),
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY) | Err(vk::Result::ERROR_FRAGMENTED_POOL) => self.current += 1,
+                Err(error) => panic!("Failed to allocate descriptor set: {}", error),
+            }
+        }
+        let pool = self.create_pool(layout);
+        self.pools.push(pool);
+        self.current = self.pools.len() - 1;
+        let set = self.allocate_from(pool, layout.layout_raw())
+            .expect("Failed to allocate descriptor set from a new pool");
+        DescriptorSet::from_raw(set)
+    }
+
+    /// Frees every set allocated so far, keeping the pools for reuse. None of the sets may still be in use by the GPU.
+    pub fn reset(&mut self) {
+        for &pool in &self.pools {
+            unsafe {
+                self.device
+                    .ash_device()
+                    .reset_descriptor_pool(pool, vk::DescriptorPoolResetFlags::empty())
+                    .expect("Failed to reset descriptor pool");
+            }
+        }
+        self.current = 0;
+    }
+
+    fn allocate_from(&self, pool : vk::DescriptorPool, layout : vk::DescriptorSetLayout)
+        -> Result<vk::DescriptorSet, vk::Result> {
+        let set_layouts = [layout];
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(pool)
+            .set_layouts(&set_layouts);
+        unsafe {
+            self.device
+                .ash_device()
+                .allocate_descriptor_sets(&allocate_info)
+                .map(|mut sets| sets.remove(0))
+        }
+    }
+
+    /// Creates a pool for `sets_per_pool` sets, also making room for at least one set of `layout` even if it uses
+    /// types or counts beyond the configured pool sizes.
+    fn create_pool(&mut self, layout : &DescriptorSetLayout) -> vk::DescriptorPool {
+        let sets = self.sets_per_pool;
+        self.sets_per_pool = (self.sets_per_pool * 2).min(MAX_SETS_PER_POOL);
+
+        let mut counts = self.pool_sizes.clone();
+        for binding in layout.bindings() {
+            match counts.iter_mut().find(|(ty, _)| *ty == binding.descriptor_type) {
+                Some((_, count)) => *count = (*count).max(binding.descriptor_count),
+                None => counts.push((binding.descriptor_type, binding.descriptor_count)),
+            }
+        }
+        let pool_sizes : Vec<vk::DescriptorPoolSize> = counts
+            .iter()
+            .filter(|(_, count)| *count > 0)
+            .map(|&(ty, count)| vk::DescriptorPoolSize { ty, descriptor_count: count * sets })
+            .collect();
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(sets)
+            .pool_sizes(pool_sizes.as_slice());
+        unsafe {
+            self.device
+                .ash_device()
+                .create_descriptor_pool(&pool_info, None)
+                .expect("Failed to create descriptor pool")
+        }
+    }
+}
+
+/// Allocates descriptor sets which are only used by the frame being recorded. Each frame allocates from its own
+/// `DescriptorAllocator`, which is reset and reused once that frame has retired.
+pub struct TransientDescriptorAllocator {
+    device : Arc<Device>,
+    pool_sizes : Vec<(vk::DescriptorType, u32)>,
+    /// Allocators along with the last frame which allocated from them. The last allocator belongs to the current frame.
+    allocators : Vec<(u64, DescriptorAllocator)>,
+}
+
+impl TransientDescriptorAllocator {
+    pub fn new(device : Arc<Device>) -> Self {
+        Self::with_pool_sizes(device, DEFAULT_POOL_SIZES.to_vec())
+    }
+
+    pub fn with_pool_sizes(device : Arc<Device>, pool_sizes : Vec<(vk::DescriptorType, u32)>) -> Self {
+        Self { device, pool_sizes, allocators: Vec::new() }
+    }
+
+    /// Allocates a set which remains valid until the current frame has retired.
+    pub fn allocate(&mut self, layout : &DescriptorSetLayout) -> DescriptorSet {
+        let frame = self.device.current_frame();
+        if self.allocators.last().map(|(used, _)| *used) != Some(frame) {
+            self.begin_frame(frame);
+        }
+        self.allocators.last_mut().unwrap().1.allocate(layout)
+    }
+
+    /// Makes an allocator current for `frame`, reusing one whose frame has retired if there is one.
+    fn begin_frame(&mut self, frame : u64) {
+        let retired = self.device.retired_frame();
+        let reusable = self.allocators
+            .iter()
+            .position(|(used, _)| retired.is_some_and(|retired| *used <= retired));
+        let allocator = match reusable {
+            Some(index) => {
+                let (_, mut allocator) = self.allocators.remove(index);
+                allocator.reset();
+                allocator
+            }
+            None => DescriptorAllocator::with_pool_sizes(Arc::clone(&self.device), self.pool_sizes.clone()),
+        };
+        self.allocators.push((frame, allocator));
+    }
+}
+
+enum DescriptorInfo {
+    Buffer(vk::DescriptorBufferInfo),
+    Image(vk::DescriptorImageInfo),
+}
+
+/// Collects descriptors to write into a set, which are written all at once by `update`.
+#[derive(Default)]
+pub struct DescriptorWriter {
+    writes : Vec<(u32, u32, vk::DescriptorType, DescriptorInfo)>,
+}
+
+impl DescriptorWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes `range` bytes of `buffer` from `offset` as a uniform buffer.
+    pub fn uniform_buffer(self, binding : u32, buffer : &Buffer, offset : vk::DeviceSize, range : vk::DeviceSize) -> Self {
+        self.buffer(binding, 0, vk::DescriptorType::UNIFORM_BUFFER, buffer, offset, range)
+    }
+
+    /// Writes `range` bytes of `buffer` from `offset` as a uniform buffer, to which a dynamic offset is added when the
+    /// set is bound.
+    pub fn uniform_buffer_dynamic(self,
+                                  binding : u32,
+                                  buffer : &Buffer,
+                                  offset : vk::DeviceSize,
+                                  range : vk::DeviceSize) -> Self {
+        self.buffer(binding, 0, vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC, buffer, offset, range)
+    }
+
+    /// Writes `range` bytes of `buffer` from `offset` as a storage buffer. Use `vk::WHOLE_SIZE` to bind the rest of the
+    /// buffer.
+    pub fn storage_buffer(self, binding : u32, buffer : &Buffer, offset : vk::DeviceSize, range : vk::DeviceSize) -> Self {
+        self.buffer(binding, 0, vk::DescriptorType::STORAGE_BUFFER, buffer, offset, range)
+    }
+
+    /// Writes an image view which is sampled with a separate sampler.
+    pub fn sampled_image(self, binding : u32, view : vk::ImageView, layout : vk::ImageLayout) -> Self {
+        self.image(binding, 0, vk::DescriptorType::SAMPLED_IMAGE, vk::Sampler::null(), view, layout)
+    }
+
+    pub fn storage_image(self, binding : u32, view : vk::ImageView) -> Self {
+        self.image(binding, 0, vk::DescriptorType::STORAGE_IMAGE, vk::Sampler::null(), view, vk::ImageLayout::GENERAL)
+    }
+
+    pub fn sampler(self, binding : u32, sampler : vk::Sampler) -> Self {
+        self.image(binding, 0, vk::DescriptorType::SAMPLER, sampler, vk::ImageView::null(), vk::ImageLayout::UNDEFINED)
+    }
+
+    pub fn combined_image_sampler(self,
+                                  binding : u32,
+                                  view : vk::ImageView,
+                                  layout : vk::ImageLayout,
+                                  sampler : vk::Sampler) -> Self {
+        self.image(binding, 0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, sampler, view, layout)
+    }
+
+    /// Writes a texture and its sampler as a combined image sampler.
+    pub fn texture(self, binding : u32, texture : &Texture) -> Self {
+        self.combined_image_sampler(binding, texture.view_raw(), texture.layout(), texture.sampler_raw())
+    }
+
+    /// Writes a buffer descriptor into element `array_element` of the array at `binding`.
+    pub fn buffer(mut self,
+                  binding : u32,
+                  array_element : u32,
+                  descriptor_type : vk::DescriptorType,
+                  buffer : &Buffer,
+                  offset : vk::DeviceSize,
+                  range : vk::DeviceSize) -> Self {
+        let info = vk::DescriptorBufferInfo { buffer: buffer.buffer_raw(), offset, range };
+        self.writes.push((binding, array_element, descriptor_type, DescriptorInfo::Buffer(info)));
+        self
+    }
+
+    /// Writes an image or sampler descriptor into element `array_element` of the array at `binding`. Handles which the
+    /// descriptor type does not use are ignored.
+    pub fn image(mut self,
+                 binding : u32,
+                 array_element : u32,
+                 descriptor_type : vk::DescriptorType,
+                 sampler : vk::Sampler,
+                 view : vk::ImageView,
+                 layout : vk::ImageLayout) -> Self {
+        let info = vk::DescriptorImageInfo { sampler, image_view: view, image_layout: layout };
+        self.writes.push((binding, array_element, descriptor_type, DescriptorInfo::Image(info)));
+        self
+    }
+
+    /// Writes every descriptor into `set`. The set must not be in use by the GPU.
+    pub fn update(&self, device : &Device, set : DescriptorSet) {
+        // The infos are borrowed from `self`, so they stay alive until the update has been made.
+        let writes : Vec<vk::WriteDescriptorSet> = self.writes
+            .iter()
+            .map(|(binding, array_element, descriptor_type, info)| {
+                let write = vk::WriteDescriptorSet::builder()
+                    .dst_set(set.set_raw())
+                    .dst_binding(*binding)
+                    .dst_array_element(*array_element)
+                    .descriptor_type(*descriptor_type);
+                match info {
+                    DescriptorInfo::Buffer(info) => write.buffer_info(slice::from_ref(info)).build(),
+                    DescriptorInfo::Image(info) => write.image_info(slice::from_ref(info)).build(),
+                }
+            })
+            .collect();
+        unsafe {
+            device
+                .ash_device()
+                .update_descriptor_sets(writes.as_slice(), &[]);
+        }
+    }
+}
@@ -1,14 +1,26 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{ffi::{c_void, CStr}, sync::{Mutex, MutexGuard}};
 use ash::extensions::khr::Swapchain;
-use ash::version::{InstanceV1_0, DeviceV1_0};
+use ash::version::{InstanceV1_0, InstanceV1_1, DeviceV1_0};
 use ash::vk;
 use super::{Instance, Queue};
+use super::deletion::{DeferredObject, DeletionQueue, DestroyedResources};
+use super::pipeline_cache::{pipeline_cache_path, PipelineCache};
+use super::sampler_cache::{SamplerCache, SamplerDesc};
 
 pub enum DeviceCreationError {
     MissingExtensions
 }
 
+/// How many descriptors a bindless heap may hold, for devices which support descriptor indexing.
+#[derive(Clone, Copy, Debug)]
+pub struct BindlessLimits {
+    pub max_sampled_images : u32,
+    pub max_samplers : u32,
+    pub max_storage_buffers : u32,
+}
+
 pub struct Device {
+    instance : ash::Instance,
     physical_device : vk::PhysicalDevice,
     properties : vk::PhysicalDeviceProperties,
     limits : vk::PhysicalDeviceLimits,
@@ -17,12 +29,20 @@ pub struct Device {
     compute_index : u32,
     graphics_index : u32,
     transfer_index : u32,
+    queue_locks : Vec<Mutex<()>>,
+    deletion_queue : DeletionQueue,
+    pipeline_cache : PipelineCache,
+    sampler_cache : SamplerCache,
+    bindless_limits : Option<BindlessLimits>,
 }
 
 impl Drop for Device {
     fn drop(&mut self) {
         unsafe {
             self.device.device_wait_idle().unwrap();
+            self.deletion_queue.flush(&self.device);
+            self.pipeline_cache.destroy(&self.device);
+            self.sampler_cache.destroy(&self.device);
             self.device.destroy_device(None);
         }
         info!("Dropped Device")
@@ -79,16 +99,32 @@ impl Device {
         let transfer_info = vk::DeviceQueueCreateInfo::builder()
             .queue_family_index(transfer_index)
             .queue_priorities(&priorities);
+        let bindless_limits = query_bindless_limits(instance, physical_device, &properties);
+        // Bindless shaders index their texture arrays with handles from push constants.
         let enabled_features = vk::PhysicalDeviceFeatures::builder()
             .sampler_anisotropy(true)
-            .fill_mode_non_solid(true);
+            .fill_mode_non_solid(true)
+            .shader_sampled_image_array_dynamic_indexing(bindless_limits.is_some());
 
         let queue_infos = vec![compute_info.build(), graphics_info.build(), transfer_info.build()];
-        let device_extensions = [Swapchain::name().as_ptr()];
-        let device_info = vk::DeviceCreateInfo::builder()
+        let mut device_extensions = vec![Swapchain::name().as_ptr()];
+        let mut indexing_features = vk::PhysicalDeviceDescriptorIndexingFeatures::builder()
+            .shader_sampled_image_array_non_uniform_indexing(true)
+            .shader_storage_buffer_array_non_uniform_indexing(true)
+            .descriptor_binding_sampled_image_update_after_bind(true)
+            .descriptor_binding_storage_buffer_update_after_bind(true)
+            .descriptor_binding_update_unused_while_pending(true)
+            .descriptor_binding_partially_bound(true)
+            .runtime_descriptor_array(true);
+        let mut device_info = vk::DeviceCreateInfo::builder()
             .queue_create_infos(queue_infos.as_slice())
-            .enabled_extension_names(device_extensions.as_ref())
-            .enabled_features(&enabled_features)
+            .enabled_features(&enabled_features);
+        if bindless_limits.is_some() {
+            device_extensions.push(vk::ExtDescriptorIndexingFn::name().as_ptr());
+            device_info = device_info.push_next(&mut indexing_features);
+        }
+        let device_info = device_info
+            .enabled_extension_names(device_extensions.as_slice())
             .build();
 
         let device = unsafe {
@@ -98,7 +134,10 @@ impl Device {
                 .unwrap()
         };
 
+        let pipeline_cache = PipelineCache::load(&device, &properties, &pipeline_cache_path());
+
         Ok(Self {
+            instance: instance.ash_instance().clone(),
             physical_device,
             properties,
             limits,
@@ -107,6 +146,11 @@ impl Device {
             compute_index,
             graphics_index,
             transfer_index,
+            queue_locks: queue_families.iter().map(|_| Mutex::new(())).collect(),
+            deletion_queue: DeletionQueue::new(),
+            pipeline_cache,
+            sampler_cache: SamplerCache::new(),
+            bindless_limits,
         })
     }
 
@@ -130,6 +174,71 @@ impl Device {
         self.transfer_index
     }
 
+    /// Locks the queue of the given family. Queues are externally synchronized, so this must be held while submitting
+    /// or presenting, since several `Queue`s may share the same family.
+    pub fn lock_queue(&self, family_index : u32) -> MutexGuard<'_, ()> {
+        self.queue_locks[family_index as usize].lock().unwrap()
+    }
+
+    /// Destroys the object once every frame which may still be using it has retired, rather than waiting for the
+    /// device to become idle.
+    pub fn destroy_deferred(&self, object : DeferredObject) {
+        self.deletion_queue.push(object);
+    }
+
+    /// Returns a list which every image and buffer is added to once it has been destroyed, so that its state can be
+    /// forgotten before the handle is reused.
+    pub fn destroyed_resources(&self) -> DestroyedResources {
+        self.deletion_queue.subscribe()
+    }
+
+    /// Returns the frame which is currently being recorded.
+    pub fn current_frame(&self) -> u64 {
+        self.deletion_queue.current_frame()
+    }
+
+    /// Moves on to the next frame once the current one has been submitted, returning its number.
+    pub fn advance_frame(&self) -> u64 {
+        self.deletion_queue.advance_frame()
+    }
+
+    /// Destroys the objects dropped during or before `frame`. Call this once the fence signalled by the last submission
+    /// of that frame has been waited on.
+    pub fn retire_frame(&self, frame : u64) {
+        self.deletion_queue.retire(&self.device, frame);
+    }
+
+    /// Returns the newest frame which has retired, if any. Every frame before it has retired as well.
+    pub fn retired_frame(&self) -> Option<u64> {
+        self.deletion_queue.retired_frame()
+    }
+
+    /// Returns the size limits of a bindless descriptor heap, or `None` if the device does not support descriptor
+    /// indexing.
+    pub fn bindless_limits(&self) -> Option<BindlessLimits> {
+        self.bindless_limits
+    }
+
+    pub fn supports_bindless(&self) -> bool {
+        self.bindless_limits.is_some()
+    }
+
+    /// Returns the pipeline cache shared by every pipeline created on this device, which is saved when it is dropped.
+    pub fn pipeline_cache_raw(&self) -> vk::PipelineCache {
+        self.pipeline_cache.cache_raw()
+    }
+
+    /// Returns the shared sampler for `desc`, creating it on first use. The sampler is destroyed with the device, so it
+    /// must not be destroyed by the caller.
+    pub fn sampler(&self, desc : &SamplerDesc) -> vk::Sampler {
+        self.sampler_cache.get(&self.device, &self.limits, desc)
+    }
+
+    /// Returns the number of distinct samplers which have been created through `sampler`.
+    pub fn sampler_count(&self) -> usize {
+        self.sampler_cache.len()
+    }
+
     pub fn properties(&self) -> vk::PhysicalDeviceProperties {
         self.properties
     }
@@ -141,4 +250,72 @@ impl Device {
     pub fn memory_properties(&self) -> vk::PhysicalDeviceMemoryProperties {
         self.memory_properties
     }
-}
\ No newline at end of file
+
+    /// Returns the features supported by `format` with linear and optimal tiling, and in buffers.
+    pub fn format_properties(&self, format : vk::Format) -> vk::FormatProperties {
+        unsafe {
+            self.instance
+                .get_physical_device_format_properties(self.physical_device, format)
+        }
+    }
+}
+
+/// Checks whether the device supports the parts of `VK_EXT_descriptor_indexing` used by a bindless heap, returning the
+/// heap limits if so. Querying the features requires Vulkan 1.1 on both the instance and the device.
+fn query_bindless_limits(instance : &Instance,
+                         physical_device : vk::PhysicalDevice,
+                         properties : &vk::PhysicalDeviceProperties) -> Option<BindlessLimits> {
+    let version = vk::make_version(1, 1, 0);
+    if instance.api_version() < version || properties.api_version < version {
+        return None;
+    }
+    let extensions = unsafe {
+        instance
+            .ash_instance()
+            .enumerate_device_extension_properties(physical_device)
+            .ok()?
+    };
+    let has_extension = extensions
+        .iter()
+        .any(|extension| unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) } == vk::ExtDescriptorIndexingFn::name());
+    if !has_extension {
+        return None;
+    }
+
+    let mut indexing_features = vk::PhysicalDeviceDescriptorIndexingFeatures::default();
+    let mut indexing_properties = vk::PhysicalDeviceDescriptorIndexingProperties::default();
+    // The indexing features do not implement the trait needed by `push_next`, so they are chained by hand.
+    let mut features = vk::PhysicalDeviceFeatures2 {
+        p_next: &mut indexing_features as *mut _ as *mut c_void,
+        ..Default::default()
+    };
+    unsafe {
+        instance
+            .ash_instance()
+            .get_physical_device_features2(physical_device, &mut features);
+        let mut device_properties = vk::PhysicalDeviceProperties2::builder()
+            .push_next(&mut indexing_properties);
+        instance
+            .ash_instance()
+            .get_physical_device_properties2(physical_device, &mut device_properties);
+    }
+    let supported = features.features.shader_sampled_image_array_dynamic_indexing == vk::TRUE
+        && indexing_features.shader_sampled_image_array_non_uniform_indexing == vk::TRUE
+        && indexing_features.shader_storage_buffer_array_non_uniform_indexing == vk::TRUE
+        && indexing_features.descriptor_binding_sampled_image_update_after_bind == vk::TRUE
+        && indexing_features.descriptor_binding_storage_buffer_update_after_bind == vk::TRUE
+        && indexing_features.descriptor_binding_update_unused_while_pending == vk::TRUE
+        && indexing_features.descriptor_binding_partially_bound == vk::TRUE
+        && indexing_features.runtime_descriptor_array == vk::TRUE;
+    if !supported {
+        return None;
+    }
+    Some(BindlessLimits {
+        max_sampled_images: indexing_properties.max_per_stage_descriptor_update_after_bind_sampled_images
+            .min(indexing_properties.max_descriptor_set_update_after_bind_sampled_images),
+        max_samplers: indexing_properties.max_per_stage_descriptor_update_after_bind_samplers
+            .min(indexing_properties.max_descriptor_set_update_after_bind_samplers),
+        max_storage_buffers: indexing_properties.max_per_stage_descriptor_update_after_bind_storage_buffers
+            .min(indexing_properties.max_descriptor_set_update_after_bind_storage_buffers),
+    })
+}
@@ -0,0 +1,409 @@
+use std::{fmt, path::Path, sync::Arc};
+use ash::version::DeviceV1_0;
+use ash::vk;
+use nalgebra::Vector3;
+use super::{CmdBuffer, CmdPool, CmdRecordingError, DescriptorSet, Device, PipelineBuilder, Queue};
+use super::deletion::DeferredObject;
+use super::descriptors::{DescriptorAllocator, DescriptorSetLayoutBuilder, DescriptorWriter};
+use super::image::{mip_level_count, ImageData, ImageError};
+use super::pipeline::PipelineError;
+use super::sampler_cache::SamplerDesc;
+use super::sync::ImageAccess;
+use super::texture::{Texture, TextureError, TextureOptions};
+use super::util::find_memory_type_index;
+
+/// The size of each face of the cube map the equirectangular image is converted to before it is filtered.
+const ENVIRONMENT_SIZE : u32 = 512;
+const IRRADIANCE_SIZE : u32 = 32;
+const PREFILTERED_SIZE : u32 = 128;
+/// The levels of the prefiltered map go from 128 down to 4 texels, which is enough detail for fully rough surfaces.
+const PREFILTERED_MIP_LEVELS : u32 = 6;
+const BRDF_LUT_SIZE : u32 = 256;
+/// Number of invocations along each side of a workgroup of the generation shaders.
+const WORKGROUP_SIZE : u32 = 8;
+/// Every generated image is half float, which every device can store to and filter.
+const FORMAT : vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+/// Describes why an environment map could not be created.
+#[derive(Debug)]
+pub enum EnvironmentError {
+    Image(ImageError),
+    Texture(TextureError),
+    /// No device local memory could be allocated for the generated maps.
+    AllocationFailed,
+    Recording(CmdRecordingError),
+    Pipeline(PipelineError),
+}
+
+impl fmt::Display for EnvironmentError {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvironmentError::Image(error) => write!(f, "{}", error),
+            EnvironmentError::Texture(error) => write!(f, "failed to upload environment: {}", error),
+            EnvironmentError::AllocationFailed => write!(f, "failed to allocate environment map memory"),
+            EnvironmentError::Recording(error) => write!(f, "failed to record environment filtering: {:?}", error),
+            EnvironmentError::Pipeline(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl From<ImageError> for EnvironmentError {
+    fn from(error : ImageError) -> Self {
+        EnvironmentError::Image(error)
+    }
+}
+
+impl From<TextureError> for EnvironmentError {
+    fn from(error : TextureError) -> Self {
+        EnvironmentError::Texture(error)
+    }
+}
+
+impl From<PipelineError> for EnvironmentError {
+    fn from(error : PipelineError) -> Self {
+        EnvironmentError::Pipeline(error)
+    }
+}
+
+impl From<CmdRecordingError> for EnvironmentError {
+    fn from(error : CmdRecordingError) -> Self {
+        EnvironmentError::Recording(error)
+    }
+}
+
+/// The push constants of the prefilter shader.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct PrefilterConstants {
+    roughness : f32,
+}
+
+/// An image written by the generation shaders and sampled afterwards, with a view of every level for sampling and a
+/// storage view of each level for writing. Cube maps are written through 2D array views, one layer per face.
+struct GeneratedImage {
+    device : Arc<Device>,
+    image : vk::Image,
+    memory : vk::DeviceMemory,
+    view : vk::ImageView,
+    storage_views : Vec<vk::ImageView>,
+    size : u32,
+    mip_levels : u32,
+    layers : u32,
+}
+
+impl Drop for GeneratedImage {
+    fn drop(&mut self) {
+        for view in self.storage_views.drain(..) {
+            self.device.destroy_deferred(DeferredObject::ImageView(view));
+        }
+        self.device.destroy_deferred(DeferredObject::ImageView(self.view));
+        self.device.destroy_deferred(DeferredObject::Image(self.image));
+        self.device.destroy_deferred(DeferredObject::Memory(self.memory));
+    }
+}
+
+impl GeneratedImage {
+    /// Creates a square image, which is a cube map if `cube` is set.
+    fn new(device : &Arc<Device>,
+           size : u32,
+           mip_levels : u32,
+           cube : bool,
+           usage : vk::ImageUsageFlags) -> Result<Self, EnvironmentError> {
+        let layers = if cube { 6 } else { 1 };
+        let flags = if cube { vk::ImageCreateFlags::CUBE_COMPATIBLE } else { vk::ImageCreateFlags::empty() };
+        let image_info = vk::ImageCreateInfo::builder()
+            .flags(flags)
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(FORMAT)
+            .extent(vk::Extent3D { width: size, height: size, depth: 1 })
+            .mip_levels(mip_levels)
+            .array_layers(layers)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(usage | vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+        let ash_device = device.ash_device();
+        let (image, memory_requirements) = unsafe {
+            let image = ash_device
+                .create_image(&image_info, None)
+                .expect("Failed to create environment image");
+            (image, ash_device.get_image_memory_requirements(image))
+        };
+        let memory_properties = device.memory_properties();
+        let memory_flags = vk::MemoryPropertyFlags::DEVICE_LOCAL;
+        let memory = find_memory_type_index(&memory_requirements, &memory_properties, memory_flags)
+            .and_then(|memory_index| {
+                let allocate_info = vk::MemoryAllocateInfo::builder()
+                    .memory_type_index(memory_index)
+                    .allocation_size(memory_requirements.size);
+                unsafe { ash_device.allocate_memory(&allocate_info, None).ok() }
+            });
+        let memory = match memory {
+            Some(memory) => memory,
+            None => {
+                unsafe { ash_device.destroy_image(image, None) };
+                return Err(EnvironmentError::AllocationFailed);
+            }
+        };
+        unsafe {
+            ash_device
+                .bind_image_memory(image, memory, 0)
+                .expect("Failed to bind environment memory");
+        }
+
+        let create_view = |view_type, base_mip_level, level_count| {
+            let view_info = vk::ImageViewCreateInfo::builder()
+                .image(image)
+                .view_type(view_type)
+                .format(FORMAT)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    base_mip_level,
+                    level_count,
+                    base_array_layer: 0,
+                    layer_count: layers,
+                });
+            unsafe {
+                ash_device
+                    .create_image_view(&view_info, None)
+                    .expect("Failed to create environment image view")
+            }
+        };
+        let (view_type, storage_view_type) = if cube {
+            (vk::ImageViewType::CUBE, vk::ImageViewType::TYPE_2D_ARRAY)
+        } else {
+            (vk::ImageViewType::TYPE_2D, vk::ImageViewType::TYPE_2D)
+        };
+        let view = create_view(view_type, 0, mip_levels);
+        let storage_views = (0..mip_levels).map(|level| create_view(storage_view_type, level, 1)).collect();
+        Ok(Self { device: Arc::clone(device), image, memory, view, storage_views, size, mip_levels, layers })
+    }
+
+    fn range(&self) -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: self.mip_levels,
+            base_array_layer: 0,
+            layer_count: self.layers,
+        }
+    }
+
+    /// Returns the number of workgroups covering each side of a level.
+    fn group_count(&self, level : u32) -> u32 {
+        (self.size >> level).max(1).div_ceil(WORKGROUP_SIZE)
+    }
+}
+
+/// The maps used to light materials with their surroundings, generated from an equirectangular environment image by
+/// compute shaders:
+///
+/// * an irradiance cube map, holding the diffuse light arriving from each direction divided by pi,
+/// * a prefiltered cube map, holding the environment convolved with the GGX distribution, with increasing roughness in
+///   each mip level,
+/// * a BRDF lookup table, holding the scale and bias applied to the specular color for each view angle and roughness.
+///
+/// Every map is in `SHADER_READ_ONLY_OPTIMAL` once created, and is sampled with the sampler returned by `sampler_raw`.
+pub struct EnvironmentMap {
+    irradiance : GeneratedImage,
+    prefiltered : GeneratedImage,
+    brdf_lut : GeneratedImage,
+    sampler : vk::Sampler,
+}
+
+impl Drop for EnvironmentMap {
+    fn drop(&mut self) {
+        info!("Dropped EnvironmentMap")
+    }
+}
+
+impl EnvironmentMap {
+    /// Loads an equirectangular image, usually a Radiance HDR image, and generates the maps from it.
+    pub fn from_file(device : Arc<Device>,
+                     transfer_queue : &Queue,
+                     graphics_queue : &Queue,
+                     path : &Path) -> Result<Self, EnvironmentError> {
+        Self::new(device, transfer_queue, graphics_queue, ImageData::load(path)?)
+    }
+
+    /// Creates the maps of an environment which has the same radiance in every direction.
+    pub fn from_color(device : Arc<Device>,
+                      transfer_queue : &Queue,
+                      graphics_queue : &Queue,
+                      radiance : Vector3<f32>) -> Result<Self, EnvironmentError> {
+        let pixel = [radiance.x, radiance.y, radiance.z, 1.0]
+            .iter()
+            .flat_map(|component| component.to_le_bytes())
+            .collect();
+        let data = ImageData { width: 1, height: 1, format: vk::Format::R32G32B32A32_SFLOAT, levels: vec![pixel] };
+        Self::new(device, transfer_queue, graphics_queue, data)
+    }
+
+    /// Uploads an equirectangular image, then converts it to a cube map and generates the maps from it on the graphics
+    /// queue, which must support compute. Float images are converted to half float before they are uploaded. Blocks
+    /// until the maps are complete.
+    pub fn new(device : Arc<Device>,
+               transfer_queue : &Queue,
+               graphics_queue : &Queue,
+               data : ImageData) -> Result<Self, EnvironmentError> {
+        let data = data.to_half_float().unwrap_or(data);
+        // The image wraps around horizontally, but not over the poles.
+        let equirect_sampler = SamplerDesc {
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            ..SamplerDesc::default()
+        };
+        let options = TextureOptions { srgb: true, generate_mipmaps: true, sampler: equirect_sampler };
+        let equirect = Texture::from_image_data(Arc::clone(&device), transfer_queue, graphics_queue, data, options)?;
+
+        let environment = GeneratedImage::new(
+            &device,
+            ENVIRONMENT_SIZE,
+            mip_level_count(ENVIRONMENT_SIZE, ENVIRONMENT_SIZE),
+            true,
+            vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST)?;
+        let irradiance = GeneratedImage::new(&device, IRRADIANCE_SIZE, 1, true, vk::ImageUsageFlags::empty())?;
+        let prefiltered = GeneratedImage::new(
+            &device,
+            PREFILTERED_SIZE,
+            PREFILTERED_MIP_LEVELS,
+            true,
+            vk::ImageUsageFlags::empty())?;
+        let brdf_lut = GeneratedImage::new(&device, BRDF_LUT_SIZE, 1, false, vk::ImageUsageFlags::empty())?;
+        let sampler = device.sampler(&SamplerDesc::default().address_mode(vk::SamplerAddressMode::CLAMP_TO_EDGE));
+
+        // Every shader reads its source from bindings 0 and 1 and writes a level of its target to binding 2.
+        let set_layout = DescriptorSetLayoutBuilder::new(Arc::clone(&device))
+            .sampled_image(0, vk::ShaderStageFlags::COMPUTE)
+            .sampler(1, vk::ShaderStageFlags::COMPUTE)
+            .binding(2, vk::DescriptorType::STORAGE_IMAGE, 1, vk::ShaderStageFlags::COMPUTE)
+            .build();
+        let set_count = 3 + PREFILTERED_MIP_LEVELS;
+        let mut descriptor_allocator = DescriptorAllocator::with_pool_sizes(
+            Arc::clone(&device),
+            vec![(vk::DescriptorType::SAMPLED_IMAGE, set_count),
+                 (vk::DescriptorType::SAMPLER, set_count),
+                 (vk::DescriptorType::STORAGE_IMAGE, set_count)]);
+        let mut allocate_set = |source : Option<(vk::ImageView, vk::Sampler)>, target : vk::ImageView| {
+            let set = descriptor_allocator.allocate(&set_layout);
+            let writer = match source {
+                Some((view, sampler)) => DescriptorWriter::new()
+                    .sampled_image(0, view, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                    .sampler(1, sampler),
+                None => DescriptorWriter::new(),
+            };
+            writer
+                .storage_image(2, target)
+                .update(&device, set);
+            set
+        };
+        let equirect_set = allocate_set(
+            Some((equirect.view_raw(), equirect.sampler_raw())),
+            environment.storage_views[0]);
+        let irradiance_set = allocate_set(Some((environment.view, sampler)), irradiance.storage_views[0]);
+        let prefilter_sets : Vec<DescriptorSet> = prefiltered.storage_views
+            .iter()
+            .map(|view| allocate_set(Some((environment.view, sampler)), *view))
+            .collect();
+        let brdf_set = allocate_set(None, brdf_lut.storage_views[0]);
+
+        let build = |code : &[u8]| PipelineBuilder::new(Arc::clone(&device))
+            .descriptor_set_layout(&set_layout)
+            .build_compute(code);
+        let equirect_pipeline = build(include_bytes!("../assets/shaders/ibl_equirect_comp.spv"))?;
+        let irradiance_pipeline = build(include_bytes!("../assets/shaders/ibl_irradiance_comp.spv"))?;
+        let brdf_pipeline = build(include_bytes!("../assets/shaders/ibl_brdf_comp.spv"))?;
+        let prefilter_pipeline = PipelineBuilder::new(Arc::clone(&device))
+            .descriptor_set_layout(&set_layout)
+            .push_constant_range(vk::ShaderStageFlags::COMPUTE, 0, std::mem::size_of::<PrefilterConstants>() as u32)
+            .build_compute(include_bytes!("../assets/shaders/ibl_prefilter_comp.spv"))?;
+
+        let cmd_pool = Arc::new(CmdPool::new(Arc::clone(&device), graphics_queue));
+        let mut cmd_buffer = CmdBuffer::new(Arc::clone(&device), cmd_pool);
+        cmd_buffer.begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+
+        cmd_buffer.use_image(environment.image, environment.range(), ImageAccess::ComputeShaderWrite);
+        cmd_buffer.bind_pipeline(&equirect_pipeline)?;
+        cmd_buffer.bind_descriptor_sets(&equirect_pipeline, 0, &[equirect_set], &[])?;
+        cmd_buffer.dispatch(environment.group_count(0), environment.group_count(0), 6)?;
+        // Smaller levels of the environment are sampled where its detail would alias.
+        cmd_buffer.generate_layered_mipmaps(
+            environment.image,
+            vk::Extent2D { width: ENVIRONMENT_SIZE, height: ENVIRONMENT_SIZE },
+            environment.mip_levels,
+            6,
+            ImageAccess::ComputeShaderRead)?;
+
+        cmd_buffer.use_image(irradiance.image, irradiance.range(), ImageAccess::ComputeShaderWrite);
+        cmd_buffer.bind_pipeline(&irradiance_pipeline)?;
+        cmd_buffer.bind_descriptor_sets(&irradiance_pipeline, 0, &[irradiance_set], &[])?;
+        cmd_buffer.dispatch(irradiance.group_count(0), irradiance.group_count(0), 6)?;
+
+        // Roughness increases linearly with each level, reaching 1 at the last.
+        cmd_buffer.use_image(prefiltered.image, prefiltered.range(), ImageAccess::ComputeShaderWrite);
+        cmd_buffer.bind_pipeline(&prefilter_pipeline)?;
+        for (level, set) in prefilter_sets.iter().enumerate() {
+            let level = level as u32;
+            let constants = PrefilterConstants { roughness: level as f32 / (PREFILTERED_MIP_LEVELS - 1) as f32 };
+            cmd_buffer.bind_descriptor_sets(&prefilter_pipeline, 0, &[*set], &[])?;
+            cmd_buffer.push_constants(&prefilter_pipeline, vk::ShaderStageFlags::COMPUTE, 0, &constants)?;
+            cmd_buffer.dispatch(prefiltered.group_count(level), prefiltered.group_count(level), 6)?;
+        }
+
+        cmd_buffer.use_image(brdf_lut.image, brdf_lut.range(), ImageAccess::ComputeShaderWrite);
+        cmd_buffer.bind_pipeline(&brdf_pipeline)?;
+        cmd_buffer.bind_descriptor_sets(&brdf_pipeline, 0, &[brdf_set], &[])?;
+        cmd_buffer.dispatch(brdf_lut.group_count(0), brdf_lut.group_count(0), 1)?;
+
+        for map in &[&irradiance, &prefiltered, &brdf_lut] {
+            cmd_buffer.use_image(map.image, map.range(), ImageAccess::FragmentShaderRead);
+        }
+        cmd_buffer.flush_barriers();
+        cmd_buffer.end()?;
+
+        let fence_info = vk::FenceCreateInfo::builder();
+        let fence = unsafe {
+            device
+                .ash_device()
+                .create_fence(&fence_info, None)
+                .expect("Failed to create fence")
+        };
+        graphics_queue.submit_with(&[&cmd_buffer], &[], &[], Some(fence));
+        unsafe {
+            device
+                .ash_device()
+                .wait_for_fences(&[fence], true, u64::MAX)
+                .expect("Failed to wait for environment filtering");
+        }
+        device.destroy_deferred(DeferredObject::Fence(fence));
+
+        Ok(Self { irradiance, prefiltered, brdf_lut, sampler })
+    }
+
+    pub fn irradiance_view(&self) -> vk::ImageView {
+        self.irradiance.view
+    }
+
+    pub fn prefiltered_view(&self) -> vk::ImageView {
+        self.prefiltered.view
+    }
+
+    /// Returns the number of levels of the prefiltered map. The last level is filtered for a roughness of 1.
+    pub fn prefiltered_mip_levels(&self) -> u32 {
+        self.prefiltered.mip_levels
+    }
+
+    pub fn brdf_lut_view(&self) -> vk::ImageView {
+        self.brdf_lut.view
+    }
+
+    /// Returns the sampler every map is sampled with, which filters linearly and clamps to the edge.
+    pub fn sampler_raw(&self) -> vk::Sampler {
+        self.sampler
+    }
+
+    /// The layout every map is in whenever it is sampled.
+    pub fn layout(&self) -> vk::ImageLayout {
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+    }
+}
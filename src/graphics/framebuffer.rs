@@ -1,22 +1,20 @@
-use std::{cell::RefCell, rc::Rc};
+use std::sync::Arc;
 use ash::version::DeviceV1_0;
 use ash::vk;
 use super::{Device, RenderPass};
+use super::deletion::DeferredObject;
 
 /// A framebuffer manages an image created by the swapchain.
 pub struct Framebuffer {
-    device : Rc<RefCell<Device>>,
+    device : Arc<Device>,
     framebuffer : vk::Framebuffer,
     color_view : vk::ImageView,
 }
 
 impl Drop for Framebuffer {
     fn drop(&mut self) {
-        unsafe {
-            self.device.borrow().ash_device().device_wait_idle().unwrap();
-            self.device.borrow().ash_device().destroy_framebuffer(self.framebuffer, None);
-            self.device.borrow().ash_device().destroy_image_view(self.color_view, None);
-        }
+        self.device.destroy_deferred(DeferredObject::Framebuffer(self.framebuffer));
+        self.device.destroy_deferred(DeferredObject::ImageView(self.color_view));
         info!("Dropped Framebuffer")
     }
 }
@@ -25,18 +23,23 @@ impl Framebuffer {
     pub fn framebuffer_raw(&self) -> vk::Framebuffer {
         self.framebuffer
     }
+
+    /// Returns the view of the color image the framebuffer was created from.
+    pub fn color_view_raw(&self) -> vk::ImageView {
+        self.color_view
+    }
 }
 
 pub struct FramebufferBuilder {
-    device : Rc<RefCell<Device>>,
-    render_pass : Rc<RefCell<RenderPass>>,
+    device : Arc<Device>,
+    render_pass : Arc<RenderPass>,
     extent : vk::Extent2D,
     color_view : vk::ImageView,
 }
 
 impl FramebufferBuilder {
-    pub fn new(device : Rc<RefCell<Device>>,
-               render_pass : Rc<RefCell<RenderPass>>,
+    pub fn new(device : Arc<Device>,
+               render_pass : Arc<RenderPass>,
                color_image : vk::Image,
                color_format : vk::Format,
                extent : vk::Extent2D) -> Self {
@@ -52,7 +55,6 @@ impl FramebufferBuilder {
             .subresource_range(color_subresource_range.build());
         let color_view = unsafe {
             device
-                .borrow()
                 .ash_device()
                 .create_image_view(&color_view_info, None)
                 .unwrap()
@@ -73,16 +75,15 @@ impl FramebufferBuilder {
             .layers(1)
             .width(self.extent.width)
             .height(self.extent.height)
-            .render_pass(self.render_pass.borrow().render_pass_raw())
+            .render_pass(self.render_pass.render_pass_raw())
             .attachments(&[self.color_view])
             .build();
         let framebuffer = unsafe {
             self.device
-                .borrow()
                 .ash_device()
                 .create_framebuffer(&framebuffer_info, None)
                 .expect("Failed to create framebuffer")
         };
-        Framebuffer { device: Rc::clone(&self.device), framebuffer, color_view: self.color_view }
+        Framebuffer { device: Arc::clone(&self.device), framebuffer, color_view: self.color_view }
     }
 }
\ No newline at end of file
@@ -0,0 +1,187 @@
+use std::convert::TryInto;
+use super::GltfError;
+use super::json::{Accessor, Root};
+
+/// Reads the elements of accessors from the loaded buffers of a glTF asset.
+pub struct AccessorReader<'a> {
+    root : &'a Root,
+    buffers : &'a [Vec<u8>],
+}
+
+impl<'a> AccessorReader<'a> {
+    pub fn new(root : &'a Root, buffers : &'a [Vec<u8>]) -> Self {
+        Self { root, buffers }
+    }
+
+    /// Returns the bytes of a buffer view and the stride between its elements, if it has one.
+    pub fn view(&self, index : usize) -> Result<(&'a [u8], Option<usize>), GltfError> {
+        let view = self.root.buffer_views
+            .get(index)
+            .ok_or_else(|| GltfError::invalid("buffer view", index))?;
+        let buffer = self.buffers
+            .get(view.buffer)
+            .ok_or_else(|| GltfError::invalid("buffer", view.buffer))?;
+        let bytes = view.byte_offset
+            .checked_add(view.byte_length)
+            .and_then(|end| buffer.get(view.byte_offset..end))
+            .ok_or_else(|| GltfError::Invalid(format!("buffer view {} is outside of its buffer", index)))?;
+        Ok((bytes, view.byte_stride))
+    }
+
+    /// Reads every component of an accessor as a float, converting normalized integers to the range [0, 1] or
+    /// [-1, 1]. Returns the components along with the number of components of each element.
+    pub fn read_floats(&self, index : usize) -> Result<(Vec<f32>, usize), GltfError> {
+        let accessor = self.accessor(index)?;
+        let (component_type, normalized) = (accessor.component_type, accessor.normalized);
+        self.read(index, 0.0, |bytes| {
+            match (component_type, normalized) {
+                (Accessor::FLOAT, _) => f32::from_le_bytes(bytes.try_into().unwrap()),
+                (Accessor::UNSIGNED_BYTE, true) => bytes[0] as f32 / 255.0,
+                (Accessor::BYTE, true) => (bytes[0] as i8 as f32 / 127.0).max(-1.0),
+                (Accessor::UNSIGNED_SHORT, true) => u16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 65535.0,
+                (Accessor::SHORT, true) => (i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32767.0).max(-1.0),
+                (Accessor::BYTE, false) => bytes[0] as i8 as f32,
+                (Accessor::SHORT, false) => i16::from_le_bytes([bytes[0], bytes[1]]) as f32,
+                _ => read_unsigned(bytes) as f32,
+            }
+        })
+    }
+
+    /// Reads every component of an accessor of unsigned integers, such as indices or joints.
+    pub fn read_unsigned(&self, index : usize) -> Result<(Vec<u32>, usize), GltfError> {
+        match self.accessor(index)?.component_type {
+            Accessor::UNSIGNED_BYTE | Accessor::UNSIGNED_SHORT | Accessor::UNSIGNED_INT => {}
+            _ => return Err(GltfError::Invalid(format!("accessor {} does not hold unsigned integers", index))),
+        }
+        self.read(index, 0, read_unsigned)
+    }
+
+    fn accessor(&self, index : usize) -> Result<&'a Accessor, GltfError> {
+        self.root.accessors.get(index).ok_or_else(|| GltfError::invalid("accessor", index))
+    }
+
+    /// Reads every component of an accessor, converting each from its bytes with `convert`. Accessors without a
+    /// buffer view are zero before any sparse values are applied. The accessor is checked to fit in its buffer views
+    /// before anything is allocated, and one without a buffer view may not describe more bytes than the buffers hold,
+    /// since it always accompanies accessors of the same count which do have data.
+    fn read<T : Copy>(&self, index : usize, zero : T, convert : impl Fn(&[u8]) -> T)
+        -> Result<(Vec<T>, usize), GltfError> {
+        let accessor = self.accessor(index)?;
+        let out_of_bounds = || GltfError::Invalid(format!("accessor {} does not fit in its buffer views", index));
+        let component_size = size_of_component(accessor.component_type)
+            .ok_or_else(|| GltfError::Invalid(format!("accessor {} has an unknown component type", index)))?;
+        let (columns, rows) = element_shape(&accessor.element_type)
+            .ok_or_else(|| GltfError::Invalid(format!("accessor {} has an unknown type", index)))?;
+        // The columns of matrices start on 4 byte boundaries.
+        let column_stride = if columns > 1 { align_up(rows * component_size, 4) } else { rows * component_size };
+        let element_size = columns * column_stride;
+        let components = columns * rows;
+
+        let view = match accessor.buffer_view {
+            Some(view) => {
+                let (bytes, stride) = self.view(view)?;
+                let stride = stride.unwrap_or(element_size);
+                if !fits(bytes.len(), accessor.byte_offset, accessor.count, stride, element_size) {
+                    return Err(out_of_bounds());
+                }
+                Some((bytes, stride))
+            }
+            None => {
+                let buffer_size : usize = self.buffers.iter().map(Vec::len).sum();
+                if !fits(buffer_size, 0, accessor.count, element_size, element_size) {
+                    return Err(out_of_bounds());
+                }
+                None
+            }
+        };
+        if let Some(sparse) = &accessor.sparse {
+            let (value_bytes, _) = self.view(sparse.values.buffer_view)?;
+            if sparse.count > accessor.count
+                || !fits(value_bytes.len(), sparse.values.byte_offset, sparse.count, element_size, element_size) {
+                return Err(out_of_bounds());
+            }
+        }
+        let value_count = accessor.count.checked_mul(components).ok_or_else(out_of_bounds)?;
+
+        let mut values = vec![zero; value_count];
+        let read_element = |bytes : &[u8], element : &mut [T]| {
+            for column in 0..columns {
+                for row in 0..rows {
+                    let offset = column * column_stride + row * component_size;
+                    element[column * rows + row] = convert(&bytes[offset..offset + component_size]);
+                }
+            }
+        };
+        if let Some((bytes, stride)) = view {
+            for (element_index, element) in values.chunks_exact_mut(components).enumerate() {
+                let start = accessor.byte_offset + element_index * stride;
+                let bytes = bytes.get(start..start + element_size).ok_or_else(out_of_bounds)?;
+                read_element(bytes, element);
+            }
+        }
+        if let Some(sparse) = &accessor.sparse {
+            let index_size = size_of_component(sparse.indices.component_type)
+                .ok_or_else(|| GltfError::Invalid(format!("accessor {} has unknown sparse indices", index)))?;
+            let (index_bytes, _) = self.view(sparse.indices.buffer_view)?;
+            let (value_bytes, _) = self.view(sparse.values.buffer_view)?;
+            for sparse_index in 0..sparse.count {
+                let start = sparse.indices.byte_offset + sparse_index * index_size;
+                let target = index_bytes
+                    .get(start..start + index_size)
+                    .map(|bytes| read_unsigned(bytes) as usize)
+                    .ok_or_else(out_of_bounds)?;
+                let start = sparse.values.byte_offset + sparse_index * element_size;
+                let bytes = value_bytes.get(start..start + element_size).ok_or_else(out_of_bounds)?;
+                let element = values
+                    .get_mut(target * components..(target + 1) * components)
+                    .ok_or_else(out_of_bounds)?;
+                read_element(bytes, element);
+            }
+        }
+        Ok((values, components))
+    }
+}
+
+/// Returns true if `count` elements of `element_size` bytes, `stride` bytes apart from `offset`, fit in `length` bytes.
+fn fits(length : usize, offset : usize, count : usize, stride : usize, element_size : usize) -> bool {
+    let size = match count.checked_sub(1) {
+        Some(last) => last.checked_mul(stride).and_then(|start| start.checked_add(element_size)),
+        None => Some(0),
+    };
+    size.and_then(|size| size.checked_add(offset)).is_some_and(|end| end <= length)
+}
+
+fn read_unsigned(bytes : &[u8]) -> u32 {
+    match *bytes {
+        [byte] => byte as u32,
+        [low, high] => u16::from_le_bytes([low, high]) as u32,
+        _ => u32::from_le_bytes(bytes.try_into().unwrap()),
+    }
+}
+
+fn size_of_component(component_type : u32) -> Option<usize> {
+    match component_type {
+        Accessor::BYTE | Accessor::UNSIGNED_BYTE => Some(1),
+        Accessor::SHORT | Accessor::UNSIGNED_SHORT => Some(2),
+        Accessor::UNSIGNED_INT | Accessor::FLOAT => Some(4),
+        _ => None,
+    }
+}
+
+/// Returns the number of columns and rows of an element type.
+fn element_shape(element_type : &str) -> Option<(usize, usize)> {
+    match element_type {
+        "SCALAR" => Some((1, 1)),
+        "VEC2" => Some((1, 2)),
+        "VEC3" => Some((1, 3)),
+        "VEC4" => Some((1, 4)),
+        "MAT2" => Some((2, 2)),
+        "MAT3" => Some((3, 3)),
+        "MAT4" => Some((4, 4)),
+        _ => None,
+    }
+}
+
+fn align_up(value : usize, alignment : usize) -> usize {
+    value.div_ceil(alignment) * alignment
+}
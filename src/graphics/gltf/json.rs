@@ -0,0 +1,363 @@
+// The parts of the glTF 2.0 JSON schema which are imported. Unknown properties are ignored, and missing properties
+// take the defaults given by the specification.
+
+use std::collections::HashMap;
+use serde::Deserialize;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Root {
+    pub asset : Asset,
+    pub extensions_required : Vec<String>,
+    pub scene : Option<usize>,
+    pub scenes : Vec<Scene>,
+    pub nodes : Vec<Node>,
+    pub meshes : Vec<Mesh>,
+    pub materials : Vec<Material>,
+    pub textures : Vec<Texture>,
+    pub images : Vec<Image>,
+    pub samplers : Vec<Sampler>,
+    pub cameras : Vec<Camera>,
+    pub skins : Vec<Skin>,
+    pub animations : Vec<Animation>,
+    pub accessors : Vec<Accessor>,
+    pub buffer_views : Vec<BufferView>,
+    pub buffers : Vec<Buffer>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Asset {
+    pub version : String,
+    pub min_version : Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Scene {
+    pub name : Option<String>,
+    pub nodes : Vec<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Node {
+    pub name : Option<String>,
+    pub children : Vec<usize>,
+    pub matrix : Option<[f32; 16]>,
+    pub translation : Option<[f32; 3]>,
+    pub rotation : Option<[f32; 4]>,
+    pub scale : Option<[f32; 3]>,
+    pub mesh : Option<usize>,
+    pub camera : Option<usize>,
+    pub skin : Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Mesh {
+    pub name : Option<String>,
+    pub primitives : Vec<Primitive>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Primitive {
+    pub attributes : HashMap<String, usize>,
+    pub indices : Option<usize>,
+    pub material : Option<usize>,
+    #[serde(default = "Primitive::default_mode")]
+    pub mode : u32,
+}
+
+impl Primitive {
+    pub const TRIANGLES : u32 = 4;
+    pub const TRIANGLE_STRIP : u32 = 5;
+    pub const TRIANGLE_FAN : u32 = 6;
+
+    fn default_mode() -> u32 {
+        Self::TRIANGLES
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Material {
+    pub name : Option<String>,
+    pub pbr_metallic_roughness : PbrMetallicRoughness,
+    pub normal_texture : Option<NormalTextureInfo>,
+    pub occlusion_texture : Option<OcclusionTextureInfo>,
+    pub emissive_texture : Option<TextureInfo>,
+    pub emissive_factor : [f32; 3],
+    pub alpha_mode : AlphaMode,
+    pub alpha_cutoff : f32,
+    pub double_sided : bool,
+    pub extensions : MaterialExtensions,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            name: None,
+            pbr_metallic_roughness: PbrMetallicRoughness::default(),
+            normal_texture: None,
+            occlusion_texture: None,
+            emissive_texture: None,
+            emissive_factor: [0.0; 3],
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            double_sided: false,
+            extensions: MaterialExtensions::default(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct MaterialExtensions {
+    #[serde(rename = "KHR_materials_emissive_strength")]
+    pub emissive_strength : Option<EmissiveStrength>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmissiveStrength {
+    #[serde(default = "one")]
+    pub emissive_strength : f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum AlphaMode {
+    Opaque,
+    Mask,
+    Blend,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PbrMetallicRoughness {
+    pub base_color_factor : [f32; 4],
+    pub base_color_texture : Option<TextureInfo>,
+    pub metallic_factor : f32,
+    pub roughness_factor : f32,
+    pub metallic_roughness_texture : Option<TextureInfo>,
+}
+
+impl Default for PbrMetallicRoughness {
+    fn default() -> Self {
+        Self {
+            base_color_factor: [1.0; 4],
+            base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextureInfo {
+    pub index : usize,
+    #[serde(default)]
+    pub tex_coord : u32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NormalTextureInfo {
+    pub index : usize,
+    #[serde(default)]
+    pub tex_coord : u32,
+    #[serde(default = "one")]
+    pub scale : f32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OcclusionTextureInfo {
+    pub index : usize,
+    #[serde(default)]
+    pub tex_coord : u32,
+    #[serde(default = "one")]
+    pub strength : f32,
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Texture {
+    pub sampler : Option<usize>,
+    pub source : Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Image {
+    pub name : Option<String>,
+    pub uri : Option<String>,
+    pub buffer_view : Option<usize>,
+    pub mime_type : Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Sampler {
+    pub mag_filter : Option<u32>,
+    pub min_filter : Option<u32>,
+    pub wrap_s : u32,
+    pub wrap_t : u32,
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Self { mag_filter: None, min_filter: None, wrap_s: Self::REPEAT, wrap_t: Self::REPEAT }
+    }
+}
+
+impl Sampler {
+    pub const NEAREST : u32 = 9728;
+    pub const LINEAR : u32 = 9729;
+    pub const NEAREST_MIPMAP_NEAREST : u32 = 9984;
+    pub const LINEAR_MIPMAP_NEAREST : u32 = 9985;
+    pub const NEAREST_MIPMAP_LINEAR : u32 = 9986;
+    pub const LINEAR_MIPMAP_LINEAR : u32 = 9987;
+    pub const CLAMP_TO_EDGE : u32 = 33071;
+    pub const MIRRORED_REPEAT : u32 = 33648;
+    pub const REPEAT : u32 = 10497;
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Camera {
+    pub name : Option<String>,
+    pub perspective : Option<Perspective>,
+    pub orthographic : Option<Orthographic>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Perspective {
+    pub aspect_ratio : Option<f32>,
+    pub yfov : f32,
+    pub znear : f32,
+    pub zfar : Option<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Orthographic {
+    pub xmag : f32,
+    pub ymag : f32,
+    pub znear : f32,
+    pub zfar : f32,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Skin {
+    pub name : Option<String>,
+    pub inverse_bind_matrices : Option<usize>,
+    pub skeleton : Option<usize>,
+    pub joints : Vec<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Animation {
+    pub name : Option<String>,
+    pub channels : Vec<Channel>,
+    pub samplers : Vec<AnimationSampler>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Channel {
+    pub sampler : usize,
+    pub target : ChannelTarget,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChannelTarget {
+    pub node : Option<usize>,
+    pub path : String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnimationSampler {
+    pub input : usize,
+    #[serde(default = "AnimationSampler::default_interpolation")]
+    pub interpolation : String,
+    pub output : usize,
+}
+
+impl AnimationSampler {
+    fn default_interpolation() -> String {
+        "LINEAR".to_string()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Accessor {
+    pub buffer_view : Option<usize>,
+    #[serde(default)]
+    pub byte_offset : usize,
+    pub component_type : u32,
+    #[serde(default)]
+    pub normalized : bool,
+    pub count : usize,
+    #[serde(rename = "type")]
+    pub element_type : String,
+    pub sparse : Option<Sparse>,
+}
+
+impl Accessor {
+    pub const BYTE : u32 = 5120;
+    pub const UNSIGNED_BYTE : u32 = 5121;
+    pub const SHORT : u32 = 5122;
+    pub const UNSIGNED_SHORT : u32 = 5123;
+    pub const UNSIGNED_INT : u32 = 5125;
+    pub const FLOAT : u32 = 5126;
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Sparse {
+    pub count : usize,
+    pub indices : SparseIndices,
+    pub values : SparseValues,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SparseIndices {
+    pub buffer_view : usize,
+    #[serde(default)]
+    pub byte_offset : usize,
+    pub component_type : u32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SparseValues {
+    pub buffer_view : usize,
+    #[serde(default)]
+    pub byte_offset : usize,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BufferView {
+    pub buffer : usize,
+    #[serde(default)]
+    pub byte_offset : usize,
+    pub byte_length : usize,
+    pub byte_stride : Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Buffer {
+    pub uri : Option<String>,
+    pub byte_length : usize,
+}
@@ -0,0 +1,928 @@
+use std::{convert::TryInto, fmt, fs, io, path::{Path, PathBuf}};
+use ash::vk;
+use nalgebra::{Matrix4, Quaternion, UnitQuaternion, Vector2, Vector3, Vector4};
+use super::{BlendMode, DepthStencilState};
+use super::buffer::BufferCreationError;
+use super::image::{ImageData, ImageError};
+use super::material::{MaterialDesc, MaterialError, ParameterValue, PbrMaterial, PbrVertex};
+use super::mesh::MeshData;
+use super::sampler_cache::SamplerDesc;
+use super::scene::{Animation, AnimationChannel, Camera, ChannelValues, Interpolation, Node, Projection, Scene, Skin,
+                   Transform};
+use self::accessor::AccessorReader;
+
+mod accessor;
+mod json;
+mod model;
+
+pub use self::model::GltfModel;
+
+/// Extensions which may be required by an asset, since importing without them would not lose anything.
+const SUPPORTED_EXTENSIONS : &[&str] = &["KHR_mesh_quantization", "KHR_materials_emissive_strength"];
+
+const GLB_MAGIC : &[u8] = b"glTF";
+const GLB_CHUNK_JSON : u32 = 0x4e4f_534a;
+const GLB_CHUNK_BIN : u32 = 0x004e_4942;
+
+/// Describes why a glTF asset could not be imported or uploaded.
+#[derive(Debug)]
+pub enum GltfError {
+    Io(PathBuf, io::Error),
+    Json(serde_json::Error),
+    /// The binary container is truncated or malformed.
+    Glb(&'static str),
+    /// The asset is not glTF 2.0, or requires a feature which is not supported.
+    Unsupported(String),
+    /// An object refers to one which does not exist, or data lies outside of its buffer.
+    Invalid(String),
+    /// The image with this index could not be decoded.
+    Image(usize, ImageError),
+    Mesh(BufferCreationError),
+    Material(MaterialError),
+}
+
+impl GltfError {
+    fn invalid(object : &str, index : usize) -> Self {
+        GltfError::Invalid(format!("{} {} does not exist", object, index))
+    }
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GltfError::Io(path, error) => write!(f, "{}: {}", path.display(), error),
+            GltfError::Json(error) => write!(f, "invalid JSON: {}", error),
+            GltfError::Glb(reason) => write!(f, "invalid GLB container: {}", reason),
+            GltfError::Unsupported(feature) => write!(f, "unsupported glTF feature: {}", feature),
+            GltfError::Invalid(reason) => write!(f, "invalid glTF: {}", reason),
+            GltfError::Image(index, error) => write!(f, "image {}: {}", index, error),
+            GltfError::Mesh(error) => write!(f, "failed to create mesh buffers: {:?}", error),
+            GltfError::Material(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl From<serde_json::Error> for GltfError {
+    fn from(error : serde_json::Error) -> Self {
+        GltfError::Json(error)
+    }
+}
+
+impl From<MaterialError> for GltfError {
+    fn from(error : MaterialError) -> Self {
+        GltfError::Material(error)
+    }
+}
+
+/// The joints and weights of the vertices of a skinned mesh, indexed like its vertices. Vertices of primitives which
+/// are not skinned have no weights.
+#[derive(Clone, Debug, Default)]
+pub struct SkinVertices {
+    /// Indices into the joints of the skin of the node drawing the mesh.
+    pub joints : Vec<[u16; 4]>,
+    pub weights : Vec<Vector4<f32>>,
+}
+
+/// A mesh of a glTF asset, with a submesh for each of its primitives.
+#[derive(Clone, Debug)]
+pub struct GltfMesh {
+    pub name : Option<String>,
+    pub data : MeshData<PbrVertex>,
+    /// The joints and weights of the vertices, if any primitive is skinned.
+    pub skin : Option<SkinVertices>,
+}
+
+/// A texture of a glTF asset, which is an image sampled in a certain way.
+#[derive(Clone, Copy, Debug)]
+pub struct GltfTexture {
+    pub image : usize,
+    pub sampler : SamplerDesc,
+}
+
+/// A material of a glTF asset, described as a `PbrMaterial`.
+#[derive(Clone, Debug)]
+pub struct GltfMaterial {
+    pub name : Option<String>,
+    /// The factors and render state of the material. Its maps have no paths, since the images may be embedded.
+    pub desc : MaterialDesc,
+    /// The texture of each map of the material, as an index into the textures of the asset.
+    pub maps : Vec<(&'static str, usize)>,
+}
+
+/// A glTF 2.0 asset, read from a `.gltf` file with its external or embedded buffers and images, or from a binary
+/// `.glb` file, and converted into the engine's scene, mesh and material formats. Upload it with `GltfModel` to draw
+/// it.
+///
+/// Only the default scene is imported, or the first scene if there is no default. Every primitive is triangulated, and
+/// points and lines are skipped. Vertices read the first set of texture coordinates, so maps using another set are
+/// sampled with the first. Normals are computed flat when a primitive has none, and tangents are computed when it has
+/// none. Morph targets and the `MASK` alpha mode are not supported, so masked materials are drawn opaque.
+pub struct Gltf {
+    pub scene : Scene,
+    pub meshes : Vec<GltfMesh>,
+    pub materials : Vec<GltfMaterial>,
+    pub textures : Vec<GltfTexture>,
+    pub images : Vec<ImageData>,
+}
+
+impl Gltf {
+    /// Reads a `.gltf` or `.glb` file. Relative URIs are resolved against the directory containing it.
+    pub fn load(path : &Path) -> Result<Self, GltfError> {
+        let bytes = fs::read(path).map_err(|error| GltfError::Io(path.to_path_buf(), error))?;
+        Self::from_slice(&bytes, path.parent().unwrap_or_else(|| Path::new("")))
+    }
+
+    /// Reads an asset from the contents of a `.gltf` or `.glb` file, resolving relative URIs against `directory`.
+    pub fn from_slice(bytes : &[u8], directory : &Path) -> Result<Self, GltfError> {
+        let (json, binary) = if bytes.starts_with(GLB_MAGIC) {
+            read_glb(bytes)?
+        } else {
+            (bytes, None)
+        };
+        let root : json::Root = serde_json::from_slice(json)?;
+        if !root.asset.version.starts_with("2.") {
+            return Err(GltfError::Unsupported(format!("version {}", root.asset.version)));
+        }
+        let unsupported = root.extensions_required
+            .iter()
+            .find(|extension| !SUPPORTED_EXTENSIONS.contains(&extension.as_str()));
+        if let Some(extension) = unsupported {
+            return Err(GltfError::Unsupported(format!("required extension {}", extension)));
+        }
+
+        let mut buffers = Vec::with_capacity(root.buffers.len());
+        for (index, buffer) in root.buffers.iter().enumerate() {
+            let data = match (&buffer.uri, binary) {
+                (Some(uri), _) => read_uri(uri, directory)?,
+                // Only the first buffer may refer to the binary chunk of a GLB file.
+                (None, Some(binary)) if index == 0 => binary.to_vec(),
+                (None, _) => return Err(GltfError::Invalid(format!("buffer {} has no data", index))),
+            };
+            if data.len() < buffer.byte_length {
+                return Err(GltfError::Invalid(format!("buffer {} is shorter than its length", index)));
+            }
+            buffers.push(data);
+        }
+
+        let importer = Importer { root: &root, reader: AccessorReader::new(&root, &buffers) };
+        let images = root.images
+            .iter()
+            .enumerate()
+            .map(|(index, image)| importer.image(index, image, directory))
+            .collect::<Result<_, _>>()?;
+        let textures = root.textures
+            .iter()
+            .enumerate()
+            .map(|(index, texture)| importer.texture(index, texture))
+            .collect::<Result<_, _>>()?;
+        let materials = root.materials
+            .iter()
+            .map(|material| importer.material(material))
+            .collect::<Result<_, _>>()?;
+        let meshes = root.meshes
+            .iter()
+            .map(|mesh| importer.mesh(mesh))
+            .collect::<Result<_, _>>()?;
+        let scene = importer.scene()?;
+        Ok(Self { scene, meshes, materials, textures, images })
+    }
+}
+
+/// Returns the JSON chunk and the binary chunk, if any, of a GLB file.
+fn read_glb(bytes : &[u8]) -> Result<(&[u8], Option<&[u8]>), GltfError> {
+    let read_u32 = |offset : usize| bytes
+        .get(offset..offset + 4)
+        .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+        .ok_or(GltfError::Glb("truncated header"));
+    if read_u32(4)? != 2 {
+        return Err(GltfError::Unsupported(format!("GLB container version {}", read_u32(4)?)));
+    }
+    let length = (read_u32(8)? as usize).min(bytes.len());
+    let mut chunks = Vec::new();
+    let mut offset = 12;
+    while offset + 8 <= length {
+        let chunk_length = read_u32(offset)? as usize;
+        let chunk_type = read_u32(offset + 4)?;
+        let data = bytes
+            .get(offset + 8..offset + 8 + chunk_length)
+            .ok_or(GltfError::Glb("truncated chunk"))?;
+        chunks.push((chunk_type, data));
+        // Chunks are padded to 4 bytes.
+        offset += 8 + chunk_length.div_ceil(4) * 4;
+    }
+    match chunks.as_slice() {
+        [(GLB_CHUNK_JSON, json), rest @ ..] => {
+            let binary = rest.first().filter(|(chunk_type, _)| *chunk_type == GLB_CHUNK_BIN).map(|(_, data)| *data);
+            Ok((json, binary))
+        }
+        _ => Err(GltfError::Glb("the first chunk is not JSON")),
+    }
+}
+
+/// Reads the data of a URI, which is either a base64 data URI or a path relative to `directory`.
+fn read_uri(uri : &str, directory : &Path) -> Result<Vec<u8>, GltfError> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (media_type, payload) = data
+            .split_once(',')
+            .ok_or_else(|| GltfError::Invalid("data URI without data".to_string()))?;
+        if !media_type.ends_with(";base64") {
+            return Err(GltfError::Unsupported("data URIs which are not base64".to_string()));
+        }
+        return decode_base64(payload).ok_or_else(|| GltfError::Invalid("invalid base64 in data URI".to_string()));
+    }
+    let path = directory.join(decode_percent(uri));
+    fs::read(&path).map_err(|error| GltfError::Io(path, error))
+}
+
+fn decode_base64(text : &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+    for character in text.bytes().filter(|character| !character.is_ascii_whitespace() && *character != b'=') {
+        let value = match character {
+            b'A'..=b'Z' => character - b'A',
+            b'a'..=b'z' => character - b'a' + 26,
+            b'0'..=b'9' => character - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return None,
+        };
+        bits = bits << 6 | value as u32;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            bytes.push((bits >> bit_count) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+/// Decodes the escaped characters of a relative URI, such as spaces written as `%20`.
+fn decode_percent(uri : &str) -> PathBuf {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = bytes
+            .get(index + 1..index + 3)
+            .filter(|_| bytes[index] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    PathBuf::from(String::from_utf8_lossy(&decoded).into_owned())
+}
+
+/// Converts the objects of a parsed asset.
+struct Importer<'a> {
+    root : &'a json::Root,
+    reader : AccessorReader<'a>,
+}
+
+impl<'a> Importer<'a> {
+    fn image(&self, index : usize, image : &json::Image, directory : &Path) -> Result<ImageData, GltfError> {
+        let bytes = match (&image.uri, image.buffer_view) {
+            (Some(uri), _) => read_uri(uri, directory)?,
+            (None, Some(view)) => self.reader.view(view)?.0.to_vec(),
+            (None, None) => return Err(GltfError::Invalid(format!("image {} has no data", index))),
+        };
+        ImageData::decode(&bytes).map_err(|error| GltfError::Image(index, error))
+    }
+
+    fn texture(&self, index : usize, texture : &json::Texture) -> Result<GltfTexture, GltfError> {
+        // Textures without a source only have images in extensions, such as KTX2 with Basis Universal or WebP.
+        let image = texture.source
+            .ok_or_else(|| GltfError::Unsupported(format!("texture {} has no PNG or JPEG source", index)))?;
+        if image >= self.root.images.len() {
+            return Err(GltfError::invalid("image", image));
+        }
+        let sampler = match texture.sampler {
+            Some(sampler) => self.root.samplers.get(sampler).ok_or_else(|| GltfError::invalid("sampler", sampler))?,
+            None => &json::Sampler::default(),
+        };
+        let filter = |filter| if filter == json::Sampler::NEAREST { vk::Filter::NEAREST } else { vk::Filter::LINEAR };
+        let min_filter = sampler.min_filter.unwrap_or(json::Sampler::LINEAR_MIPMAP_LINEAR);
+        let (min_filter, mipmap_mode) = match min_filter {
+            json::Sampler::NEAREST_MIPMAP_NEAREST => (vk::Filter::NEAREST, vk::SamplerMipmapMode::NEAREST),
+            json::Sampler::LINEAR_MIPMAP_NEAREST => (vk::Filter::LINEAR, vk::SamplerMipmapMode::NEAREST),
+            json::Sampler::NEAREST_MIPMAP_LINEAR => (vk::Filter::NEAREST, vk::SamplerMipmapMode::LINEAR),
+            filter_type => (filter(filter_type), vk::SamplerMipmapMode::LINEAR),
+        };
+        let address_mode = |wrap| match wrap {
+            json::Sampler::CLAMP_TO_EDGE => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            json::Sampler::MIRRORED_REPEAT => vk::SamplerAddressMode::MIRRORED_REPEAT,
+            _ => vk::SamplerAddressMode::REPEAT,
+        };
+        let sampler = SamplerDesc {
+            mag_filter: filter(sampler.mag_filter.unwrap_or(json::Sampler::LINEAR)),
+            min_filter,
+            mipmap_mode,
+            address_mode_u: address_mode(sampler.wrap_s),
+            address_mode_v: address_mode(sampler.wrap_t),
+            ..SamplerDesc::default()
+        };
+        let max_anisotropy = if min_filter == vk::Filter::LINEAR { 16 } else { 1 };
+        Ok(GltfTexture { image, sampler: sampler.anisotropy(max_anisotropy) })
+    }
+
+    fn material(&self, material : &json::Material) -> Result<GltfMaterial, GltfError> {
+        let pbr = &material.pbr_metallic_roughness;
+        let mut desc = PbrMaterial::desc(
+            Vector4::from(pbr.base_color_factor),
+            pbr.metallic_factor,
+            pbr.roughness_factor);
+        let emissive_strength = material.extensions.emissive_strength
+            .as_ref()
+            .map_or(1.0, |extension| extension.emissive_strength);
+        let emissive = Vector3::from(material.emissive_factor) * emissive_strength;
+        desc.set_parameter(PbrMaterial::EMISSIVE, ParameterValue::Color(emissive.push(1.0)));
+        if let Some(normal) = &material.normal_texture {
+            desc.set_parameter(PbrMaterial::NORMAL_SCALE, ParameterValue::Scalar(normal.scale));
+        }
+        if let Some(occlusion) = &material.occlusion_texture {
+            desc.set_parameter(PbrMaterial::OCCLUSION_STRENGTH, ParameterValue::Scalar(occlusion.strength));
+        }
+        if material.double_sided {
+            desc.render_state.cull_mode = vk::CullModeFlags::NONE;
+        }
+        match material.alpha_mode {
+            json::AlphaMode::Opaque => {}
+            json::AlphaMode::Mask => warn!("Drawing masked material {:?} as opaque", material.name),
+            json::AlphaMode::Blend => {
+                desc.render_state.blend = BlendMode::Alpha;
+                desc.render_state.depth = Some(DepthStencilState::read_only());
+            }
+        }
+
+        let textures = [
+            (PbrMaterial::BASE_COLOR_MAP, pbr.base_color_texture.as_ref().map(|info| (info.index, info.tex_coord))),
+            (PbrMaterial::METALLIC_ROUGHNESS_MAP,
+                pbr.metallic_roughness_texture.as_ref().map(|info| (info.index, info.tex_coord))),
+            (PbrMaterial::NORMAL_MAP, material.normal_texture.as_ref().map(|info| (info.index, info.tex_coord))),
+            (PbrMaterial::OCCLUSION_MAP, material.occlusion_texture.as_ref().map(|info| (info.index, info.tex_coord))),
+            (PbrMaterial::EMISSIVE_MAP, material.emissive_texture.as_ref().map(|info| (info.index, info.tex_coord))),
+        ];
+        let mut maps = Vec::new();
+        for (name, texture) in textures.iter() {
+            if let Some((texture, texture_coord_set)) = *texture {
+                if texture >= self.root.textures.len() {
+                    return Err(GltfError::invalid("texture", texture));
+                }
+                if texture_coord_set != 0 {
+                    warn!("Sampling {} of material {:?} with the first texture coordinates", name, material.name);
+                }
+                maps.push((*name, texture));
+            }
+        }
+        Ok(GltfMaterial { name: material.name.clone(), desc, maps })
+    }
+
+    fn mesh(&self, mesh : &json::Mesh) -> Result<GltfMesh, GltfError> {
+        let mut data = MeshData::default();
+        let mut skin : Option<SkinVertices> = None;
+        for primitive in &mesh.primitives {
+            let material = match primitive.material {
+                Some(material) if material >= self.root.materials.len() =>
+                    return Err(GltfError::invalid("material", material)),
+                material => material,
+            };
+            let primitive = match self.primitive(primitive)? {
+                Some(primitive) => primitive,
+                None => {
+                    warn!("Skipping a primitive of mesh {:?} which is not made of triangles", mesh.name);
+                    continue;
+                }
+            };
+            let first_vertex = data.vertices.len();
+            let vertex_count = primitive.vertices.len();
+            if primitive.skin.is_some() || skin.is_some() {
+                let skin = skin.get_or_insert_with(SkinVertices::default);
+                skin.joints.resize(first_vertex, [0; 4]);
+                skin.weights.resize(first_vertex, Vector4::zeros());
+                match primitive.skin {
+                    Some(primitive_skin) => {
+                        skin.joints.extend(primitive_skin.joints);
+                        skin.weights.extend(primitive_skin.weights);
+                    }
+                    None => {
+                        skin.joints.resize(first_vertex + vertex_count, [0; 4]);
+                        skin.weights.resize(first_vertex + vertex_count, Vector4::zeros());
+                    }
+                }
+            }
+            data.vertices.extend(primitive.vertices);
+            let indices : Vec<u32> = primitive.indices.iter().map(|index| index + first_vertex as u32).collect();
+            data.push_submesh(&indices, material);
+        }
+        if let Some(skin) = &mut skin {
+            skin.joints.resize(data.vertices.len(), [0; 4]);
+            skin.weights.resize(data.vertices.len(), Vector4::zeros());
+        }
+        Ok(GltfMesh { name: mesh.name.clone(), data, skin })
+    }
+
+    /// Reads a primitive as a triangle list, or returns `None` if it is made of points or lines.
+    fn primitive(&self, primitive : &json::Primitive) -> Result<Option<PrimitiveData>, GltfError> {
+        let attribute = |name : &str, components : usize| -> Result<Option<Vec<f32>>, GltfError> {
+            match primitive.attributes.get(name) {
+                Some(accessor) => {
+                    let (values, accessor_components) = self.reader.read_floats(*accessor)?;
+                    if accessor_components != components {
+                        return Err(GltfError::Invalid(format!("{} has {} components", name, accessor_components)));
+                    }
+                    Ok(Some(values))
+                }
+                None => Ok(None),
+            }
+        };
+        let positions = attribute("POSITION", 3)?
+            .ok_or_else(|| GltfError::Invalid("primitive has no positions".to_string()))?;
+        let vertex_count = positions.len() / 3;
+        let check_count = |name : &str, values : Option<Vec<f32>>, components : usize| match values {
+            Some(values) if values.len() != vertex_count * components =>
+                Err(GltfError::Invalid(format!("{} has a different count than POSITION", name))),
+            values => Ok(values),
+        };
+        let normals = check_count("NORMAL", attribute("NORMAL", 3)?, 3)?;
+        let tangents = check_count("TANGENT", attribute("TANGENT", 4)?, 4)?;
+        let texture_coords = check_count("TEXCOORD_0", attribute("TEXCOORD_0", 2)?, 2)?;
+        let weights = check_count("WEIGHTS_0", attribute("WEIGHTS_0", 4)?, 4)?;
+        let joints = match primitive.attributes.get("JOINTS_0") {
+            Some(accessor) => match self.reader.read_unsigned(*accessor)? {
+                (joints, 4) if joints.len() == vertex_count * 4 => Some(joints),
+                _ => return Err(GltfError::Invalid("JOINTS_0 does not match POSITION".to_string())),
+            },
+            None => None,
+        };
+
+        let indices = match primitive.indices {
+            Some(accessor) => match self.reader.read_unsigned(accessor)? {
+                (indices, 1) => indices,
+                _ => return Err(GltfError::Invalid("indices are not scalars".to_string())),
+            },
+            None => (0..vertex_count as u32).collect(),
+        };
+        if indices.iter().any(|index| *index as usize >= vertex_count) {
+            return Err(GltfError::Invalid("an index is out of range".to_string()));
+        }
+        let indices = match primitive.mode {
+            json::Primitive::TRIANGLES => indices,
+            json::Primitive::TRIANGLE_STRIP => (0..indices.len().saturating_sub(2))
+                .flat_map(|i| if i % 2 == 0 {
+                    [indices[i], indices[i + 1], indices[i + 2]]
+                } else {
+                    [indices[i], indices[i + 2], indices[i + 1]]
+                })
+                .collect(),
+            json::Primitive::TRIANGLE_FAN => (1..indices.len().saturating_sub(1))
+                .flat_map(|i| [indices[i], indices[i + 1], indices[0]])
+                .collect(),
+            _ => return Ok(None),
+        };
+        let indices : Vec<u32> = indices.chunks_exact(3).flatten().copied().collect();
+
+        let vertex = |index : usize| PbrVertex {
+            position: Vector3::from_column_slice(&positions[index * 3..index * 3 + 3]),
+            normal: normals.as_ref().map_or(Vector3::zeros(), |normals| {
+                Vector3::from_column_slice(&normals[index * 3..index * 3 + 3])
+            }),
+            tangent: tangents.as_ref().map_or(Vector4::zeros(), |tangents| {
+                Vector4::from_column_slice(&tangents[index * 4..index * 4 + 4])
+            }),
+            texture_coord: texture_coords.as_ref().map_or(Vector2::zeros(), |texture_coords| {
+                Vector2::from_column_slice(&texture_coords[index * 2..index * 2 + 2])
+            }),
+        };
+        let skin = |index : usize| {
+            let joints = joints.as_ref().map_or([0; 4], |joints| {
+                let joints = &joints[index * 4..index * 4 + 4];
+                [joints[0] as u16, joints[1] as u16, joints[2] as u16, joints[3] as u16]
+            });
+            let weights = weights.as_ref().map_or(Vector4::zeros(), |weights| {
+                Vector4::from_column_slice(&weights[index * 4..index * 4 + 4])
+            });
+            (joints, weights)
+        };
+        // Flat normals need a vertex for each corner of each triangle.
+        let corners : Vec<usize> = if normals.is_some() {
+            (0..vertex_count).collect()
+        } else {
+            indices.iter().map(|index| *index as usize).collect()
+        };
+        let mut data = MeshData {
+            vertices: corners.iter().map(|corner| vertex(*corner)).collect(),
+            indices: if normals.is_some() { indices } else { (0..corners.len() as u32).collect() },
+            submeshes: Vec::new(),
+        };
+        if normals.is_none() {
+            data.compute_normals();
+        }
+        if tangents.is_none() {
+            data.compute_tangents();
+        }
+        let skin = if joints.is_some() || weights.is_some() {
+            let (joints, weights) = corners.iter().map(|corner| skin(*corner)).unzip();
+            Some(SkinVertices { joints, weights })
+        } else {
+            None
+        };
+        Ok(Some(PrimitiveData { vertices: data.vertices, indices: data.indices, skin }))
+    }
+
+    fn scene(&self) -> Result<Scene, GltfError> {
+        let root = self.root;
+        let mut nodes = Vec::with_capacity(root.nodes.len());
+        for node in &root.nodes {
+            let transform = match node.matrix {
+                Some(matrix) => Transform::from_matrix(&Matrix4::from_column_slice(&matrix)),
+                None => {
+                    let [x, y, z, w] = node.rotation.unwrap_or([0.0, 0.0, 0.0, 1.0]);
+                    Transform {
+                        translation: Vector3::from(node.translation.unwrap_or([0.0; 3])),
+                        rotation: UnitQuaternion::new_normalize(Quaternion::new(w, x, y, z)),
+                        scale: Vector3::from(node.scale.unwrap_or([1.0; 3])),
+                    }
+                }
+            };
+            let check = |index : Option<usize>, count : usize, object : &str| match index {
+                Some(index) if index >= count => Err(GltfError::invalid(object, index)),
+                index => Ok(index),
+            };
+            nodes.push(Node {
+                name: node.name.clone(),
+                transform,
+                parent: None,
+                children: node.children.clone(),
+                mesh: check(node.mesh, root.meshes.len(), "mesh")?,
+                camera: check(node.camera, root.cameras.len(), "camera")?,
+                skin: check(node.skin, root.skins.len(), "skin")?,
+            });
+        }
+        for index in 0..nodes.len() {
+            for child in nodes[index].children.clone() {
+                let child_node = nodes.get_mut(child).ok_or_else(|| GltfError::invalid("node", child))?;
+                if child_node.parent.is_some() {
+                    return Err(GltfError::Invalid(format!("node {} has several parents", child)));
+                }
+                child_node.parent = Some(index);
+            }
+        }
+        // With at most one parent each, a cycle is a chain of parents longer than the number of nodes.
+        for index in 0..nodes.len() {
+            let mut ancestor = nodes[index].parent;
+            for _ in 0..nodes.len() {
+                ancestor = ancestor.and_then(|ancestor| nodes[ancestor].parent);
+            }
+            if ancestor.is_some() {
+                return Err(GltfError::Invalid(format!("node {} is its own ancestor", index)));
+            }
+        }
+
+        let check_node = |node : usize| if node < nodes.len() {
+            Ok(node)
+        } else {
+            Err(GltfError::invalid("node", node))
+        };
+        let (name, roots) = match root.scene.or(if root.scenes.is_empty() { None } else { Some(0) }) {
+            Some(scene) => {
+                let scene = root.scenes.get(scene).ok_or_else(|| GltfError::invalid("scene", scene))?;
+                let roots = scene.nodes.iter().map(|node| check_node(*node)).collect::<Result<_, _>>()?;
+                (scene.name.clone(), roots)
+            }
+            // Without scenes, every node at the top of a hierarchy is shown.
+            None => (None, (0..nodes.len()).filter(|node| nodes[*node].parent.is_none()).collect()),
+        };
+
+        let cameras = root.cameras
+            .iter()
+            .enumerate()
+            .map(|(index, camera)| {
+                let projection = match (&camera.perspective, &camera.orthographic) {
+                    (Some(perspective), _) => Projection::Perspective {
+                        y_fov: perspective.yfov,
+                        aspect_ratio: perspective.aspect_ratio,
+                        z_near: perspective.znear,
+                        z_far: perspective.zfar,
+                    },
+                    (None, Some(orthographic)) => Projection::Orthographic {
+                        x_mag: orthographic.xmag,
+                        y_mag: orthographic.ymag,
+                        z_near: orthographic.znear,
+                        z_far: orthographic.zfar,
+                    },
+                    (None, None) => return Err(GltfError::Invalid(format!("camera {} has no projection", index))),
+                };
+                Ok(Camera { name: camera.name.clone(), projection })
+            })
+            .collect::<Result<_, _>>()?;
+
+        let mut skins = Vec::with_capacity(root.skins.len());
+        for skin in &root.skins {
+            let joints : Vec<usize> = skin.joints.iter().map(|joint| check_node(*joint)).collect::<Result<_, _>>()?;
+            let inverse_bind_matrices = match skin.inverse_bind_matrices {
+                Some(accessor) => match self.reader.read_floats(accessor)? {
+                    (matrices, 16) if matrices.len() >= joints.len() * 16 =>
+                        matrices.chunks_exact(16).map(Matrix4::from_column_slice).collect(),
+                    _ => return Err(GltfError::Invalid(format!("skin {:?} lacks inverse bind matrices", skin.name))),
+                },
+                None => vec![Matrix4::identity(); joints.len()],
+            };
+            let skeleton = skin.skeleton.map(check_node).transpose()?;
+            skins.push(Skin { name: skin.name.clone(), joints, inverse_bind_matrices, skeleton });
+        }
+
+        let animations = root.animations
+            .iter()
+            .map(|animation| self.animation(animation, nodes.len()))
+            .collect::<Result<_, _>>()?;
+        Ok(Scene { name, nodes, roots, cameras, skins, animations })
+    }
+
+    fn animation(&self, animation : &json::Animation, node_count : usize) -> Result<Animation, GltfError> {
+        let mut channels = Vec::with_capacity(animation.channels.len());
+        for channel in &animation.channels {
+            let node = match channel.target.node {
+                Some(node) if node < node_count => node,
+                Some(node) => return Err(GltfError::invalid("node", node)),
+                // Channels without a node target extensions.
+                None => continue,
+            };
+            let sampler = animation.samplers
+                .get(channel.sampler)
+                .ok_or_else(|| GltfError::invalid("animation sampler", channel.sampler))?;
+            let interpolation = match sampler.interpolation.as_str() {
+                "STEP" => Interpolation::Step,
+                "LINEAR" => Interpolation::Linear,
+                "CUBICSPLINE" => Interpolation::CubicSpline,
+                other => return Err(GltfError::Invalid(format!("unknown interpolation {}", other))),
+            };
+            let components = match channel.target.path.as_str() {
+                "translation" | "scale" => 3,
+                "rotation" => 4,
+                _ => {
+                    warn!("Skipping animation of {} in {:?}", channel.target.path, animation.name);
+                    continue;
+                }
+            };
+            let (times, _) = self.reader.read_floats(sampler.input)?;
+            let (values, output_components) = self.reader.read_floats(sampler.output)?;
+            let values_per_time = if interpolation == Interpolation::CubicSpline { 3 } else { 1 };
+            if output_components != components || values.len() != times.len() * values_per_time * components {
+                return Err(GltfError::Invalid(format!("animation {:?} has mismatched keyframes", animation.name)));
+            }
+            let values = match components {
+                4 => ChannelValues::Rotation(values
+                    .chunks_exact(4)
+                    .map(|value| Quaternion::new(value[3], value[0], value[1], value[2]))
+                    .collect()),
+                _ => {
+                    let vectors = values.chunks_exact(3).map(Vector3::from_column_slice).collect();
+                    if channel.target.path == "translation" {
+                        ChannelValues::Translation(vectors)
+                    } else {
+                        ChannelValues::Scale(vectors)
+                    }
+                }
+            };
+            channels.push(AnimationChannel { node, interpolation, times, values });
+        }
+        Ok(Animation { name: animation.name.clone(), channels })
+    }
+}
+
+/// The triangles of a primitive, with vertices ready to be appended to a mesh.
+struct PrimitiveData {
+    vertices : Vec<PbrVertex>,
+    indices : Vec<u32>,
+    skin : Option<SkinVertices>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_1_SQRT_2;
+    use super::*;
+
+    const FIXTURES : &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/assets/tests/gltf");
+
+    fn load(name : &str) -> Result<Gltf, GltfError> {
+        Gltf::load(&Path::new(FIXTURES).join(name))
+    }
+
+    fn positions(mesh : &GltfMesh) -> Vec<Vector3<f32>> {
+        mesh.data.vertices.iter().map(|vertex| vertex.position).collect()
+    }
+
+    #[test]
+    fn loads_external_buffers() {
+        let gltf = load("Box.gltf").unwrap();
+        let scene = &gltf.scene;
+        assert_eq!(scene.name.as_deref(), Some("Scene"));
+        assert_eq!(scene.nodes.len(), 2);
+        assert_eq!(scene.roots, [0]);
+        assert_eq!(scene.nodes[0].children, [1]);
+        assert_eq!(scene.nodes[1].parent, Some(0));
+        assert_eq!(scene.nodes[1].mesh, Some(0));
+        assert!((scene.nodes[0].transform.translation - Vector3::new(0.0, 2.0, 0.0)).norm() < 1e-6);
+
+        assert_eq!(gltf.meshes.len(), 1);
+        let data = &gltf.meshes[0].data;
+        assert_eq!(data.submeshes.len(), 1);
+        assert_eq!((data.submeshes[0].index_count, data.submeshes[0].material), (12, Some(0)));
+        assert_eq!(data.indices, [0, 1, 2, 0, 2, 3, 4, 5, 6, 4, 6, 7]);
+        assert_eq!(data.vertices.len(), 8);
+        // Positions and normals are interleaved in one buffer view.
+        assert_eq!(data.vertices[0].position, Vector3::new(-0.5, -0.5, 0.5));
+        assert_eq!(data.vertices[0].normal, Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(data.vertices[2].texture_coord, Vector2::new(1.0, 1.0));
+        assert_eq!(data.vertices[4].position, Vector3::new(0.5, -0.5, -0.5));
+        assert_eq!(data.vertices[4].normal, Vector3::new(0.0, 0.0, -1.0));
+        assert!(gltf.meshes[0].skin.is_none());
+
+        assert_eq!(gltf.materials.len(), 1);
+        let material = &gltf.materials[0];
+        assert_eq!(material.name.as_deref(), Some("Red"));
+        assert_eq!(material.desc.parameter(PbrMaterial::BASE_COLOR),
+                   Some(&ParameterValue::Color(Vector4::new(0.8, 0.0, 0.0, 1.0))));
+        assert_eq!(material.desc.parameter(PbrMaterial::METALLIC), Some(&ParameterValue::Scalar(0.0)));
+        assert_eq!(material.desc.parameter(PbrMaterial::ROUGHNESS), Some(&ParameterValue::Scalar(1.0)));
+        assert_eq!(material.desc.render_state.blend, BlendMode::Opaque);
+        assert_eq!(material.desc.render_state.cull_mode, vk::CullModeFlags::BACK);
+        assert!(material.maps.is_empty());
+        assert!(gltf.textures.is_empty() && gltf.images.is_empty());
+    }
+
+    #[test]
+    fn loads_binary_containers() {
+        let gltf = Gltf::from_slice(include_bytes!("../../assets/tests/gltf/Box.glb"), Path::new("")).unwrap();
+        let external = load("Box.gltf").unwrap();
+        assert_eq!(gltf.scene.nodes.len(), 2);
+        assert_eq!(gltf.meshes.len(), 1);
+        assert_eq!(gltf.meshes[0].data.vertices, external.meshes[0].data.vertices);
+        assert_eq!(gltf.meshes[0].data.indices, external.meshes[0].data.indices);
+
+        assert_eq!(gltf.images.len(), 1);
+        let image = &gltf.images[0];
+        assert_eq!((image.width, image.height), (7, 5));
+        assert_eq!(image.levels[0], include_bytes!("../../assets/tests/images/rgba8.rgba")[..]);
+        assert_eq!(gltf.textures.len(), 1);
+        let sampler = gltf.textures[0].sampler;
+        assert_eq!(gltf.textures[0].image, 0);
+        assert_eq!(sampler.mag_filter, vk::Filter::NEAREST);
+        assert_eq!(sampler.address_mode_u, vk::SamplerAddressMode::CLAMP_TO_EDGE);
+        assert_eq!(sampler.address_mode_v, vk::SamplerAddressMode::REPEAT);
+
+        let material = &gltf.materials[0];
+        assert_eq!(material.maps, [(PbrMaterial::BASE_COLOR_MAP, 0), (PbrMaterial::NORMAL_MAP, 0)]);
+        assert_eq!(material.desc.parameter(PbrMaterial::NORMAL_SCALE), Some(&ParameterValue::Scalar(0.5)));
+        assert_eq!(material.desc.render_state.blend, BlendMode::Alpha);
+        assert_eq!(material.desc.render_state.cull_mode, vk::CullModeFlags::NONE);
+    }
+
+    #[test]
+    fn loads_data_uris_and_encoded_image_paths() {
+        let gltf = load("BoxTextured.gltf").unwrap();
+        assert_eq!(gltf.meshes[0].data.vertices, load("Box.gltf").unwrap().meshes[0].data.vertices);
+        assert_eq!(gltf.images.len(), 1);
+        assert_eq!(gltf.images[0].levels[0], include_bytes!("../../assets/tests/images/rgba8.rgba")[..]);
+    }
+
+    #[test]
+    fn loads_sparse_accessors_and_primitive_modes() {
+        let gltf = load("Animated.gltf").unwrap();
+        let mesh = &gltf.meshes[0];
+        // The strip, list and fan become triangle lists with a vertex per corner, and the lines are skipped.
+        let counts : Vec<u32> = mesh.data.submeshes.iter().map(|submesh| submesh.index_count).collect();
+        assert_eq!(counts, [6, 3, 6]);
+        let positions = positions(mesh);
+        let corner = |x, y, z| Vector3::new(x, y, z);
+        assert_eq!(positions[..6],
+                   [corner(0.0, 0.0, 0.0), corner(1.0, 0.0, 0.0), corner(0.0, 1.0, 0.0),
+                    corner(1.0, 0.0, 0.0), corner(1.0, 1.0, 0.0), corner(0.0, 1.0, 0.0)]);
+        // Sparse values replace those of the buffer view, or zeros without one.
+        assert_eq!(positions[6..9], [corner(0.0, 0.0, 0.0), corner(5.0, 5.0, 5.0), corner(0.0, 1.0, 0.0)]);
+        assert_eq!(positions[9..12], [corner(5.0, 5.0, 5.0), Vector3::zeros(), Vector3::zeros()]);
+        assert_eq!(positions[12..], [Vector3::zeros(); 3]);
+    }
+
+    #[test]
+    fn loads_skins_cameras_and_animations() {
+        let gltf = load("Animated.gltf").unwrap();
+        let skin = gltf.meshes[0].skin.as_ref().unwrap();
+        assert_eq!(skin.joints.len(), 15);
+        assert_eq!(skin.weights.len(), 15);
+        assert!(skin.joints[..6].iter().all(|joints| *joints == [0, 1, 0, 0]));
+        assert!(skin.weights[..6].iter().all(|weights| *weights == Vector4::new(0.5, 0.5, 0.0, 0.0)));
+        assert!(skin.joints[6..].iter().all(|joints| *joints == [0; 4]));
+
+        let scene = &gltf.scene;
+        assert_eq!(scene.nodes.len(), 4);
+        // Without scenes, every root node is shown.
+        assert_eq!(scene.roots, [0]);
+        assert_eq!(scene.nodes[3].parent, Some(2));
+        assert_eq!((scene.nodes[1].mesh, scene.nodes[1].skin, scene.nodes[3].camera), (Some(0), Some(0), Some(0)));
+        assert_eq!(scene.skins.len(), 1);
+        assert_eq!(scene.skins[0].joints, [0, 2]);
+        assert_eq!(scene.skins[0].inverse_bind_matrices[0], Matrix4::identity());
+        assert_eq!(scene.skins[0].inverse_bind_matrices[1], Matrix4::new_translation(&Vector3::new(-1.0, 0.0, 0.0)));
+        assert!(matches!(scene.cameras[0].projection, Projection::Perspective { y_fov, .. } if y_fov == 1.0));
+        assert!(matches!(scene.cameras[1].projection, Projection::Orthographic { z_far, .. } if z_far == 10.0));
+
+        assert_eq!(scene.animations.len(), 1);
+        let animation = &scene.animations[0];
+        assert_eq!(animation.name.as_deref(), Some("Move"));
+        // Morph target weights are skipped.
+        assert_eq!(animation.channels.len(), 2);
+        let rotation = &animation.channels[0];
+        assert_eq!((rotation.node, rotation.interpolation), (2, Interpolation::Linear));
+        assert_eq!(rotation.times, [0.0, 1.0, 2.0]);
+        match &rotation.values {
+            ChannelValues::Rotation(values) => {
+                let expected = Quaternion::new(FRAC_1_SQRT_2, 0.0, 0.0, FRAC_1_SQRT_2);
+                assert!((values[1].coords - expected.coords).norm() < 1e-6, "{:?}", values[1]);
+            }
+            values => panic!("{:?}", values),
+        }
+        let translation = &animation.channels[1];
+        assert_eq!((translation.node, translation.interpolation), (1, Interpolation::CubicSpline));
+        match &translation.values {
+            ChannelValues::Translation(values) => {
+                assert_eq!(values.len(), 6);
+                assert_eq!(values[4], Vector3::new(10.0, 0.0, 0.0));
+            }
+            values => panic!("{:?}", values),
+        }
+    }
+
+    #[test]
+    fn loads_external_skinned_models() {
+        let gltf = load("external/SimpleSkin.gltf").unwrap();
+        let data = &gltf.meshes[0].data;
+        // Without normals, each corner gets its own vertex for flat shading.
+        assert_eq!((data.vertices.len(), data.submeshes[0].index_count), (24, 24));
+        assert_eq!(data.vertices[2].position, Vector3::new(1.0, 0.5, 0.0));
+        assert_eq!(data.vertices[23].position, Vector3::new(0.0, 2.0, 0.0));
+        let skin = gltf.meshes[0].skin.as_ref().unwrap();
+        assert!(skin.joints.iter().all(|joints| *joints == [0, 1, 0, 0]));
+        assert_eq!(skin.weights[2], Vector4::new(0.75, 0.25, 0.0, 0.0));
+
+        let scene = &gltf.scene;
+        assert_eq!(scene.roots, [0]);
+        assert_eq!((scene.nodes[0].mesh, scene.nodes[0].skin, scene.nodes[2].parent), (Some(0), Some(0), Some(1)));
+        assert_eq!(scene.skins[0].joints, [1, 2]);
+        assert_eq!(scene.skins[0].inverse_bind_matrices[1], Matrix4::new_translation(&Vector3::new(-0.5, -1.0, 0.0)));
+        let channel = &scene.animations[0].channels[0];
+        assert_eq!((channel.node, channel.interpolation), (2, Interpolation::Linear));
+        assert_eq!((channel.times.len(), channel.times[11]), (12, 5.5));
+        match &channel.values {
+            ChannelValues::Rotation(values) => assert_eq!(values[2].coords, Vector4::new(0.0, 0.0, 0.707, 0.707)),
+            values => panic!("{:?}", values),
+        }
+
+        let gltf = Gltf::from_slice(include_bytes!("../../assets/tests/gltf/external/Fox.glb"), Path::new("")).unwrap();
+        let mesh = &gltf.meshes[0];
+        assert_eq!(mesh.name.as_deref(), Some("fox1"));
+        assert_eq!(mesh.data.vertices.len(), 1728);
+        assert!(mesh.data.indices.iter().copied().eq(0..1728));
+        let skin = mesh.skin.as_ref().unwrap();
+        assert!(skin.weights.iter().all(|weights| (weights.sum() - 1.0).abs() < 1e-3));
+        assert!(skin.joints.iter().flatten().all(|&joint| joint < 24));
+
+        assert_eq!((gltf.images[0].width, gltf.images[0].height), (1024, 1024));
+        assert_eq!(gltf.textures[0].sampler.mipmap_mode, vk::SamplerMipmapMode::LINEAR);
+        let material = &gltf.materials[0];
+        assert_eq!(material.name.as_deref(), Some("fox_material"));
+        assert_eq!(material.maps, [(PbrMaterial::BASE_COLOR_MAP, 0)]);
+        assert_eq!(material.desc.parameter(PbrMaterial::ROUGHNESS), Some(&ParameterValue::Scalar(0.58)));
+
+        let scene = &gltf.scene;
+        assert_eq!(scene.nodes.len(), 26);
+        assert_eq!(scene.skins[0].joints, (2..26).collect::<Vec<_>>());
+        let names : Vec<_> = scene.animations.iter().map(|animation| animation.name.as_deref()).collect();
+        assert_eq!(names, [Some("Survey"), Some("Walk"), Some("Run")]);
+        assert!(scene.animations.iter().all(|animation| animation.channels.len() == 21));
+        assert_eq!(scene.animations[1].channels[0].times.len(), 18);
+    }
+
+    #[test]
+    fn rejects_invalid_assets() {
+        assert!(matches!(load("InvalidExtension.gltf"), Err(GltfError::Unsupported(_))));
+        assert!(matches!(load("InvalidCycle.gltf"), Err(GltfError::Invalid(_))));
+        // The count of an accessor without a buffer view is checked before its values are allocated.
+        for name in &["InvalidView.gltf", "InvalidCount.gltf"] {
+            match load(name) {
+                Err(GltfError::Invalid(reason)) => assert!(reason.contains("does not fit"), "{}: {}", name, reason),
+                result => panic!("{}: {:?}", name, result.map(|_| ())),
+            }
+        }
+        assert!(matches!(load("Missing.gltf"), Err(GltfError::Io(..))));
+    }
+}
@@ -0,0 +1,166 @@
+use std::{collections::HashMap, sync::Arc};
+use ash::vk;
+use nalgebra::{Matrix4, Vector4};
+use super::{Gltf, GltfError};
+use super::super::{BlendMode, CmdBuffer, Device, Queue, RenderPass};
+use super::super::descriptors::DescriptorAllocator;
+use super::super::image::ImageData;
+use super::super::lighting::Lighting;
+use super::super::material::{MaterialError, MaterialInstance, MaterialTemplate, PbrDefaultTextures, PbrMaterial};
+use super::super::mesh::Mesh;
+use super::super::ring_buffer::{RingAllocation, RingBuffer};
+use super::super::scene::Scene;
+use super::super::texture::{Texture, TextureOptions};
+
+/// The transforms pushed to the PBR vertex shader before each draw.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ModelConstants {
+    model : Matrix4<f32>,
+    normal : Matrix4<f32>,
+}
+
+/// A glTF asset uploaded to the device, with a mesh for each glTF mesh and a `PbrMaterial` instance for each glTF
+/// material. Materials share a template for each combination of blending and culling they use.
+///
+/// The scene can be animated through `scene_mut`, since nodes are placed when they are drawn. Skins are imported but
+/// not applied, so skinned meshes are drawn in their bind pose.
+pub struct GltfModel {
+    scene : Scene,
+    meshes : Vec<Mesh>,
+    /// The instance of each material, followed by the default material of primitives without one.
+    materials : Vec<MaterialInstance>,
+}
+
+impl GltfModel {
+    /// Uploads the meshes and textures of an asset and creates its materials, which are drawn in `render_pass` and
+    /// lit by `lighting`. Instances allocate their descriptor sets from `allocator` and must be bound with
+    /// `ring_buffer`. Maps without a texture use `default_textures`. Blocks until the uploads are complete.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(device : Arc<Device>,
+               transfer_queue : &Queue,
+               graphics_queue : &Queue,
+               render_pass : &RenderPass,
+               lighting : &Lighting,
+               default_textures : &PbrDefaultTextures,
+               allocator : &mut DescriptorAllocator,
+               ring_buffer : &RingBuffer,
+               gltf : Gltf) -> Result<Self, GltfError> {
+        let meshes = gltf.meshes
+            .iter()
+            .map(|mesh| Mesh::new(Arc::clone(&device), transfer_queue, graphics_queue, &mesh.data))
+            .collect::<Result<_, _>>()
+            .map_err(GltfError::Mesh)?;
+
+        // glTF materials default to a white rough metal.
+        let default_desc = PbrMaterial::desc(Vector4::new(1.0, 1.0, 1.0, 1.0), 1.0, 1.0);
+        let descs = gltf.materials.iter().map(|material| &material.desc).chain(Some(&default_desc));
+        let mut templates : HashMap<(BlendMode, vk::CullModeFlags), Arc<MaterialTemplate>> = HashMap::new();
+        let mut textures : HashMap<(usize, bool), Arc<Texture>> = HashMap::new();
+        let mut materials = Vec::with_capacity(gltf.materials.len() + 1);
+        for (index, desc) in descs.enumerate() {
+            let key = (desc.render_state.blend, desc.render_state.cull_mode);
+            let template = match templates.get(&key) {
+                Some(template) => Arc::clone(template),
+                None => {
+                    let template = MaterialTemplate::from_desc(
+                        Arc::clone(&device),
+                        render_pass,
+                        desc,
+                        &[lighting.set_layout()])?;
+                    // Instances share their template through an `Arc`, even though templates stay on one thread.
+                    #[allow(clippy::arc_with_non_send_sync)]
+                    let template = Arc::new(template);
+                    Arc::clone(templates.entry(key).or_insert(template))
+                }
+            };
+            let mut instance = MaterialInstance::new(template, allocator, ring_buffer);
+            instance.apply(desc)?;
+            let maps = gltf.materials.get(index).map_or(&[][..], |material| &material.maps);
+            for (name, texture_index) in maps {
+                let options = PbrMaterial::texture_options(name);
+                // An image may be sampled as both sRGB color and linear data, which need separate textures.
+                let key = (*texture_index, options.srgb);
+                let texture = match textures.get(&key) {
+                    Some(texture) => Arc::clone(texture),
+                    None => {
+                        let texture = &gltf.textures[*texture_index];
+                        let image = &gltf.images[texture.image];
+                        let data = ImageData {
+                            width: image.width,
+                            height: image.height,
+                            format: image.format,
+                            levels: image.levels.clone(),
+                        };
+                        let options = TextureOptions { sampler: texture.sampler, ..options };
+                        let texture = Texture::from_image_data(
+                            Arc::clone(&device),
+                            transfer_queue,
+                            graphics_queue,
+                            data,
+                            options).map_err(MaterialError::from)?;
+                        Arc::clone(textures.entry(key).or_insert_with(|| Arc::new(texture)))
+                    }
+                };
+                instance.set_texture(name, texture)?;
+            }
+            default_textures.apply(&mut instance)?;
+            materials.push(instance);
+        }
+        Ok(Self { scene: gltf.scene, meshes, materials })
+    }
+
+    pub fn scene(&self) -> &Scene {
+        &self.scene
+    }
+
+    pub fn scene_mut(&mut self) -> &mut Scene {
+        &mut self.scene
+    }
+
+    pub fn meshes(&self) -> &[Mesh] {
+        &self.meshes
+    }
+
+    /// Returns the instance of each glTF material, followed by the default material.
+    pub fn materials(&self) -> &[MaterialInstance] {
+        &self.materials
+    }
+
+    /// Draws every mesh in the hierarchy of the scene's roots inside a render pass, with the frame of `lighting`
+    /// written by `Lighting::write`. Opaque submeshes are drawn before blended ones, which are not sorted by depth.
+    pub fn draw(&self,
+                cmd_buffer : &mut CmdBuffer,
+                ring_buffer : &mut RingBuffer,
+                lighting : &Lighting,
+                frame : &RingAllocation) -> Result<(), MaterialError> {
+        let world_transforms = self.scene.world_transforms();
+        let nodes = self.scene.visible_nodes();
+        for blended in &[false, true] {
+            for node in &nodes {
+                let mesh = match self.scene.nodes[*node].mesh {
+                    Some(mesh) => &self.meshes[mesh],
+                    None => continue,
+                };
+                let model = world_transforms[*node];
+                let constants = ModelConstants {
+                    model,
+                    normal: model.try_inverse().unwrap_or_else(Matrix4::identity).transpose(),
+                };
+                mesh.bind(cmd_buffer)?;
+                for (index, submesh) in mesh.submeshes().iter().enumerate() {
+                    let material = &self.materials[submesh.material.unwrap_or(self.materials.len() - 1)];
+                    let pipeline = material.template().pipeline();
+                    if (material.template().render_state().blend != BlendMode::Opaque) != *blended {
+                        continue;
+                    }
+                    material.bind(cmd_buffer, ring_buffer)?;
+                    lighting.bind(cmd_buffer, pipeline, frame)?;
+                    cmd_buffer.push_constants(pipeline, vk::ShaderStageFlags::VERTEX, 0, &constants)?;
+                    mesh.draw_submesh(cmd_buffer, index)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1066 @@
+use std::{collections::HashMap, sync::Arc};
+use ash::version::DeviceV1_0;
+use ash::vk;
+use super::{CmdBuffer, CmdRecordingError, Device, util::find_memory_type_index};
+use super::sync::{AccessInfo, AccessState, BufferAccess, ImageAccess, Transition};
+use super::deletion::DeferredObject;
+
+/// Provides a brief overview of why a render graph failed to compile.
+#[derive(Debug)]
+pub enum RenderGraphError {
+    /// A pass referenced a resource which was not created by this graph.
+    UnknownResource,
+    /// A pass reads a transient resource which no earlier pass has written. Contains the pass and resource names.
+    ReadBeforeWrite(String, String),
+    /// A resource was accessed as an image when it is a buffer, or the other way around.
+    MismatchedResourceType,
+    /// An imported resource was not bound before the graph was executed.
+    UnboundResource(String),
+    /// Creating or allocating a transient resource failed.
+    AllocationFailed,
+    /// A pass or a barrier failed to record.
+    Recording(CmdRecordingError),
+}
+
+impl From<CmdRecordingError> for RenderGraphError {
+    fn from(error : CmdRecordingError) -> Self {
+        RenderGraphError::Recording(error)
+    }
+}
+
+/// Handle to an image or buffer declared on a `RenderGraph`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ResourceId(usize);
+
+/// Handle to a pass declared on a `RenderGraph`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PassId(usize);
+
+/// Describes an image used by the graph. Transient images with equal descriptions may share the same physical image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ImageDesc {
+    pub format : vk::Format,
+    pub extent : vk::Extent2D,
+    pub mip_levels : u32,
+    pub array_layers : u32,
+    pub samples : vk::SampleCountFlags,
+}
+
+impl ImageDesc {
+    /// A single-sampled, single-level 2D image.
+    pub fn new(format : vk::Format, extent : vk::Extent2D) -> Self {
+        Self { format, extent, mip_levels: 1, array_layers: 1, samples: vk::SampleCountFlags::TYPE_1 }
+    }
+
+    /// Returns the aspect of the image which is affected by barriers and views.
+    pub fn aspect_mask(&self) -> vk::ImageAspectFlags {
+        match self.format {
+            vk::Format::D16_UNORM | vk::Format::D32_SFLOAT | vk::Format::X8_D24_UNORM_PACK32 =>
+                vk::ImageAspectFlags::DEPTH,
+            vk::Format::D16_UNORM_S8_UINT | vk::Format::D24_UNORM_S8_UINT | vk::Format::D32_SFLOAT_S8_UINT =>
+                vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL,
+            vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+            _ => vk::ImageAspectFlags::COLOR,
+        }
+    }
+
+    /// Returns a subresource range covering every level and layer of the image.
+    pub fn subresource_range(&self) -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange::builder()
+            .aspect_mask(self.aspect_mask())
+            .level_count(self.mip_levels)
+            .layer_count(self.array_layers)
+            .build()
+    }
+}
+
+/// Describes a buffer used by the graph. Transient buffers may share the same physical buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BufferDesc {
+    pub size : vk::DeviceSize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ResourceDesc {
+    Image(ImageDesc),
+    Buffer(BufferDesc),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Access {
+    Image(ImageAccess),
+    Buffer(BufferAccess),
+}
+
+impl Access {
+    fn info(self) -> AccessInfo {
+        match self {
+            Access::Image(access) => access.info(),
+            Access::Buffer(access) => access.info(),
+        }
+    }
+
+    fn is_write(self) -> bool {
+        self.info().write
+    }
+}
+
+struct ResourceNode {
+    name : String,
+    desc : ResourceDesc,
+    imported : bool,
+    initial : Option<Access>,
+    wait_stages : vk::PipelineStageFlags,
+    exported : Option<Option<Access>>,
+}
+
+/// Passed to the execute callback of a pass while the graph is being recorded.
+pub struct PassContext<'a> {
+    pub cmd_buffer : &'a mut CmdBuffer,
+    pub resources : &'a GraphResources,
+}
+
+type PassCallback<'g> = Box<dyn FnMut(&mut PassContext) -> Result<(), CmdRecordingError> + 'g>;
+
+struct PassNode<'g> {
+    name : String,
+    accesses : Vec<(ResourceId, Access)>,
+    side_effect : bool,
+    callback : PassCallback<'g>,
+}
+
+/// Declares the resources a single pass reads and writes.
+pub struct PassBuilder<'a, 'g> {
+    graph : &'a mut RenderGraph<'g>,
+    name : String,
+    accesses : Vec<(ResourceId, Access)>,
+    side_effect : bool,
+}
+
+impl<'a, 'g> PassBuilder<'a, 'g> {
+    pub fn read_image(mut self, image : ResourceId, access : ImageAccess) -> Self {
+        debug_assert!(!access.is_write());
+        self.accesses.push((image, Access::Image(access)));
+        self
+    }
+
+    pub fn write_image(mut self, image : ResourceId, access : ImageAccess) -> Self {
+        debug_assert!(access.is_write());
+        self.accesses.push((image, Access::Image(access)));
+        self
+    }
+
+    pub fn read_buffer(mut self, buffer : ResourceId, access : BufferAccess) -> Self {
+        debug_assert!(!access.is_write());
+        self.accesses.push((buffer, Access::Buffer(access)));
+        self
+    }
+
+    pub fn write_buffer(mut self, buffer : ResourceId, access : BufferAccess) -> Self {
+        debug_assert!(access.is_write());
+        self.accesses.push((buffer, Access::Buffer(access)));
+        self
+    }
+
+    /// Marks the pass as having effects outside of the graph, so it will never be culled.
+    pub fn side_effect(mut self) -> Self {
+        self.side_effect = true;
+        self
+    }
+
+    /// Finishes declaring the pass with the function used to record its commands. The function may borrow anything
+    /// which outlives the graph, since it is only called by `RenderGraph::execute`.
+    pub fn execute<F>(self, callback : F) -> PassId
+        where F : FnMut(&mut PassContext) -> Result<(), CmdRecordingError> + 'g {
+        let id = PassId(self.graph.passes.len());
+        self.graph.passes.push(PassNode {
+            name: self.name,
+            accesses: self.accesses,
+            side_effect: self.side_effect,
+            callback: Box::new(callback),
+        });
+        id
+    }
+}
+
+/// A single image or buffer barrier computed by the graph.
+#[derive(Clone, Copy, Debug)]
+pub struct ResourceBarrier {
+    pub resource : ResourceId,
+    pub transition : Transition,
+}
+
+/// Barriers which are recorded with a single `cmd_pipeline_barrier`.
+#[derive(Clone, Debug, Default)]
+pub struct BarrierBatch {
+    pub barriers : Vec<ResourceBarrier>,
+}
+
+impl BarrierBatch {
+    pub fn is_empty(&self) -> bool {
+        self.barriers.is_empty()
+    }
+
+    pub fn src_stage(&self) -> vk::PipelineStageFlags {
+        self.barriers.iter().fold(vk::PipelineStageFlags::empty(), |stage, barrier| stage | barrier.transition.src_stage)
+    }
+
+    pub fn dst_stage(&self) -> vk::PipelineStageFlags {
+        self.barriers.iter().fold(vk::PipelineStageFlags::empty(), |stage, barrier| stage | barrier.transition.dst_stage)
+    }
+}
+
+/// A physical image or buffer which one or more transient resources are aliased onto.
+#[derive(Clone, Debug)]
+pub struct PhysicalResource {
+    pub image : Option<(ImageDesc, vk::ImageUsageFlags)>,
+    pub buffer : Option<(BufferDesc, vk::BufferUsageFlags)>,
+    /// The transient resources which occupy this physical resource, in execution order.
+    pub aliases : Vec<ResourceId>,
+}
+
+/// The result of compiling a `RenderGraph`: execution order, culled passes, barriers and transient allocations.
+#[derive(Clone, Debug)]
+pub struct CompiledGraph {
+    order : Vec<PassId>,
+    culled : Vec<PassId>,
+    barriers : Vec<BarrierBatch>,
+    final_barriers : BarrierBatch,
+    physical_resources : Vec<PhysicalResource>,
+    physical_index : HashMap<ResourceId, usize>,
+    lifetimes : HashMap<ResourceId, (usize, usize)>,
+}
+
+impl CompiledGraph {
+    /// Passes in the order they will be executed.
+    pub fn order(&self) -> &[PassId] {
+        &self.order
+    }
+
+    /// Passes which do not contribute to an exported resource and have no side effects.
+    pub fn culled(&self) -> &[PassId] {
+        &self.culled
+    }
+
+    /// Barriers which are recorded before the pass at `index` in `order`.
+    pub fn barriers(&self, index : usize) -> &BarrierBatch {
+        &self.barriers[index]
+    }
+
+    /// Barriers which move exported resources into their final state after the last pass.
+    pub fn final_barriers(&self) -> &BarrierBatch {
+        &self.final_barriers
+    }
+
+    pub fn physical_resources(&self) -> &[PhysicalResource] {
+        &self.physical_resources
+    }
+
+    /// Returns the index of the physical resource a transient resource is aliased onto.
+    pub fn physical_index(&self, resource : ResourceId) -> Option<usize> {
+        self.physical_index.get(&resource).cloned()
+    }
+
+    /// Returns the first and last position in `order` where a resource is used.
+    pub fn lifetime(&self, resource : ResourceId) -> Option<(usize, usize)> {
+        self.lifetimes.get(&resource).cloned()
+    }
+}
+
+/// Composes passes from the resources they read and write. Compiling the graph orders the passes, culls the ones that
+/// do not contribute to an exported resource, aliases transient resources and computes the barriers between passes.
+///
+/// The graph is usually declared every frame, so the callbacks of its passes can borrow the state of that frame.
+pub struct RenderGraph<'g> {
+    resources : Vec<ResourceNode>,
+    passes : Vec<PassNode<'g>>,
+}
+
+impl<'g> Default for RenderGraph<'g> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'g> RenderGraph<'g> {
+    pub fn new() -> Self {
+        Self { resources: Vec::new(), passes: Vec::new() }
+    }
+
+    fn add_resource(&mut self, name : &str, desc : ResourceDesc, imported : bool, initial : Option<Access>)
+        -> ResourceId {
+        self.resources.push(ResourceNode {
+            name: name.to_string(),
+            desc,
+            imported,
+            initial,
+            wait_stages: vk::PipelineStageFlags::empty(),
+            exported: None,
+        });
+        ResourceId(self.resources.len() - 1)
+    }
+
+    /// Declares an image which only lives for the duration of the graph.
+    pub fn create_image(&mut self, name : &str, desc : ImageDesc) -> ResourceId {
+        self.add_resource(name, ResourceDesc::Image(desc), false, None)
+    }
+
+    /// Declares a buffer which only lives for the duration of the graph.
+    pub fn create_buffer(&mut self, name : &str, desc : BufferDesc) -> ResourceId {
+        self.add_resource(name, ResourceDesc::Buffer(desc), false, None)
+    }
+
+    /// Declares an image owned outside of the graph, such as a swapchain image. `initial` is the last access made to the
+    /// image before the graph executes, or `None` if its contents can be discarded.
+    pub fn import_image(&mut self, name : &str, desc : ImageDesc, initial : Option<ImageAccess>) -> ResourceId {
+        self.add_resource(name, ResourceDesc::Image(desc), true, initial.map(Access::Image))
+    }
+
+    /// Declares a buffer owned outside of the graph.
+    pub fn import_buffer(&mut self, name : &str, desc : BufferDesc, initial : Option<BufferAccess>) -> ResourceId {
+        self.add_resource(name, ResourceDesc::Buffer(desc), true, initial.map(Access::Buffer))
+    }
+
+    /// Makes the first barrier of an imported resource wait for `stages`. A resource which only becomes available once
+    /// a semaphore is signalled, such as an acquired swapchain image, is waited on at those stages by the submission,
+    /// and its first layout transition has to happen after that wait.
+    pub fn wait_for_stages(&mut self, resource : ResourceId, stages : vk::PipelineStageFlags) {
+        self.resources[resource.0].wait_stages |= stages;
+    }
+
+    /// Marks an image as an output of the graph. Passes which do not contribute to an output are culled. If `final_access`
+    /// is provided, the image is transitioned to it after the last pass, i.e. `ImageAccess::Present` for the swapchain.
+    pub fn export_image(&mut self, image : ResourceId, final_access : Option<ImageAccess>) {
+        self.resources[image.0].exported = Some(final_access.map(Access::Image));
+    }
+
+    /// Marks a buffer as an output of the graph.
+    pub fn export_buffer(&mut self, buffer : ResourceId, final_access : Option<BufferAccess>) {
+        self.resources[buffer.0].exported = Some(final_access.map(Access::Buffer));
+    }
+
+    /// Begins declaring a pass. Passes are ordered after the passes whose results they depend on.
+    pub fn add_pass(&mut self, name : &str) -> PassBuilder<'_, 'g> {
+        PassBuilder { graph: self, name: name.to_string(), accesses: Vec::new(), side_effect: false }
+    }
+
+    pub fn pass_name(&self, pass : PassId) -> &str {
+        &self.passes[pass.0].name
+    }
+
+    pub fn resource_name(&self, resource : ResourceId) -> &str {
+        &self.resources[resource.0].name
+    }
+
+    /// Validates the accesses of every pass against the declared resources.
+    fn validate(&self) -> Result<(), RenderGraphError> {
+        let mut written : Vec<bool> = self.resources
+            .iter()
+            .map(|resource| resource.imported)
+            .collect();
+        for pass in &self.passes {
+            for &(resource, access) in &pass.accesses {
+                let node = self.resources.get(resource.0).ok_or(RenderGraphError::UnknownResource)?;
+                match (node.desc, access) {
+                    (ResourceDesc::Image(_), Access::Image(_)) | (ResourceDesc::Buffer(_), Access::Buffer(_)) => (),
+                    _ => return Err(RenderGraphError::MismatchedResourceType),
+                }
+                if !access.is_write() && !written[resource.0] {
+                    return Err(RenderGraphError::ReadBeforeWrite(pass.name.clone(), node.name.clone()));
+                }
+            }
+            for &(resource, access) in &pass.accesses {
+                if access.is_write() {
+                    written[resource.0] = true;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Computes the dependencies of each pass. The first list holds read-after-write dependencies, which are the only
+    /// ones that keep a pass alive, the second holds every ordering dependency.
+    fn dependencies(&self) -> (Vec<Vec<usize>>, Vec<Vec<usize>>) {
+        let mut last_writer : Vec<Option<usize>> = vec![None; self.resources.len()];
+        let mut readers : Vec<Vec<usize>> = vec![Vec::new(); self.resources.len()];
+        let mut data = vec![Vec::new(); self.passes.len()];
+        let mut order = vec![Vec::new(); self.passes.len()];
+
+        for (index, pass) in self.passes.iter().enumerate() {
+            for &(resource, access) in &pass.accesses {
+                let writer = last_writer[resource.0].filter(|&writer| writer != index);
+                if !access.is_write() {
+                    if let Some(writer) = writer {
+                        data[index].push(writer);
+                        order[index].push(writer);
+                    }
+                } else {
+                    // Write-after-write and write-after-read only constrain the order.
+                    order[index].extend(writer);
+                    order[index].extend(readers[resource.0].iter().filter(|&&reader| reader != index));
+                }
+            }
+            for &(resource, access) in &pass.accesses {
+                if access.is_write() {
+                    last_writer[resource.0] = Some(index);
+                    readers[resource.0].clear();
+                } else {
+                    readers[resource.0].push(index);
+                }
+            }
+        }
+
+        for dependencies in data.iter_mut().chain(order.iter_mut()) {
+            dependencies.sort();
+            dependencies.dedup();
+        }
+        (data, order)
+    }
+
+    /// Returns which passes contribute to an exported resource or have side effects.
+    fn live_passes(&self, data : &[Vec<usize>]) -> Vec<bool> {
+        let mut last_writer : Vec<Option<usize>> = vec![None; self.resources.len()];
+        for (index, pass) in self.passes.iter().enumerate() {
+            for &(resource, access) in &pass.accesses {
+                if access.is_write() {
+                    last_writer[resource.0] = Some(index);
+                }
+            }
+        }
+
+        let mut live = vec![false; self.passes.len()];
+        let mut stack : Vec<usize> = self.passes
+            .iter()
+            .enumerate()
+            .filter(|(_, pass)| pass.side_effect)
+            .map(|(index, _)| index)
+            .collect();
+        stack.extend(self.resources
+            .iter()
+            .enumerate()
+            .filter(|(_, resource)| resource.exported.is_some())
+            .filter_map(|(index, _)| last_writer[index]));
+
+        while let Some(index) = stack.pop() {
+            if !live[index] {
+                live[index] = true;
+                stack.extend(data[index].iter().cloned());
+            }
+        }
+        live
+    }
+
+    /// Orders the live passes so that each pass runs after its dependencies. Among the passes that are ready, the one
+    /// declared first is picked, so independent work keeps the order it was declared in.
+    fn schedule(&self, live : &[bool], order : &[Vec<usize>]) -> Vec<usize> {
+        let mut remaining : Vec<usize> = order
+            .iter()
+            .map(|dependencies| dependencies.iter().filter(|&&dependency| live[dependency]).count())
+            .collect();
+        let mut dependents = vec![Vec::new(); self.passes.len()];
+        for (index, dependencies) in order.iter().enumerate() {
+            for &dependency in dependencies {
+                dependents[dependency].push(index);
+            }
+        }
+
+        let mut ready : Vec<usize> = (0..self.passes.len())
+            .filter(|&index| live[index] && remaining[index] == 0)
+            .collect();
+        let mut scheduled = Vec::new();
+        while !ready.is_empty() {
+            ready.sort_by(|a, b| b.cmp(a));
+            let index = ready.pop().unwrap();
+            scheduled.push(index);
+            for &dependent in &dependents[index] {
+                if live[dependent] {
+                    remaining[dependent] -= 1;
+                    if remaining[dependent] == 0 {
+                        ready.push(dependent);
+                    }
+                }
+            }
+        }
+        scheduled
+    }
+
+    /// Assigns each transient resource to a physical resource, reusing physical resources whose previous occupant is no
+    /// longer used by the time the next one is first used.
+    fn allocate(&self, scheduled : &[usize], lifetimes : &HashMap<ResourceId, (usize, usize)>)
+        -> (Vec<PhysicalResource>, HashMap<ResourceId, usize>) {
+        let mut transients : Vec<(ResourceId, (usize, usize))> = lifetimes
+            .iter()
+            .filter(|(resource, _)| !self.resources[resource.0].imported)
+            .map(|(&resource, &lifetime)| (resource, lifetime))
+            .collect();
+        transients.sort_by_key(|&(resource, (first, _))| (first, resource.0));
+
+        let mut physical_resources : Vec<PhysicalResource> = Vec::new();
+        let mut physical_last_use : Vec<usize> = Vec::new();
+        let mut physical_index = HashMap::new();
+        for (resource, (first, last)) in transients {
+            let desc = self.resources[resource.0].desc;
+            let mut image_usage = vk::ImageUsageFlags::empty();
+            let mut buffer_usage = vk::BufferUsageFlags::empty();
+            for &pass in scheduled {
+                for &(accessed, access) in &self.passes[pass].accesses {
+                    if accessed == resource {
+                        match access {
+                            Access::Image(access) => image_usage |= access.usage(),
+                            Access::Buffer(access) => buffer_usage |= access.usage(),
+                        }
+                    }
+                }
+            }
+
+            let slot = (0..physical_resources.len()).find(|&slot| {
+                physical_last_use[slot] < first && match (&physical_resources[slot], desc) {
+                    (PhysicalResource { image: Some((image, _)), .. }, ResourceDesc::Image(desc)) => *image == desc,
+                    (PhysicalResource { buffer: Some(_), .. }, ResourceDesc::Buffer(_)) => true,
+                    _ => false,
+                }
+            });
+            let slot = match slot {
+                Some(slot) => slot,
+                None => {
+                    physical_resources.push(PhysicalResource { image: None, buffer: None, aliases: Vec::new() });
+                    physical_last_use.push(0);
+                    physical_resources.len() - 1
+                }
+            };
+
+            let physical = &mut physical_resources[slot];
+            match desc {
+                ResourceDesc::Image(desc) => {
+                    let usage = physical.image.map(|(_, usage)| usage).unwrap_or_default();
+                    physical.image = Some((desc, usage | image_usage));
+                }
+                ResourceDesc::Buffer(desc) => {
+                    let (size, usage) = physical.buffer
+                        .map(|(buffer, usage)| (buffer.size, usage))
+                        .unwrap_or_default();
+                    physical.buffer = Some((BufferDesc { size: size.max(desc.size) }, usage | buffer_usage));
+                }
+            }
+            physical.aliases.push(resource);
+            physical_last_use[slot] = last;
+            physical_index.insert(resource, slot);
+        }
+        (physical_resources, physical_index)
+    }
+
+    /// Orders, culls and allocates the graph, and computes the barriers required between passes.
+    pub fn compile(&self) -> Result<CompiledGraph, RenderGraphError> {
+        self.validate()?;
+        let (data, order) = self.dependencies();
+        let live = self.live_passes(&data);
+        let scheduled = self.schedule(&live, &order);
+
+        let mut lifetimes : HashMap<ResourceId, (usize, usize)> = HashMap::new();
+        for (position, &pass) in scheduled.iter().enumerate() {
+            for &(resource, _) in &self.passes[pass].accesses {
+                let lifetime = lifetimes.entry(resource).or_insert((position, position));
+                lifetime.1 = position;
+            }
+        }
+        let (physical_resources, physical_index) = self.allocate(&scheduled, &lifetimes);
+
+        // Imported resources start in the state they were imported with, transient ones start undefined. An aliased
+        // resource also has to wait for the previous occupant of its physical resource to be finished with, by the
+        // passes which were not culled.
+        let mut states : Vec<AccessState> = self.resources
+            .iter()
+            .map(|resource| {
+                let mut state = resource.initial
+                    .map(|access| AccessState::from_access(access.info()))
+                    .unwrap_or_else(AccessState::undefined);
+                state.wait_for_stages(resource.wait_stages);
+                state
+            })
+            .collect();
+        for physical in &physical_resources {
+            for pair in physical.aliases.windows(2) {
+                let previous_stages = scheduled
+                    .iter()
+                    .flat_map(|&pass| self.passes[pass].accesses.iter())
+                    .filter(|(resource, _)| *resource == pair[0])
+                    .fold(vk::PipelineStageFlags::empty(), |stages, (_, access)| stages | access.info().stage);
+                states[pair[1].0].wait_for_stages(previous_stages);
+            }
+        }
+
+        let mut barriers = Vec::new();
+        for &pass in &scheduled {
+            let mut batch = BarrierBatch::default();
+            for (resource, access) in self.merged_accesses(pass) {
+                if let Some(transition) = states[resource.0].transition(access.info()) {
+                    batch.barriers.push(ResourceBarrier { resource, transition });
+                }
+            }
+            barriers.push(batch);
+        }
+
+        let mut final_barriers = BarrierBatch::default();
+        for (index, resource) in self.resources.iter().enumerate() {
+            if let Some(Some(access)) = resource.exported {
+                let id = ResourceId(index);
+                if let Some(transition) = states[index].transition(access.info()) {
+                    final_barriers.barriers.push(ResourceBarrier { resource: id, transition });
+                }
+            }
+        }
+
+        Ok(CompiledGraph {
+            order: scheduled.iter().map(|&index| PassId(index)).collect(),
+            culled: (0..self.passes.len()).filter(|&index| !live[index]).map(PassId).collect(),
+            barriers,
+            final_barriers,
+            physical_resources,
+            physical_index,
+            lifetimes,
+        })
+    }
+
+    /// Combines multiple accesses of the same resource within a pass into one, with writes taking precedence.
+    fn merged_accesses(&self, pass : usize) -> Vec<(ResourceId, Access)> {
+        let mut merged : Vec<(ResourceId, Access)> = Vec::new();
+        for &(resource, access) in &self.passes[pass].accesses {
+            match merged.iter_mut().find(|(merged_resource, _)| *merged_resource == resource) {
+                Some(entry) => if access.is_write() { entry.1 = access },
+                None => merged.push((resource, access)),
+            }
+        }
+        merged
+    }
+
+    /// Records the compiled graph into a command buffer which is recording outside of a render pass, calling each pass
+    /// in order with its barriers recorded before it.
+    pub fn execute(&mut self,
+                   compiled : &CompiledGraph,
+                   cmd_buffer : &mut CmdBuffer,
+                   resources : &GraphResources) -> Result<(), RenderGraphError> {
+        for (index, resource) in self.resources.iter().enumerate() {
+            if resource.imported && compiled.lifetimes.contains_key(&ResourceId(index))
+                && !resources.is_bound(ResourceId(index)) {
+                return Err(RenderGraphError::UnboundResource(resource.name.clone()));
+            }
+        }
+
+        for (position, pass) in compiled.order.iter().enumerate() {
+            self.record_barriers(compiled.barriers(position), cmd_buffer, resources)?;
+            let mut context = PassContext { cmd_buffer: &mut *cmd_buffer, resources };
+            (self.passes[pass.0].callback)(&mut context)?;
+        }
+        self.record_barriers(compiled.final_barriers(), cmd_buffer, resources)
+    }
+
+    fn record_barriers(&self, batch : &BarrierBatch, cmd_buffer : &mut CmdBuffer, resources : &GraphResources)
+        -> Result<(), RenderGraphError> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let mut image_barriers = Vec::new();
+        let mut buffer_barriers = Vec::new();
+        for barrier in &batch.barriers {
+            match self.resources[barrier.resource.0].desc {
+                ResourceDesc::Image(desc) => image_barriers.push(vk::ImageMemoryBarrier::builder()
+                    .image(resources.image(barrier.resource))
+                    .subresource_range(desc.subresource_range())
+                    .src_access_mask(barrier.transition.src_access)
+                    .dst_access_mask(barrier.transition.dst_access)
+                    .old_layout(barrier.transition.old_layout)
+                    .new_layout(barrier.transition.new_layout)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .build()),
+                ResourceDesc::Buffer(_) => buffer_barriers.push(vk::BufferMemoryBarrier::builder()
+                    .buffer(resources.buffer(barrier.resource))
+                    .size(vk::WHOLE_SIZE)
+                    .src_access_mask(barrier.transition.src_access)
+                    .dst_access_mask(barrier.transition.dst_access)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .build()),
+            }
+        }
+        cmd_buffer.pipeline_barrier(
+            batch.src_stage(),
+            batch.dst_stage(),
+            buffer_barriers.as_slice(),
+            image_barriers.as_slice())?;
+        Ok(())
+    }
+}
+
+/// Owns the physical resources of a compiled graph and the handles of any imported resources.
+pub struct GraphResources {
+    device : Arc<Device>,
+    images : HashMap<ResourceId, (vk::Image, vk::ImageView)>,
+    buffers : HashMap<ResourceId, vk::Buffer>,
+    owned_images : Vec<(vk::Image, vk::ImageView)>,
+    owned_buffers : Vec<vk::Buffer>,
+    owned_memory : Vec<vk::DeviceMemory>,
+}
+
+impl Drop for GraphResources {
+    fn drop(&mut self) {
+        for (image, view) in self.owned_images.drain(..) {
+            self.device.destroy_deferred(DeferredObject::ImageView(view));
+            self.device.destroy_deferred(DeferredObject::Image(image));
+        }
+        for buffer in self.owned_buffers.drain(..) {
+            self.device.destroy_deferred(DeferredObject::Buffer(buffer));
+        }
+        for memory in self.owned_memory.drain(..) {
+            self.device.destroy_deferred(DeferredObject::Memory(memory));
+        }
+        info!("Dropped GraphResources")
+    }
+}
+
+impl GraphResources {
+    /// Creates the physical resources required by the compiled graph. Imported resources have to be bound afterwards.
+    pub fn new(device : Arc<Device>, compiled : &CompiledGraph) -> Result<Self, RenderGraphError> {
+        let mut resources = Self {
+            device,
+            images: HashMap::new(),
+            buffers: HashMap::new(),
+            owned_images: Vec::new(),
+            owned_buffers: Vec::new(),
+            owned_memory: Vec::new(),
+        };
+
+        for physical in compiled.physical_resources() {
+            if let Some((desc, usage)) = physical.image {
+                let image = resources.create_image(desc, usage)?;
+                for &alias in &physical.aliases {
+                    resources.images.insert(alias, image);
+                }
+            }
+            if let Some((desc, usage)) = physical.buffer {
+                let buffer = resources.create_buffer(desc, usage)?;
+                for &alias in &physical.aliases {
+                    resources.buffers.insert(alias, buffer);
+                }
+            }
+        }
+        Ok(resources)
+    }
+
+    fn allocate(&mut self, memory_requirements : vk::MemoryRequirements) -> Result<vk::DeviceMemory, RenderGraphError> {
+        let device = &self.device;
+        let memory_index = find_memory_type_index(
+            &memory_requirements,
+            &device.memory_properties(),
+            vk::MemoryPropertyFlags::DEVICE_LOCAL)
+            .ok_or(RenderGraphError::AllocationFailed)?;
+        let allocate_info = vk::MemoryAllocateInfo::builder()
+            .memory_type_index(memory_index)
+            .allocation_size(memory_requirements.size);
+        let memory = unsafe {
+            device
+                .ash_device()
+                .allocate_memory(&allocate_info, None)
+                .map_err(|_| RenderGraphError::AllocationFailed)?
+        };
+        self.owned_memory.push(memory);
+        Ok(memory)
+    }
+
+    fn create_image(&mut self, desc : ImageDesc, usage : vk::ImageUsageFlags)
+        -> Result<(vk::Image, vk::ImageView), RenderGraphError> {
+        let image_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(desc.format)
+            .extent(vk::Extent3D { width: desc.extent.width, height: desc.extent.height, depth: 1 })
+            .mip_levels(desc.mip_levels)
+            .array_layers(desc.array_layers)
+            .samples(desc.samples)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+        let (image, memory_requirements) = unsafe {
+            let device = &self.device;
+            let image = device
+                .ash_device()
+                .create_image(&image_info, None)
+                .map_err(|_| RenderGraphError::AllocationFailed)?;
+            (image, device.ash_device().get_image_memory_requirements(image))
+        };
+        let memory = self.allocate(memory_requirements)?;
+
+        let view_type = if desc.array_layers > 1 { vk::ImageViewType::TYPE_2D_ARRAY } else { vk::ImageViewType::TYPE_2D };
+        let view_info = vk::ImageViewCreateInfo::builder()
+            .image(image)
+            .format(desc.format)
+            .view_type(view_type)
+            .subresource_range(desc.subresource_range());
+        let view = unsafe {
+            let device = &self.device;
+            device
+                .ash_device()
+                .bind_image_memory(image, memory, 0)
+                .map_err(|_| RenderGraphError::AllocationFailed)?;
+            device
+                .ash_device()
+                .create_image_view(&view_info, None)
+                .map_err(|_| RenderGraphError::AllocationFailed)?
+        };
+        self.owned_images.push((image, view));
+        Ok((image, view))
+    }
+
+    fn create_buffer(&mut self, desc : BufferDesc, usage : vk::BufferUsageFlags) -> Result<vk::Buffer, RenderGraphError> {
+        let buffer_info = vk::BufferCreateInfo::builder()
+            .size(desc.size)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let (buffer, memory_requirements) = unsafe {
+            let device = &self.device;
+            let buffer = device
+                .ash_device()
+                .create_buffer(&buffer_info, None)
+                .map_err(|_| RenderGraphError::AllocationFailed)?;
+            (buffer, device.ash_device().get_buffer_memory_requirements(buffer))
+        };
+        let memory = self.allocate(memory_requirements)?;
+        unsafe {
+            self.device
+                .ash_device()
+                .bind_buffer_memory(buffer, memory, 0)
+                .map_err(|_| RenderGraphError::AllocationFailed)?;
+        }
+        self.owned_buffers.push(buffer);
+        Ok(buffer)
+    }
+
+    /// Binds an imported image, such as the swapchain image acquired for this frame.
+    pub fn bind_image(&mut self, resource : ResourceId, image : vk::Image, view : vk::ImageView) {
+        self.images.insert(resource, (image, view));
+    }
+
+    /// Binds an imported buffer.
+    pub fn bind_buffer(&mut self, resource : ResourceId, buffer : vk::Buffer) {
+        self.buffers.insert(resource, buffer);
+    }
+
+    pub fn is_bound(&self, resource : ResourceId) -> bool {
+        self.images.contains_key(&resource) || self.buffers.contains_key(&resource)
+    }
+
+    pub fn image(&self, resource : ResourceId) -> vk::Image {
+        self.images[&resource].0
+    }
+
+    pub fn image_view(&self, resource : ResourceId) -> vk::ImageView {
+        self.images[&resource].1
+    }
+
+    pub fn buffer(&self, resource : ResourceId) -> vk::Buffer {
+        self.buffers[&resource]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn color_desc() -> ImageDesc {
+        ImageDesc::new(vk::Format::R8G8B8A8_UNORM, vk::Extent2D { width: 64, height: 64 })
+    }
+
+    /// A pass which records nothing, since compiling never calls it.
+    fn empty(_ : &mut PassContext) -> Result<(), CmdRecordingError> {
+        Ok(())
+    }
+
+    #[test]
+    fn culls_passes_without_exported_results() {
+        let mut graph = RenderGraph::new();
+        let backbuffer = graph.import_image("backbuffer", color_desc(), None);
+        let unused = graph.create_image("unused", color_desc());
+        let debug = graph.create_image("debug", color_desc());
+        graph.export_image(backbuffer, Some(ImageAccess::Present));
+        let write_unused = graph.add_pass("write unused")
+            .write_image(unused, ImageAccess::ColorAttachmentWrite)
+            .execute(empty);
+        let read_unused = graph.add_pass("read unused")
+            .read_image(unused, ImageAccess::FragmentShaderRead)
+            .write_image(debug, ImageAccess::ColorAttachmentWrite)
+            .execute(empty);
+        let upload = graph.add_pass("upload")
+            .side_effect()
+            .execute(empty);
+        let scene = graph.add_pass("scene")
+            .write_image(backbuffer, ImageAccess::ColorAttachmentWrite)
+            .execute(empty);
+
+        let compiled = graph.compile().unwrap();
+        assert_eq!(compiled.culled(), &[write_unused, read_unused]);
+        assert_eq!(compiled.order(), &[upload, scene]);
+        assert_eq!(compiled.lifetime(unused), None);
+        assert!(compiled.physical_resources().is_empty());
+    }
+
+    #[test]
+    fn orders_passes_after_their_dependencies() {
+        let mut graph = RenderGraph::new();
+        let backbuffer = graph.import_image("backbuffer", color_desc(), None);
+        let shadow = graph.create_image("shadow", ImageDesc::new(
+            vk::Format::D32_SFLOAT,
+            vk::Extent2D { width: 64, height: 64 }));
+        let lit = graph.create_image("lit", color_desc());
+        graph.export_image(backbuffer, Some(ImageAccess::Present));
+        let shadow_pass = graph.add_pass("shadow")
+            .write_image(shadow, ImageAccess::DepthStencilWrite)
+            .execute(empty);
+        let lighting = graph.add_pass("lighting")
+            .read_image(shadow, ImageAccess::FragmentShaderRead)
+            .write_image(lit, ImageAccess::ColorAttachmentWrite)
+            .execute(empty);
+        let post = graph.add_pass("post")
+            .read_image(lit, ImageAccess::FragmentShaderRead)
+            .write_image(backbuffer, ImageAccess::ColorAttachmentWrite)
+            .execute(empty);
+
+        let compiled = graph.compile().unwrap();
+        assert!(compiled.culled().is_empty());
+        assert_eq!(compiled.order(), &[shadow_pass, lighting, post]);
+        assert_eq!(compiled.lifetime(shadow), Some((0, 1)));
+        assert_eq!(compiled.lifetime(lit), Some((1, 2)));
+    }
+
+    #[test]
+    fn rejects_reads_before_writes() {
+        let mut graph = RenderGraph::new();
+        let image = graph.create_image("image", color_desc());
+        graph.add_pass("read")
+            .read_image(image, ImageAccess::FragmentShaderRead)
+            .side_effect()
+            .execute(empty);
+        match graph.compile() {
+            Err(RenderGraphError::ReadBeforeWrite(pass, resource)) => {
+                assert_eq!(pass, "read");
+                assert_eq!(resource, "image");
+            }
+            other => panic!("expected a read before write, got {:?}", other.map(|compiled| compiled.order().to_vec())),
+        }
+    }
+
+    #[test]
+    fn aliases_transients_with_disjoint_lifetimes() {
+        let mut graph = RenderGraph::new();
+        let backbuffer = graph.import_image("backbuffer", color_desc(), None);
+        let first = graph.create_image("first", color_desc());
+        let second = graph.create_image("second", color_desc());
+        let overlapping = graph.create_image("overlapping", color_desc());
+        let discarded = graph.create_buffer("discarded", BufferDesc { size: 256 });
+        graph.export_image(backbuffer, Some(ImageAccess::Present));
+        graph.add_pass("write first")
+            .write_image(first, ImageAccess::ColorAttachmentWrite)
+            .execute(empty);
+        graph.add_pass("read first")
+            .read_image(first, ImageAccess::FragmentShaderRead)
+            .write_image(overlapping, ImageAccess::ColorAttachmentWrite)
+            .execute(empty);
+        // Culled, so its compute read of `first` must not delay the reuse of its memory.
+        graph.add_pass("culled")
+            .read_image(first, ImageAccess::ComputeShaderRead)
+            .write_buffer(discarded, BufferAccess::ComputeShaderWrite)
+            .execute(empty);
+        graph.add_pass("write second")
+            .read_image(overlapping, ImageAccess::FragmentShaderRead)
+            .write_image(second, ImageAccess::ColorAttachmentWrite)
+            .execute(empty);
+        graph.add_pass("read second")
+            .read_image(second, ImageAccess::FragmentShaderRead)
+            .write_image(backbuffer, ImageAccess::ColorAttachmentWrite)
+            .execute(empty);
+
+        let compiled = graph.compile().unwrap();
+        assert_eq!(compiled.order().len(), 4);
+        assert_eq!(compiled.physical_resources().len(), 2);
+        assert_eq!(compiled.physical_index(first), compiled.physical_index(second));
+        assert_ne!(compiled.physical_index(first), compiled.physical_index(overlapping));
+        assert_eq!(compiled.physical_index(discarded), None);
+        let physical = &compiled.physical_resources()[compiled.physical_index(first).unwrap()];
+        assert_eq!(physical.aliases, vec![first, second]);
+        assert_eq!(
+            physical.image.unwrap().1,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED);
+
+        // The second occupant waits for every stage the first was used in by the passes which were not culled.
+        let barrier = compiled.barriers(2).barriers
+            .iter()
+            .find(|barrier| barrier.resource == second)
+            .unwrap();
+        assert_eq!(
+            barrier.transition.src_stage,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::FRAGMENT_SHADER);
+        assert_eq!(barrier.transition.old_layout, vk::ImageLayout::UNDEFINED);
+    }
+
+    #[test]
+    fn computes_barriers_between_passes() {
+        let mut graph = RenderGraph::new();
+        let backbuffer = graph.import_image("backbuffer", color_desc(), None);
+        graph.wait_for_stages(backbuffer, vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT);
+        let particles = graph.import_buffer("particles", BufferDesc { size: 1024 }, Some(BufferAccess::ShaderRead));
+        let scene = graph.create_image("scene", color_desc());
+        graph.export_image(backbuffer, Some(ImageAccess::Present));
+        graph.add_pass("simulate")
+            .write_buffer(particles, BufferAccess::ComputeShaderWrite)
+            .execute(empty);
+        graph.add_pass("draw")
+            .read_buffer(particles, BufferAccess::ShaderRead)
+            .write_image(scene, ImageAccess::ColorAttachmentWrite)
+            .execute(empty);
+        graph.add_pass("compose")
+            .read_image(scene, ImageAccess::FragmentShaderRead)
+            .write_image(backbuffer, ImageAccess::ColorAttachmentWrite)
+            .execute(empty);
+
+        let compiled = graph.compile().unwrap();
+        let transition = |index : usize, resource : ResourceId| compiled.barriers(index).barriers
+            .iter()
+            .find(|barrier| barrier.resource == resource)
+            .map(|barrier| barrier.transition);
+
+        // The simulation overwrites particles which were read by the previous frame.
+        let simulate = transition(0, particles).unwrap();
+        assert_eq!(simulate.src_stage, BufferAccess::ShaderRead.info().stage);
+        assert_eq!(simulate.src_access, vk::AccessFlags::empty());
+        assert_eq!(simulate.dst_stage, vk::PipelineStageFlags::COMPUTE_SHADER);
+
+        // The draw waits for the simulation, and the scene image is transitioned for rendering.
+        let draw = transition(1, particles).unwrap();
+        assert_eq!(draw.src_stage, vk::PipelineStageFlags::COMPUTE_SHADER);
+        assert_eq!(draw.src_access, vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+        assert_eq!(draw.dst_access, vk::AccessFlags::SHADER_READ);
+        let scene_write = transition(1, scene).unwrap();
+        assert_eq!(scene_write.src_stage, vk::PipelineStageFlags::TOP_OF_PIPE);
+        assert_eq!(scene_write.new_layout, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+
+        // The composition samples the scene and renders to the backbuffer once it has been acquired.
+        let scene_read = transition(2, scene).unwrap();
+        assert_eq!(scene_read.src_stage, vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT);
+        assert_eq!(
+            scene_read.src_access,
+            vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE);
+        assert_eq!(scene_read.dst_stage, vk::PipelineStageFlags::FRAGMENT_SHADER);
+        assert_eq!(scene_read.old_layout, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        assert_eq!(scene_read.new_layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        let acquire = transition(2, backbuffer).unwrap();
+        assert_eq!(acquire.src_stage, vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT);
+        assert_eq!(acquire.old_layout, vk::ImageLayout::UNDEFINED);
+        assert_eq!(acquire.new_layout, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+
+        let present = compiled.final_barriers();
+        assert_eq!(present.barriers.len(), 1);
+        assert_eq!(present.barriers[0].resource, backbuffer);
+        assert_eq!(present.barriers[0].transition.old_layout, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        assert_eq!(present.barriers[0].transition.new_layout, vk::ImageLayout::PRESENT_SRC_KHR);
+        assert_eq!(present.dst_stage(), vk::PipelineStageFlags::BOTTOM_OF_PIPE);
+    }
+}
@@ -0,0 +1,589 @@
+use std::convert::TryInto;
+
+/// The number of levels values may be quantized to in integer sequences, in increasing order.
+const QUANT_LEVELS : [u32; 21] = [2, 3, 4, 5, 6, 8, 10, 12, 16, 20, 24, 32, 40, 48, 64, 80, 96, 128, 160, 192, 256];
+
+/// The color of every texel of a block which is malformed or uses HDR endpoints, as decoded by the LDR profile.
+const ERROR_COLOR : [u8; 4] = [255, 0, 255, 255];
+
+/// How each value of an integer sequence is stored: as low bits with a trit or quint, or as bits alone.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Bits,
+    Trits,
+    Quints,
+}
+
+/// Returns how values with `levels` levels are encoded, and the number of low bits each is stored with.
+fn encoding(levels : u32) -> (Encoding, u32) {
+    if levels.is_multiple_of(3) {
+        (Encoding::Trits, (levels / 3).trailing_zeros())
+    } else if levels.is_multiple_of(5) {
+        (Encoding::Quints, (levels / 5).trailing_zeros())
+    } else {
+        (Encoding::Bits, levels.trailing_zeros())
+    }
+}
+
+/// Returns the number of bits an integer sequence of `count` values with `levels` levels occupies.
+fn sequence_bits(count : u32, levels : u32) -> u32 {
+    let (encoding, bits) = encoding(levels);
+    count * bits + match encoding {
+        Encoding::Bits => 0,
+        Encoding::Trits => (8 * count).div_ceil(5),
+        Encoding::Quints => (7 * count).div_ceil(3),
+    }
+}
+
+/// Returns the low `length` bits of `bits`.
+fn low_bits(bits : u128, length : u32) -> u128 {
+    if length >= 128 { bits } else { bits & ((1 << length) - 1) }
+}
+
+/// Unpacks the five trits packed into 8 bits.
+fn decode_trits(packed : u32) -> [u32; 5] {
+    let bit = |value : u32, index : u32| (value >> index) & 1;
+    let (c, t4, t3);
+    if (packed >> 2) & 7 == 7 {
+        c = ((packed >> 5) & 7) << 2 | (packed & 3);
+        t4 = 2;
+        t3 = 2;
+    } else {
+        c = packed & 31;
+        if (packed >> 5) & 3 == 3 {
+            t4 = 2;
+            t3 = bit(packed, 7);
+        } else {
+            t4 = bit(packed, 7);
+            t3 = (packed >> 5) & 3;
+        }
+    }
+    let (t2, t1, t0);
+    if c & 3 == 3 {
+        t2 = 2;
+        t1 = bit(c, 4);
+        t0 = bit(c, 3) << 1 | (bit(c, 2) & !bit(c, 3) & 1);
+    } else if (c >> 2) & 3 == 3 {
+        t2 = 2;
+        t1 = 2;
+        t0 = c & 3;
+    } else {
+        t2 = bit(c, 4);
+        t1 = (c >> 2) & 3;
+        t0 = bit(c, 1) << 1 | (bit(c, 0) & !bit(c, 1) & 1);
+    }
+    [t0, t1, t2, t3, t4]
+}
+
+/// Unpacks the three quints packed into 7 bits.
+fn decode_quints(packed : u32) -> [u32; 3] {
+    let bit = |value : u32, index : u32| (value >> index) & 1;
+    if (packed >> 1) & 3 == 3 && (packed >> 5) & 3 == 0 {
+        let low = bit(packed, 0);
+        let q2 = low << 2 | (bit(packed, 4) & !low & 1) << 1 | (bit(packed, 3) & !low & 1);
+        return [4, 4, q2];
+    }
+    let (q2, c);
+    if (packed >> 1) & 3 == 3 {
+        q2 = 4;
+        c = ((packed >> 3) & 3) << 3 | (!(packed >> 5) & 3) << 1 | (packed & 1);
+    } else {
+        q2 = (packed >> 5) & 3;
+        c = packed & 31;
+    }
+    if c & 7 == 5 {
+        [(c >> 3) & 3, 4, q2]
+    } else {
+        [c & 7, (c >> 3) & 3, q2]
+    }
+}
+
+/// Decodes `count` values with `levels` levels from an integer sequence starting at the lowest bit of `bits`. Bits
+/// past the end of the sequence must be zero, as the last block of trits or quints may be cut short.
+fn decode_sequence(bits : u128, count : usize, levels : u32) -> Vec<u32> {
+    let (encoding, bit_count) = encoding(levels);
+    let mut position = 0;
+    let mut read = |length : u32| {
+        let value = if position >= 128 { 0 } else { (bits >> position) as u32 & ((1 << length) - 1) };
+        position += length;
+        value
+    };
+    let mut values = Vec::with_capacity(count + 4);
+    while values.len() < count {
+        match encoding {
+            Encoding::Bits => values.push(read(bit_count)),
+            Encoding::Trits => {
+                let mut low = [0; 5];
+                let mut packed = 0;
+                for (i, (offset, length)) in [(0, 2), (2, 2), (4, 1), (5, 2), (7, 1)].iter().enumerate() {
+                    low[i] = read(bit_count);
+                    packed |= read(*length) << offset;
+                }
+                let trits = decode_trits(packed);
+                values.extend(trits.iter().zip(low.iter()).map(|(trit, low)| trit << bit_count | low));
+            },
+            Encoding::Quints => {
+                let mut low = [0; 3];
+                let mut packed = 0;
+                for (i, (offset, length)) in [(0, 3), (3, 2), (5, 2)].iter().enumerate() {
+                    low[i] = read(bit_count);
+                    packed |= read(*length) << offset;
+                }
+                let quints = decode_quints(packed);
+                values.extend(quints.iter().zip(low.iter()).map(|(quint, low)| quint << bit_count | low));
+            },
+        }
+    }
+    values.truncate(count);
+    values
+}
+
+/// Repeats the `bits` low bits of `value` until they fill `length` bits.
+fn replicate(value : u32, bits : u32, length : u32) -> u32 {
+    let (mut result, mut filled) = (0, 0);
+    while filled < length {
+        result = result << bits | value;
+        filled += bits;
+    }
+    result >> (filled - length)
+}
+
+/// Scales a quantized color endpoint value to 8 bits. Trits and quints are spread over the range with their low bit
+/// mirroring it, so values are not in increasing order.
+fn unquantize_color(value : u32, levels : u32) -> i32 {
+    let (encoding, bits) = encoding(levels);
+    if encoding == Encoding::Bits {
+        return replicate(value, bits, 8) as i32;
+    }
+    let (digit, low) = (value >> bits, value & ((1 << bits) - 1));
+    let x = low >> 1;
+    let (scale, pattern) = match (encoding, bits) {
+        (Encoding::Trits, 1) => (204, 0),
+        (Encoding::Quints, 1) => (113, 0),
+        (Encoding::Trits, 2) => (93, x * 0x116),
+        (Encoding::Quints, 2) => (54, x * 0x10c),
+        (Encoding::Trits, 3) => (44, x << 7 | x << 2 | x),
+        (Encoding::Quints, 3) => (26, x << 7 | x << 1 | x >> 1),
+        (Encoding::Trits, 4) => (22, x << 6 | x),
+        (Encoding::Quints, 4) => (13, x << 6 | x >> 1),
+        (Encoding::Trits, 5) => (11, x << 5 | x >> 2),
+        (Encoding::Quints, 5) => (6, x << 5 | x >> 3),
+        _ => (5, x << 4 | x >> 4),
+    };
+    let mask = if low & 1 == 1 { 0x1ff } else { 0 };
+    ((mask & 0x80) | ((digit * scale + pattern) ^ mask) >> 2) as i32
+}
+
+/// Scales a quantized weight to the range 0 to 64.
+fn unquantize_weight(value : u32, levels : u32) -> u32 {
+    let (encoding, bits) = encoding(levels);
+    let weight = match (encoding, bits) {
+        (Encoding::Bits, _) => replicate(value, bits, 6),
+        (Encoding::Trits, 0) => [0, 32, 63][value as usize],
+        (Encoding::Quints, 0) => [0, 16, 32, 47, 63][value as usize],
+        _ => {
+            let (digit, low) = (value >> bits, value & ((1 << bits) - 1));
+            let x = low >> 1;
+            let (scale, pattern) = match (encoding, bits) {
+                (Encoding::Trits, 1) => (50, 0),
+                (Encoding::Quints, 1) => (28, 0),
+                (Encoding::Trits, 2) => (23, x * 0x45),
+                (Encoding::Quints, 2) => (13, x * 0x42),
+                _ => (11, x << 5 | x),
+            };
+            let mask = if low & 1 == 1 { 0x7f } else { 0 };
+            (mask & 0x20) | ((digit * scale + pattern) ^ mask) >> 2
+        },
+    };
+    if weight > 32 { weight + 1 } else { weight }
+}
+
+/// The weight grid described by the block mode.
+struct WeightGrid {
+    width : usize,
+    height : usize,
+    dual_plane : bool,
+    levels : u32,
+}
+
+/// Decodes the 11-bit block mode, returning `None` for reserved modes.
+fn block_mode(mode : u32) -> Option<WeightGrid> {
+    let field = |start : u32, length : u32| ((mode >> start) & ((1 << length) - 1)) as usize;
+    let (mut high_precision, mut dual_plane) = (field(9, 1) == 1, field(10, 1) == 1);
+    let a = field(5, 2);
+    let (range, width, height);
+    if mode & 3 != 0 {
+        range = field(4, 1) | field(0, 2) << 1;
+        let b = field(7, 2);
+        (width, height) = match field(2, 2) {
+            0 => (b + 4, a + 2),
+            1 => (b + 8, a + 2),
+            2 => (a + 2, b + 8),
+            _ if field(8, 1) == 1 => ((b & 1) + 2, a + 2),
+            _ => (a + 2, (b & 1) + 6),
+        };
+    } else {
+        range = field(4, 1) | field(2, 2) << 1;
+        if field(2, 2) == 0 {
+            return None;
+        }
+        (width, height) = match field(7, 2) {
+            0 => (12, a + 2),
+            1 => (a + 2, 12),
+            2 => {
+                high_precision = false;
+                dual_plane = false;
+                (a + 6, field(9, 2) + 6)
+            },
+            _ => match a {
+                0 => (6, 10),
+                1 => (10, 6),
+                _ => return None,
+            },
+        };
+    }
+    let levels = QUANT_LEVELS[range - 2 + if high_precision { 6 } else { 0 }];
+    Some(WeightGrid { width, height, dual_plane, levels })
+}
+
+/// Moves the base and offset of a base and offset endpoint mode into the range of a signed offset.
+fn transfer_signed(offset : &mut i32, base : &mut i32) {
+    *base = (*base >> 1) | (*offset & 0x80);
+    *offset = (*offset >> 1) & 0x3f;
+    if *offset & 0x20 != 0 {
+        *offset -= 0x40;
+    }
+}
+
+fn blue_contract(color : [i32; 4]) -> [i32; 4] {
+    [(color[0] + color[2]) >> 1, (color[1] + color[2]) >> 1, color[2], color[3]]
+}
+
+/// Decodes the two endpoints of a partition from its color endpoint mode and values. Returns `None` for the HDR modes.
+fn decode_endpoints(mode : u32, values : &[i32]) -> Option<[[i32; 4]; 2]> {
+    let mut v = [0i32; 8];
+    v[..values.len()].copy_from_slice(values);
+    let scale = |value : i32| (value * v[3]) >> 8;
+    let endpoints = match mode {
+        0 => [[v[0], v[0], v[0], 255], [v[1], v[1], v[1], 255]],
+        1 => {
+            let low = (v[0] >> 2) | (v[1] & 0xc0);
+            let high = (low + (v[1] & 0x3f)).min(255);
+            [[low, low, low, 255], [high, high, high, 255]]
+        },
+        4 => [[v[0], v[0], v[0], v[2]], [v[1], v[1], v[1], v[3]]],
+        5 => {
+            let [v0, v1, v2, v3, ..] = &mut v;
+            transfer_signed(v1, v0);
+            transfer_signed(v3, v2);
+            [[v[0], v[0], v[0], v[2]], [v[0] + v[1], v[0] + v[1], v[0] + v[1], v[2] + v[3]]]
+        },
+        6 => [[scale(v[0]), scale(v[1]), scale(v[2]), 255], [v[0], v[1], v[2], 255]],
+        8 | 12 => {
+            let (alpha0, alpha1) = if mode == 12 { (v[6], v[7]) } else { (255, 255) };
+            let first = [v[0], v[2], v[4], alpha0];
+            let second = [v[1], v[3], v[5], alpha1];
+            if v[1] + v[3] + v[5] >= v[0] + v[2] + v[4] {
+                [first, second]
+            } else {
+                [blue_contract(second), blue_contract(first)]
+            }
+        },
+        9 | 13 => {
+            for pair in v.chunks_exact_mut(2) {
+                let [base, offset] = pair else { unreachable!() };
+                transfer_signed(offset, base);
+            }
+            let (alpha0, alpha1) = if mode == 13 { (v[6], v[6] + v[7]) } else { (255, 255) };
+            let base = [v[0], v[2], v[4], alpha0];
+            let offset = [v[0] + v[1], v[2] + v[3], v[4] + v[5], alpha1];
+            if v[1] + v[3] + v[5] >= 0 {
+                [base, offset]
+            } else {
+                [blue_contract(offset), blue_contract(base)]
+            }
+        },
+        10 => [[scale(v[0]), scale(v[1]), scale(v[2]), v[4]], [v[0], v[1], v[2], v[5]]],
+        _ => return None,
+    };
+    Some(endpoints.map(|endpoint| endpoint.map(|value| value.clamp(0, 255))))
+}
+
+fn hash(mut seed : u32) -> u32 {
+    seed ^= seed >> 15;
+    seed = seed.wrapping_sub(seed << 17);
+    seed = seed.wrapping_add(seed << 7);
+    seed = seed.wrapping_add(seed << 4);
+    seed ^= seed >> 5;
+    seed = seed.wrapping_add(seed << 16);
+    seed ^= seed >> 7;
+    seed ^= seed >> 3;
+    seed ^= seed << 6;
+    seed ^= seed >> 17;
+    seed
+}
+
+/// Returns the partition a texel belongs to, which is generated from the partition index rather than stored.
+fn select_partition(seed : u32, x : u32, y : u32, partitions : u32, small_block : bool) -> usize {
+    let (x, y) = if small_block { (x << 1, y << 1) } else { (x, y) };
+    let seed = seed + (partitions - 1) * 1024;
+    let random = hash(seed);
+    let mut seeds = [0u32; 8];
+    for (i, value) in seeds.iter_mut().enumerate() {
+        let nibble = (random >> (i * 4)) & 15;
+        *value = nibble * nibble;
+    }
+    let (shift1, shift2) = if seed & 1 == 1 {
+        (if seed & 2 == 2 { 4 } else { 5 }, if partitions == 3 { 6 } else { 5 })
+    } else {
+        (if partitions == 3 { 6 } else { 5 }, if seed & 2 == 2 { 4 } else { 5 })
+    };
+    for (i, value) in seeds.iter_mut().enumerate() {
+        *value >>= if i % 2 == 0 { shift1 } else { shift2 };
+    }
+    // The seeds for the z coordinate are not needed, as textures are two dimensional.
+    let mut distances = [
+        seeds[0] * x + seeds[1] * y + (random >> 14),
+        seeds[2] * x + seeds[3] * y + (random >> 10),
+        seeds[4] * x + seeds[5] * y + (random >> 6),
+        seeds[6] * x + seeds[7] * y + (random >> 2)];
+    for (i, distance) in distances.iter_mut().enumerate() {
+        *distance = if i < partitions as usize { *distance & 63 } else { 0 };
+    }
+    let [a, b, c, d] = distances;
+    if a >= b && a >= c && a >= d {
+        0
+    } else if b >= c && b >= d {
+        1
+    } else if c >= d {
+        2
+    } else {
+        3
+    }
+}
+
+/// Bilinearly interpolates the weight grid at every texel of a `width` by `height` block, returning the weights of
+/// one plane.
+fn infill_weights(weights : &[u32], grid : &WeightGrid, plane : usize, width : usize, height : usize) -> Vec<u32> {
+    let planes = 1 + grid.dual_plane as usize;
+    let grid_weight = |x : usize, y : usize| {
+        if x < grid.width && y < grid.height { weights[(y * grid.width + x) * planes + plane] } else { 0 }
+    };
+    let (scale_x, scale_y) = ((1024 + width / 2) / (width - 1), (1024 + height / 2) / (height - 1));
+    let mut infilled = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let grid_x = (scale_x * x * (grid.width - 1) + 32) >> 6;
+            let grid_y = (scale_y * y * (grid.height - 1) + 32) >> 6;
+            let (x0, fraction_x) = (grid_x >> 4, (grid_x & 15) as u32);
+            let (y0, fraction_y) = (grid_y >> 4, (grid_y & 15) as u32);
+            let w11 = (fraction_x * fraction_y + 8) >> 4;
+            let (w10, w01) = (fraction_y - w11, fraction_x - w11);
+            let w00 = 16 + w11 - fraction_x - fraction_y;
+            let sum = grid_weight(x0, y0) * w00
+                + grid_weight(x0 + 1, y0) * w01
+                + grid_weight(x0, y0 + 1) * w10
+                + grid_weight(x0 + 1, y0 + 1) * w11;
+            infilled.push((sum + 8) >> 4);
+        }
+    }
+    infilled
+}
+
+/// Decodes a block whose texels all share one color, stored as 16-bit values.
+fn decode_void_extent(bits : u128, texels : &mut [[u8; 4]]) -> Option<()> {
+    // HDR void extent blocks store half floats, which the LDR profile does not support.
+    if (bits >> 9) & 1 == 1 || (bits >> 10) & 3 != 3 {
+        return None;
+    }
+    let channel = |index : u32| (bits >> (64 + index * 16 + 8)) as u8;
+    texels.fill([channel(0), channel(1), channel(2), channel(3)]);
+    Some(())
+}
+
+fn decode_texels(bits : u128, width : usize, height : usize, srgb : bool, texels : &mut [[u8; 4]]) -> Option<()> {
+    let field = |start : u32, length : u32| (bits >> start) as u32 & ((1 << length) - 1);
+    let mode = field(0, 11);
+    if mode & 0x1ff == 0x1fc {
+        return decode_void_extent(bits, texels);
+    }
+    let grid = block_mode(mode)?;
+    let partitions = field(11, 2) + 1;
+    let weight_count = grid.width * grid.height * (1 + grid.dual_plane as usize);
+    let weight_bits = sequence_bits(weight_count as u32, grid.levels);
+    if grid.width > width
+        || grid.height > height
+        || (grid.dual_plane && partitions == 4)
+        || weight_count > 64
+        || !(24..=96).contains(&weight_bits) {
+        return None;
+    }
+
+    // Partitioned blocks may store the high bits of their endpoint modes below the weights.
+    let mut below_weights = 128 - weight_bits;
+    let mut modes = [0u32; 4];
+    let endpoints_start = if partitions == 1 {
+        modes[0] = field(13, 4);
+        17
+    } else {
+        let selector = field(23, 6);
+        if selector & 3 == 0 {
+            modes = [selector >> 2; 4];
+        } else {
+            let extra_bits = 3 * partitions - 4;
+            below_weights -= extra_bits;
+            let encoded = selector | field(below_weights, extra_bits) << 6;
+            let class = (encoded & 3) - 1;
+            for (i, mode) in modes.iter_mut().take(partitions as usize).enumerate() {
+                let high = ((encoded >> (2 + i)) & 1) + class;
+                *mode = high << 2 | (encoded >> (2 + partitions as usize + i * 2)) & 3;
+            }
+        }
+        29
+    };
+    let plane_component = if grid.dual_plane {
+        below_weights -= 2;
+        Some(field(below_weights, 2) as usize)
+    } else {
+        None
+    };
+
+    let value_count = modes.iter().take(partitions as usize).map(|mode| ((mode >> 2) + 1) * 2).sum::<u32>();
+    let available = below_weights.checked_sub(endpoints_start)?;
+    if value_count > 18 {
+        return None;
+    }
+    // Endpoints use the most levels that fit in the remaining bits, which must be at least six.
+    let levels = *QUANT_LEVELS.iter().rev().find(|levels| sequence_bits(value_count, **levels) <= available)?;
+    if levels < 6 {
+        return None;
+    }
+    let endpoint_bits = low_bits(bits >> endpoints_start, sequence_bits(value_count, levels));
+    let values = decode_sequence(endpoint_bits, value_count as usize, levels)
+        .iter()
+        .map(|value| unquantize_color(*value, levels))
+        .collect::<Vec<_>>();
+    let mut endpoints = [[[0; 4]; 2]; 4];
+    let mut offset = 0;
+    for (endpoint, mode) in endpoints.iter_mut().zip(modes.iter()).take(partitions as usize) {
+        let count = ((mode >> 2) as usize + 1) * 2;
+        *endpoint = decode_endpoints(*mode, &values[offset..offset + count])?;
+        offset += count;
+    }
+
+    // Weights are stored from the most significant bit downwards.
+    let weight_bits = low_bits(bits.reverse_bits(), weight_bits);
+    let weights = decode_sequence(weight_bits, weight_count, grid.levels)
+        .iter()
+        .map(|weight| unquantize_weight(*weight, grid.levels))
+        .collect::<Vec<_>>();
+    let planes = [
+        infill_weights(&weights, &grid, 0, width, height),
+        if grid.dual_plane { infill_weights(&weights, &grid, 1, width, height) } else { Vec::new() }];
+
+    let seed = field(13, 10);
+    let small_block = width * height < 31;
+    for (i, texel) in texels.iter_mut().enumerate().take(width * height) {
+        let partition = if partitions > 1 {
+            select_partition(seed, (i % width) as u32, (i / width) as u32, partitions, small_block)
+        } else {
+            0
+        };
+        let [first, second] = endpoints[partition];
+        for (channel, value) in texel.iter_mut().enumerate() {
+            let weight = if plane_component == Some(channel) { planes[1][i] } else { planes[0][i] } as i32;
+            // Endpoints are expanded to 16 bits, and the top 8 bits of the interpolated value are kept.
+            let expand = |value : i32| value << 8 | if srgb { 0x80 } else { value };
+            let color = (expand(first[channel]) * (64 - weight) + expand(second[channel]) * weight + 32) >> 6;
+            *value = (color >> 8) as u8;
+        }
+    }
+    Some(())
+}
+
+/// Decodes an ASTC block with a `width` by `height` footprint to its texels in row-major order. Malformed blocks and
+/// blocks with HDR endpoints decode to magenta, as in the LDR profile.
+pub(super) fn decode_block(block : &[u8], width : usize, height : usize, srgb : bool, texels : &mut [[u8; 4]]) {
+    let bits = u128::from_le_bytes(block[..16].try_into().unwrap());
+    if decode_texels(bits, width, height, srgb, texels).is_none() {
+        texels.fill(ERROR_COLOR);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The footprints of the blocks in `astc_blocks.bin`, which has eight random blocks of each followed by a void
+    /// extent block, an HDR void extent block and a block with a reserved block mode.
+    const FOOTPRINTS : [(usize, usize); 14] = [
+        (4, 4), (5, 4), (5, 5), (6, 5), (6, 6), (8, 5), (8, 6), (8, 8), (10, 5), (10, 6), (10, 8), (10, 10), (12, 10),
+        (12, 12)];
+
+    fn decode(block : u128, width : usize, height : usize, srgb : bool) -> Vec<[u8; 4]> {
+        let mut texels = vec![[0; 4]; width * height];
+        decode_block(&block.to_le_bytes(), width, height, srgb, &mut texels);
+        texels
+    }
+
+    #[test]
+    fn unquantizes_trits_and_quints_in_specification_order() {
+        let colors = (0..6).map(|value| unquantize_color(value, 6)).collect::<Vec<_>>();
+        assert_eq!(colors, [0, 255, 51, 204, 102, 153]);
+        let colors = (0..10).map(|value| unquantize_color(value, 10)).collect::<Vec<_>>();
+        assert_eq!(colors, [0, 255, 28, 227, 56, 199, 84, 171, 113, 142]);
+        let weights = (0..6).map(|value| unquantize_weight(value, 6)).collect::<Vec<_>>();
+        assert_eq!(weights, [0, 64, 12, 52, 25, 39]);
+        let weights = (0..5).map(|value| unquantize_weight(value, 5)).collect::<Vec<_>>();
+        assert_eq!(weights, [0, 16, 32, 48, 64]);
+    }
+
+    #[test]
+    fn decodes_direct_rgb_endpoints() {
+        // A 4x4 grid of 2-bit weights, one partition with direct RGB endpoints stored as 8-bit values, and weight
+        // `i % 4` at texel `i`. Weights are stored bit reversed from the top of the block.
+        let mut block = 0x042 | 8 << 13;
+        for (i, value) in [10u128, 250, 20, 200, 30, 100].iter().enumerate() {
+            block |= value << (17 + i * 8);
+        }
+        let weights = (0..16).map(|i| (i % 4) << (i * 2)).sum::<u128>();
+        block |= weights.reverse_bits();
+        let texels = decode(block, 4, 4, false);
+        for (i, texel) in texels.iter().enumerate() {
+            let weight = [0, 21, 43, 64][i % 4];
+            let lerp = |a : i32, b : i32| (((a * 257 * (64 - weight) + b * 257 * weight + 32) >> 6) >> 8) as u8;
+            assert_eq!(*texel, [lerp(10, 250), lerp(20, 200), lerp(30, 100), 255], "texel {}", i);
+        }
+        assert_eq!(texels[0], [10, 20, 30, 255]);
+        assert_eq!(texels[3], [250, 200, 100, 255]);
+    }
+
+    #[test]
+    fn decodes_void_extent_blocks() {
+        let block = 0xdfc | 0xfff_ffff_ffff_f000 | 0x4000_c000_8000_1234 << 64;
+        assert!(decode(block, 6, 5, false).iter().all(|texel| *texel == [0x12, 0x80, 0xc0, 0x40]));
+        // HDR void extent blocks and reserved block modes cannot be decoded with the LDR profile.
+        assert!(decode(block | 0x200, 6, 5, false).iter().all(|texel| *texel == ERROR_COLOR));
+        assert!(decode(0x1c0, 4, 4, false).iter().all(|texel| *texel == ERROR_COLOR));
+    }
+
+    #[test]
+    fn matches_reference_decoder() {
+        let blocks = include_bytes!("../../assets/tests/images/astc_blocks.bin");
+        let expected = [
+            &include_bytes!("../../assets/tests/images/astc_blocks.rgba")[..],
+            &include_bytes!("../../assets/tests/images/astc_blocks_srgb.rgba")[..]];
+        for (srgb, expected) in [false, true].iter().zip(expected.iter()) {
+            let (mut blocks, mut expected) = (blocks.chunks_exact(16), expected.chunks_exact(1));
+            for (width, height) in FOOTPRINTS.iter() {
+                for i in 0..11 {
+                    let block = u128::from_le_bytes(blocks.next().unwrap().try_into().unwrap());
+                    let texels = decode(block, *width, *height, *srgb);
+                    let reference = expected.by_ref().take(width * height * 4).flatten().copied().collect::<Vec<_>>();
+                    assert_eq!(texels.concat(), reference, "block {} of {}x{}, sRGB {}", i, width, height, srgb);
+                }
+            }
+            assert!(blocks.next().is_none() && expected.next().is_none());
+        }
+    }
+}
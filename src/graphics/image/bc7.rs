@@ -0,0 +1,311 @@
+use std::convert::TryInto;
+
+/// The layout of a BC7 mode: how many subsets it has and how many bits it stores for each field.
+struct Mode {
+    subsets : usize,
+    partition_bits : u32,
+    rotation_bits : u32,
+    index_selection_bits : u32,
+    color_bits : u32,
+    alpha_bits : u32,
+    /// Whether each endpoint has its own p-bit, rather than one shared by both endpoints of a subset.
+    endpoint_p_bits : bool,
+    shared_p_bits : bool,
+    index_bits : u32,
+    secondary_index_bits : u32,
+}
+
+#[allow(clippy::too_many_arguments)]
+const fn mode(subsets : usize,
+              partition_bits : u32,
+              rotation_bits : u32,
+              index_selection_bits : u32,
+              color_bits : u32,
+              alpha_bits : u32,
+              endpoint_p_bits : bool,
+              shared_p_bits : bool,
+              index_bits : u32,
+              secondary_index_bits : u32) -> Mode {
+    Mode {
+        subsets,
+        partition_bits,
+        rotation_bits,
+        index_selection_bits,
+        color_bits,
+        alpha_bits,
+        endpoint_p_bits,
+        shared_p_bits,
+        index_bits,
+        secondary_index_bits,
+    }
+}
+
+const MODES : [Mode; 8] = [
+    mode(3, 4, 0, 0, 4, 0, true, false, 3, 0),
+    mode(2, 6, 0, 0, 6, 0, false, true, 3, 0),
+    mode(3, 6, 0, 0, 5, 0, false, false, 2, 0),
+    mode(2, 6, 0, 0, 7, 0, true, false, 2, 0),
+    mode(1, 0, 2, 1, 5, 6, false, false, 2, 3),
+    mode(1, 0, 2, 0, 7, 8, false, false, 2, 2),
+    mode(1, 0, 0, 0, 7, 7, true, false, 4, 0),
+    mode(2, 6, 0, 0, 5, 5, true, false, 2, 0),
+];
+
+/// The interpolation weights of 2, 3 and 4-bit indices, out of 64.
+const WEIGHTS_2 : [u32; 4] = [0, 21, 43, 64];
+const WEIGHTS_3 : [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const WEIGHTS_4 : [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+/// The two subset partitions, where bit `i` is set if texel `i` belongs to the second subset.
+const PARTITIONS_2 : [u16; 64] = [
+    0xcccc, 0x8888, 0xeeee, 0xecc8, 0xc880, 0xfeec, 0xfec8, 0xec80, 0xc800, 0xffec, 0xfe80, 0xe800, 0xffe8, 0xff00,
+    0xfff0, 0xf000, 0xf710, 0x008e, 0x7100, 0x08ce, 0x008c, 0x7310, 0x3100, 0x8cce, 0x088c, 0x3110, 0x6666, 0x366c,
+    0x17e8, 0x0ff0, 0x718e, 0x399c, 0xaaaa, 0xf0f0, 0x5a5a, 0x33cc, 0x3c3c, 0x55aa, 0x9696, 0xa55a, 0x73ce, 0x13c8,
+    0x324c, 0x3bdc, 0x6996, 0xc33c, 0x9966, 0x0660, 0x0272, 0x04e4, 0x4e40, 0x2720, 0xc936, 0x936c, 0x39c6, 0x639c,
+    0x9336, 0x9cc6, 0x817e, 0xe718, 0xccf0, 0x0fcc, 0x7744, 0xee22];
+
+/// The three subset partitions, with two bits per texel giving its subset, texel 0 in the lowest bits.
+const PARTITIONS_3 : [u32; 64] = [
+    0xaa685050, 0x6a5a5040, 0x5a5a4200, 0x5450a0a8, 0xa5a50000, 0xa0a05050, 0x5555a0a0, 0x5a5a5050, 0xaa550000,
+    0xaa555500, 0xaaaa5500, 0x90909090, 0x94949494, 0xa4a4a4a4, 0xa9a59450, 0x2a0a4250, 0xa5945040, 0x0a425054,
+    0xa5a5a500, 0x55a0a0a0, 0xa8a85454, 0x6a6a4040, 0xa4a45000, 0x1a1a0500, 0x0050a4a4, 0xaaa59090, 0x14696914,
+    0x69691400, 0xa08585a0, 0xaa821414, 0x50a4a450, 0x6a5a0200, 0xa9a58000, 0x5090a0a8, 0xa8a09050, 0x24242424,
+    0x00aa5500, 0x24924924, 0x24499224, 0x50a50a50, 0x500aa550, 0xaaaa4444, 0x66660000, 0xa5a0a5a0, 0x50a050a0,
+    0x69286928, 0x44aaaa44, 0x66666600, 0xaa444444, 0x54a854a8, 0x95809580, 0x96969600, 0xa85454a8, 0x80959580,
+    0xaa141414, 0x96960000, 0xaaaa1414, 0xa05050a0, 0xa0a5a5a0, 0x96000000, 0x40804080, 0xa9a8a9a8, 0xaaaaaa44,
+    0x2a4a5254];
+
+/// The texel whose index has its high bit dropped, for the second subset of two subset partitions.
+const ANCHORS_2 : [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 2, 8, 2, 2, 8, 8, 15, 2, 8, 2, 2, 8, 8, 2, 2,
+    15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6, 6, 2, 6, 8, 15, 15, 2, 2, 15, 15, 15, 15, 15, 2, 2, 15];
+/// The anchor texels of the second and third subsets of three subset partitions.
+const ANCHORS_3 : [[u8; 2]; 64] = [
+    [3, 15], [3, 8], [15, 8], [15, 3], [8, 15], [3, 15], [15, 3], [15, 8], [8, 15], [8, 15], [6, 15], [6, 15],
+    [6, 15], [5, 15], [3, 15], [3, 8], [3, 15], [3, 8], [8, 15], [15, 3], [3, 15], [3, 8], [6, 15], [10, 8],
+    [5, 3], [8, 15], [8, 6], [6, 10], [8, 15], [5, 15], [15, 10], [15, 8], [8, 15], [15, 3], [3, 15], [5, 10],
+    [6, 10], [10, 8], [8, 9], [15, 10], [15, 6], [3, 15], [15, 8], [5, 15], [15, 3], [15, 6], [15, 6], [15, 8],
+    [3, 15], [15, 3], [5, 15], [5, 15], [5, 15], [8, 15], [5, 15], [10, 15], [5, 15], [10, 15], [8, 15], [13, 15],
+    [15, 3], [12, 15], [3, 15], [3, 8]];
+
+/// Reads fields of a block from its least significant bit upwards.
+struct BitReader {
+    bits : u128,
+    position : u32,
+}
+
+impl BitReader {
+    fn read(&mut self, length : u32) -> u32 {
+        let value = (self.bits >> self.position) as u32 & ((1 << length) - 1);
+        self.position += length;
+        value
+    }
+}
+
+/// Returns the subset texel `texel` belongs to in `partition`.
+fn subset(subsets : usize, partition : usize, texel : usize) -> usize {
+    match subsets {
+        2 => (PARTITIONS_2[partition] >> texel) as usize & 1,
+        3 => (PARTITIONS_3[partition] >> (texel * 2)) as usize & 3,
+        _ => 0,
+    }
+}
+
+fn is_anchor(subsets : usize, partition : usize, texel : usize) -> bool {
+    texel == 0 || match subsets {
+        2 => ANCHORS_2[partition] as usize == texel,
+        3 => ANCHORS_3[partition].contains(&(texel as u8)),
+        _ => false,
+    }
+}
+
+fn weight(index_bits : u32, index : u32) -> u32 {
+    match index_bits {
+        2 => WEIGHTS_2[index as usize],
+        3 => WEIGHTS_3[index as usize],
+        _ => WEIGHTS_4[index as usize],
+    }
+}
+
+/// Expands an endpoint of `bits` bits to 8 bits by replicating its high bits.
+fn expand(value : u32, bits : u32) -> u32 {
+    value << (8 - bits) | value >> (2 * bits - 8)
+}
+
+/// Decodes a BC7 block to 16 texels. Blocks with the reserved mode 8 decode to transparent black.
+pub(super) fn decode_block(block : &[u8], texels : &mut [[u8; 4]]) {
+    if block[0] == 0 {
+        texels.fill([0; 4]);
+        return;
+    }
+    let mode_index = block[0].trailing_zeros();
+    let mode = &MODES[mode_index as usize];
+    let mut reader = BitReader {
+        bits: u128::from_le_bytes(block[..16].try_into().unwrap()),
+        position: mode_index + 1,
+    };
+    let partition = reader.read(mode.partition_bits) as usize;
+    let rotation = reader.read(mode.rotation_bits);
+    let index_selection = reader.read(mode.index_selection_bits);
+
+    let mut endpoints = [[[0u32; 4]; 2]; 3];
+    for channel in 0..3 {
+        for endpoint in endpoints.iter_mut().take(mode.subsets).flatten() {
+            endpoint[channel] = reader.read(mode.color_bits);
+        }
+    }
+    for endpoint in endpoints.iter_mut().take(mode.subsets).flatten() {
+        endpoint[3] = reader.read(mode.alpha_bits);
+    }
+    let (mut color_bits, mut alpha_bits) = (mode.color_bits, mode.alpha_bits);
+    if mode.endpoint_p_bits || mode.shared_p_bits {
+        for subset in endpoints.iter_mut().take(mode.subsets) {
+            let shared = if mode.shared_p_bits { reader.read(1) } else { 0 };
+            for endpoint in subset.iter_mut() {
+                let p_bit = if mode.endpoint_p_bits { reader.read(1) } else { shared };
+                for value in endpoint.iter_mut() {
+                    *value = *value << 1 | p_bit;
+                }
+            }
+        }
+        color_bits += 1;
+        if alpha_bits > 0 {
+            alpha_bits += 1;
+        }
+    }
+    for endpoint in endpoints.iter_mut().take(mode.subsets).flatten() {
+        for value in endpoint.iter_mut().take(3) {
+            *value = expand(*value, color_bits);
+        }
+        endpoint[3] = if alpha_bits > 0 { expand(endpoint[3], alpha_bits) } else { 255 };
+    }
+
+    // The anchor texel of each subset has the high bit of its index dropped, as it is implicitly zero.
+    let mut indices = [[0u32; 16]; 2];
+    for (texel, index) in indices[0].iter_mut().enumerate() {
+        *index = reader.read(mode.index_bits - is_anchor(mode.subsets, partition, texel) as u32);
+    }
+    if mode.secondary_index_bits > 0 {
+        for (texel, index) in indices[1].iter_mut().enumerate() {
+            *index = reader.read(mode.secondary_index_bits - (texel == 0) as u32);
+        }
+    }
+
+    for (texel, output) in texels.iter_mut().enumerate().take(16) {
+        let [first, second] = endpoints[subset(mode.subsets, partition, texel)];
+        let color_weight = weight(mode.index_bits, indices[0][texel]);
+        let (color_weight, alpha_weight) = if mode.secondary_index_bits == 0 {
+            (color_weight, color_weight)
+        } else {
+            let alpha_weight = weight(mode.secondary_index_bits, indices[1][texel]);
+            if index_selection == 1 { (alpha_weight, color_weight) } else { (color_weight, alpha_weight) }
+        };
+        let mut color = [0u8; 4];
+        for (channel, value) in color.iter_mut().enumerate() {
+            let weight = if channel == 3 { alpha_weight } else { color_weight };
+            *value = (((64 - weight) * first[channel] + weight * second[channel] + 32) >> 6) as u8;
+        }
+        if rotation > 0 {
+            color.swap(3, rotation as usize - 1);
+        }
+        *output = color;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Packs fields into a block from its least significant bit upwards.
+    fn pack(fields : &[(u32, u32)]) -> [u8; 16] {
+        let (mut bits, mut position) = (0u128, 0);
+        for &(length, value) in fields {
+            bits |= (value as u128) << position;
+            position += length;
+        }
+        assert_eq!(position, 128);
+        bits.to_le_bytes()
+    }
+
+    fn decode(block : &[u8; 16]) -> [[u8; 4]; 16] {
+        let mut texels = [[1; 4]; 16];
+        decode_block(block, &mut texels);
+        texels
+    }
+
+    #[test]
+    fn anchors_belong_to_their_subsets() {
+        for partition in 0..64 {
+            assert_eq!(subset(2, partition, ANCHORS_2[partition] as usize), 1);
+            assert_eq!(subset(3, partition, 0), 0);
+            assert_eq!(subset(3, partition, ANCHORS_3[partition][0] as usize), 1);
+            assert_eq!(subset(3, partition, ANCHORS_3[partition][1] as usize), 2);
+        }
+    }
+
+    #[test]
+    fn decodes_mode_6_endpoints_and_weights() {
+        // Red from 0 to 254 and opaque, with p-bits 0 and 1, and index i at texel i.
+        let mut fields = vec![(7, 1 << 6), (7, 0), (7, 127), (7, 0), (7, 0), (7, 0), (7, 0), (7, 127), (7, 127)];
+        fields.extend_from_slice(&[(1, 0), (1, 1)]);
+        fields.push((3, 0));
+        fields.extend((1..16).map(|index| (4, index)));
+        let texels = decode(&pack(&fields));
+        for (i, texel) in texels.iter().enumerate() {
+            let weight = WEIGHTS_4[i];
+            // The first endpoint is (0, 0, 0, 254) and the second (255, 1, 1, 255).
+            let lerp = |a : u32, b : u32| (((64 - weight) * a + weight * b + 32) >> 6) as u8;
+            assert_eq!(*texel, [lerp(0, 255), lerp(0, 1), lerp(0, 1), lerp(254, 255)], "texel {}", i);
+        }
+        assert_eq!(texels[0], [0, 0, 0, 254]);
+        assert_eq!(texels[15], [255, 1, 1, 255]);
+    }
+
+    #[test]
+    fn decodes_two_subset_partitions() {
+        // Mode 1 with partition 0, where the two right columns belong to the second subset. Each subset is a solid
+        // color since every index is zero, and its p-bit is the low bit of every channel.
+        let mut fields = vec![(2, 2), (6, 0)];
+        // Red, green and blue of both endpoints of both subsets.
+        fields.extend_from_slice(&[(6, 63), (6, 63), (6, 0), (6, 0)]);
+        fields.extend_from_slice(&[(6, 0), (6, 0), (6, 63), (6, 63)]);
+        fields.extend_from_slice(&[(6, 0), (6, 0), (6, 32), (6, 32)]);
+        fields.extend_from_slice(&[(1, 1), (1, 0)]);
+        fields.extend_from_slice(&[(46, 0)]);
+        let texels = decode(&pack(&fields));
+        for (i, texel) in texels.iter().enumerate() {
+            let expected = if i % 4 < 2 { [255, 2, 2, 255] } else { [0, 253, 129, 255] };
+            assert_eq!(*texel, expected, "texel {}", i);
+        }
+    }
+
+    #[test]
+    fn rotates_and_selects_indices_in_mode_4() {
+        // Rotation 1 swaps red and alpha, and the index selection bit gives color the 3-bit indices.
+        let mut fields = vec![(5, 1 << 4), (2, 1), (1, 1)];
+        fields.extend_from_slice(&[(5, 0), (5, 31), (5, 0), (5, 0), (5, 0), (5, 0), (6, 63), (6, 0)]);
+        fields.push((1, 0));
+        fields.extend((1..16).map(|_| (2, 0)));
+        fields.push((2, 0));
+        fields.extend((1..16).map(|_| (3, 7)));
+        let texels = decode(&pack(&fields));
+        // Texel 0 uses the first endpoint everywhere: alpha 255 rotated into red, and red 0 rotated into alpha.
+        assert_eq!(texels[0], [255, 0, 0, 0]);
+        assert_eq!(texels[1], [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn matches_reference_decoder() {
+        let blocks = include_bytes!("../../assets/tests/images/bc7_blocks.bin");
+        let expected = include_bytes!("../../assets/tests/images/bc7_blocks.rgba");
+        for (i, (block, expected)) in blocks.chunks_exact(16).zip(expected.chunks_exact(64)).enumerate() {
+            let texels = decode(block.try_into().unwrap());
+            assert_eq!(texels.concat(), expected, "block {} in mode {}", i, block[0].trailing_zeros());
+        }
+    }
+
+    #[test]
+    fn decodes_reserved_mode_as_transparent_black() {
+        assert_eq!(decode(&[0; 16]), [[0; 4]; 16]);
+    }
+}
@@ -0,0 +1,388 @@
+use ash::vk;
+use super::{astc, bc7, is_srgb};
+
+/// The size of a block of a block-compressed format, in texels and bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockInfo {
+    pub width : u32,
+    pub height : u32,
+    pub bytes : u32,
+}
+
+/// Returns the block size of a BCn, ETC2, EAC or ASTC format, or `None` for other formats.
+pub fn block_info(format : vk::Format) -> Option<BlockInfo> {
+    let block = |width, height, bytes| Some(BlockInfo { width, height, bytes });
+    match format {
+        vk::Format::BC1_RGB_UNORM_BLOCK
+        | vk::Format::BC1_RGB_SRGB_BLOCK
+        | vk::Format::BC1_RGBA_UNORM_BLOCK
+        | vk::Format::BC1_RGBA_SRGB_BLOCK
+        | vk::Format::BC4_UNORM_BLOCK
+        | vk::Format::BC4_SNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8_UNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8_SRGB_BLOCK
+        | vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK
+        | vk::Format::EAC_R11_UNORM_BLOCK
+        | vk::Format::EAC_R11_SNORM_BLOCK => block(4, 4, 8),
+        vk::Format::BC2_UNORM_BLOCK
+        | vk::Format::BC2_SRGB_BLOCK
+        | vk::Format::BC3_UNORM_BLOCK
+        | vk::Format::BC3_SRGB_BLOCK
+        | vk::Format::BC5_UNORM_BLOCK
+        | vk::Format::BC5_SNORM_BLOCK
+        | vk::Format::BC6H_UFLOAT_BLOCK
+        | vk::Format::BC6H_SFLOAT_BLOCK
+        | vk::Format::BC7_UNORM_BLOCK
+        | vk::Format::BC7_SRGB_BLOCK
+        | vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK
+        | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK
+        | vk::Format::EAC_R11G11_UNORM_BLOCK
+        | vk::Format::EAC_R11G11_SNORM_BLOCK
+        | vk::Format::ASTC_4X4_UNORM_BLOCK
+        | vk::Format::ASTC_4X4_SRGB_BLOCK => block(4, 4, 16),
+        vk::Format::ASTC_5X4_UNORM_BLOCK | vk::Format::ASTC_5X4_SRGB_BLOCK => block(5, 4, 16),
+        vk::Format::ASTC_5X5_UNORM_BLOCK | vk::Format::ASTC_5X5_SRGB_BLOCK => block(5, 5, 16),
+        vk::Format::ASTC_6X5_UNORM_BLOCK | vk::Format::ASTC_6X5_SRGB_BLOCK => block(6, 5, 16),
+        vk::Format::ASTC_6X6_UNORM_BLOCK | vk::Format::ASTC_6X6_SRGB_BLOCK => block(6, 6, 16),
+        vk::Format::ASTC_8X5_UNORM_BLOCK | vk::Format::ASTC_8X5_SRGB_BLOCK => block(8, 5, 16),
+        vk::Format::ASTC_8X6_UNORM_BLOCK | vk::Format::ASTC_8X6_SRGB_BLOCK => block(8, 6, 16),
+        vk::Format::ASTC_8X8_UNORM_BLOCK | vk::Format::ASTC_8X8_SRGB_BLOCK => block(8, 8, 16),
+        vk::Format::ASTC_10X5_UNORM_BLOCK | vk::Format::ASTC_10X5_SRGB_BLOCK => block(10, 5, 16),
+        vk::Format::ASTC_10X6_UNORM_BLOCK | vk::Format::ASTC_10X6_SRGB_BLOCK => block(10, 6, 16),
+        vk::Format::ASTC_10X8_UNORM_BLOCK | vk::Format::ASTC_10X8_SRGB_BLOCK => block(10, 8, 16),
+        vk::Format::ASTC_10X10_UNORM_BLOCK | vk::Format::ASTC_10X10_SRGB_BLOCK => block(10, 10, 16),
+        vk::Format::ASTC_12X10_UNORM_BLOCK | vk::Format::ASTC_12X10_SRGB_BLOCK => block(12, 10, 16),
+        vk::Format::ASTC_12X12_UNORM_BLOCK | vk::Format::ASTC_12X12_SRGB_BLOCK => block(12, 12, 16),
+        _ => None,
+    }
+}
+
+/// Decodes a block to its texels in row-major order.
+type DecodeBlock = Box<dyn Fn(&[u8], &mut [[u8; 4]])>;
+
+/// Decompresses a level of a BC1-5, BC7, ETC2, unsigned EAC or ASTC format to 8-bit RGBA. ASTC is decoded with the
+/// LDR profile, so blocks with HDR endpoints decode to magenta. Returns `None` for formats which cannot be decompressed
+/// on the CPU, which are BC6H and the signed formats since their values do not fit in 8-bit unsigned channels, or if
+/// `data` is too short.
+pub fn decompress(format : vk::Format, width : u32, height : u32, data : &[u8]) -> Option<Vec<u8>> {
+    let block = block_info(format)?;
+    let decode_block : DecodeBlock = match format {
+        vk::Format::BC1_RGB_UNORM_BLOCK
+        | vk::Format::BC1_RGB_SRGB_BLOCK
+        | vk::Format::BC1_RGBA_UNORM_BLOCK
+        | vk::Format::BC1_RGBA_SRGB_BLOCK => Box::new(|block, texels| decode_bc1(block, texels, true)),
+        vk::Format::BC2_UNORM_BLOCK | vk::Format::BC2_SRGB_BLOCK => Box::new(decode_bc2),
+        vk::Format::BC3_UNORM_BLOCK | vk::Format::BC3_SRGB_BLOCK => Box::new(decode_bc3),
+        vk::Format::BC4_UNORM_BLOCK => Box::new(|block, texels| {
+            let red = decode_bc4(block);
+            for (texel, red) in texels.iter_mut().zip(red.iter()) {
+                *texel = [*red, 0, 0, 255];
+            }
+        }),
+        vk::Format::BC5_UNORM_BLOCK => Box::new(|block, texels| {
+            let (red, green) = (decode_bc4(&block[..8]), decode_bc4(&block[8..]));
+            for (i, texel) in texels.iter_mut().enumerate() {
+                *texel = [red[i], green[i], 0, 255];
+            }
+        }),
+        vk::Format::ETC2_R8G8B8_UNORM_BLOCK | vk::Format::ETC2_R8G8B8_SRGB_BLOCK =>
+            Box::new(|block, texels| decode_etc2(block, texels, false)),
+        vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK | vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK =>
+            Box::new(|block, texels| decode_etc2(block, texels, true)),
+        vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK | vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK => Box::new(|block, texels| {
+            decode_etc2(&block[8..], texels, false);
+            let alpha = decode_eac(&block[..8], false);
+            for (texel, alpha) in texels.iter_mut().zip(alpha.iter()) {
+                texel[3] = (*alpha >> 3) as u8;
+            }
+        }),
+        vk::Format::EAC_R11_UNORM_BLOCK => Box::new(|block, texels| {
+            let red = decode_eac(block, true);
+            for (texel, red) in texels.iter_mut().zip(red.iter()) {
+                *texel = [(*red >> 3) as u8, 0, 0, 255];
+            }
+        }),
+        vk::Format::EAC_R11G11_UNORM_BLOCK => Box::new(|block, texels| {
+            let (red, green) = (decode_eac(&block[..8], true), decode_eac(&block[8..], true));
+            for (i, texel) in texels.iter_mut().enumerate() {
+                *texel = [(red[i] >> 3) as u8, (green[i] >> 3) as u8, 0, 255];
+            }
+        }),
+        vk::Format::BC7_UNORM_BLOCK | vk::Format::BC7_SRGB_BLOCK => Box::new(bc7::decode_block),
+        _ if is_astc(format) => {
+            let (width, height, srgb) = (block.width as usize, block.height as usize, is_srgb(format));
+            Box::new(move |block, texels| astc::decode_block(block, width, height, srgb, texels))
+        },
+        _ => return None,
+    };
+
+    let (block_width, block_height) = (block.width as usize, block.height as usize);
+    let (blocks_x, blocks_y) = (width.div_ceil(block.width) as usize, height.div_ceil(block.height) as usize);
+    if data.len() < blocks_x * blocks_y * block.bytes as usize {
+        return None;
+    }
+    let mut rgba = vec![0u8; width as usize * height as usize * 4];
+    let mut texels = vec![[0u8; 4]; block_width * block_height];
+    for (index, block) in data.chunks_exact(block.bytes as usize).take(blocks_x * blocks_y).enumerate() {
+        decode_block(block, &mut texels);
+        let (block_x, block_y) = ((index % blocks_x) * block_width, (index / blocks_x) * block_height);
+        for (i, texel) in texels.iter().enumerate() {
+            let (x, y) = (block_x + i % block_width, block_y + i / block_width);
+            if x < width as usize && y < height as usize {
+                let offset = (y * width as usize + x) * 4;
+                rgba[offset..offset + 4].copy_from_slice(texel);
+            }
+        }
+    }
+    Some(rgba)
+}
+
+fn is_astc(format : vk::Format) -> bool {
+    (vk::Format::ASTC_4X4_UNORM_BLOCK.as_raw()..=vk::Format::ASTC_12X12_SRGB_BLOCK.as_raw()).contains(&format.as_raw())
+}
+
+fn expand_565(color : u16) -> [u8; 3] {
+    let (r, g, b) = ((color >> 11) as u8, ((color >> 5) & 63) as u8, (color & 31) as u8);
+    [(r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2)]
+}
+
+/// Decodes a BC1 color block. `punchthrough` enables the three color mode with transparent black, which BC2 and BC3
+/// color blocks do not use.
+fn decode_bc1(block : &[u8], texels : &mut [[u8; 4]], punchthrough : bool) {
+    let (color0, color1) = (u16::from_le_bytes([block[0], block[1]]), u16::from_le_bytes([block[2], block[3]]));
+    let (c0, c1) = (expand_565(color0), expand_565(color1));
+    let mix = |a : u8, b : u8, wa : u16, wb : u16| ((a as u16 * wa + b as u16 * wb) / (wa + wb)) as u8;
+    let mut palette = [[c0[0], c0[1], c0[2], 255], [c1[0], c1[1], c1[2], 255], [0; 4], [0; 4]];
+    if color0 > color1 || !punchthrough {
+        for i in 0..3 {
+            palette[2][i] = mix(c0[i], c1[i], 2, 1);
+            palette[3][i] = mix(c0[i], c1[i], 1, 2);
+        }
+        palette[2][3] = 255;
+        palette[3][3] = 255;
+    } else {
+        for i in 0..3 {
+            palette[2][i] = mix(c0[i], c1[i], 1, 1);
+        }
+        palette[2][3] = 255;
+    }
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    for (i, texel) in texels.iter_mut().enumerate() {
+        *texel = palette[((indices >> (i * 2)) & 3) as usize];
+    }
+}
+
+fn decode_bc2(block : &[u8], texels : &mut [[u8; 4]]) {
+    decode_bc1(&block[8..], texels, false);
+    let alpha = u64::from_le_bytes([block[0], block[1], block[2], block[3], block[4], block[5], block[6], block[7]]);
+    for (i, texel) in texels.iter_mut().enumerate() {
+        texel[3] = ((alpha >> (i * 4)) & 15) as u8 * 17;
+    }
+}
+
+fn decode_bc3(block : &[u8], texels : &mut [[u8; 4]]) {
+    decode_bc1(&block[8..], texels, false);
+    let alpha = decode_bc4(&block[..8]);
+    for (texel, alpha) in texels.iter_mut().zip(alpha.iter()) {
+        texel[3] = *alpha;
+    }
+}
+
+/// Decodes a single channel BC4 block, which is also the alpha block of BC3 and each channel of BC5.
+fn decode_bc4(block : &[u8]) -> [u8; 16] {
+    let (a0, a1) = (block[0] as u32, block[1] as u32);
+    let mut palette = [a0, a1, 0, 0, 0, 0, 0, 255];
+    if a0 > a1 {
+        for i in 1..7 {
+            palette[i + 1] = ((7 - i as u32) * a0 + i as u32 * a1) / 7;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = ((5 - i as u32) * a0 + i as u32 * a1) / 5;
+        }
+        palette[6] = 0;
+    }
+    let mut bits = 0u64;
+    for (i, byte) in block[2..8].iter().enumerate() {
+        bits |= (*byte as u64) << (i * 8);
+    }
+    let mut values = [0u8; 16];
+    for (i, value) in values.iter_mut().enumerate() {
+        *value = palette[((bits >> (i * 3)) & 7) as usize] as u8;
+    }
+    values
+}
+
+/// The intensity modifiers of ETC1 and ETC2 individual and differential modes, for pixel indices 0 and 1. Indices 2
+/// and 3 negate them.
+const ETC_MODIFIERS : [[i32; 2]; 8] = [[2, 8], [5, 17], [9, 29], [13, 42], [18, 60], [24, 80], [33, 106], [47, 183]];
+/// The distances used by the T and H modes of ETC2.
+const ETC_DISTANCES : [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+/// The modifiers of EAC alpha and R11 blocks.
+const EAC_MODIFIERS : [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8]];
+
+fn read_u64_be(block : &[u8]) -> u64 {
+    u64::from_be_bytes([block[0], block[1], block[2], block[3], block[4], block[5], block[6], block[7]])
+}
+
+fn clamp_u8(value : i32) -> u8 {
+    value.clamp(0, 255) as u8
+}
+
+/// Decodes an ETC2 RGB block, which is a superset of ETC1. With `punchthrough` the differential bit instead marks the
+/// block as opaque, and non-opaque blocks use pixel index 2 for transparent black.
+fn decode_etc2(block : &[u8], texels : &mut [[u8; 4]], punchthrough : bool) {
+    let bits = read_u64_be(block);
+    let field = |high : u32, low : u32| ((bits >> low) & ((1 << (high - low + 1)) - 1)) as i32;
+    let differential = field(33, 33) == 1;
+    let opaque = !punchthrough || differential;
+    // Pixels are indexed column by column, with the high bit of each index 16 bits above the low bit.
+    let index = |x : usize, y : usize| {
+        let bit = x * 4 + y;
+        (((bits >> (bit + 16)) & 1) << 1 | ((bits >> bit) & 1)) as usize
+    };
+    let extend4 = |value : i32| value << 4 | value;
+    let extend5 = |value : i32| value << 3 | value >> 2;
+
+    if punchthrough || differential {
+        let signed3 = |value : i32| if value >= 4 { value - 8 } else { value };
+        let (red, green, blue) = (field(63, 59), field(55, 51), field(47, 43));
+        let (red2, green2, blue2) = (red + signed3(field(58, 56)), green + signed3(field(50, 48)), blue + signed3(field(42, 40)));
+        if !(0..32).contains(&red2) {
+            // T mode.
+            let c1 = [extend4(field(60, 59) << 2 | field(57, 56)), extend4(field(55, 52)), extend4(field(51, 48))];
+            let c2 = [extend4(field(47, 44)), extend4(field(43, 40)), extend4(field(39, 36))];
+            let distance = ETC_DISTANCES[(field(35, 34) << 1 | field(32, 32)) as usize];
+            let paint = [c1, c2.map(|c| c + distance), c2, c2.map(|c| c - distance)];
+            paint_texels(texels, &paint, index, opaque);
+            return;
+        }
+        if !(0..32).contains(&green2) {
+            // H mode.
+            let c1 = [
+                extend4(field(62, 59)),
+                extend4(field(58, 56) << 1 | field(52, 52)),
+                extend4(field(51, 51) << 3 | field(49, 47))];
+            let c2 = [extend4(field(46, 43)), extend4(field(42, 39)), extend4(field(38, 35))];
+            let packed = |c : [i32; 3]| (c[0] >> 4) << 8 | (c[1] >> 4) << 4 | c[2] >> 4;
+            let order = (packed(c1) >= packed(c2)) as i32;
+            let distance = ETC_DISTANCES[(field(34, 34) << 2 | field(32, 32) << 1 | order) as usize];
+            let paint = [c1.map(|c| c + distance), c1.map(|c| c - distance), c2.map(|c| c + distance), c2.map(|c| c - distance)];
+            paint_texels(texels, &paint, index, opaque);
+            return;
+        }
+        if !(0..32).contains(&blue2) {
+            // Planar mode, which is always opaque.
+            let extend6 = |value : i32| value << 2 | value >> 4;
+            let extend7 = |value : i32| value << 1 | value >> 6;
+            let origin = [
+                extend6(field(62, 57)),
+                extend7(field(56, 56) << 6 | field(54, 49)),
+                extend6(field(48, 48) << 5 | field(44, 43) << 3 | field(41, 39))];
+            let horizontal = [extend6(field(38, 34) << 1 | field(32, 32)), extend7(field(31, 25)), extend6(field(24, 19))];
+            let vertical = [extend6(field(18, 13)), extend7(field(12, 6)), extend6(field(5, 0))];
+            for (i, texel) in texels.iter_mut().enumerate() {
+                let (x, y) = ((i % 4) as i32, (i / 4) as i32);
+                for c in 0..3 {
+                    let value = x * (horizontal[c] - origin[c]) + y * (vertical[c] - origin[c]) + 4 * origin[c] + 2;
+                    texel[c] = clamp_u8(value >> 2);
+                }
+                texel[3] = 255;
+            }
+            return;
+        }
+        let bases = [
+            [extend5(red), extend5(green), extend5(blue)],
+            [extend5(red2), extend5(green2), extend5(blue2)]];
+        decode_etc_subblocks(texels, bits, bases, index, opaque);
+    } else {
+        let bases = [
+            [extend4(field(63, 60)), extend4(field(55, 52)), extend4(field(47, 44))],
+            [extend4(field(59, 56)), extend4(field(51, 48)), extend4(field(43, 40))]];
+        decode_etc_subblocks(texels, bits, bases, index, true);
+    }
+}
+
+/// Decodes the individual and differential modes, where each half of the block has a base color and modifier table.
+fn decode_etc_subblocks<F : Fn(usize, usize) -> usize>(texels : &mut [[u8; 4]],
+                                                       bits : u64,
+                                                       bases : [[i32; 3]; 2],
+                                                       index : F,
+                                                       opaque : bool) {
+    let tables = [((bits >> 37) & 7) as usize, ((bits >> 34) & 7) as usize];
+    let flip = (bits >> 32) & 1 == 1;
+    for (i, texel) in texels.iter_mut().enumerate() {
+        let (x, y) = (i % 4, i / 4);
+        let subblock = if flip { (y >= 2) as usize } else { (x >= 2) as usize };
+        let [small, large] = ETC_MODIFIERS[tables[subblock]];
+        let pixel_index = index(x, y);
+        if !opaque && pixel_index == 2 {
+            *texel = [0; 4];
+            continue;
+        }
+        let modifier = match pixel_index {
+            0 if opaque => small,
+            0 => 0,
+            1 => large,
+            2 => -small,
+            _ => -large,
+        };
+        let base = bases[subblock];
+        *texel = [clamp_u8(base[0] + modifier), clamp_u8(base[1] + modifier), clamp_u8(base[2] + modifier), 255];
+    }
+}
+
+/// Writes the paint color selected by each pixel index in the T and H modes.
+fn paint_texels<F : Fn(usize, usize) -> usize>(texels : &mut [[u8; 4]],
+                                               paint : &[[i32; 3]; 4],
+                                               index : F,
+                                               opaque : bool) {
+    for (i, texel) in texels.iter_mut().enumerate() {
+        let pixel_index = index(i % 4, i / 4);
+        *texel = if !opaque && pixel_index == 2 {
+            [0; 4]
+        } else {
+            let color = paint[pixel_index];
+            [clamp_u8(color[0]), clamp_u8(color[1]), clamp_u8(color[2]), 255]
+        };
+    }
+}
+
+/// Decodes an EAC block to 11-bit values in row-major order. Alpha blocks of ETC2 RGBA8 are decoded as 8-bit values
+/// scaled up by 8, so both can be reduced to 8 bits with a shift.
+fn decode_eac(block : &[u8], eleven_bit : bool) -> [u16; 16] {
+    let bits = read_u64_be(block);
+    let base = block[0] as i32;
+    let multiplier = (block[1] >> 4) as i32;
+    let modifiers = EAC_MODIFIERS[(block[1] & 15) as usize];
+    let mut values = [0u16; 16];
+    for (i, value) in values.iter_mut().enumerate() {
+        // Pixels are stored column by column, starting from the most significant bits.
+        let (x, y) = (i % 4, i / 4);
+        let modifier = modifiers[((bits >> (45 - (x * 4 + y) * 3)) & 7) as usize];
+        *value = if eleven_bit {
+            let scaled = if multiplier == 0 { modifier } else { modifier * multiplier * 8 };
+            (base * 8 + 4 + scaled).clamp(0, 2047) as u16
+        } else {
+            ((base + modifier * multiplier).clamp(0, 255) << 3) as u16
+        };
+    }
+    values
+}
@@ -0,0 +1,146 @@
+use ash::vk;
+use super::{level_size, ImageData, ImageError};
+
+pub const MAGIC : &[u8] = b"DDS ";
+
+/// The offset of the data after the magic number and header, without the DX10 extension.
+const DATA_OFFSET : usize = 128;
+const DX10_HEADER_SIZE : usize = 20;
+
+const PIXEL_FORMAT_ALPHA : u32 = 0x1;
+const PIXEL_FORMAT_FOURCC : u32 = 0x4;
+const PIXEL_FORMAT_RGB : u32 = 0x40;
+const CAPS2_CUBEMAP : u32 = 0x200;
+const CAPS2_VOLUME : u32 = 0x20_0000;
+const DIMENSION_TEXTURE2D : u32 = 3;
+
+fn read_u32(bytes : &[u8], offset : usize) -> u32 {
+    u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+/// Maps the DXGI formats which have a Vulkan equivalent.
+fn dxgi_format(dxgi : u32) -> Option<vk::Format> {
+    Some(match dxgi {
+        2 => vk::Format::R32G32B32A32_SFLOAT,
+        10 => vk::Format::R16G16B16A16_SFLOAT,
+        28 => vk::Format::R8G8B8A8_UNORM,
+        29 => vk::Format::R8G8B8A8_SRGB,
+        71 => vk::Format::BC1_RGBA_UNORM_BLOCK,
+        72 => vk::Format::BC1_RGBA_SRGB_BLOCK,
+        74 => vk::Format::BC2_UNORM_BLOCK,
+        75 => vk::Format::BC2_SRGB_BLOCK,
+        77 => vk::Format::BC3_UNORM_BLOCK,
+        78 => vk::Format::BC3_SRGB_BLOCK,
+        80 => vk::Format::BC4_UNORM_BLOCK,
+        81 => vk::Format::BC4_SNORM_BLOCK,
+        83 => vk::Format::BC5_UNORM_BLOCK,
+        84 => vk::Format::BC5_SNORM_BLOCK,
+        87 => vk::Format::B8G8R8A8_UNORM,
+        91 => vk::Format::B8G8R8A8_SRGB,
+        95 => vk::Format::BC6H_UFLOAT_BLOCK,
+        96 => vk::Format::BC6H_SFLOAT_BLOCK,
+        98 => vk::Format::BC7_UNORM_BLOCK,
+        99 => vk::Format::BC7_SRGB_BLOCK,
+        _ => return None,
+    })
+}
+
+/// Maps the four character codes used by DDS files without the DX10 extension.
+fn four_cc_format(four_cc : &[u8]) -> Option<vk::Format> {
+    Some(match four_cc {
+        b"DXT1" => vk::Format::BC1_RGBA_UNORM_BLOCK,
+        b"DXT2" | b"DXT3" => vk::Format::BC2_UNORM_BLOCK,
+        b"DXT4" | b"DXT5" => vk::Format::BC3_UNORM_BLOCK,
+        b"ATI1" | b"BC4U" => vk::Format::BC4_UNORM_BLOCK,
+        b"BC4S" => vk::Format::BC4_SNORM_BLOCK,
+        b"ATI2" | b"BC5U" => vk::Format::BC5_UNORM_BLOCK,
+        b"BC5S" => vk::Format::BC5_SNORM_BLOCK,
+        _ => return None,
+    })
+}
+
+/// Reads every mip level of a 2D DDS texture. Block-compressed data is kept as it is, while uncompressed RGB and RGBA
+/// data described by bit masks is converted to 8-bit RGBA. Cubemaps, arrays and volume textures are not supported.
+pub fn decode(bytes : &[u8]) -> Result<ImageData, ImageError> {
+    if bytes.len() < DATA_OFFSET || read_u32(bytes, 4) != 124 {
+        return Err(ImageError::Corrupt("DDS header is too short"));
+    }
+    let height = read_u32(bytes, 12);
+    let width = read_u32(bytes, 16);
+    let level_count = read_u32(bytes, 28).max(1);
+    let pixel_flags = read_u32(bytes, 80);
+    let four_cc = &bytes[84..88];
+    let caps2 = read_u32(bytes, 112);
+    if width == 0 || height == 0 || level_count > 32 {
+        return Err(ImageError::Corrupt("DDS has an invalid size"));
+    }
+    if caps2 & (CAPS2_CUBEMAP | CAPS2_VOLUME) != 0 {
+        return Err(ImageError::Unsupported("DDS cubemaps and volume textures"));
+    }
+
+    // Uncompressed data is converted, so the masks are applied to each pixel as it is read.
+    let mut masks = None;
+    let (format, mut offset) = if pixel_flags & PIXEL_FORMAT_FOURCC != 0 && four_cc == b"DX10" {
+        if bytes.len() < DATA_OFFSET + DX10_HEADER_SIZE {
+            return Err(ImageError::Corrupt("DDS DX10 header is too short"));
+        }
+        if read_u32(bytes, 132) != DIMENSION_TEXTURE2D || read_u32(bytes, 140) > 1 {
+            return Err(ImageError::Unsupported("DDS arrays and textures other than 2D"));
+        }
+        let format = dxgi_format(read_u32(bytes, 128)).ok_or(ImageError::Unsupported("DXGI format"))?;
+        (format, DATA_OFFSET + DX10_HEADER_SIZE)
+    } else if pixel_flags & PIXEL_FORMAT_FOURCC != 0 {
+        (four_cc_format(four_cc).ok_or(ImageError::Unsupported("DDS four character code"))?, DATA_OFFSET)
+    } else if pixel_flags & PIXEL_FORMAT_RGB != 0 {
+        let bit_count = read_u32(bytes, 88);
+        if bit_count != 24 && bit_count != 32 {
+            return Err(ImageError::Unsupported("DDS RGB data other than 24 or 32 bits per pixel"));
+        }
+        let alpha = if pixel_flags & PIXEL_FORMAT_ALPHA != 0 { read_u32(bytes, 104) } else { 0 };
+        masks = Some((bit_count / 8, [read_u32(bytes, 92), read_u32(bytes, 96), read_u32(bytes, 100), alpha]));
+        (vk::Format::R8G8B8A8_UNORM, DATA_OFFSET)
+    } else {
+        return Err(ImageError::Unsupported("DDS pixel format"));
+    };
+
+    let mut levels = Vec::with_capacity(level_count as usize);
+    for level in 0..level_count {
+        let (level_width, level_height) = ((width >> level).max(1), (height >> level).max(1));
+        let size = match masks {
+            Some((pixel_size, _)) => (level_width as usize)
+                .checked_mul(level_height as usize)
+                .and_then(|pixels| pixels.checked_mul(pixel_size as usize))
+                .ok_or(ImageError::Corrupt("DDS level size overflows"))?,
+            None => level_size(format, level_width, level_height).ok_or(ImageError::Unsupported("DDS format"))?,
+        };
+        let data = offset
+            .checked_add(size)
+            .and_then(|end| bytes.get(offset..end))
+            .ok_or(ImageError::Corrupt("DDS level extends past the end of the file"))?;
+        offset += size;
+        levels.push(match masks {
+            Some((pixel_size, masks)) => unpack_masked(data, pixel_size as usize, masks),
+            None => data.to_vec(),
+        });
+    }
+    Ok(ImageData { width, height, format, levels })
+}
+
+/// Converts pixels whose channels are described by bit masks to 8-bit RGBA. A channel without a mask reads as 255
+/// for alpha and 0 otherwise.
+fn unpack_masked(data : &[u8], pixel_size : usize, masks : [u32; 4]) -> Vec<u8> {
+    let mut rgba = Vec::with_capacity(data.len() / pixel_size * 4);
+    for pixel in data.chunks_exact(pixel_size) {
+        let value = pixel.iter().rev().fold(0u32, |value, byte| value << 8 | *byte as u32);
+        for (channel, mask) in masks.iter().enumerate() {
+            rgba.push(if *mask == 0 {
+                if channel == 3 { 255 } else { 0 }
+            } else {
+                let bits = ((value & mask) >> mask.trailing_zeros()) as u64;
+                let max = (mask >> mask.trailing_zeros()) as u64;
+                (bits * 255 / max) as u8
+            });
+        }
+    }
+    rgba
+}
@@ -0,0 +1,104 @@
+use ash::vk;
+use super::{ImageData, ImageError};
+
+/// Decodes a Radiance RGBE image, with or without run length encoding, to 32-bit float RGBA. Only the standard
+/// top-to-bottom, left-to-right orientation is supported.
+pub fn decode(bytes : &[u8]) -> Result<ImageData, ImageError> {
+    // The header is a series of lines ending with an empty line, followed by the resolution line.
+    let mut offset = 0;
+    let mut next_line = || -> Result<&[u8], ImageError> {
+        let length = bytes[offset..]
+            .iter()
+            .position(|byte| *byte == b'\n')
+            .ok_or(ImageError::Corrupt("HDR header is not terminated"))?;
+        let line = &bytes[offset..offset + length];
+        offset += length + 1;
+        Ok(line)
+    };
+    loop {
+        let line = next_line()?;
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with(b"FORMAT=") && line != b"FORMAT=32-bit_rle_rgbe" {
+            return Err(ImageError::Unsupported("HDR pixel format other than RGBE"));
+        }
+    }
+    let resolution = String::from_utf8_lossy(next_line()?).into_owned();
+    let parts : Vec<&str> = resolution.split_whitespace().collect();
+    let (height, width) = match parts.as_slice() {
+        ["-Y", height, "+X", width] => (
+            height.parse::<usize>().map_err(|_| ImageError::Corrupt("HDR height is invalid"))?,
+            width.parse::<usize>().map_err(|_| ImageError::Corrupt("HDR width is invalid"))?),
+        _ => return Err(ImageError::Unsupported("HDR orientation")),
+    };
+    if width == 0 || height == 0 {
+        return Err(ImageError::Corrupt("HDR has no pixels"));
+    }
+    ImageData::check_dimensions(width, height)?;
+
+    let mut data = &bytes[offset..];
+    let mut pixels = Vec::with_capacity(width * height * 16);
+    let mut scanline = vec![0u8; width * 4];
+    for _ in 0..height {
+        data = read_scanline(data, &mut scanline, width)?;
+        for rgbe in scanline.chunks_exact(4) {
+            let scale = if rgbe[3] == 0 { 0.0 } else { 2f32.powi(rgbe[3] as i32 - 136) };
+            for value in &[rgbe[0] as f32 * scale, rgbe[1] as f32 * scale, rgbe[2] as f32 * scale, 1.0] {
+                pixels.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+    Ok(ImageData {
+        width: width as u32,
+        height: height as u32,
+        format: vk::Format::R32G32B32A32_SFLOAT,
+        levels: vec![pixels],
+    })
+}
+
+/// Reads one scanline of RGBE pixels into `scanline`, returning the remaining data.
+fn read_scanline<'a>(data : &'a [u8], scanline : &mut [u8], width : usize) -> Result<&'a [u8], ImageError> {
+    let run_length = (8..0x8000).contains(&width)
+        && data.len() >= 4
+        && data[0] == 2
+        && data[1] == 2
+        && ((data[2] as usize) << 8 | data[3] as usize) == width;
+    if !run_length {
+        let flat = data.get(..width * 4).ok_or(ImageError::Corrupt("HDR pixel data is short"))?;
+        scanline.copy_from_slice(flat);
+        return Ok(&data[width * 4..]);
+    }
+
+    // Each channel is stored separately as runs of a repeated byte or literal bytes.
+    let mut data = &data[4..];
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = *data.first().ok_or(ImageError::Corrupt("HDR pixel data is short"))? as usize;
+            if count > 128 {
+                let count = count - 128;
+                let value = *data.get(1).ok_or(ImageError::Corrupt("HDR pixel data is short"))?;
+                if x + count > width {
+                    return Err(ImageError::Corrupt("HDR run extends past the end of the scanline"));
+                }
+                for pixel in x..x + count {
+                    scanline[pixel * 4 + channel] = value;
+                }
+                x += count;
+                data = &data[2..];
+            } else {
+                if count == 0 || x + count > width {
+                    return Err(ImageError::Corrupt("HDR run extends past the end of the scanline"));
+                }
+                let values = data.get(1..1 + count).ok_or(ImageError::Corrupt("HDR pixel data is short"))?;
+                for (i, value) in values.iter().enumerate() {
+                    scanline[(x + i) * 4 + channel] = *value;
+                }
+                x += count;
+                data = &data[1 + count..];
+            }
+        }
+    }
+    Ok(data)
+}
@@ -0,0 +1,671 @@
+use std::{f32::consts::PI, sync::OnceLock};
+use super::{ImageData, ImageError};
+
+/// Maps the position of a coefficient in the encoded order to its position in the block.
+const ZIGZAG : [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20, 13, 6, 7, 14, 21,
+    28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61, 54,
+    47, 55, 62, 63];
+
+#[derive(Clone, Default)]
+struct HuffmanTable {
+    values : Vec<u8>,
+    /// The largest code of each length, or -1 if there are none, indexed by length.
+    max_code : [i32; 17],
+    /// The smallest code of each length.
+    min_code : [i32; 17],
+    /// The index in `values` of the smallest code of each length.
+    first_value : [usize; 17],
+}
+
+impl HuffmanTable {
+    fn new(counts : &[u8], values : &[u8]) -> Self {
+        let mut table = HuffmanTable { values: values.to_vec(), max_code: [-1; 17], ..Default::default() };
+        let (mut code, mut index) = (0i32, 0usize);
+        for length in 1..=16 {
+            let count = counts[length - 1] as usize;
+            if count > 0 {
+                table.first_value[length] = index;
+                table.min_code[length] = code;
+                code += count as i32;
+                index += count;
+                table.max_code[length] = code - 1;
+            }
+            code <<= 1;
+        }
+        table
+    }
+}
+
+struct Component {
+    id : u8,
+    horizontal : usize,
+    vertical : usize,
+    quantization : usize,
+    /// The width of `pixels`, which covers whole MCUs.
+    stride : usize,
+    pixels : Vec<u8>,
+    /// The quantized coefficients of each block in natural order, which progressive scans add to one by one. This is
+    /// empty for sequential frames, whose blocks are transformed as soon as they are decoded.
+    coefficients : Vec<i32>,
+    dc_table : usize,
+    ac_table : usize,
+    prediction : i32,
+}
+
+/// Reads bits from entropy coded data, removing stuffed zero bytes. Reading past the end or into a marker returns zeros.
+struct BitReader<'a> {
+    data : &'a [u8],
+    position : usize,
+    bits : u32,
+    count : u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data : &'a [u8]) -> Self {
+        Self { data, position: 0, bits: 0, count: 0 }
+    }
+
+    fn bit(&mut self) -> u32 {
+        if self.count == 0 {
+            let mut byte = 0;
+            if let Some(&next) = self.data.get(self.position) {
+                if next != 0xff {
+                    byte = next;
+                    self.position += 1;
+                } else if self.data.get(self.position + 1) == Some(&0) {
+                    byte = 0xff;
+                    self.position += 2;
+                }
+            }
+            self.bits = byte as u32;
+            self.count = 8;
+        }
+        self.count -= 1;
+        (self.bits >> self.count) & 1
+    }
+
+    fn receive(&mut self, length : u8) -> i32 {
+        (0..length).fold(0, |value, _| (value << 1) | self.bit() as i32)
+    }
+
+    /// Reads `length` bits and sign extends them to a coefficient.
+    fn receive_extend(&mut self, length : u8) -> i32 {
+        if length == 0 {
+            return 0;
+        }
+        let value = self.receive(length);
+        if value < 1 << (length - 1) { value - (1 << length) + 1 } else { value }
+    }
+
+    fn decode(&mut self, table : &HuffmanTable) -> Result<u8, ImageError> {
+        let mut code = 0;
+        for length in 1..=16 {
+            code = (code << 1) | self.bit() as i32;
+            if code <= table.max_code[length] {
+                let index = table.first_value[length] + (code - table.min_code[length]) as usize;
+                return table.values.get(index).copied().ok_or(ImageError::Corrupt("JPEG Huffman code has no value"));
+            }
+        }
+        Err(ImageError::Corrupt("JPEG contains an invalid Huffman code"))
+    }
+
+    /// Discards any buffered bits and skips the restart marker which should follow.
+    fn restart(&mut self) {
+        self.count = 0;
+        if self.data.get(self.position) == Some(&0xff)
+            && self.data.get(self.position + 1).is_some_and(|marker| (0xd0..=0xd7).contains(marker)) {
+            self.position += 2;
+        }
+    }
+}
+
+/// Decodes a baseline, extended sequential or progressive JPEG with Huffman coding to 8-bit RGBA. Hierarchical,
+/// lossless and arithmetic coded images are not supported.
+pub fn decode(bytes : &[u8]) -> Result<ImageData, ImageError> {
+    let mut quantization = [[0u16; 64]; 4];
+    let mut dc_tables = vec![HuffmanTable::default(); 4];
+    let mut ac_tables = vec![HuffmanTable::default(); 4];
+    let mut components : Vec<Component> = Vec::new();
+    let (mut width, mut height) = (0, 0);
+    let (mut max_horizontal, mut max_vertical) = (1, 1);
+    let mut restart_interval = 0;
+    let mut progressive = false;
+
+    let mut offset = 2;
+    loop {
+        // Skip fill bytes before the marker.
+        while bytes.get(offset) == Some(&0xff) && bytes.get(offset + 1) == Some(&0xff) {
+            offset += 1;
+        }
+        if offset + 2 > bytes.len() || bytes[offset] != 0xff {
+            return Err(ImageError::Corrupt("JPEG ends before the end of image marker"));
+        }
+        let marker = bytes[offset + 1];
+        if marker == 0xd9 {
+            break;
+        }
+        if offset + 4 > bytes.len() {
+            return Err(ImageError::Corrupt("JPEG ends before the end of image marker"));
+        }
+        let length = u16::from_be_bytes([bytes[offset + 2], bytes[offset + 3]]) as usize;
+        let segment = bytes
+            .get(offset + 4..offset + 2 + length)
+            .ok_or(ImageError::Corrupt("JPEG segment extends past the end of the file"))?;
+        offset += 2 + length;
+
+        match marker {
+            // Define quantization tables.
+            0xdb => {
+                let mut data = segment;
+                while !data.is_empty() {
+                    let (precision, index) = (data[0] >> 4, (data[0] & 3) as usize);
+                    let size = if precision == 0 { 64 } else { 128 };
+                    let values = data.get(1..1 + size).ok_or(ImageError::Corrupt("JPEG quantization table is short"))?;
+                    for (k, value) in quantization[index].iter_mut().enumerate() {
+                        *value = if precision == 0 {
+                            values[k] as u16
+                        } else {
+                            u16::from_be_bytes([values[k * 2], values[k * 2 + 1]])
+                        };
+                    }
+                    data = &data[1 + size..];
+                }
+            }
+            // Define Huffman tables.
+            0xc4 => {
+                let mut data = segment;
+                while data.len() >= 17 {
+                    let (class, index) = (data[0] >> 4, (data[0] & 3) as usize);
+                    let counts = &data[1..17];
+                    let total : usize = counts.iter().map(|count| *count as usize).sum();
+                    let values = data.get(17..17 + total).ok_or(ImageError::Corrupt("JPEG Huffman table is short"))?;
+                    let table = HuffmanTable::new(counts, values);
+                    if class == 0 {
+                        dc_tables[index] = table;
+                    } else {
+                        ac_tables[index] = table;
+                    }
+                    data = &data[17 + total..];
+                }
+            }
+            // Define restart interval.
+            0xdd => {
+                if segment.len() < 2 {
+                    return Err(ImageError::Corrupt("JPEG restart interval is short"));
+                }
+                restart_interval = u16::from_be_bytes([segment[0], segment[1]]) as usize;
+            }
+            // Baseline, extended sequential and progressive frames.
+            0xc0..=0xc2 => {
+                progressive = marker == 0xc2;
+                if segment.len() < 6 || segment[0] != 8 {
+                    return Err(ImageError::Unsupported("JPEG sample precision other than 8 bits"));
+                }
+                height = u16::from_be_bytes([segment[1], segment[2]]) as usize;
+                width = u16::from_be_bytes([segment[3], segment[4]]) as usize;
+                let count = segment[5] as usize;
+                if count != 1 && count != 3 {
+                    return Err(ImageError::Unsupported("JPEG with other than 1 or 3 components"));
+                }
+                if width == 0 || height == 0 || segment.len() < 6 + count * 3 {
+                    return Err(ImageError::Corrupt("JPEG frame header is invalid"));
+                }
+                ImageData::check_dimensions(width, height)?;
+                for i in 0..count {
+                    let data = &segment[6 + i * 3..9 + i * 3];
+                    let (horizontal, vertical) = ((data[1] >> 4) as usize, (data[1] & 15) as usize);
+                    if !(1..=4).contains(&horizontal) || !(1..=4).contains(&vertical) {
+                        return Err(ImageError::Corrupt("JPEG component has an invalid sampling factor"));
+                    }
+                    components.push(Component {
+                        id: data[0],
+                        horizontal,
+                        vertical,
+                        quantization: (data[2] & 3) as usize,
+                        stride: 0,
+                        pixels: Vec::new(),
+                        coefficients: Vec::new(),
+                        dc_table: 0,
+                        ac_table: 0,
+                        prediction: 0,
+                    });
+                }
+                max_horizontal = components.iter().map(|component| component.horizontal).max().unwrap();
+                max_vertical = components.iter().map(|component| component.vertical).max().unwrap();
+                let mcus_x = width.div_ceil(8 * max_horizontal);
+                let mcus_y = height.div_ceil(8 * max_vertical);
+                // Every block takes at least one bit to code, so a frame larger than the file can hold is corrupt.
+                let blocks = mcus_x * mcus_y * components.iter().map(|c| c.horizontal * c.vertical).sum::<usize>();
+                if blocks / 8 > bytes.len() {
+                    return Err(ImageError::Corrupt("JPEG frame is larger than its data"));
+                }
+                for component in &mut components {
+                    component.stride = mcus_x * component.horizontal * 8;
+                    component.pixels = vec![0; component.stride * mcus_y * component.vertical * 8];
+                    if progressive {
+                        component.coefficients = vec![0; component.pixels.len()];
+                    }
+                }
+            }
+            0xc3 | 0xc5 | 0xc6 | 0xc7 | 0xcb | 0xcd | 0xce | 0xcf => {
+                return Err(ImageError::Unsupported("lossless or hierarchical JPEG"));
+            }
+            0xc9 | 0xca => return Err(ImageError::Unsupported("arithmetic coded JPEG")),
+            // Start of scan, followed by entropy coded data up to the next marker other than a restart.
+            0xda => {
+                if components.is_empty() {
+                    return Err(ImageError::Corrupt("JPEG scan comes before the frame header"));
+                }
+                let count = segment.first().copied().unwrap_or(0) as usize;
+                if count == 0 || segment.len() < 4 + count * 2 {
+                    return Err(ImageError::Corrupt("JPEG scan header is invalid"));
+                }
+                // The spectral selection and successive approximation of progressive scans, which sequential scans
+                // always set to cover every coefficient in full.
+                let selection = &segment[1 + count * 2..];
+                let spectral = (selection[0] as usize, selection[1] as usize);
+                let approximation = (selection[2] >> 4, selection[2] & 15);
+                if progressive
+                    && (spectral.0 > spectral.1
+                        || spectral.1 > 63
+                        || (spectral.0 == 0) != (spectral.1 == 0)
+                        || (spectral.0 > 0 && count != 1)
+                        || approximation.1 > 13) {
+                    return Err(ImageError::Corrupt("JPEG progressive scan header is invalid"));
+                }
+                let mut scan = Vec::with_capacity(count);
+                for i in 0..count {
+                    let (id, tables) = (segment[1 + i * 2], segment[2 + i * 2]);
+                    let index = components
+                        .iter()
+                        .position(|component| component.id == id)
+                        .ok_or(ImageError::Corrupt("JPEG scan refers to an unknown component"))?;
+                    components[index].dc_table = (tables >> 4) as usize & 3;
+                    components[index].ac_table = (tables & 15) as usize & 3;
+                    scan.push(index);
+                }
+
+                let start = offset;
+                while offset + 1 < bytes.len()
+                    && !(bytes[offset] == 0xff && bytes[offset + 1] != 0 && !(0xd0..=0xd7).contains(&bytes[offset + 1])) {
+                    offset += 1;
+                }
+                let mut decoder = ScanDecoder {
+                    reader: BitReader::new(&bytes[start..offset]),
+                    quantization: &quantization,
+                    dc_tables: &dc_tables,
+                    ac_tables: &ac_tables,
+                    progression: if progressive { Some((spectral, approximation)) } else { None },
+                    end_of_band_run: 0,
+                };
+                decoder.decode(&mut components, &scan, width, height, max_horizontal, max_vertical, restart_interval)?;
+            }
+            _ => (),
+        }
+    }
+
+    if components.is_empty() {
+        return Err(ImageError::Corrupt("JPEG has no frame header"));
+    }
+    if progressive {
+        for component in &mut components {
+            transform_coefficients(component, &quantization[component.quantization]);
+        }
+    }
+    Ok(ImageData::rgba8(width as u32, height as u32, to_rgba(&components, width, height, max_horizontal, max_vertical)))
+}
+
+struct ScanDecoder<'a> {
+    reader : BitReader<'a>,
+    quantization : &'a [[u16; 64]; 4],
+    dc_tables : &'a [HuffmanTable],
+    ac_tables : &'a [HuffmanTable],
+    /// The first and last coefficient of the band, and the previous and current bit position, of a progressive scan.
+    progression : Option<((usize, usize), (u8, u8))>,
+    /// The number of blocks left in the band which have no further coefficients.
+    end_of_band_run : u32,
+}
+
+impl ScanDecoder<'_> {
+    #[allow(clippy::too_many_arguments)]
+    fn decode(&mut self,
+              components : &mut [Component],
+              scan : &[usize],
+              width : usize,
+              height : usize,
+              max_horizontal : usize,
+              max_vertical : usize,
+              restart_interval : usize) -> Result<(), ImageError> {
+        for &index in scan {
+            components[index].prediction = 0;
+        }
+
+        // A scan of one component is not interleaved, so its MCU is a single block and only covers the component's own
+        // size rather than whole MCUs of the frame.
+        let (mcus_x, mcus_y) = if scan.len() == 1 {
+            let component = &components[scan[0]];
+            ((width * component.horizontal).div_ceil(max_horizontal).div_ceil(8),
+             (height * component.vertical).div_ceil(max_vertical).div_ceil(8))
+        } else {
+            (width.div_ceil(8 * max_horizontal), height.div_ceil(8 * max_vertical))
+        };
+
+        let mut block = [0i32; 64];
+        for mcu in 0..mcus_x * mcus_y {
+            if restart_interval > 0 && mcu > 0 && mcu % restart_interval == 0 {
+                self.reader.restart();
+                self.end_of_band_run = 0;
+                for &index in scan {
+                    components[index].prediction = 0;
+                }
+            }
+            let (mcu_x, mcu_y) = (mcu % mcus_x, mcu / mcus_x);
+            for &index in scan {
+                let component = &mut components[index];
+                let (blocks_x, blocks_y) = if scan.len() == 1 {
+                    (1, 1)
+                } else {
+                    (component.horizontal, component.vertical)
+                };
+                for block_y in 0..blocks_y {
+                    for block_x in 0..blocks_x {
+                        let x = (mcu_x * blocks_x + block_x) * 8;
+                        let y = (mcu_y * blocks_y + block_y) * 8;
+                        if let Some(progression) = self.progression {
+                            self.refine_block(component, (y / 8 * component.stride / 8 + x / 8) * 64, progression)?;
+                        } else {
+                            self.decode_block(component, &mut block)?;
+                            inverse_dct(&block, &mut component.pixels[y * component.stride + x..], component.stride);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn decode_block(&mut self, component : &mut Component, block : &mut [i32; 64]) -> Result<(), ImageError> {
+        let quantization = &self.quantization[component.quantization];
+        *block = [0; 64];
+
+        let length = self.reader.decode(&self.dc_tables[component.dc_table])?;
+        component.prediction += self.reader.receive_extend(length);
+        block[0] = component.prediction * quantization[0] as i32;
+
+        let table = &self.ac_tables[component.ac_table];
+        let mut k = 1;
+        while k < 64 {
+            let symbol = self.reader.decode(table)?;
+            let (run, length) = ((symbol >> 4) as usize, symbol & 15);
+            if length == 0 {
+                if run != 15 {
+                    break;
+                }
+                k += 16;
+                continue;
+            }
+            k += run;
+            if k >= 64 {
+                return Err(ImageError::Corrupt("JPEG block has too many coefficients"));
+            }
+            block[ZIGZAG[k]] = self.reader.receive_extend(length) * quantization[k] as i32;
+            k += 1;
+        }
+        Ok(())
+    }
+
+    /// Adds the band of a progressive scan to the quantized coefficients of the block starting at `offset`. The first
+    /// scan of a band decodes its coefficients shifted up by the current bit position, and later scans refine them by
+    /// one bit each.
+    fn refine_block(&mut self,
+                    component : &mut Component,
+                    offset : usize,
+                    ((start, end), (previous_bit, bit)) : ((usize, usize), (u8, u8))) -> Result<(), ImageError> {
+        let block = &mut component.coefficients[offset..offset + 64];
+        if start == 0 {
+            if previous_bit == 0 {
+                let length = self.reader.decode(&self.dc_tables[component.dc_table])?;
+                component.prediction += self.reader.receive_extend(length);
+                block[0] = component.prediction << bit;
+            } else if self.reader.bit() == 1 {
+                block[0] |= 1 << bit;
+            }
+            return Ok(());
+        }
+
+        let table = &self.ac_tables[component.ac_table];
+        let mut k = start;
+        if previous_bit == 0 {
+            if self.end_of_band_run > 0 {
+                self.end_of_band_run -= 1;
+                return Ok(());
+            }
+            while k <= end {
+                let symbol = self.reader.decode(table)?;
+                let (run, length) = ((symbol >> 4) as usize, symbol & 15);
+                if length == 0 {
+                    if run != 15 {
+                        // This block is the first of the run.
+                        self.end_of_band_run = (1 << run) - 1 + self.reader.receive(run as u8) as u32;
+                        break;
+                    }
+                    k += 16;
+                    continue;
+                }
+                k += run;
+                if k > end {
+                    return Err(ImageError::Corrupt("JPEG block has too many coefficients"));
+                }
+                block[ZIGZAG[k]] = self.reader.receive_extend(length) * (1 << bit);
+                k += 1;
+            }
+            return Ok(());
+        }
+
+        // Coefficients which are already nonzero get a correction bit whenever they are passed over, while runs only
+        // count the coefficients which are still zero.
+        let correction = 1 << bit;
+        let refine = |reader : &mut BitReader, coefficient : &mut i32| {
+            if reader.bit() == 1 && *coefficient & correction == 0 {
+                *coefficient += if *coefficient >= 0 { correction } else { -correction };
+            }
+        };
+        if self.end_of_band_run == 0 {
+            while k <= end {
+                let symbol = self.reader.decode(table)?;
+                let (mut run, length) = ((symbol >> 4) as usize, symbol & 15);
+                let value = match length {
+                    0 if run != 15 => {
+                        // The run includes this block, whose remaining coefficients are refined below.
+                        self.end_of_band_run = (1 << run) + self.reader.receive(run as u8) as u32;
+                        break;
+                    }
+                    0 => 0,
+                    1 if self.reader.bit() == 1 => correction,
+                    1 => -correction,
+                    _ => return Err(ImageError::Corrupt("JPEG refinement has an invalid coefficient size")),
+                };
+                while k <= end {
+                    let coefficient = &mut block[ZIGZAG[k]];
+                    k += 1;
+                    if *coefficient != 0 {
+                        refine(&mut self.reader, coefficient);
+                    } else if run == 0 {
+                        *coefficient = value;
+                        break;
+                    } else {
+                        run -= 1;
+                    }
+                }
+            }
+        }
+        if self.end_of_band_run > 0 {
+            for k in k..=end {
+                let coefficient = &mut block[ZIGZAG[k]];
+                if *coefficient != 0 {
+                    refine(&mut self.reader, coefficient);
+                }
+            }
+            self.end_of_band_run -= 1;
+        }
+        Ok(())
+    }
+}
+
+/// Dequantizes and transforms the coefficients collected by progressive scans to samples.
+fn transform_coefficients(component : &mut Component, quantization : &[u16; 64]) {
+    let mut block = [0i32; 64];
+    for (index, coefficients) in component.coefficients.chunks_exact(64).enumerate() {
+        for (k, &position) in ZIGZAG.iter().enumerate() {
+            block[position] = coefficients[position] * quantization[k] as i32;
+        }
+        let blocks_x = component.stride / 8;
+        let (x, y) = (index % blocks_x * 8, index / blocks_x * 8);
+        inverse_dct(&block, &mut component.pixels[y * component.stride + x..], component.stride);
+    }
+}
+
+/// The basis of the inverse DCT, where `COSINES[x][u]` is `C(u) cos((2x + 1) u pi / 16) / 2`.
+static COSINES : OnceLock<[[f32; 8]; 8]> = OnceLock::new();
+
+/// Transforms a block of coefficients to samples, writing 8 rows of `output` separated by `stride`.
+fn inverse_dct(block : &[i32; 64], output : &mut [u8], stride : usize) {
+    let cosines = COSINES.get_or_init(|| {
+        let mut cosines = [[0f32; 8]; 8];
+        for (x, row) in cosines.iter_mut().enumerate() {
+            for (u, value) in row.iter_mut().enumerate() {
+                let scale = if u == 0 { 1.0 / 2f32.sqrt() } else { 1.0 };
+                *value = scale * ((2 * x + 1) as f32 * u as f32 * PI / 16.0).cos() / 2.0;
+            }
+        }
+        cosines
+    });
+
+    let mut rows = [0f32; 64];
+    for v in 0..8 {
+        for x in 0..8 {
+            rows[v * 8 + x] = (0..8).map(|u| cosines[x][u] * block[v * 8 + u] as f32).sum();
+        }
+    }
+    for y in 0..8 {
+        for x in 0..8 {
+            let value : f32 = (0..8).map(|v| cosines[y][v] * rows[v * 8 + x]).sum();
+            output[y * stride + x] = (value + 128.5).clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+/// Upsamples subsampled components and converts from YCbCr.
+fn to_rgba(components : &[Component], width : usize, height : usize, max_horizontal : usize, max_vertical : usize)
+    -> Vec<u8> {
+    let sample = |component : &Component, x : usize, y : usize| -> f32 {
+        let x = x * component.horizontal / max_horizontal;
+        let y = y * component.vertical / max_vertical;
+        component.pixels[y * component.stride + x] as f32
+    };
+
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    for y in 0..height {
+        for x in 0..width {
+            if components.len() == 1 {
+                let value = sample(&components[0], x, y) as u8;
+                rgba.extend_from_slice(&[value, value, value, 255]);
+            } else {
+                let luma = sample(&components[0], x, y);
+                let blue = sample(&components[1], x, y) - 128.0;
+                let red = sample(&components[2], x, y) - 128.0;
+                let convert = |value : f32| (value + 0.5).clamp(0.0, 255.0) as u8;
+                rgba.extend_from_slice(&[
+                    convert(luma + 1.402 * red),
+                    convert(luma - 0.344_136 * blue - 0.714_136 * red),
+                    convert(luma + 1.772 * blue),
+                    255]);
+            }
+        }
+    }
+    rgba
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRADIENT : &[u8] = include_bytes!("../../assets/tests/images/gradient.rgba");
+
+    /// Returns the largest difference between the decoded image and the gradient it was encoded from.
+    fn error(jpeg : &[u8]) -> u8 {
+        let image = decode(jpeg).unwrap();
+        assert_eq!((image.width, image.height), (21, 13));
+        assert_eq!(image.levels[0].len(), GRADIENT.len());
+        image.levels[0]
+            .iter()
+            .zip(GRADIENT)
+            .map(|(decoded, expected)| (*decoded as i16 - *expected as i16).unsigned_abs() as u8)
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn decodes_baseline_444() {
+        assert!(error(include_bytes!("../../assets/tests/images/baseline_444.jpg")) <= 8);
+    }
+
+    #[test]
+    fn decodes_baseline_420() {
+        assert!(error(include_bytes!("../../assets/tests/images/baseline_420.jpg")) <= 16);
+    }
+
+    #[test]
+    fn decodes_restart_markers() {
+        let restarted = decode(include_bytes!("../../assets/tests/images/restart_420.jpg")).unwrap();
+        let baseline = decode(include_bytes!("../../assets/tests/images/baseline_420.jpg")).unwrap();
+        assert_eq!(restarted.levels, baseline.levels);
+    }
+
+    /// Returns the largest and the mean difference between the decoded JPEG and the reference PNG in external/.
+    fn reference_error(jpeg : &[u8], png : &[u8]) -> (u8, f32) {
+        let (image, reference) = (decode(jpeg).unwrap(), super::super::png::decode(png).unwrap());
+        assert_eq!((image.width, image.height), (reference.width, reference.height));
+        let errors : Vec<u8> = image.levels[0]
+            .iter()
+            .zip(&reference.levels[0])
+            .map(|(decoded, expected)| (*decoded as i16 - *expected as i16).unsigned_abs() as u8)
+            .collect();
+        let mean = errors.iter().map(|error| *error as f32).sum::<f32>() / errors.len() as f32;
+        (*errors.iter().max().unwrap(), mean)
+    }
+
+    #[test]
+    fn decodes_progressive_scans_like_sequential_ones() {
+        // The progressive file is a lossless conversion of the baseline one, so both hold the same coefficients.
+        let baseline = decode(include_bytes!("../../assets/tests/images/external/tower.jpg")).unwrap();
+        let progressive = decode(include_bytes!("../../assets/tests/images/external/tower_progressive.jpg")).unwrap();
+        assert_eq!((progressive.width, progressive.height), (512, 512));
+        assert!(progressive.levels == baseline.levels);
+    }
+
+    #[test]
+    fn decodes_progressive_images_like_other_decoders() {
+        let (max, mean) = reference_error(include_bytes!("../../assets/tests/images/external/test.jpg"),
+                                          include_bytes!("../../assets/tests/images/external/test.expected.png"));
+        assert!(max <= 2 && mean < 0.1, "{} {}", max, mean);
+        // Chroma is upsampled by repeating samples rather than interpolating them, which differs along edges.
+        let (max, mean) = reference_error(include_bytes!("../../assets/tests/images/external/cat.jpg"),
+                                          include_bytes!("../../assets/tests/images/external/cat.expected.png"));
+        assert!(max <= 16 && mean < 0.5, "{} {}", max, mean);
+    }
+
+    #[test]
+    fn rejects_sizes_the_data_cannot_hold() {
+        let resized = |width : u16, height : u16| {
+            let mut jpeg = include_bytes!("../../assets/tests/images/baseline_444.jpg").to_vec();
+            let frame = jpeg.windows(2).position(|marker| marker == [0xff, 0xc0]).unwrap();
+            jpeg[frame + 5..frame + 7].copy_from_slice(&height.to_be_bytes());
+            jpeg[frame + 7..frame + 9].copy_from_slice(&width.to_be_bytes());
+            decode(&jpeg)
+        };
+        assert!(matches!(resized(65535, 13), Err(ImageError::Unsupported(_))));
+        assert!(matches!(resized(8000, 8000), Err(ImageError::Corrupt("JPEG frame is larger than its data"))));
+    }
+}
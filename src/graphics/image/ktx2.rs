@@ -0,0 +1,82 @@
+use std::io::Read;
+use ash::vk;
+use flate2::read::ZlibDecoder;
+use super::{level_size, ImageData, ImageError};
+
+pub const IDENTIFIER : &[u8] = &[0xab, b'K', b'T', b'X', b' ', b'2', b'0', 0xbb, b'\r', b'\n', 0x1a, b'\n'];
+
+const SUPERCOMPRESSION_NONE : u32 = 0;
+const SUPERCOMPRESSION_ZLIB : u32 = 3;
+
+/// The size of the identifier, header and index which precede the level index.
+const LEVEL_INDEX_OFFSET : usize = 80;
+
+fn read_u32(bytes : &[u8], offset : usize) -> u32 {
+    u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+fn read_u64(bytes : &[u8], offset : usize) -> u64 {
+    read_u32(bytes, offset) as u64 | (read_u32(bytes, offset + 4) as u64) << 32
+}
+
+/// Reads every mip level of a 2D KTX2 texture in its stored format, which is usually block-compressed. Levels may be
+/// supercompressed with zlib, but Basis Universal and Zstandard are not supported, nor are arrays, cubemaps and 3D
+/// textures.
+pub fn decode(bytes : &[u8]) -> Result<ImageData, ImageError> {
+    if bytes.len() < LEVEL_INDEX_OFFSET {
+        return Err(ImageError::Corrupt("KTX2 header is too short"));
+    }
+    let format = vk::Format::from_raw(read_u32(bytes, 12) as i32);
+    let width = read_u32(bytes, 20);
+    let height = read_u32(bytes, 24).max(1);
+    let depth = read_u32(bytes, 28);
+    let layers = read_u32(bytes, 32);
+    let faces = read_u32(bytes, 36);
+    // A level count of 0 asks the loader to generate mipmaps, so only the first level is stored.
+    let level_count = read_u32(bytes, 40).max(1);
+    let supercompression = read_u32(bytes, 44);
+
+    if format == vk::Format::UNDEFINED {
+        return Err(ImageError::Unsupported("KTX2 Basis Universal textures"));
+    }
+    if depth > 1 || layers > 1 || faces > 1 {
+        return Err(ImageError::Unsupported("KTX2 arrays, cubemaps and 3D textures"));
+    }
+    if supercompression != SUPERCOMPRESSION_NONE && supercompression != SUPERCOMPRESSION_ZLIB {
+        return Err(ImageError::Unsupported("KTX2 supercompression other than zlib"));
+    }
+    if width == 0 || level_count > 32 {
+        return Err(ImageError::Corrupt("KTX2 has an invalid size"));
+    }
+    if bytes.len() < LEVEL_INDEX_OFFSET + level_count as usize * 24 {
+        return Err(ImageError::Corrupt("KTX2 level index is too short"));
+    }
+
+    let mut levels = Vec::with_capacity(level_count as usize);
+    for level in 0..level_count {
+        let entry = LEVEL_INDEX_OFFSET + level as usize * 24;
+        let (offset, length) = (read_u64(bytes, entry) as usize, read_u64(bytes, entry + 8) as usize);
+        let data = offset
+            .checked_add(length)
+            .and_then(|end| bytes.get(offset..end))
+            .ok_or(ImageError::Corrupt("KTX2 level extends past the end of the file"))?;
+        let data = if supercompression == SUPERCOMPRESSION_ZLIB {
+            let mut inflated = Vec::new();
+            ZlibDecoder::new(data)
+                .read_to_end(&mut inflated)
+                .map_err(|_| ImageError::Corrupt("KTX2 level could not be inflated"))?;
+            inflated
+        } else {
+            data.to_vec()
+        };
+
+        let (level_width, level_height) = ((width >> level).max(1), (height >> level).max(1));
+        let expected = level_size(format, level_width, level_height)
+            .ok_or(ImageError::Unsupported("KTX2 format"))?;
+        if data.len() < expected {
+            return Err(ImageError::Corrupt("KTX2 level is smaller than its format requires"));
+        }
+        levels.push(data);
+    }
+    Ok(ImageData { width, height, format, levels })
+}
@@ -0,0 +1,395 @@
+use std::{fmt, fs, io, path::{Path, PathBuf}};
+use ash::vk;
+
+mod astc;
+mod bc7;
+mod compressed;
+mod dds;
+mod hdr;
+mod jpeg;
+mod ktx2;
+mod png;
+
+pub use self::compressed::{block_info, BlockInfo};
+
+/// The largest width or height an image may be decoded with, which is the `max_image_dimension2_d` of most desktop
+/// devices. Decoders check this before allocating any pixels, as the header alone does not bound the size of the file.
+pub const MAX_DIMENSION : u32 = 16384;
+
+/// Provides a brief overview of why an image could not be decoded.
+#[derive(Debug)]
+pub enum ImageError {
+    Io(PathBuf, io::Error),
+    /// The data does not start with the signature of any supported format.
+    UnknownFormat,
+    /// The file uses a feature of its format which is not supported, such as arithmetic coded JPEG or cubemaps.
+    Unsupported(&'static str),
+    /// The file is truncated or otherwise malformed.
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Io(path, error) => write!(f, "{}: {}", path.display(), error),
+            ImageError::UnknownFormat => write!(f, "unknown image format"),
+            ImageError::Unsupported(feature) => write!(f, "unsupported image feature: {}", feature),
+            ImageError::Corrupt(reason) => write!(f, "corrupt image: {}", reason),
+        }
+    }
+}
+
+/// Pixel data ready to be uploaded to an image. Decoded formats are either 8-bit RGBA, which is stored as
+/// `R8G8B8A8_UNORM` and may be treated as sRGB when uploaded, or 32-bit float RGBA for HDR images. Texture containers
+/// keep the format they were stored in, which is usually block-compressed.
+pub struct ImageData {
+    pub width : u32,
+    pub height : u32,
+    pub format : vk::Format,
+    /// The pixels of each mip level, largest first. Image decoders only produce the first level, while containers
+    /// provide every level they store.
+    pub levels : Vec<Vec<u8>>,
+}
+
+impl ImageData {
+    /// Decodes the image at `path`. The format is determined from the contents rather than the extension.
+    pub fn load(path : &Path) -> Result<Self, ImageError> {
+        let bytes = fs::read(path).map_err(|error| ImageError::Io(path.to_path_buf(), error))?;
+        Self::decode(&bytes)
+    }
+
+    /// Decodes a PNG, JPEG or Radiance HDR image, or reads a KTX2 or DDS texture.
+    pub fn decode(bytes : &[u8]) -> Result<Self, ImageError> {
+        if bytes.starts_with(ktx2::IDENTIFIER) {
+            ktx2::decode(bytes)
+        } else if bytes.starts_with(dds::MAGIC) {
+            dds::decode(bytes)
+        } else if bytes.starts_with(png::SIGNATURE) {
+            png::decode(bytes)
+        } else if bytes.starts_with(&[0xff, 0xd8]) {
+            jpeg::decode(bytes)
+        } else if bytes.starts_with(b"#?") {
+            hdr::decode(bytes)
+        } else {
+            Err(ImageError::UnknownFormat)
+        }
+    }
+
+    /// Rejects decoded images wider or taller than `MAX_DIMENSION`.
+    fn check_dimensions(width : usize, height : usize) -> Result<(), ImageError> {
+        if width > MAX_DIMENSION as usize || height > MAX_DIMENSION as usize {
+            return Err(ImageError::Unsupported("images wider or taller than MAX_DIMENSION"));
+        }
+        Ok(())
+    }
+
+    fn rgba8(width : u32, height : u32, pixels : Vec<u8>) -> Self {
+        Self { width, height, format: vk::Format::R8G8B8A8_UNORM, levels: vec![pixels] }
+    }
+
+    /// Returns true if the data is stored in a block-compressed format.
+    pub fn is_compressed(&self) -> bool {
+        block_info(self.format).is_some()
+    }
+
+    /// Decompresses every level to 8-bit RGBA, for devices which cannot sample the compressed format. sRGB formats
+    /// decompress to `R8G8B8A8_SRGB`. Returns `None` if the format cannot be decompressed on the CPU, which is the case
+    /// for uncompressed formats, BC6H and the signed formats.
+    pub fn decompress(&self) -> Option<ImageData> {
+        let levels = self.levels
+            .iter()
+            .enumerate()
+            .map(|(level, data)| {
+                let (width, height) = ((self.width >> level).max(1), (self.height >> level).max(1));
+                compressed::decompress(self.format, width, height, data)
+            })
+            .collect::<Option<Vec<Vec<u8>>>>()?;
+        let format = if is_srgb(self.format) {
+            vk::Format::R8G8B8A8_SRGB
+        } else {
+            vk::Format::R8G8B8A8_UNORM
+        };
+        Some(ImageData { width: self.width, height: self.height, format, levels })
+    }
+
+    /// Fills in every mip level down to 1x1 by averaging 2x2 blocks of the level above, replacing any levels after the
+    /// first. Returns false if the format cannot be filtered on the CPU.
+    pub fn generate_mipmaps(&mut self) -> bool {
+        let float = match self.format {
+            vk::Format::R8G8B8A8_UNORM
+            | vk::Format::R8G8B8A8_SRGB
+            | vk::Format::B8G8R8A8_UNORM
+            | vk::Format::B8G8R8A8_SRGB => false,
+            vk::Format::R32G32B32A32_SFLOAT => true,
+            _ => return false,
+        };
+        let srgb = is_srgb(self.format);
+        self.levels.truncate(1);
+        let (mut width, mut height) = (self.width, self.height);
+        while width > 1 || height > 1 {
+            let next = downsample(self.levels.last().unwrap(), width, height, float, srgb);
+            width = (width / 2).max(1);
+            height = (height / 2).max(1);
+            self.levels.push(next);
+        }
+        true
+    }
+
+    /// Converts 32-bit float data to `R16G16B16A16_SFLOAT`, which every device can filter linearly, at half the size.
+    /// Returns `None` if the data is not `R32G32B32A32_SFLOAT`.
+    pub fn to_half_float(&self) -> Option<ImageData> {
+        if self.format != vk::Format::R32G32B32A32_SFLOAT {
+            return None;
+        }
+        let levels = self.levels
+            .iter()
+            .map(|level| level
+                .chunks_exact(4)
+                .flat_map(|bytes| {
+                    let value = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                    half_from_f32(value).to_le_bytes()
+                })
+                .collect())
+            .collect();
+        Some(ImageData { width: self.width, height: self.height, format: vk::Format::R16G16B16A16_SFLOAT, levels })
+    }
+}
+
+/// Returns the number of bytes in a level of the given size, for block-compressed formats and the uncompressed
+/// formats which images and containers are read as.
+pub fn level_size(format : vk::Format, width : u32, height : u32) -> Option<usize> {
+    if let Some(block) = block_info(format) {
+        let blocks = (width.div_ceil(block.width) as usize).checked_mul(height.div_ceil(block.height) as usize)?;
+        return blocks.checked_mul(block.bytes as usize);
+    }
+    let pixel_size = match format {
+        vk::Format::R8_UNORM => 1,
+        vk::Format::R8G8_UNORM => 2,
+        vk::Format::R8G8B8_UNORM | vk::Format::R8G8B8_SRGB => 3,
+        vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SRGB
+        | vk::Format::B8G8R8A8_UNORM
+        | vk::Format::B8G8R8A8_SRGB => 4,
+        vk::Format::R16G16B16A16_SFLOAT => 8,
+        vk::Format::R32G32B32A32_SFLOAT => 16,
+        _ => return None,
+    };
+    (width as usize).checked_mul(height as usize)?.checked_mul(pixel_size)
+}
+
+/// Pairs of formats which only differ in whether their color is stored as sRGB.
+const SRGB_FORMATS : [(vk::Format, vk::Format); 24] = [
+    (vk::Format::R8G8B8A8_UNORM, vk::Format::R8G8B8A8_SRGB),
+    (vk::Format::B8G8R8A8_UNORM, vk::Format::B8G8R8A8_SRGB),
+    (vk::Format::BC1_RGB_UNORM_BLOCK, vk::Format::BC1_RGB_SRGB_BLOCK),
+    (vk::Format::BC1_RGBA_UNORM_BLOCK, vk::Format::BC1_RGBA_SRGB_BLOCK),
+    (vk::Format::BC2_UNORM_BLOCK, vk::Format::BC2_SRGB_BLOCK),
+    (vk::Format::BC3_UNORM_BLOCK, vk::Format::BC3_SRGB_BLOCK),
+    (vk::Format::BC7_UNORM_BLOCK, vk::Format::BC7_SRGB_BLOCK),
+    (vk::Format::ETC2_R8G8B8_UNORM_BLOCK, vk::Format::ETC2_R8G8B8_SRGB_BLOCK),
+    (vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK, vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK),
+    (vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK, vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK),
+    (vk::Format::ASTC_4X4_UNORM_BLOCK, vk::Format::ASTC_4X4_SRGB_BLOCK),
+    (vk::Format::ASTC_5X4_UNORM_BLOCK, vk::Format::ASTC_5X4_SRGB_BLOCK),
+    (vk::Format::ASTC_5X5_UNORM_BLOCK, vk::Format::ASTC_5X5_SRGB_BLOCK),
+    (vk::Format::ASTC_6X5_UNORM_BLOCK, vk::Format::ASTC_6X5_SRGB_BLOCK),
+    (vk::Format::ASTC_6X6_UNORM_BLOCK, vk::Format::ASTC_6X6_SRGB_BLOCK),
+    (vk::Format::ASTC_8X5_UNORM_BLOCK, vk::Format::ASTC_8X5_SRGB_BLOCK),
+    (vk::Format::ASTC_8X6_UNORM_BLOCK, vk::Format::ASTC_8X6_SRGB_BLOCK),
+    (vk::Format::ASTC_8X8_UNORM_BLOCK, vk::Format::ASTC_8X8_SRGB_BLOCK),
+    (vk::Format::ASTC_10X5_UNORM_BLOCK, vk::Format::ASTC_10X5_SRGB_BLOCK),
+    (vk::Format::ASTC_10X6_UNORM_BLOCK, vk::Format::ASTC_10X6_SRGB_BLOCK),
+    (vk::Format::ASTC_10X8_UNORM_BLOCK, vk::Format::ASTC_10X8_SRGB_BLOCK),
+    (vk::Format::ASTC_10X10_UNORM_BLOCK, vk::Format::ASTC_10X10_SRGB_BLOCK),
+    (vk::Format::ASTC_12X10_UNORM_BLOCK, vk::Format::ASTC_12X10_SRGB_BLOCK),
+    (vk::Format::ASTC_12X12_UNORM_BLOCK, vk::Format::ASTC_12X12_SRGB_BLOCK),
+];
+
+/// Returns the sRGB equivalent of a format, or the format itself if it is already sRGB or has no sRGB equivalent.
+pub fn srgb_format(format : vk::Format) -> vk::Format {
+    SRGB_FORMATS
+        .iter()
+        .find(|(linear, _)| *linear == format)
+        .map_or(format, |(_, srgb)| *srgb)
+}
+
+pub fn is_srgb(format : vk::Format) -> bool {
+    SRGB_FORMATS.iter().any(|(_, srgb)| *srgb == format)
+}
+
+/// Returns the number of mip levels in a full chain for an image of the given size.
+pub fn mip_level_count(width : u32, height : u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// Halves an RGBA image, averaging each 2x2 block. Odd edges are clamped so the last row or column is not lost. sRGB
+/// values are averaged in linear space.
+fn downsample(pixels : &[u8], width : u32, height : u32, float : bool, srgb : bool) -> Vec<u8> {
+    let (next_width, next_height) = ((width / 2).max(1), (height / 2).max(1));
+    let pixel_size = if float { 16 } else { 4 };
+    let read = |x : u32, y : u32, channel : usize| -> f32 {
+        let index = ((y.min(height - 1) * width + x.min(width - 1)) as usize) * pixel_size;
+        if float {
+            let offset = index + channel * 4;
+            f32::from_le_bytes([pixels[offset], pixels[offset + 1], pixels[offset + 2], pixels[offset + 3]])
+        } else {
+            let value = pixels[index + channel] as f32 / 255.0;
+            if srgb && channel < 3 { srgb_to_linear(value) } else { value }
+        }
+    };
+
+    let mut next = Vec::with_capacity((next_width * next_height) as usize * pixel_size);
+    for y in 0..next_height {
+        for x in 0..next_width {
+            for channel in 0..4 {
+                let (x, y) = (x * 2, y * 2);
+                let sum = read(x, y, channel) + read(x + 1, y, channel) + read(x, y + 1, channel)
+                    + read(x + 1, y + 1, channel);
+                let average = sum / 4.0;
+                if float {
+                    next.extend_from_slice(&average.to_le_bytes());
+                } else {
+                    let value = if srgb && channel < 3 { linear_to_srgb(average) } else { average };
+                    next.push((value * 255.0 + 0.5).clamp(0.0, 255.0) as u8);
+                }
+            }
+        }
+    }
+    next
+}
+
+fn srgb_to_linear(value : f32) -> f32 {
+    if value <= 0.04045 { value / 12.92 } else { ((value + 0.055) / 1.055).powf(2.4) }
+}
+
+fn linear_to_srgb(value : f32) -> f32 {
+    if value <= 0.003_130_8 { value * 12.92 } else { 1.055 * value.powf(1.0 / 2.4) - 0.055 }
+}
+
+/// Converts to the nearest half precision float, rounding ties to even. Values too large for half precision become
+/// infinity.
+fn half_from_f32(value : f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        // Infinity stays infinity, and NaN stays a quiet NaN.
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if exponent <= 0 {
+        // Subnormal or zero, shifting in the implicit leading bit.
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        let half = mantissa >> shift;
+        let remainder = mantissa & ((1 << shift) - 1);
+        let midpoint = 1 << (shift - 1);
+        let round = remainder > midpoint || (remainder == midpoint && half & 1 == 1);
+        return sign | (half + round as u32) as u16;
+    }
+    let half = ((exponent as u32) << 10) | (mantissa >> 13);
+    let remainder = mantissa & 0x1fff;
+    let round = remainder > 0x1000 || (remainder == 0x1000 && half & 1 == 1);
+    // Rounding up may carry into the exponent, which correctly gives the next power of two or infinity.
+    sign | (half + round as u32) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! fixture {
+        ($name : literal) => {
+            &include_bytes!(concat!("../../assets/tests/images/", $name))[..]
+        };
+    }
+
+    /// Decodes an 8x8 texture and checks its format and the size of each of its levels.
+    fn decode_texture(bytes : &[u8], format : vk::Format, level_count : usize) -> ImageData {
+        let image = ImageData::decode(bytes).unwrap();
+        assert_eq!((image.width, image.height), (8, 8));
+        assert_eq!(image.format, format);
+        assert_eq!(image.levels.len(), level_count);
+        for (level, data) in image.levels.iter().enumerate() {
+            let size = (8 >> level).max(1);
+            assert_eq!(Some(data.len()), level_size(format, size, size), "level {}", level);
+        }
+        image
+    }
+
+    /// Returns the decompressed levels joined together, largest first.
+    fn decompressed(image : &ImageData, format : vk::Format) -> Vec<u8> {
+        let decompressed = image.decompress().unwrap();
+        assert_eq!(decompressed.format, format);
+        assert_eq!((decompressed.width, decompressed.height), (image.width, image.height));
+        decompressed.levels.concat()
+    }
+
+    #[test]
+    fn reads_ktx2_textures() {
+        let bc1 = decode_texture(fixture!("bc1.ktx2"), vk::Format::BC1_RGBA_UNORM_BLOCK, 4);
+        assert_eq!(decompressed(&bc1, vk::Format::R8G8B8A8_UNORM), fixture!("bc1.rgba"));
+
+        let etc2 = decode_texture(fixture!("etc2.ktx2"), vk::Format::ETC2_R8G8B8_UNORM_BLOCK, 4);
+        assert_eq!(decompressed(&etc2, vk::Format::R8G8B8A8_UNORM), fixture!("etc2.rgba"));
+
+        let bc7 = decode_texture(fixture!("bc7.ktx2"), vk::Format::BC7_UNORM_BLOCK, 4);
+        assert_eq!(decompressed(&bc7, vk::Format::R8G8B8A8_UNORM), fixture!("bc7.rgba"));
+
+        let astc = decode_texture(fixture!("astc_6x6.ktx2"), vk::Format::ASTC_6X6_UNORM_BLOCK, 4);
+        assert_eq!(decompressed(&astc, vk::Format::R8G8B8A8_UNORM), fixture!("astc_6x6.rgba"));
+    }
+
+    #[test]
+    fn leaves_hdr_and_signed_formats_compressed() {
+        let mut bc7 = decode_texture(fixture!("bc7.ktx2"), vk::Format::BC7_UNORM_BLOCK, 4);
+        for format in [vk::Format::BC6H_UFLOAT_BLOCK, vk::Format::BC4_SNORM_BLOCK, vk::Format::EAC_R11G11_SNORM_BLOCK] {
+            bc7.format = format;
+            assert!(bc7.decompress().is_none(), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn inflates_zlib_supercompressed_ktx2_levels() {
+        let plain = decode_texture(fixture!("bc1.ktx2"), vk::Format::BC1_RGBA_UNORM_BLOCK, 4);
+        let inflated = decode_texture(fixture!("bc1_zlib.ktx2"), vk::Format::BC1_RGBA_UNORM_BLOCK, 4);
+        assert_eq!(plain.levels, inflated.levels);
+    }
+
+    #[test]
+    fn reads_dds_textures() {
+        let bc1 = decode_texture(fixture!("bc1.dds"), vk::Format::BC1_RGBA_UNORM_BLOCK, 4);
+        assert_eq!(decompressed(&bc1, vk::Format::R8G8B8A8_UNORM), fixture!("bc1.rgba"));
+
+        let bc7 = decode_texture(fixture!("bc7_dx10.dds"), vk::Format::BC7_UNORM_BLOCK, 2);
+        let ktx2 = decode_texture(fixture!("bc7.ktx2"), vk::Format::BC7_UNORM_BLOCK, 4);
+        assert_eq!(bc7.levels, ktx2.levels[..2]);
+
+        let bgr = decode_texture(fixture!("bgr.dds"), vk::Format::R8G8B8A8_UNORM, 3);
+        assert_eq!(bgr.levels.concat(), fixture!("bgr.rgba"));
+    }
+
+    #[test]
+    fn rejects_truncated_textures() {
+        let ktx2 = fixture!("bc1.ktx2");
+        assert!(matches!(ImageData::decode(&ktx2[..ktx2.len() - 8]), Err(ImageError::Corrupt(_))));
+        let dds = fixture!("bc7_dx10.dds");
+        assert!(matches!(ImageData::decode(&dds[..dds.len() - 8]), Err(ImageError::Corrupt(_))));
+    }
+
+    #[test]
+    fn rejects_dds_levels_larger_than_the_file() {
+        // Widen the uncompressed fixture so a 32-bit level size would wrap around.
+        let mut dds = fixture!("bgr.dds").to_vec();
+        dds[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+        dds[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(ImageData::decode(&dds), Err(ImageError::Corrupt(_))));
+    }
+}
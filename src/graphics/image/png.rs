@@ -0,0 +1,352 @@
+use std::io::Read;
+use flate2::read::ZlibDecoder;
+use super::{ImageData, ImageError};
+
+pub const SIGNATURE : &[u8] = &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+const COLOR_GRAY : u8 = 0;
+const COLOR_RGB : u8 = 2;
+const COLOR_PALETTE : u8 = 3;
+const COLOR_GRAY_ALPHA : u8 = 4;
+const COLOR_RGBA : u8 = 6;
+
+/// The starting column, starting row, column step and row step of each Adam7 pass.
+const ADAM7 : [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2)];
+
+struct Header {
+    width : u32,
+    height : u32,
+    bit_depth : u8,
+    color_type : u8,
+    interlaced : bool,
+}
+
+impl Header {
+    fn channels(&self) -> usize {
+        match self.color_type {
+            COLOR_GRAY | COLOR_PALETTE => 1,
+            COLOR_GRAY_ALPHA => 2,
+            COLOR_RGB => 3,
+            _ => 4,
+        }
+    }
+
+    /// The number of bytes per pixel used by filtering, which is at least 1.
+    fn filter_stride(&self) -> usize {
+        (self.channels() * self.bit_depth as usize).div_ceil(8)
+    }
+
+    fn row_size(&self, width : usize) -> usize {
+        (width * self.channels() * self.bit_depth as usize).div_ceil(8)
+    }
+
+    /// The starting column and row, steps, width and height of each pass, which covers the whole image unless it is
+    /// interlaced. Empty passes are skipped.
+    fn passes(&self) -> Vec<(usize, usize, usize, usize, usize, usize)> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        if !self.interlaced {
+            return vec![(0, 0, 1, 1, width, height)];
+        }
+        ADAM7
+            .iter()
+            .filter(|&&(x0, y0, _, _)| x0 < width && y0 < height)
+            .map(|&(x0, y0, dx, dy)| (x0, y0, dx, dy, (width - x0).div_ceil(dx), (height - y0).div_ceil(dy)))
+            .collect()
+    }
+
+    /// The number of bytes of filtered data, which includes a filter byte at the start of each row of each pass.
+    fn data_size(&self) -> usize {
+        self.passes().iter().map(|&(.., width, height)| (self.row_size(width) + 1) * height).sum()
+    }
+}
+
+/// Decodes a PNG of any standard color type and bit depth, including interlaced images, to 8-bit RGBA. 16-bit samples
+/// are reduced to their high byte.
+pub fn decode(bytes : &[u8]) -> Result<ImageData, ImageError> {
+    let mut header = None;
+    let mut palette : &[u8] = &[];
+    let mut transparency : &[u8] = &[];
+    let mut compressed = Vec::new();
+
+    let mut offset = SIGNATURE.len();
+    loop {
+        if offset + 8 > bytes.len() {
+            return Err(ImageError::Corrupt("PNG ends before IEND"));
+        }
+        let length = read_u32(&bytes[offset..]) as usize;
+        let kind = &bytes[offset + 4..offset + 8];
+        let data = bytes
+            .get(offset + 8..offset + 8 + length)
+            .ok_or(ImageError::Corrupt("PNG chunk extends past the end of the file"))?;
+        // Skip the data and its CRC.
+        offset += 12 + length;
+        match kind {
+            b"IHDR" => {
+                if data.len() < 13 {
+                    return Err(ImageError::Corrupt("PNG header is too short"));
+                }
+                if data[10] != 0 || data[11] != 0 {
+                    return Err(ImageError::Unsupported("PNG compression or filter method"));
+                }
+                header = Some(Header {
+                    width: read_u32(data),
+                    height: read_u32(&data[4..]),
+                    bit_depth: data[8],
+                    color_type: data[9],
+                    interlaced: data[12] == 1,
+                });
+            }
+            b"PLTE" => palette = data,
+            b"tRNS" => transparency = data,
+            b"IDAT" => compressed.extend_from_slice(data),
+            b"IEND" => break,
+            _ => (),
+        }
+    }
+
+    let header = header.ok_or(ImageError::Corrupt("PNG has no header"))?;
+    let valid_depth = match header.color_type {
+        COLOR_GRAY => matches!(header.bit_depth, 1 | 2 | 4 | 8 | 16),
+        COLOR_PALETTE => matches!(header.bit_depth, 1 | 2 | 4 | 8),
+        COLOR_RGB | COLOR_GRAY_ALPHA | COLOR_RGBA => matches!(header.bit_depth, 8 | 16),
+        _ => return Err(ImageError::Corrupt("PNG has an invalid color type")),
+    };
+    if !valid_depth {
+        return Err(ImageError::Corrupt("PNG has an invalid bit depth for its color type"));
+    }
+    if header.width == 0 || header.height == 0 {
+        return Err(ImageError::Corrupt("PNG has no pixels"));
+    }
+    ImageData::check_dimensions(header.width as usize, header.height as usize)?;
+
+    // Anything inflated beyond the size of the image is ignored, so the data cannot grow without bound.
+    let data_size = header.data_size();
+    let mut filtered = Vec::new();
+    ZlibDecoder::new(compressed.as_slice())
+        .take(data_size as u64)
+        .read_to_end(&mut filtered)
+        .map_err(|_| ImageError::Corrupt("PNG data could not be inflated"))?;
+    if filtered.len() < data_size {
+        return Err(ImageError::Corrupt("PNG data is too short"));
+    }
+
+    let full_width = header.width as usize;
+    let mut rgba = vec![0u8; full_width * header.height as usize * 4];
+    let mut offset = 0;
+    for (x0, y0, dx, dy, width, height) in header.passes() {
+        let size = (header.row_size(width) + 1) * height;
+        let pixels = unfilter(&header, &filtered[offset..offset + size], width, height)?;
+        offset += size;
+        expand(&header, &pixels, width, height, palette, transparency, |x, y, pixel| {
+            let index = ((y0 + y * dy) * full_width + x0 + x * dx) * 4;
+            rgba[index..index + 4].copy_from_slice(&pixel);
+        });
+    }
+    Ok(ImageData::rgba8(header.width, header.height, rgba))
+}
+
+fn read_u32(bytes : &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Reverses the filter of each row, returning the rows without their filter bytes.
+fn unfilter(header : &Header, data : &[u8], width : usize, height : usize) -> Result<Vec<u8>, ImageError> {
+    let row_size = header.row_size(width);
+    let stride = header.filter_stride();
+    if data.len() < (row_size + 1) * height {
+        return Err(ImageError::Corrupt("PNG data is too short"));
+    }
+    let mut pixels = vec![0u8; row_size * height];
+    for y in 0..height {
+        let filter = data[y * (row_size + 1)];
+        let source = &data[y * (row_size + 1) + 1..(y + 1) * (row_size + 1)];
+        let (previous, current) = pixels.split_at_mut(y * row_size);
+        let above = if y == 0 { None } else { Some(&previous[(y - 1) * row_size..]) };
+        let current = &mut current[..row_size];
+        for x in 0..row_size {
+            let a = if x >= stride { current[x - stride] } else { 0 };
+            let b = above.map_or(0, |above| above[x]);
+            let c = if x >= stride { above.map_or(0, |above| above[x - stride]) } else { 0 };
+            let predictor = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(ImageError::Corrupt("PNG row has an invalid filter")),
+            };
+            current[x] = source[x].wrapping_add(predictor);
+        }
+    }
+    Ok(pixels)
+}
+
+fn paeth(a : u8, b : u8, c : u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Converts unfiltered rows to RGBA, passing each pixel to `write` along with its position.
+fn expand<F : FnMut(usize, usize, [u8; 4])>(header : &Header,
+                                         pixels : &[u8],
+                                         width : usize,
+                                         height : usize,
+                                         palette : &[u8],
+                                         transparency : &[u8],
+                                         mut write : F) {
+    let row_size = header.row_size(width);
+    let depth = header.bit_depth as usize;
+    let channels = header.channels();
+    // Reads sample `index` of a row, scaled to 8 bits for grayscale but left as an index for palettes.
+    let sample = |row : &[u8], index : usize| -> u16 {
+        match depth {
+            16 => u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]),
+            8 => row[index] as u16,
+            _ => {
+                let bit = index * depth;
+                let shift = 8 - depth - bit % 8;
+                ((row[bit / 8] >> shift) & ((1 << depth) - 1) as u8) as u16
+            }
+        }
+    };
+    let to_u8 = |value : u16| -> u8 {
+        match depth {
+            16 => (value >> 8) as u8,
+            8 => value as u8,
+            _ => (value * 255 / ((1 << depth) - 1)) as u8,
+        }
+    };
+    // A single transparent color for grayscale and RGB images, compared before scaling.
+    let transparent = |values : &[u16]| -> bool {
+        transparency.len() >= values.len() * 2
+            && values
+                .iter()
+                .enumerate()
+                .all(|(i, value)| u16::from_be_bytes([transparency[i * 2], transparency[i * 2 + 1]]) == *value)
+    };
+
+    for y in 0..height {
+        let row = &pixels[y * row_size..(y + 1) * row_size];
+        for x in 0..width {
+            let base = x * channels;
+            let pixel = match header.color_type {
+                COLOR_GRAY => {
+                    let gray = sample(row, base);
+                    let value = to_u8(gray);
+                    [value, value, value, if transparent(&[gray]) { 0 } else { 255 }]
+                }
+                COLOR_GRAY_ALPHA => {
+                    let value = to_u8(sample(row, base));
+                    [value, value, value, to_u8(sample(row, base + 1))]
+                }
+                COLOR_RGB => {
+                    let values = [sample(row, base), sample(row, base + 1), sample(row, base + 2)];
+                    let alpha = if transparent(&values) { 0 } else { 255 };
+                    [to_u8(values[0]), to_u8(values[1]), to_u8(values[2]), alpha]
+                }
+                COLOR_PALETTE => {
+                    let index = sample(row, base) as usize;
+                    let color = palette.get(index * 3..index * 3 + 3).unwrap_or(&[0, 0, 0]);
+                    [color[0], color[1], color[2], transparency.get(index).copied().unwrap_or(255)]
+                }
+                _ => [
+                    to_u8(sample(row, base)),
+                    to_u8(sample(row, base + 1)),
+                    to_u8(sample(row, base + 2)),
+                    to_u8(sample(row, base + 3))],
+            };
+            write(x, y, pixel);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk;
+    use super::*;
+
+    /// Pairs each fixture with the pixels it should decode to.
+    macro_rules! fixtures {
+        ($($name : literal),*) => {
+            [$(($name,
+                &include_bytes!(concat!("../../assets/tests/images/", $name, ".png"))[..],
+                &include_bytes!(concat!("../../assets/tests/images/", $name, ".rgba"))[..])),*]
+        };
+    }
+
+    fn check(fixtures : &[(&str, &[u8], &[u8])]) {
+        for (name, png, rgba) in fixtures {
+            let image = decode(png).unwrap_or_else(|error| panic!("{}: {}", name, error));
+            assert_eq!((image.width, image.height), (7, 5), "{}", name);
+            assert_eq!(image.format, vk::Format::R8G8B8A8_UNORM, "{}", name);
+            assert_eq!(image.levels.len(), 1, "{}", name);
+            assert_eq!(image.levels[0], *rgba, "{}", name);
+        }
+    }
+
+    #[test]
+    fn decodes_every_color_type_and_bit_depth() {
+        check(&fixtures!("gray1", "gray2", "gray4", "gray8", "gray16", "gray_alpha8", "gray_alpha16", "rgb8", "rgb16",
+                         "rgba8", "rgba16"));
+    }
+
+    #[test]
+    fn decodes_palettes_with_transparency() {
+        check(&fixtures!("palette1", "palette2", "palette4", "palette8"));
+    }
+
+    #[test]
+    fn decodes_transparent_colors() {
+        check(&fixtures!("gray8_trns", "rgb16_trns"));
+    }
+
+    #[test]
+    fn decodes_interlaced_images() {
+        check(&fixtures!("rgba8_adam7", "palette2_adam7", "gray16_adam7"));
+    }
+
+    #[test]
+    fn decodes_png_suite_images() {
+        macro_rules! external {
+            ($($name : literal),*) => {
+                [$(($name,
+                    &include_bytes!(concat!("../../assets/tests/images/external/", $name, ".png"))[..],
+                    &include_bytes!(concat!("../../assets/tests/images/external/", $name, ".expected.png"))[..])),*]
+            };
+        }
+        let images = external!("basn6a16", "basi2c08", "tbbn0g04", "tbbn3p08", "tbrn2c08", "tm3n3p02", "tp0n0g08",
+                               "tp0n2c08", "tp0n3p08", "tp1n3p08");
+        for (name, png, expected) in &images {
+            let image = decode(png).unwrap_or_else(|error| panic!("{}: {}", name, error));
+            let expected = decode(expected).unwrap();
+            assert_eq!((image.width, image.height), (expected.width, expected.height), "{}", name);
+            assert!(image.levels == expected.levels, "{}", name);
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_images() {
+        let png = include_bytes!("../../assets/tests/images/rgba8.png");
+        assert!(matches!(decode(&png[..png.len() - 12]), Err(ImageError::Corrupt(_))));
+    }
+
+    #[test]
+    fn rejects_sizes_the_data_cannot_hold() {
+        let resized = |width : u32, height : u32| {
+            let mut png = include_bytes!("../../assets/tests/images/rgba8.png").to_vec();
+            png[16..20].copy_from_slice(&width.to_be_bytes());
+            png[20..24].copy_from_slice(&height.to_be_bytes());
+            decode(&png)
+        };
+        assert!(matches!(resized(1 << 20, 5), Err(ImageError::Unsupported(_))));
+        assert!(matches!(resized(10000, 10000), Err(ImageError::Corrupt("PNG data is too short"))));
+    }
+}
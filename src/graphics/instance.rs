@@ -26,6 +26,7 @@ pub struct Instance {
     debug_report_loader : Option<DebugReport>,
     debug_report : Option<vk::DebugReportCallbackEXT>,
     physical_devices : Vec<vk::PhysicalDevice>,
+    api_version : u32,
 }
 
 impl Drop for Instance {
@@ -54,14 +55,24 @@ impl Instance {
 
         let extension_names = get_required_instance_extensions();
 
+        // Vulkan 1.1 is needed to query extended device features, but a 1.0 loader rejects any version above 1.0.
+        let api_version = match entry.try_enumerate_instance_version() {
+            Ok(Some(version)) if version >= vk::make_version(1, 1, 0) => vk::make_version(1, 1, 0),
+            _ => vk::make_version(1, 0, 0),
+        };
+        let application_info = vk::ApplicationInfo::builder()
+            .api_version(api_version);
+
         // Enable validation layer only on debug builds.
         let instance_info = if cfg!(debug_assertions) {
             vk::InstanceCreateInfo::builder()
+                .application_info(&application_info)
                 .enabled_extension_names(&extension_names)
                 .enabled_layer_names(&layer_names_raw)
                 .build()
         } else {
             vk::InstanceCreateInfo::builder()
+                .application_info(&application_info)
                 .enabled_extension_names(&extension_names)
                 .build()
         };
@@ -115,6 +126,7 @@ impl Instance {
 
         Ok(Self { entry,
             instance,
+            api_version,
             debug_report_loader,
             debug_report,
             physical_devices
@@ -131,6 +143,11 @@ impl Instance {
         &self.instance
     }
 
+    /// Returns the Vulkan version the instance was created for, which is 1.1 where the loader supports it.
+    pub fn api_version(&self) -> u32 {
+        self.api_version
+    }
+
     /// Returns all physical devices.
     pub fn physical_devices(&self) -> Vec<vk::PhysicalDevice> {
         self.physical_devices.clone()
@@ -0,0 +1,216 @@
+use std::{mem::size_of, sync::Arc};
+use ash::vk;
+use nalgebra::{Matrix4, Vector3, Vector4};
+use super::{CmdBuffer, CmdRecordingError, DescriptorSet, Device, Pipeline};
+use super::descriptors::{DescriptorAllocator, DescriptorSetLayout, DescriptorSetLayoutBuilder, DescriptorWriter};
+use super::environment::EnvironmentMap;
+use super::ring_buffer::{RingAllocation, RingBuffer, RingBufferError};
+
+/// The number of lights which can light a frame. Any further lights given to `Lighting::write` are ignored.
+pub const MAX_LIGHTS : usize = 16;
+
+/// The descriptor set `Lighting` is bound to, which follows the parameters of the material.
+const SCENE_SET : u32 = 1;
+
+/// A light following the punctual lights of glTF. Colors are linear RGB, and intensities are in lux for directional
+/// lights and in candela for point and spot lights.
+#[derive(Clone, Copy, Debug)]
+pub enum Light {
+    /// A light infinitely far away, such as the sun, shining along `direction`.
+    Directional {
+        direction : Vector3<f32>,
+        color : Vector3<f32>,
+        intensity : f32,
+    },
+    /// A light shining in every direction from `position`. Its intensity falls off with the inverse square of the
+    /// distance, reaching zero at `range` if one is given.
+    Point {
+        position : Vector3<f32>,
+        color : Vector3<f32>,
+        intensity : f32,
+        range : Option<f32>,
+    },
+    /// A point light restricted to a cone around `direction`. The light is at full intensity within
+    /// `inner_cone_angle` of the direction, and fades out towards `outer_cone_angle`. Angles are in radians.
+    Spot {
+        position : Vector3<f32>,
+        direction : Vector3<f32>,
+        color : Vector3<f32>,
+        intensity : f32,
+        range : Option<f32>,
+        inner_cone_angle : f32,
+        outer_cone_angle : f32,
+    },
+}
+
+/// A light as laid out in the scene block of the shaders.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct LightBlock {
+    /// The position, with the type of the light in w.
+    position : Vector4<f32>,
+    /// The direction, with the range in w, which is 0 for lights without one.
+    direction : Vector4<f32>,
+    /// The color premultiplied by the intensity.
+    color : Vector4<f32>,
+    /// The cosine of the outer cone angle and the reciprocal of the difference between the inner and outer cosines.
+    cone : Vector4<f32>,
+}
+
+impl Light {
+    fn block(&self) -> LightBlock {
+        let zero = Vector3::zeros();
+        let (light_type, position, direction, color, intensity, range, cone) = match *self {
+            Light::Directional { direction, color, intensity } =>
+                (0.0, zero, direction, color, intensity, None, Vector4::zeros()),
+            Light::Point { position, color, intensity, range } =>
+                (1.0, position, zero, color, intensity, range, Vector4::zeros()),
+            Light::Spot { position, direction, color, intensity, range, inner_cone_angle, outer_cone_angle } => {
+                let outer_cos = outer_cone_angle.cos();
+                let scale = 1.0 / (inner_cone_angle.cos() - outer_cos).max(0.001);
+                (2.0, position, direction, color, intensity, range, Vector4::new(outer_cos, scale, 0.0, 0.0))
+            }
+        };
+        LightBlock {
+            position: position.push(light_type),
+            direction: direction.push(range.unwrap_or(0.0)),
+            color: (color * intensity).push(0.0),
+            cone,
+        }
+    }
+}
+
+/// Where a frame is viewed from.
+#[derive(Clone, Copy, Debug)]
+pub struct SceneView {
+    pub view_projection : Matrix4<f32>,
+    pub camera_position : Vector3<f32>,
+    /// Scales the light reaching the camera before it is tone mapped.
+    pub exposure : f32,
+}
+
+/// The scene block of the shaders, holding the view and the lights of a frame.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SceneBlock {
+    view_projection : Matrix4<f32>,
+    /// The camera position, with the exposure in w.
+    camera_position : Vector4<f32>,
+    /// The number of lights and the number of levels of the prefiltered environment map.
+    counts : [u32; 4],
+    lights : [LightBlock; MAX_LIGHTS],
+}
+
+/// The per-frame data read by lit materials from descriptor set 1: the view, the lights and the `EnvironmentMap`
+/// lighting the scene. Pass `set_layout` to the templates of lit materials, such as `PbrMaterial::template`.
+///
+/// Each frame, `write` the view and lights into a `RingBuffer` once, then `bind` the allocation after binding each
+/// material instance.
+pub struct Lighting {
+    device : Arc<Device>,
+    set_layout : DescriptorSetLayout,
+    _descriptor_allocator : DescriptorAllocator,
+    descriptor_set : DescriptorSet,
+    environment : Arc<EnvironmentMap>,
+    /// The ring buffer the scene block is bound from, which must be given to `write`.
+    ring_buffer : vk::Buffer,
+}
+
+impl Drop for Lighting {
+    fn drop(&mut self) {
+        info!("Dropped Lighting")
+    }
+}
+
+impl Lighting {
+    /// Creates the scene set, referring to `ring_buffer`, which must be the ring the scene block is written into.
+    pub fn new(device : Arc<Device>, environment : Arc<EnvironmentMap>, ring_buffer : &RingBuffer) -> Self {
+        let stages = vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT;
+        let set_layout = DescriptorSetLayoutBuilder::new(Arc::clone(&device))
+            .binding(0, vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC, 1, stages)
+            .sampled_image(1, vk::ShaderStageFlags::FRAGMENT)
+            .sampled_image(2, vk::ShaderStageFlags::FRAGMENT)
+            .sampled_image(3, vk::ShaderStageFlags::FRAGMENT)
+            .sampler(4, vk::ShaderStageFlags::FRAGMENT)
+            .build();
+        let mut descriptor_allocator = DescriptorAllocator::with_pool_sizes(
+            Arc::clone(&device),
+            vec![(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC, 1),
+                 (vk::DescriptorType::SAMPLED_IMAGE, 3),
+                 (vk::DescriptorType::SAMPLER, 1)]);
+        let descriptor_set = descriptor_allocator.allocate(&set_layout);
+        DescriptorWriter::new()
+            .uniform_buffer_dynamic(0, ring_buffer.buffer(), 0, size_of::<SceneBlock>() as vk::DeviceSize)
+            .update(&device, descriptor_set);
+        let lighting = Self {
+            device,
+            set_layout,
+            _descriptor_allocator: descriptor_allocator,
+            descriptor_set,
+            environment,
+            ring_buffer: ring_buffer.buffer().buffer_raw(),
+        };
+        lighting.write_environment();
+        lighting
+    }
+
+    /// Returns the layout of the scene set, which lit material templates are created with.
+    pub fn set_layout(&self) -> &DescriptorSetLayout {
+        &self.set_layout
+    }
+
+    pub fn environment(&self) -> &Arc<EnvironmentMap> {
+        &self.environment
+    }
+
+    /// Lights the scene with another environment. Must not be called while a frame which used the lighting is still
+    /// in flight.
+    pub fn set_environment(&mut self, environment : Arc<EnvironmentMap>) {
+        self.environment = environment;
+        self.write_environment();
+    }
+
+    /// Writes the view and lights of a frame into `ring_buffer`, which must be the ring the lighting was created with.
+    /// Only the first `MAX_LIGHTS` lights are used.
+    pub fn write(&self, ring_buffer : &mut RingBuffer, view : &SceneView, lights : &[Light])
+        -> Result<RingAllocation, RingBufferError> {
+        debug_assert_eq!(ring_buffer.buffer().buffer_raw(), self.ring_buffer,
+            "Lighting written into a different ring buffer than it was created with");
+        if lights.len() > MAX_LIGHTS {
+            warn!("Ignoring {} lights beyond the limit of {}", lights.len() - MAX_LIGHTS, MAX_LIGHTS);
+        }
+        let unused = LightBlock {
+            position: Vector4::zeros(),
+            direction: Vector4::zeros(),
+            color: Vector4::zeros(),
+            cone: Vector4::zeros(),
+        };
+        let mut block = SceneBlock {
+            view_projection: view.view_projection,
+            camera_position: view.camera_position.push(view.exposure),
+            counts: [lights.len().min(MAX_LIGHTS) as u32, self.environment.prefiltered_mip_levels(), 0, 0],
+            lights: [unused; MAX_LIGHTS],
+        };
+        for (light, light_block) in lights.iter().zip(block.lights.iter_mut()) {
+            *light_block = light.block();
+        }
+        ring_buffer.write(&block)
+    }
+
+    /// Binds the scene set with a frame written by `write`. Must be called after binding the pipeline of a lit
+    /// material, since binding a pipeline with a different layout may disturb set 1.
+    pub fn bind(&self, cmd_buffer : &mut CmdBuffer, pipeline : &Pipeline, frame : &RingAllocation)
+        -> Result<(), CmdRecordingError> {
+        cmd_buffer.bind_descriptor_sets(pipeline, SCENE_SET, &[self.descriptor_set], &[frame.dynamic_offset()])
+    }
+
+    fn write_environment(&self) {
+        let layout = self.environment.layout();
+        DescriptorWriter::new()
+            .sampled_image(1, self.environment.irradiance_view(), layout)
+            .sampled_image(2, self.environment.prefiltered_view(), layout)
+            .sampled_image(3, self.environment.brdf_lut_view(), layout)
+            .sampler(4, self.environment.sampler_raw())
+            .update(&self.device, self.descriptor_set);
+    }
+}
@@ -0,0 +1,593 @@
+use std::{fmt::{self, Write}, fs, io, path::{Component, Path, PathBuf}, str::FromStr};
+use ash::vk;
+use nalgebra::Vector4;
+use super::{ParameterValue, ProgramDesc, RenderState};
+use super::super::{BlendMode, DepthStencilState};
+
+/// Describes why a material file could not be read.
+#[derive(Debug)]
+pub enum MaterialParseError {
+    Io(PathBuf, io::Error),
+    /// The file has no `program` line.
+    MissingProgram,
+    /// The line, counted from 1, could not be parsed.
+    Syntax { line : usize, message : &'static str },
+}
+
+impl fmt::Display for MaterialParseError {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MaterialParseError::Io(path, error) => write!(f, "{}: {}", path.display(), error),
+            MaterialParseError::MissingProgram => write!(f, "material has no program"),
+            MaterialParseError::Syntax { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+/// Everything needed to recreate a material: its program, render state and the value of each parameter, in the order
+/// the program's parameter block declares them.
+///
+/// Materials are stored as text, with one setting per line and `#` starting a comment:
+///
+/// ```text
+/// program textured
+/// blend alpha
+/// cull back
+/// polygon fill
+/// depth less write
+/// stencil front keep replace keep always 255 255 1
+/// stencil back keep replace keep always 255 255 1
+/// stencil on
+/// color tint 1 0.5 0.5 1
+/// scalar roughness 0.8
+/// texture albedo "textures/red bricks.png"
+/// ```
+///
+/// `program` is either a built-in program or the paths of a SPIR-V vertex and fragment shader. Render state lines may
+/// be omitted to use the defaults of `RenderState`. `depth off` removes the depth-stencil state, while a trailing
+/// `untested` keeps it but disables the depth test. Each `stencil` face takes the fail, pass and depth fail ops, the
+/// compare op, the compare and write masks and the reference, and must come after the depth line. A texture parameter
+/// without a path has no default texture. Paths containing spaces, `#` or quotes are written in double quotes, with
+/// `\"` and `\\` escaping quotes and backslashes.
+#[derive(Clone, Debug)]
+pub struct MaterialDesc {
+    pub program : ProgramDesc,
+    pub render_state : RenderState,
+    pub parameters : Vec<(String, ParameterValue)>,
+}
+
+impl MaterialDesc {
+    /// Reads a material file. Relative paths in the file are resolved against the directory containing it.
+    pub fn load(path : &Path) -> Result<Self, MaterialParseError> {
+        let text = fs::read_to_string(path).map_err(|error| MaterialParseError::Io(path.to_path_buf(), error))?;
+        let mut desc : Self = text.parse()?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        desc.map_paths(|path| normalize(&directory.join(path)));
+        Ok(desc)
+    }
+
+    /// Writes the material in the text format read by `load`. Paths are written relative to the directory of `path`
+    /// where possible, so that loading the file again gives the same paths. Fails if the render state has values the
+    /// format cannot name, or a path is not valid Unicode.
+    pub fn save(&self, path : &Path) -> io::Result<()> {
+        let directory = normalize(path.parent().unwrap_or_else(|| Path::new("")));
+        let mut desc = self.clone();
+        desc.map_paths(|path| relative_path(&normalize(path), &directory));
+        let mut text = String::new();
+        write!(text, "{}", desc)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "material cannot be written as text"))?;
+        fs::write(path, text)
+    }
+
+    /// Returns the value of the named parameter.
+    pub fn parameter(&self, name : &str) -> Option<&ParameterValue> {
+        self.parameters
+            .iter()
+            .find(|(parameter, _)| parameter == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Sets the value of the named parameter, adding it after the others if the description does not have it.
+    pub fn set_parameter(&mut self, name : &str, value : ParameterValue) {
+        match self.parameters.iter_mut().find(|(parameter, _)| parameter == name) {
+            Some((_, parameter_value)) => *parameter_value = value,
+            None => self.parameters.push((name.to_string(), value)),
+        }
+    }
+
+    /// Replaces every shader and texture path with the result of `map`.
+    fn map_paths<F : Fn(&Path) -> PathBuf>(&mut self, map : F) {
+        if let ProgramDesc::Spirv { vertex, fragment } = &mut self.program {
+            *vertex = map(vertex);
+            *fragment = map(fragment);
+        }
+        for (_, value) in &mut self.parameters {
+            if let ParameterValue::Texture(Some(path)) = value {
+                *path = map(path);
+            }
+        }
+    }
+}
+
+/// Removes `.` components and folds `..` into the component before it, without touching the file system.
+fn normalize(path : &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir if matches!(normalized.components().next_back(), Some(Component::Normal(_))) => {
+                normalized.pop();
+            }
+            // `..` cannot go above the root.
+            Component::ParentDir if normalized.has_root() => (),
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Returns `path` relative to `directory`, stepping out of `directory` with `..` where needed. Both paths are expected
+/// to be normalized. Paths which cannot be made relative, i.e. on another drive or with more `..` components than
+/// `directory`, are returned unchanged.
+fn relative_path(path : &Path, directory : &Path) -> PathBuf {
+    if path.is_absolute() != directory.is_absolute() {
+        return path.to_path_buf();
+    }
+    let (mut path_components, mut directory_components) = (path.components().peekable(), directory.components());
+    let mut remaining = directory_components.clone();
+    while let (Some(a), Some(b)) = (path_components.peek(), directory_components.next()) {
+        if *a != b {
+            break;
+        }
+        path_components.next();
+        remaining = directory_components.clone();
+    }
+    let mut relative = PathBuf::new();
+    for component in remaining {
+        match component {
+            Component::Normal(_) => relative.push(".."),
+            _ => return path.to_path_buf(),
+        }
+    }
+    relative.extend(path_components);
+    relative
+}
+
+impl FromStr for MaterialDesc {
+    type Err = MaterialParseError;
+
+    fn from_str(text : &str) -> Result<Self, MaterialParseError> {
+        let mut program = None;
+        let mut render_state = RenderState::default();
+        let mut parameters : Vec<(String, ParameterValue)> = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let syntax = |message| MaterialParseError::Syntax { line: index + 1, message };
+            let words = split_words(line).map_err(syntax)?;
+            let (keyword, words) = match words.split_first() {
+                Some((keyword, words)) => (keyword.as_str(), words.iter().map(String::as_str).collect::<Vec<_>>()),
+                None => continue,
+            };
+            match (keyword, words.as_slice()) {
+                ("program", ["colored"]) => program = Some(ProgramDesc::Colored),
+                ("program", ["textured"]) => program = Some(ProgramDesc::Textured),
+                ("program", ["pbr"]) => program = Some(ProgramDesc::Pbr),
+                ("program", [vertex, fragment]) => program = Some(ProgramDesc::Spirv {
+                    vertex: PathBuf::from(vertex),
+                    fragment: PathBuf::from(fragment),
+                }),
+                ("program", _) => return Err(syntax("expected a built-in program or two shader paths")),
+                ("blend", [mode]) => {
+                    render_state.blend = parse_blend(mode).ok_or_else(|| syntax("unknown blend mode"))?;
+                }
+                ("cull", [mode]) => {
+                    render_state.cull_mode = parse_cull(mode).ok_or_else(|| syntax("unknown cull mode"))?;
+                }
+                ("polygon", [mode]) => {
+                    render_state.polygon_mode = parse_polygon(mode).ok_or_else(|| syntax("unknown polygon mode"))?;
+                }
+                ("depth", ["off"]) => render_state.depth = None,
+                ("depth", [compare_op, write, rest @ ..]) if rest.len() <= 1 => {
+                    let compare_op = parse_compare_op(compare_op).ok_or_else(|| syntax("unknown compare op"))?;
+                    let depth_write = match *write {
+                        "write" => true,
+                        "read" => false,
+                        _ => return Err(syntax("expected `write` or `read`")),
+                    };
+                    let depth_test = match rest {
+                        [] => true,
+                        ["untested"] => false,
+                        _ => return Err(syntax("expected `untested`")),
+                    };
+                    let depth = render_state.depth.get_or_insert_with(DepthStencilState::default);
+                    depth.depth_test = depth_test;
+                    depth.depth_write = depth_write;
+                    depth.compare_op = compare_op;
+                }
+                ("stencil", [enabled @ ("on" | "off")]) => {
+                    let depth = render_state.depth.as_mut().ok_or_else(|| syntax("stencil comes before depth"))?;
+                    depth.stencil_test = *enabled == "on";
+                }
+                ("stencil", [face, values @ ..]) if values.len() == 7 => {
+                    let depth = render_state.depth.as_mut().ok_or_else(|| syntax("stencil comes before depth"))?;
+                    let state = match *face {
+                        "front" => &mut depth.front,
+                        "back" => &mut depth.back,
+                        _ => return Err(syntax("expected `front` or `back`")),
+                    };
+                    *state = parse_stencil(values).ok_or_else(|| syntax("invalid stencil op state"))?;
+                }
+                ("scalar", [name, value]) => {
+                    let value = value.parse().map_err(|_| syntax("invalid scalar"))?;
+                    push_parameter(&mut parameters, name, ParameterValue::Scalar(value)).map_err(syntax)?;
+                }
+                ("color", [name, r, g, b, a]) => {
+                    let mut color = [0.0; 4];
+                    for (component, word) in color.iter_mut().zip(&[r, g, b, a]) {
+                        *component = word.parse().map_err(|_| syntax("invalid color component"))?;
+                    }
+                    push_parameter(&mut parameters, name, ParameterValue::Color(Vector4::from(color))).map_err(syntax)?;
+                }
+                ("texture", [name]) => {
+                    push_parameter(&mut parameters, name, ParameterValue::Texture(None)).map_err(syntax)?;
+                }
+                ("texture", [name, path]) => {
+                    let value = ParameterValue::Texture(Some(PathBuf::from(path)));
+                    push_parameter(&mut parameters, name, value).map_err(syntax)?;
+                }
+                ("blend", _) | ("cull", _) | ("polygon", _) | ("depth", _) | ("stencil", _) | ("scalar", _) |
+                ("color", _) | ("texture", _) => return Err(syntax("wrong number of values")),
+                _ => return Err(syntax("unknown setting")),
+            }
+        }
+        let program = program.ok_or(MaterialParseError::MissingProgram)?;
+        Ok(Self { program, render_state, parameters })
+    }
+}
+
+impl fmt::Display for MaterialDesc {
+    /// Fails if the render state has a value without a name in the format, or a path is not valid Unicode.
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.program {
+            ProgramDesc::Colored => writeln!(f, "program colored")?,
+            ProgramDesc::Textured => writeln!(f, "program textured")?,
+            ProgramDesc::Pbr => writeln!(f, "program pbr")?,
+            ProgramDesc::Spirv { vertex, fragment } =>
+                writeln!(f, "program {} {}", quote_path(vertex)?, quote_path(fragment)?)?,
+        }
+        let state = &self.render_state;
+        writeln!(f, "blend {}", name_of(&BLEND_MODES, state.blend)?)?;
+        writeln!(f, "cull {}", name_of(&CULL_MODES, state.cull_mode)?)?;
+        writeln!(f, "polygon {}", name_of(&POLYGON_MODES, state.polygon_mode)?)?;
+        match state.depth {
+            Some(depth) => {
+                write!(f, "depth {} {}", name_of(&COMPARE_OPS, depth.compare_op)?,
+                       if depth.depth_write { "write" } else { "read" })?;
+                writeln!(f, "{}", if depth.depth_test { "" } else { " untested" })?;
+                let default = DepthStencilState::default();
+                let (front, back) = (stencil_words(&depth.front)?, stencil_words(&depth.back)?);
+                if depth.stencil_test
+                    || front != stencil_words(&default.front)?
+                    || back != stencil_words(&default.back)? {
+                    writeln!(f, "stencil front {}", front)?;
+                    writeln!(f, "stencil back {}", back)?;
+                    writeln!(f, "stencil {}", if depth.stencil_test { "on" } else { "off" })?;
+                }
+            }
+            None => writeln!(f, "depth off")?,
+        }
+        for (name, value) in &self.parameters {
+            match value {
+                ParameterValue::Scalar(value) => writeln!(f, "scalar {} {}", name, value)?,
+                ParameterValue::Color(color) =>
+                    writeln!(f, "color {} {} {} {} {}", name, color.x, color.y, color.z, color.w)?,
+                ParameterValue::Texture(Some(path)) => writeln!(f, "texture {} {}", name, quote_path(path)?)?,
+                ParameterValue::Texture(None) => writeln!(f, "texture {}", name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+const BLEND_MODES : [(&str, BlendMode); 4] = [
+    ("opaque", BlendMode::Opaque),
+    ("alpha", BlendMode::Alpha),
+    ("additive", BlendMode::Additive),
+    ("premultiplied", BlendMode::Premultiplied),
+];
+
+const CULL_MODES : [(&str, vk::CullModeFlags); 4] = [
+    ("none", vk::CullModeFlags::NONE),
+    ("front", vk::CullModeFlags::FRONT),
+    ("back", vk::CullModeFlags::BACK),
+    ("both", vk::CullModeFlags::FRONT_AND_BACK),
+];
+
+const POLYGON_MODES : [(&str, vk::PolygonMode); 3] = [
+    ("fill", vk::PolygonMode::FILL),
+    ("line", vk::PolygonMode::LINE),
+    ("point", vk::PolygonMode::POINT),
+];
+
+const COMPARE_OPS : [(&str, vk::CompareOp); 8] = [
+    ("never", vk::CompareOp::NEVER),
+    ("less", vk::CompareOp::LESS),
+    ("equal", vk::CompareOp::EQUAL),
+    ("less_equal", vk::CompareOp::LESS_OR_EQUAL),
+    ("greater", vk::CompareOp::GREATER),
+    ("not_equal", vk::CompareOp::NOT_EQUAL),
+    ("greater_equal", vk::CompareOp::GREATER_OR_EQUAL),
+    ("always", vk::CompareOp::ALWAYS),
+];
+
+const STENCIL_OPS : [(&str, vk::StencilOp); 8] = [
+    ("keep", vk::StencilOp::KEEP),
+    ("zero", vk::StencilOp::ZERO),
+    ("replace", vk::StencilOp::REPLACE),
+    ("increment_clamp", vk::StencilOp::INCREMENT_AND_CLAMP),
+    ("decrement_clamp", vk::StencilOp::DECREMENT_AND_CLAMP),
+    ("invert", vk::StencilOp::INVERT),
+    ("increment_wrap", vk::StencilOp::INCREMENT_AND_WRAP),
+    ("decrement_wrap", vk::StencilOp::DECREMENT_AND_WRAP),
+];
+
+/// Returns the name of `value` in `names`, or an error if it has none.
+fn name_of<T : PartialEq>(names : &[(&'static str, T)], value : T) -> Result<&'static str, fmt::Error> {
+    names.iter().find(|(_, named)| *named == value).map(|(name, _)| *name).ok_or(fmt::Error)
+}
+
+/// Writes the ops, compare op, masks and reference of a stencil face.
+fn stencil_words(state : &vk::StencilOpState) -> Result<String, fmt::Error> {
+    Ok(format!("{} {} {} {} {} {} {}",
+               name_of(&STENCIL_OPS, state.fail_op)?,
+               name_of(&STENCIL_OPS, state.pass_op)?,
+               name_of(&STENCIL_OPS, state.depth_fail_op)?,
+               name_of(&COMPARE_OPS, state.compare_op)?,
+               state.compare_mask,
+               state.write_mask,
+               state.reference))
+}
+
+fn parse_stencil(words : &[&str]) -> Option<vk::StencilOpState> {
+    let op = |name : &str| STENCIL_OPS.iter().find(|(op, _)| *op == name).map(|(_, op)| *op);
+    Some(vk::StencilOpState {
+        fail_op: op(words[0])?,
+        pass_op: op(words[1])?,
+        depth_fail_op: op(words[2])?,
+        compare_op: parse_compare_op(words[3])?,
+        compare_mask: words[4].parse().ok()?,
+        write_mask: words[5].parse().ok()?,
+        reference: words[6].parse().ok()?,
+    })
+}
+
+/// Splits a line into words separated by whitespace, up to a `#` starting a comment. Words in double quotes may contain
+/// whitespace and `#`, with a backslash escaping the next character.
+fn split_words(line : &str) -> Result<Vec<String>, &'static str> {
+    let mut words = Vec::new();
+    let mut characters = line.chars().peekable();
+    while let Some(&character) = characters.peek() {
+        if character.is_whitespace() {
+            characters.next();
+        } else if character == '#' {
+            break;
+        } else if character == '"' {
+            characters.next();
+            let mut word = String::new();
+            loop {
+                match characters.next() {
+                    Some('"') => break,
+                    Some('\\') => word.push(characters.next().ok_or("unterminated quote")?),
+                    Some(character) => word.push(character),
+                    None => return Err("unterminated quote"),
+                }
+            }
+            words.push(word);
+        } else {
+            let mut word = String::new();
+            while let Some(&character) = characters.peek() {
+                if character.is_whitespace() || character == '#' {
+                    break;
+                }
+                word.push(character);
+                characters.next();
+            }
+            words.push(word);
+        }
+    }
+    Ok(words)
+}
+
+/// Writes a path as a single word, quoting it if it is empty or contains whitespace, `#`, quotes or backslashes.
+fn quote_path(path : &Path) -> Result<String, fmt::Error> {
+    let path = path.to_str().ok_or(fmt::Error)?;
+    if !path.is_empty() && !path.contains(|character : char| character.is_whitespace() || "#\"\\".contains(character)) {
+        return Ok(path.to_string());
+    }
+    let mut quoted = String::from("\"");
+    for character in path.chars() {
+        if character == '"' || character == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(character);
+    }
+    quoted.push('"');
+    Ok(quoted)
+}
+
+fn parse_blend(name : &str) -> Option<BlendMode> {
+    BLEND_MODES.iter().find(|(mode, _)| *mode == name).map(|(_, mode)| *mode)
+}
+
+fn parse_cull(name : &str) -> Option<vk::CullModeFlags> {
+    CULL_MODES.iter().find(|(mode, _)| *mode == name).map(|(_, mode)| *mode)
+}
+
+fn parse_polygon(name : &str) -> Option<vk::PolygonMode> {
+    POLYGON_MODES.iter().find(|(mode, _)| *mode == name).map(|(_, mode)| *mode)
+}
+
+fn parse_compare_op(name : &str) -> Option<vk::CompareOp> {
+    COMPARE_OPS.iter().find(|(op, _)| *op == name).map(|(_, op)| *op)
+}
+
+fn push_parameter(parameters : &mut Vec<(String, ParameterValue)>, name : &str, value : ParameterValue)
+    -> Result<(), &'static str> {
+    if parameters.iter().any(|(existing, _)| existing == name) {
+        return Err("parameter is declared twice");
+    }
+    parameters.push((name.to_string(), value));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(desc : &MaterialDesc) -> MaterialDesc {
+        let text = desc.to_string();
+        let parsed : MaterialDesc = text.parse().unwrap_or_else(|error| panic!("{}\n{}", error, text));
+        assert_eq!(parsed.program, desc.program);
+        assert_eq!(parsed.parameters, desc.parameters);
+        // The Vulkan structures do not implement `PartialEq`.
+        assert_eq!(format!("{:?}", parsed.render_state), format!("{:?}", desc.render_state));
+        parsed
+    }
+
+    #[test]
+    fn round_trips_depth_stencil_state() {
+        let stencil = vk::StencilOpState {
+            fail_op: vk::StencilOp::KEEP,
+            pass_op: vk::StencilOp::REPLACE,
+            depth_fail_op: vk::StencilOp::INCREMENT_AND_WRAP,
+            compare_op: vk::CompareOp::NOT_EQUAL,
+            compare_mask: 0xff,
+            write_mask: 0x0f,
+            reference: 3,
+        };
+        let depth = DepthStencilState {
+            depth_test: false,
+            depth_write: false,
+            compare_op: vk::CompareOp::GREATER_OR_EQUAL,
+            stencil_test: true,
+            front: stencil,
+            back: vk::StencilOpState { pass_op: vk::StencilOp::INVERT, ..stencil },
+        };
+        let mut desc = MaterialDesc {
+            program: ProgramDesc::Pbr,
+            render_state: RenderState {
+                blend: BlendMode::Premultiplied,
+                cull_mode: vk::CullModeFlags::BACK,
+                polygon_mode: vk::PolygonMode::LINE,
+                depth: Some(depth),
+            },
+            parameters: Vec::new(),
+        };
+        round_trip(&desc);
+
+        // Faces which are kept while the stencil test is disabled.
+        desc.render_state.depth = Some(DepthStencilState { stencil_test: false, ..depth });
+        assert!(desc.to_string().contains("stencil off"));
+        round_trip(&desc);
+
+        desc.render_state.depth = Some(DepthStencilState::read_only());
+        assert!(!desc.to_string().contains("stencil"));
+        round_trip(&desc);
+
+        desc.render_state.depth = None;
+        round_trip(&desc);
+    }
+
+    #[test]
+    fn round_trips_paths_and_parameters() {
+        let desc = MaterialDesc {
+            program: ProgramDesc::Spirv {
+                vertex: PathBuf::from("shaders/my shader.vert.spv"),
+                fragment: PathBuf::from("shaders/#1 \"final\".frag.spv"),
+            },
+            render_state: RenderState::default(),
+            parameters: vec![
+                ("tint".to_string(), ParameterValue::Color(Vector4::new(1.0, 0.25, 0.1, 0.5))),
+                ("roughness".to_string(), ParameterValue::Scalar(0.8)),
+                ("albedo".to_string(), ParameterValue::Texture(Some(PathBuf::from("textures/red bricks.png")))),
+                ("normal".to_string(), ParameterValue::Texture(Some(PathBuf::from("C:\\textures\\normal.png")))),
+                ("plain".to_string(), ParameterValue::Texture(Some(PathBuf::from("textures/plain.png")))),
+                ("mask".to_string(), ParameterValue::Texture(None)),
+            ],
+        };
+        let text = desc.to_string();
+        assert!(text.contains("texture albedo \"textures/red bricks.png\""));
+        assert!(text.contains("texture plain textures/plain.png\n"));
+        round_trip(&desc);
+    }
+
+    #[test]
+    fn parses_comments_and_quotes() {
+        let desc : MaterialDesc = "# A material\n\
+                                   program textured # built in\n\
+                                   depth less_equal read\n\
+                                   texture albedo \"a # b.png\" # not part of the path\n"
+            .parse()
+            .unwrap();
+        assert_eq!(desc.program, ProgramDesc::Textured);
+        assert_eq!(desc.parameter("albedo"), Some(&ParameterValue::Texture(Some(PathBuf::from("a # b.png")))));
+        let depth = desc.render_state.depth.unwrap();
+        assert!(depth.depth_test && !depth.depth_write && !depth.stencil_test);
+        assert_eq!(depth.compare_op, vk::CompareOp::LESS_OR_EQUAL);
+
+        let error = "program pbr\ntexture albedo \"open.png\n".parse::<MaterialDesc>().unwrap_err();
+        assert!(matches!(error, MaterialParseError::Syntax { line: 2, message: "unterminated quote" }));
+        let error = "program pbr\nstencil on\n".parse::<MaterialDesc>().unwrap_err();
+        assert!(matches!(error, MaterialParseError::Syntax { line: 2, .. }));
+    }
+
+    #[test]
+    fn save_fails_on_unnamed_state() {
+        let mut desc = MaterialDesc {
+            program: ProgramDesc::Colored,
+            render_state: RenderState::default(),
+            parameters: Vec::new(),
+        };
+        desc.render_state.polygon_mode = vk::PolygonMode::FILL_RECTANGLE_NV;
+        let path = std::env::temp_dir().join("halogen_unnamed_state.material");
+        let error = desc.save(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn keeps_paths_when_saved_and_loaded_again() {
+        let dir = std::env::temp_dir().join(format!("halogen-materials-{}", std::process::id()));
+        fs::create_dir_all(dir.join("a")).unwrap();
+        fs::create_dir_all(dir.join("b")).unwrap();
+        let text = "program ../shaders/lit.vert.spv ./lit.frag.spv\n\
+                    texture albedo \"textures/red bricks.png\"\n\
+                    texture mask\n";
+        fs::write(dir.join("a").join("brick.material"), text).unwrap();
+
+        let loaded = MaterialDesc::load(&dir.join("a").join("brick.material")).unwrap();
+        let expected = |path : &str| dir.join(path);
+        match &loaded.program {
+            ProgramDesc::Spirv { vertex, fragment } => {
+                assert_eq!((vertex.clone(), fragment.clone()), (expected("shaders/lit.vert.spv"),
+                                                                expected("a/lit.frag.spv")));
+            }
+            program => panic!("unexpected program {:?}", program),
+        }
+        assert_eq!(loaded.parameter("albedo"),
+                   Some(&ParameterValue::Texture(Some(expected("a/textures/red bricks.png")))));
+
+        // Saving next to the original writes the same relative paths, and saving elsewhere steps out with `..`.
+        loaded.save(&dir.join("a").join("copy.material")).unwrap();
+        loaded.save(&dir.join("b").join("moved.material")).unwrap();
+        let copy = fs::read_to_string(dir.join("a").join("copy.material")).unwrap();
+        let moved = fs::read_to_string(dir.join("b").join("moved.material")).unwrap();
+        assert!(copy.contains("program ../shaders/lit.vert.spv lit.frag.spv\n"), "{}", copy);
+        assert!(moved.contains("texture albedo \"../a/textures/red bricks.png\"\n"), "{}", moved);
+        for path in &[dir.join("a").join("copy.material"), dir.join("b").join("moved.material")] {
+            let reloaded = MaterialDesc::load(path).unwrap();
+            assert_eq!(reloaded.program, loaded.program);
+            assert_eq!(reloaded.parameters, loaded.parameters);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
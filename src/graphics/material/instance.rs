@@ -0,0 +1,254 @@
+use std::sync::Arc;
+use ash::vk;
+use nalgebra::Vector4;
+use super::{MaterialDesc, MaterialError, MaterialTemplate, ParameterType, ParameterValue, BINDLESS_HANDLE_OFFSET};
+use super::super::{CmdBuffer, DescriptorSet, Queue};
+use super::super::bindless::{BindlessHeap, TextureHandle};
+use super::super::descriptors::{DescriptorAllocator, DescriptorWriter};
+use super::super::ring_buffer::RingBuffer;
+use super::super::texture::{Texture, TextureOptions};
+
+/// A material drawn with the pipeline of its template, but with its own parameter values.
+///
+/// Scalars and colors are written into a `RingBuffer` each time the instance is bound, so they can be changed at any
+/// time. Textures are written into the instance's descriptor set when they are set, so they must not be changed while
+/// a frame which drew the instance is still in flight. Instances of bindless templates add their textures to the heap
+/// instead and push their handles when bound, so their textures can be changed at any time.
+pub struct MaterialInstance {
+    template : Arc<MaterialTemplate>,
+    values : Vec<ParameterValue>,
+    /// The texture of each texture parameter, indexed like `values`.
+    textures : Vec<Option<Arc<Texture>>>,
+    /// The handle of each texture in the bindless heap, indexed like `values`.
+    handles : Vec<Option<TextureHandle>>,
+    descriptor_set : DescriptorSet,
+    /// The ring buffer the parameter block is bound from, which must be given to `bind`.
+    ring_buffer : vk::Buffer,
+}
+
+impl MaterialInstance {
+    /// Creates an instance with the template's default values. The descriptor set is allocated from `allocator` and
+    /// refers to `ring_buffer`, which must be the ring the instance is bound with. Default textures are not loaded
+    /// until `load_textures` is called.
+    pub fn new(template : Arc<MaterialTemplate>,
+               allocator : &mut DescriptorAllocator,
+               ring_buffer : &RingBuffer) -> Self {
+        let descriptor_set = allocator.allocate(template.set_layout());
+        if template.block_size() > 0 {
+            DescriptorWriter::new()
+                .uniform_buffer_dynamic(0, ring_buffer.buffer(), 0, template.block_size() as vk::DeviceSize)
+                .update(template.device(), descriptor_set);
+        }
+        let values = template.default_values();
+        Self {
+            textures: vec![None; values.len()],
+            handles: vec![None; values.len()],
+            values,
+            template,
+            descriptor_set,
+            ring_buffer: ring_buffer.buffer().buffer_raw(),
+        }
+    }
+
+    pub fn template(&self) -> &Arc<MaterialTemplate> {
+        &self.template
+    }
+
+    pub fn set_scalar(&mut self, name : &str, value : f32) -> Result<(), MaterialError> {
+        let index = self.index(name, ParameterType::Scalar)?;
+        self.values[index] = ParameterValue::Scalar(value);
+        Ok(())
+    }
+
+    pub fn set_color(&mut self, name : &str, color : Vector4<f32>) -> Result<(), MaterialError> {
+        let index = self.index(name, ParameterType::Color)?;
+        self.values[index] = ParameterValue::Color(color);
+        Ok(())
+    }
+
+    /// Sets a texture parameter and writes it into the descriptor set. Textures of bindless templates must be set with
+    /// `set_bindless_texture` instead.
+    pub fn set_texture(&mut self, name : &str, texture : Arc<Texture>) -> Result<(), MaterialError> {
+        let index = self.index(name, ParameterType::Texture)?;
+        if self.template.is_bindless() {
+            return Err(MaterialError::NeedsHeap(name.to_string()));
+        }
+        let binding = self.template.parameter_location(index);
+        DescriptorWriter::new()
+            .sampled_image(binding, texture.view_raw(), texture.layout())
+            .sampler(binding + 1, texture.sampler_raw())
+            .update(self.template.device(), self.descriptor_set);
+        self.values[index] = ParameterValue::Texture(texture.path().map(|path| path.to_path_buf()));
+        self.textures[index] = Some(texture);
+        Ok(())
+    }
+
+    /// Sets a texture parameter of a bindless template by adding the texture to `heap`, which must be the heap the
+    /// template was created with. The parameter's previous texture is removed from the heap. For other templates this
+    /// is the same as `set_texture`.
+    pub fn set_bindless_texture(&mut self, name : &str, texture : Arc<Texture>, heap : &mut BindlessHeap)
+        -> Result<(), MaterialError> {
+        if !self.template.is_bindless() {
+            return self.set_texture(name, texture);
+        }
+        let index = self.index(name, ParameterType::Texture)?;
+        let handle = heap
+            .add_texture(texture.view_raw(), texture.layout(), texture.sampler_raw())
+            .ok_or_else(|| MaterialError::HeapFull(name.to_string()))?;
+        if let Some(previous) = self.handles[index].replace(handle) {
+            heap.remove_texture(previous);
+        }
+        self.values[index] = ParameterValue::Texture(texture.path().map(|path| path.to_path_buf()));
+        self.textures[index] = Some(texture);
+        Ok(())
+    }
+
+    /// Removes the instance's textures from the bindless heap, which should be done before dropping instances of
+    /// bindless templates so their slots can be reused.
+    pub fn remove_bindless_textures(&mut self, heap : &mut BindlessHeap) {
+        for (handle, texture) in self.handles.iter_mut().zip(&mut self.textures) {
+            if let Some(handle) = handle.take() {
+                heap.remove_texture(handle);
+                *texture = None;
+            }
+        }
+    }
+
+    pub fn value(&self, name : &str) -> Option<&ParameterValue> {
+        self.template.parameter_index(name).map(|index| &self.values[index])
+    }
+
+    pub fn texture(&self, name : &str) -> Option<&Arc<Texture>> {
+        self.template.parameter_index(name).and_then(|index| self.textures[index].as_ref())
+    }
+
+    /// Loads the file of every texture parameter which has a path but no texture yet. `options` gives the options of
+    /// each texture from its parameter's name, since color maps are usually sRGB while data maps are not.
+    pub fn load_textures(&mut self,
+                         transfer_queue : &Queue,
+                         graphics_queue : &Queue,
+                         options : impl Fn(&str) -> TextureOptions) -> Result<(), MaterialError> {
+        for index in 0..self.values.len() {
+            let path = match &self.values[index] {
+                ParameterValue::Texture(Some(path)) if self.textures[index].is_none() => path.clone(),
+                _ => continue,
+            };
+            let name = self.template.parameter_name(index).to_string();
+            let texture = Texture::from_file(
+                Arc::clone(self.template.device()),
+                transfer_queue,
+                graphics_queue,
+                &path,
+                options(&name))?;
+            self.set_texture(&name, Arc::new(texture))?;
+        }
+        Ok(())
+    }
+
+    /// Sets the values of the parameters in `desc`, ignoring its program and render state. A texture parameter whose
+    /// path changes loses its texture until `load_textures` is called.
+    pub fn apply(&mut self, desc : &MaterialDesc) -> Result<(), MaterialError> {
+        for (name, value) in &desc.parameters {
+            let index = self.index(name, value.parameter_type())?;
+            if let ParameterValue::Texture(_) = value {
+                if self.values[index] != *value {
+                    self.textures[index] = None;
+                }
+            }
+            self.values[index] = value.clone();
+        }
+        Ok(())
+    }
+
+    /// Describes the instance, with the template's program and render state and the instance's parameter values.
+    pub fn desc(&self) -> MaterialDesc {
+        let mut desc = self.template.desc();
+        for ((_, value), instance_value) in desc.parameters.iter_mut().zip(&self.values) {
+            *value = instance_value.clone();
+        }
+        desc
+    }
+
+    /// Binds the template's pipeline and the instance's parameters. The scalars and colors are written into
+    /// `ring_buffer`, which must be the ring the instance was created with.
+    pub fn bind(&self, cmd_buffer : &mut CmdBuffer, ring_buffer : &mut RingBuffer) -> Result<(), MaterialError> {
+        self.check_textures()?;
+        let dynamic_offsets = self.write_parameters(ring_buffer)?;
+        self.template.bind(cmd_buffer)?;
+        self.bind_parameters(cmd_buffer, &dynamic_offsets)
+    }
+
+    /// Writes the scalars and colors into `ring_buffer`, which must be the ring the instance was created with. Returns
+    /// the dynamic offsets to give to `bind_parameters`.
+    pub fn write_parameters(&self, ring_buffer : &mut RingBuffer) -> Result<Vec<u32>, MaterialError> {
+        debug_assert_eq!(ring_buffer.buffer().buffer_raw(), self.ring_buffer,
+            "Material instance bound with a different ring buffer than it was created with");
+        let mut dynamic_offsets = Vec::new();
+        if self.template.block_size() > 0 {
+            let allocation = ring_buffer.write_bytes(&self.block())?;
+            dynamic_offsets.push(allocation.dynamic_offset());
+        }
+        Ok(dynamic_offsets)
+    }
+
+    /// Binds the parameters written by `write_parameters` and, if the template is bindless, pushes the handles of the
+    /// textures. The template must already be bound, which lets instances sharing a template be drawn one after
+    /// another without binding the pipeline or the heap again.
+    pub fn bind_parameters(&self, cmd_buffer : &mut CmdBuffer, dynamic_offsets : &[u32]) -> Result<(), MaterialError> {
+        self.check_textures()?;
+        let pipeline = self.template.pipeline();
+        cmd_buffer.bind_descriptor_sets(pipeline, 0, &[self.descriptor_set], dynamic_offsets)?;
+        if self.template.is_bindless() && self.template.texture_count() > 0 {
+            let mut indices = vec![0; self.template.texture_count()];
+            for (index, handle) in self.handles.iter().enumerate() {
+                if let Some(handle) = handle {
+                    indices[self.template.parameter_location(index) as usize] = handle.index();
+                }
+            }
+            let stages = self.template.push_constant_stages();
+            BindlessHeap::push_handles(cmd_buffer, pipeline, stages, BINDLESS_HANDLE_OFFSET, &indices)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that every texture parameter has a texture.
+    fn check_textures(&self) -> Result<(), MaterialError> {
+        let missing = self.values
+            .iter()
+            .zip(&self.textures)
+            .position(|(value, texture)| value.parameter_type() == ParameterType::Texture && texture.is_none());
+        match missing {
+            Some(index) => Err(MaterialError::MissingTexture(self.template.parameter_name(index).to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Packs the scalars and colors into the parameter block.
+    fn block(&self) -> Vec<u8> {
+        let mut block = vec![0; self.template.block_size() as usize];
+        for (index, value) in self.values.iter().enumerate() {
+            let offset = self.template.parameter_location(index) as usize;
+            let components : &[f32] = match value {
+                ParameterValue::Scalar(value) => std::slice::from_ref(value),
+                ParameterValue::Color(color) => color.as_slice(),
+                ParameterValue::Texture(_) => continue,
+            };
+            for (component, bytes) in components.iter().zip(block[offset..].chunks_exact_mut(4)) {
+                bytes.copy_from_slice(&component.to_le_bytes());
+            }
+        }
+        block
+    }
+
+    /// Returns the index of the named parameter, checking that it has the expected type.
+    fn index(&self, name : &str, expected : ParameterType) -> Result<usize, MaterialError> {
+        let index = self.template
+            .parameter_index(name)
+            .ok_or_else(|| MaterialError::UnknownParameter(name.to_string()))?;
+        if self.values[index].parameter_type() != expected {
+            let expected = self.values[index].parameter_type();
+            return Err(MaterialError::MismatchedType { name: name.to_string(), expected });
+        }
+        Ok(index)
+    }
+}
@@ -0,0 +1,273 @@
+use std::{ffi::CString, fs::File, io::Read, mem::size_of, sync::Arc};
+use ash::vk;
+use nalgebra::{Vector2, Vector3, Vector4};
+use super::Device;
+use super::reflect::{PipelineReflection, ShaderReflection};
+use super::shader::{ShaderModule, ShaderModuleError};
+#[cfg(feature = "shader-compiler")]
+use super::shader_compiler::{ShaderCompileError, ShaderCompiler};
+#[cfg(feature = "shader-compiler")]
+use std::path::{Path, PathBuf};
+
+mod desc;
+mod instance;
+mod pbr;
+mod template;
+
+pub use self::desc::{MaterialDesc, MaterialParseError};
+pub use self::instance::MaterialInstance;
+pub use self::pbr::{PbrDefaultTextures, PbrMaterial};
+pub use self::template::{ColoredMaterial, MaterialError, MaterialTemplate, ParameterType, ParameterValue, ProgramDesc,
+                         RenderState, TexturedMaterial, BINDLESS_HANDLE_OFFSET};
+
+/// Stores the vertex information associated.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vertex {
+    pub position : Vector3<f32>,
+    pub color : Vector4<f32>,
+    pub texture_coord : Vector2<f32>,
+}
+
+impl Vertex {
+    pub fn new(position : Vector3<f32>, color : Vector4<f32>, texture_coord : Vector2<f32>) -> Self {
+        Self { position, color, texture_coord }
+    }
+
+    /// Describes a buffer of vertices bound at `binding`.
+    pub fn binding_description(binding : u32) -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription {
+            binding,
+            stride: size_of::<Vertex>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }
+    }
+
+    /// Describes the position, color and texture coordinate at locations 0, 1 and 2 respectively.
+    pub fn attribute_descriptions(binding : u32) -> Vec<vk::VertexInputAttributeDescription> {
+        let color_offset = size_of::<Vector3<f32>>() as u32;
+        let texture_coord_offset = color_offset + size_of::<Vector4<f32>>() as u32;
+        vec![
+            vk::VertexInputAttributeDescription { location: 0, binding, format: vk::Format::R32G32B32_SFLOAT, offset: 0 },
+            vk::VertexInputAttributeDescription {
+                location: 1,
+                binding,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                offset: color_offset,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 2,
+                binding,
+                format: vk::Format::R32G32_SFLOAT,
+                offset: texture_coord_offset,
+            },
+        ]
+    }
+}
+
+/// A vertex of a lit mesh, as read by `PbrMaterial`. The tangent's w is the handedness of the bitangent, which is
+/// `cross(normal, tangent) * w`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PbrVertex {
+    pub position : Vector3<f32>,
+    pub normal : Vector3<f32>,
+    pub tangent : Vector4<f32>,
+    pub texture_coord : Vector2<f32>,
+}
+
+impl PbrVertex {
+    /// Describes a buffer of vertices bound at `binding`.
+    pub fn binding_description(binding : u32) -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription {
+            binding,
+            stride: size_of::<PbrVertex>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }
+    }
+
+    /// Describes the position, normal, tangent and texture coordinate at locations 0, 1, 2 and 3 respectively.
+    pub fn attribute_descriptions(binding : u32) -> Vec<vk::VertexInputAttributeDescription> {
+        let normal_offset = size_of::<Vector3<f32>>() as u32;
+        let tangent_offset = normal_offset + size_of::<Vector3<f32>>() as u32;
+        let texture_coord_offset = tangent_offset + size_of::<Vector4<f32>>() as u32;
+        vec![
+            vk::VertexInputAttributeDescription { location: 0, binding, format: vk::Format::R32G32B32_SFLOAT, offset: 0 },
+            vk::VertexInputAttributeDescription {
+                location: 1,
+                binding,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: normal_offset,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 2,
+                binding,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                offset: tangent_offset,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 3,
+                binding,
+                format: vk::Format::R32G32_SFLOAT,
+                offset: texture_coord_offset,
+            },
+        ]
+    }
+}
+
+/// Reflects the vertex and fragment shaders and merges their interfaces. Reflection is best effort, so failures are
+/// logged and the pipeline falls back to the layout given to its builder.
+fn reflect(vertex_module : &ShaderModule, fragment_module : &ShaderModule) -> Option<PipelineReflection> {
+    ShaderReflection::new(vertex_module.code())
+        .and_then(|vertex| Ok((vertex, ShaderReflection::new(fragment_module.code())?)))
+        .and_then(|(vertex, fragment)| PipelineReflection::merge(&[&vertex, &fragment]))
+        .map_err(|error| warn!("Failed to reflect material shaders: {:?}", error))
+        .ok()
+}
+
+/// The shader program of a material, along with the vertex input it reads. A `MaterialTemplate` adds parameters and
+/// render state to a program, and `MaterialInstance`s give those parameters their values.
+pub struct Material {
+    device : Arc<Device>,
+    entry_point : CString,
+    vertex_module : ShaderModule,
+    fragment_module : ShaderModule,
+    vertex_bindings : Vec<vk::VertexInputBindingDescription>,
+    vertex_attributes : Vec<vk::VertexInputAttributeDescription>,
+    reflection : Option<PipelineReflection>,
+    /// The vertex and fragment shader sources, if the material was compiled from them.
+    #[cfg(feature = "shader-compiler")]
+    sources : Option<(PathBuf, PathBuf)>,
+    /// Every file the shaders were compiled from, including included files.
+    #[cfg(feature = "shader-compiler")]
+    dependencies : Vec<PathBuf>,
+}
+
+impl Drop for Material {
+    fn drop(&mut self) {
+        info!("Dropped Material")
+    }
+}
+
+impl Material {
+    pub fn new(device : Arc<Device>) -> Self {
+        Self::from_spirv(
+            device,
+            include_bytes!("../../assets/shaders/vert.spv"),
+            include_bytes!("../../assets/shaders/frag.spv"))
+            .expect("Failed to load default shaders")
+    }
+
+    /// Creates a material from SPIR-V vertex and fragment shaders, both using `main` as the entry point.
+    pub fn from_spirv(device : Arc<Device>, vertex_code : &[u8], fragment_code : &[u8])
+        -> Result<Self, ShaderModuleError> {
+        let vertex_module = ShaderModule::from_bytes(Arc::clone(&device), vertex_code)?;
+        let fragment_module = ShaderModule::from_bytes(Arc::clone(&device), fragment_code)?;
+        Ok(Self::from_modules(device, vertex_module, fragment_module))
+    }
+
+    /// Creates a material from vertex and fragment shader modules, both using `main` as the entry point.
+    pub fn from_modules(device : Arc<Device>, vertex_module : ShaderModule, fragment_module : ShaderModule) -> Self {
+        // Have to keep this pointer alive.
+        let entry_point = CString::new("main").unwrap();
+        let reflection = reflect(&vertex_module, &fragment_module);
+        Self { device,
+            entry_point,
+            vertex_module,
+            fragment_module,
+            vertex_bindings: Vec::new(),
+            vertex_attributes: Vec::new(),
+            reflection,
+            #[cfg(feature = "shader-compiler")]
+            sources: None,
+            #[cfg(feature = "shader-compiler")]
+            dependencies: Vec::new(),
+        }
+    }
+
+    /// Compiles the GLSL or WGSL vertex and fragment shaders at the given paths and creates a material from them. WGSL
+    /// entry points need to be called `main`.
+    #[cfg(feature = "shader-compiler")]
+    pub fn from_glsl(device : Arc<Device>,
+                     compiler : &ShaderCompiler,
+                     vertex_path : &Path,
+                     fragment_path : &Path) -> Result<Self, ShaderCompileError> {
+        let (vertex_code, mut dependencies) = compiler.compile_file_with_dependencies(vertex_path)?;
+        let (fragment_code, fragment_dependencies) = compiler.compile_file_with_dependencies(fragment_path)?;
+        dependencies.extend(fragment_dependencies);
+        let vertex_module = ShaderModule::from_words(Arc::clone(&device), vertex_code)
+            .map_err(ShaderCompileError::Module)?;
+        let fragment_module = ShaderModule::from_words(Arc::clone(&device), fragment_code)
+            .map_err(ShaderCompileError::Module)?;
+        let mut material = Self::from_modules(device, vertex_module, fragment_module);
+        material.sources = Some((vertex_path.to_path_buf(), fragment_path.to_path_buf()));
+        material.dependencies = dependencies;
+        Ok(material)
+    }
+
+    /// Returns the files the material was compiled from, which is empty if it was not compiled from source.
+    #[cfg(feature = "shader-compiler")]
+    pub fn dependencies(&self) -> &[PathBuf] {
+        &self.dependencies
+    }
+
+    /// Compiles the material's sources again, returning a new material. Returns `None` if the material was not
+    /// compiled from source.
+    #[cfg(feature = "shader-compiler")]
+    pub fn recompile(&self, compiler : &ShaderCompiler) -> Option<Result<Self, ShaderCompileError>> {
+        let (vertex_path, fragment_path) = self.sources.as_ref()?;
+        Some(Self::from_glsl(Arc::clone(&self.device), compiler, vertex_path, fragment_path))
+    }
+
+    pub fn vertex_buffer_size(&self) -> vk::DeviceSize { size_of::<Vertex>() as vk::DeviceSize }
+
+    /// Returns the shader stages used to create a pipeline. These point into the material, so they are built on each
+    /// call rather than stored, which keeps the material safe to share between threads.
+    pub fn pipeline_shader_stages(&self) -> Vec<vk::PipelineShaderStageCreateInfo> {
+        let vertex_pipeline_stage = vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::VERTEX)
+            .module(self.vertex_module.module_raw())
+            .name(self.entry_point.as_c_str());
+        let fragment_pipeline_stage = vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::FRAGMENT)
+            .module(self.fragment_module.module_raw())
+            .name(self.entry_point.as_c_str());
+        vec![vertex_pipeline_stage.build(), fragment_pipeline_stage.build()]
+    }
+
+    /// Returns the vertex input state used to create a pipeline, which points into the material.
+    pub fn pipeline_vertex_input_state(&self) -> vk::PipelineVertexInputStateCreateInfo {
+        vk::PipelineVertexInputStateCreateInfo::builder()
+            .vertex_binding_descriptions(self.vertex_bindings.as_slice())
+            .vertex_attribute_descriptions(self.vertex_attributes.as_slice())
+            .build()
+    }
+
+    /// Returns the shader modules of each stage, which identify the material's shaders when deduplicating pipelines.
+    pub fn shader_modules(&self) -> Vec<(vk::ShaderStageFlags, vk::ShaderModule)> {
+        vec![(vk::ShaderStageFlags::VERTEX, self.vertex_module.module_raw()),
+             (vk::ShaderStageFlags::FRAGMENT, self.fragment_module.module_raw())]
+    }
+
+    pub fn vertex_bindings(&self) -> &[vk::VertexInputBindingDescription] {
+        &self.vertex_bindings
+    }
+
+    pub fn vertex_attributes(&self) -> &[vk::VertexInputAttributeDescription] {
+        &self.vertex_attributes
+    }
+
+    /// Sets the vertex buffers the material's vertex shader reads from, such as those described by `Vertex`.
+    pub fn with_vertex_input(mut self,
+                             bindings : Vec<vk::VertexInputBindingDescription>,
+                             attributes : Vec<vk::VertexInputAttributeDescription>) -> Self {
+        self.vertex_bindings = bindings;
+        self.vertex_attributes = attributes;
+        self
+    }
+
+    /// Returns the merged interface of the material's shaders, or `None` if they could not be reflected.
+    pub fn reflection(&self) -> Option<&PipelineReflection> {
+        self.reflection.as_ref()
+    }
+}
\ No newline at end of file
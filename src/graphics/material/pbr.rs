@@ -0,0 +1,116 @@
+use std::sync::Arc;
+use ash::vk;
+use nalgebra::Vector4;
+use super::{MaterialDesc, MaterialError, MaterialInstance, MaterialTemplate, ParameterValue, ProgramDesc, RenderState};
+use super::super::{DepthStencilState, Device, Queue, RenderPass};
+use super::super::image::ImageData;
+use super::super::lighting::Lighting;
+use super::super::texture::{Texture, TextureError, TextureOptions};
+
+/// A material lit by the metallic-roughness model used by glTF, with the lights and environment of a `Lighting`.
+///
+/// The base color, emissive color, metallic and roughness factors scale the matching maps. Roughness is read from the
+/// green channel of the metallic-roughness map and metalness from its blue channel, while occlusion is read from the
+/// red channel of the occlusion map. The normal map is in tangent space, so meshes need tangents in their `PbrVertex`.
+///
+/// The vertex shader reads the model matrix and the normal matrix, which is the inverse transpose of the model matrix,
+/// as two `mat4` push constants.
+pub struct PbrMaterial;
+
+impl PbrMaterial {
+    pub const BASE_COLOR : &'static str = "base_color";
+    pub const EMISSIVE : &'static str = "emissive";
+    pub const METALLIC : &'static str = "metallic";
+    pub const ROUGHNESS : &'static str = "roughness";
+    pub const NORMAL_SCALE : &'static str = "normal_scale";
+    pub const OCCLUSION_STRENGTH : &'static str = "occlusion_strength";
+    pub const BASE_COLOR_MAP : &'static str = "base_color_map";
+    pub const METALLIC_ROUGHNESS_MAP : &'static str = "metallic_roughness_map";
+    pub const NORMAL_MAP : &'static str = "normal_map";
+    pub const OCCLUSION_MAP : &'static str = "occlusion_map";
+    pub const EMISSIVE_MAP : &'static str = "emissive_map";
+
+    /// Describes a PBR material with the given factors and no maps, drawn with a depth test and back face culling.
+    pub fn desc(base_color : Vector4<f32>, metallic : f32, roughness : f32) -> MaterialDesc {
+        let scalar = |name : &str, value| (name.to_string(), ParameterValue::Scalar(value));
+        let map = |name : &str| (name.to_string(), ParameterValue::Texture(None));
+        MaterialDesc {
+            program: ProgramDesc::Pbr,
+            render_state: RenderState {
+                cull_mode: vk::CullModeFlags::BACK,
+                depth: Some(DepthStencilState::default()),
+                ..RenderState::default()
+            },
+            // The order matches the parameter block of the fragment shader.
+            parameters: vec![
+                (Self::BASE_COLOR.to_string(), ParameterValue::Color(base_color)),
+                (Self::EMISSIVE.to_string(), ParameterValue::Color(Vector4::new(0.0, 0.0, 0.0, 1.0))),
+                scalar(Self::METALLIC, metallic),
+                scalar(Self::ROUGHNESS, roughness),
+                scalar(Self::NORMAL_SCALE, 1.0),
+                scalar(Self::OCCLUSION_STRENGTH, 1.0),
+                map(Self::BASE_COLOR_MAP),
+                map(Self::METALLIC_ROUGHNESS_MAP),
+                map(Self::NORMAL_MAP),
+                map(Self::OCCLUSION_MAP),
+                map(Self::EMISSIVE_MAP),
+            ],
+        }
+    }
+
+    /// Creates the template shared by PBR materials drawn in `render_pass` and lit by `lighting`, defaulting to a
+    /// white dielectric.
+    pub fn template(device : Arc<Device>, render_pass : &RenderPass, lighting : &Lighting)
+        -> Result<MaterialTemplate, MaterialError> {
+        let desc = Self::desc(Vector4::new(1.0, 1.0, 1.0, 1.0), 0.0, 1.0);
+        MaterialTemplate::from_desc(device, render_pass, &desc, &[lighting.set_layout()])
+    }
+
+    /// Returns the options for loading the named map, for `MaterialInstance::load_textures`. The base color and
+    /// emissive maps are sRGB, while the others hold linear data.
+    pub fn texture_options(name : &str) -> TextureOptions {
+        let srgb = name == Self::BASE_COLOR_MAP || name == Self::EMISSIVE_MAP;
+        TextureOptions { srgb, ..TextureOptions::default() }
+    }
+}
+
+/// Neutral textures for the maps of a PBR material which have not been given one, so the maps are optional. Create
+/// them once and share them between every instance.
+pub struct PbrDefaultTextures {
+    /// A white texel, which leaves the factors of the material unchanged.
+    white : Arc<Texture>,
+    /// A texel pointing straight out of the surface.
+    flat_normal : Arc<Texture>,
+}
+
+impl PbrDefaultTextures {
+    pub fn new(device : Arc<Device>, transfer_queue : &Queue, graphics_queue : &Queue) -> Result<Self, TextureError> {
+        let texel = |pixel : [u8; 4]| {
+            let levels = vec![pixel.to_vec()];
+            let data = ImageData { width: 1, height: 1, format: vk::Format::R8G8B8A8_UNORM, levels };
+            let options = TextureOptions { srgb: false, generate_mipmaps: false, ..TextureOptions::default() };
+            Texture::from_image_data(Arc::clone(&device), transfer_queue, graphics_queue, data, options).map(Arc::new)
+        };
+        Ok(Self { white: texel([255, 255, 255, 255])?, flat_normal: texel([128, 128, 255, 255])? })
+    }
+
+    /// Gives every map of `instance` without a path or a texture the matching default texture. Maps with a path are
+    /// left to `MaterialInstance::load_textures`, so this is usually called after it.
+    pub fn apply(&self, instance : &mut MaterialInstance) -> Result<(), MaterialError> {
+        let maps = [
+            (PbrMaterial::BASE_COLOR_MAP, &self.white),
+            (PbrMaterial::METALLIC_ROUGHNESS_MAP, &self.white),
+            (PbrMaterial::NORMAL_MAP, &self.flat_normal),
+            (PbrMaterial::OCCLUSION_MAP, &self.white),
+            (PbrMaterial::EMISSIVE_MAP, &self.white),
+        ];
+        for (name, texture) in maps.iter() {
+            let unset = instance.texture(name).is_none()
+                && matches!(instance.value(name), Some(ParameterValue::Texture(None)));
+            if unset {
+                instance.set_texture(name, Arc::clone(texture))?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,521 @@
+use std::{fmt, path::PathBuf, sync::Arc};
+use ash::vk;
+use nalgebra::Vector4;
+use super::{Material, MaterialDesc, PbrVertex, Vertex};
+use super::super::{BlendMode, CmdBuffer, CmdRecordingError, DepthStencilState, DescriptorSet, Device, Pipeline,
+                   PipelineBuilder, RenderPass};
+use super::super::bindless::BindlessHeap;
+use super::super::descriptors::{DescriptorSetLayout, DescriptorSetLayoutBuilder};
+use super::super::pipeline::PipelineError;
+use super::super::ring_buffer::RingBufferError;
+use super::super::shader::ShaderModuleError;
+use super::super::texture::TextureError;
+
+/// Describes why a material could not be created or bound.
+#[derive(Debug)]
+pub enum MaterialError {
+    Program(ShaderModuleError),
+    /// The material has no parameter with this name.
+    UnknownParameter(String),
+    /// The parameter was given a value of a different type than it was declared with.
+    MismatchedType { name : String, expected : ParameterType },
+    /// The texture parameter has not been given a texture, so the material cannot be bound.
+    MissingTexture(String),
+    Texture(TextureError),
+    RingBuffer(RingBufferError),
+    Recording(CmdRecordingError),
+    Pipeline(PipelineError),
+    /// The program has no variant which reads its textures from a `BindlessHeap`.
+    BindlessUnsupported(ProgramDesc),
+    /// The texture parameter belongs to a bindless material, so it must be set with
+    /// `MaterialInstance::set_bindless_texture`.
+    NeedsHeap(String),
+    /// The bindless heap has no free slots left for the texture.
+    HeapFull(String),
+}
+
+impl fmt::Display for MaterialError {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MaterialError::Program(error) => write!(f, "failed to load material program: {}", error),
+            MaterialError::UnknownParameter(name) => write!(f, "material has no parameter `{}`", name),
+            MaterialError::MismatchedType { name, expected } =>
+                write!(f, "material parameter `{}` is a {:?}", name, expected),
+            MaterialError::MissingTexture(name) => write!(f, "material texture `{}` has not been set", name),
+            MaterialError::Texture(error) => write!(f, "failed to load material texture: {}", error),
+            MaterialError::RingBuffer(error) => write!(f, "failed to write material parameters: {:?}", error),
+            MaterialError::Recording(error) => write!(f, "failed to bind material: {:?}", error),
+            MaterialError::Pipeline(error) => write!(f, "{}", error),
+            MaterialError::BindlessUnsupported(program) => write!(f, "program {:?} cannot be bindless", program),
+            MaterialError::NeedsHeap(name) => write!(f, "bindless material texture `{}` needs the heap", name),
+            MaterialError::HeapFull(name) => write!(f, "bindless heap is full, cannot add texture `{}`", name),
+        }
+    }
+}
+
+impl From<ShaderModuleError> for MaterialError {
+    fn from(error : ShaderModuleError) -> Self {
+        MaterialError::Program(error)
+    }
+}
+
+impl From<PipelineError> for MaterialError {
+    fn from(error : PipelineError) -> Self {
+        MaterialError::Pipeline(error)
+    }
+}
+
+impl From<TextureError> for MaterialError {
+    fn from(error : TextureError) -> Self {
+        MaterialError::Texture(error)
+    }
+}
+
+impl From<RingBufferError> for MaterialError {
+    fn from(error : RingBufferError) -> Self {
+        MaterialError::RingBuffer(error)
+    }
+}
+
+impl From<CmdRecordingError> for MaterialError {
+    fn from(error : CmdRecordingError) -> Self {
+        MaterialError::Recording(error)
+    }
+}
+
+/// The vertex and fragment shaders of a material. Every program reads its parameters from set 0, as described by
+/// `MaterialTemplate`, and vertices from binding 0. `Pbr` reads `PbrVertex`, while the other programs read `Vertex`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProgramDesc {
+    /// Multiplies the vertex color by a `color` parameter.
+    Colored,
+    /// Multiplies the vertex color by a `tint` parameter and an `albedo` texture.
+    Textured,
+    /// Shades with the metallic-roughness model and the lights and environment of a `Lighting`, as described by
+    /// `PbrMaterial`.
+    Pbr,
+    /// Precompiled SPIR-V vertex and fragment shaders, both using `main` as the entry point.
+    Spirv { vertex : PathBuf, fragment : PathBuf },
+}
+
+impl ProgramDesc {
+    /// Loads the shaders of the program.
+    pub fn load(&self, device : Arc<Device>) -> Result<Material, ShaderModuleError> {
+        let material = match self {
+            ProgramDesc::Colored => Material::from_spirv(
+                device,
+                include_bytes!("../../assets/shaders/material_vert.spv"),
+                include_bytes!("../../assets/shaders/colored_frag.spv"))?,
+            ProgramDesc::Textured => Material::from_spirv(
+                device,
+                include_bytes!("../../assets/shaders/material_vert.spv"),
+                include_bytes!("../../assets/shaders/textured_frag.spv"))?,
+            ProgramDesc::Pbr => {
+                let material = Material::from_spirv(
+                    device,
+                    include_bytes!("../../assets/shaders/pbr_vert.spv"),
+                    include_bytes!("../../assets/shaders/pbr_frag.spv"))?;
+                return Ok(material.with_vertex_input(
+                    vec![PbrVertex::binding_description(0)],
+                    PbrVertex::attribute_descriptions(0)));
+            }
+            ProgramDesc::Spirv { vertex, fragment } => {
+                let read = |path : &PathBuf| std::fs::read(path).map_err(|error| ShaderModuleError::Io(path.clone(), error));
+                Material::from_spirv(device, &read(vertex)?, &read(fragment)?)?
+            }
+        };
+        Ok(material.with_vertex_input(vec![Vertex::binding_description(0)], Vertex::attribute_descriptions(0)))
+    }
+
+    /// Loads the shaders of the program's bindless variant, described by `MaterialTemplate::new_bindless`. Precompiled
+    /// programs are assumed to be written for it already, and programs without textures need no variant.
+    pub fn load_bindless(&self, device : Arc<Device>) -> Result<Material, MaterialError> {
+        match self {
+            ProgramDesc::Colored | ProgramDesc::Spirv { .. } => Ok(self.load(device)?),
+            ProgramDesc::Textured => {
+                let material = Material::from_spirv(
+                    device,
+                    include_bytes!("../../assets/shaders/material_vert.spv"),
+                    include_bytes!("../../assets/shaders/textured_bindless_frag.spv"))?;
+                Ok(material.with_vertex_input(vec![Vertex::binding_description(0)], Vertex::attribute_descriptions(0)))
+            }
+            ProgramDesc::Pbr => Err(MaterialError::BindlessUnsupported(self.clone())),
+        }
+    }
+}
+
+/// The type of a material parameter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParameterType {
+    /// A `float` in the parameter block.
+    Scalar,
+    /// A `vec4` in the parameter block.
+    Color,
+    /// A `texture2D` followed by its `sampler`.
+    Texture,
+}
+
+/// The value of a material parameter. Textures are identified by the file they are loaded from, if any.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParameterValue {
+    Scalar(f32),
+    Color(Vector4<f32>),
+    Texture(Option<PathBuf>),
+}
+
+impl ParameterValue {
+    pub fn parameter_type(&self) -> ParameterType {
+        match self {
+            ParameterValue::Scalar(_) => ParameterType::Scalar,
+            ParameterValue::Color(_) => ParameterType::Color,
+            ParameterValue::Texture(_) => ParameterType::Texture,
+        }
+    }
+}
+
+/// The fixed function state a material is drawn with.
+#[derive(Clone, Copy, Debug)]
+pub struct RenderState {
+    pub blend : BlendMode,
+    pub cull_mode : vk::CullModeFlags,
+    pub polygon_mode : vk::PolygonMode,
+    /// The depth test, or `None` to draw without one. Must be `None` for render passes without a depth attachment.
+    pub depth : Option<DepthStencilState>,
+}
+
+impl Default for RenderState {
+    /// Opaque, unculled and filled, without a depth test.
+    fn default() -> Self {
+        Self {
+            blend: BlendMode::Opaque,
+            cull_mode: vk::CullModeFlags::NONE,
+            polygon_mode: vk::PolygonMode::FILL,
+            depth: None,
+        }
+    }
+}
+
+impl RenderState {
+    /// Applies the state to a pipeline builder. Front faces are wound counter-clockwise.
+    pub fn apply(&self, builder : PipelineBuilder) -> PipelineBuilder {
+        let builder = builder
+            .blend(self.blend)
+            .cull_mode(self.cull_mode, vk::FrontFace::COUNTER_CLOCKWISE)
+            .polygon_mode(self.polygon_mode);
+        match self.depth {
+            Some(depth) => builder.depth_stencil(depth),
+            None => builder,
+        }
+    }
+}
+
+/// The size of the transform which bindless templates reserve at the start of their push constants, which is the
+/// model matrix read by the vertex shader of the built-in programs. Texture handles are pushed after it.
+pub const BINDLESS_HANDLE_OFFSET : u32 = 64;
+
+/// A declared parameter and where the shaders read it from.
+struct ParameterSlot {
+    name : String,
+    /// The offset in the parameter block, or for textures the binding of the image, or the index of the handle if the
+    /// template is bindless.
+    location : u32,
+    default : ParameterValue,
+}
+
+/// The parts of a material which are shared by its instances: the program, the declared parameters along with their
+/// default values, the render state and the pipeline built from them.
+///
+/// Parameters are read from descriptor set 0. Scalars and colors are packed into a uniform block at binding 0 in the
+/// order they are declared, following std140 rules, so the shaders must declare the block's members in the same
+/// order. Each texture is bound as a `texture2D` followed by a `sampler`, from binding 1 onwards in declaration order.
+/// Programs which read per-frame data, such as lights, get it from the scene sets given to the template, which follow
+/// set 0.
+///
+/// Bindless templates, created with `new_bindless`, read their textures from a `BindlessHeap` instead, so that draws
+/// of different instances only differ by their parameter block and push constants.
+pub struct MaterialTemplate {
+    device : Arc<Device>,
+    program_desc : ProgramDesc,
+    render_state : RenderState,
+    parameters : Vec<ParameterSlot>,
+    block_size : u32,
+    set_layout : DescriptorSetLayout,
+    /// The set of the heap the textures are read from, if the template is bindless.
+    heap_set : Option<DescriptorSet>,
+    pipeline : Pipeline,
+    /// Kept alive for the pipeline, which was built from its shader modules.
+    _program : Material,
+}
+
+impl MaterialTemplate {
+    /// Loads the program and builds its pipeline for `render_pass`. The value given for each parameter is the default
+    /// of new instances. `scene_set_layouts` are the layouts of sets 1 onwards, which the caller binds itself.
+    pub fn new(device : Arc<Device>,
+               render_pass : &RenderPass,
+               program_desc : ProgramDesc,
+               parameters : Vec<(String, ParameterValue)>,
+               render_state : RenderState,
+               scene_set_layouts : &[&DescriptorSetLayout]) -> Result<Self, MaterialError> {
+        let program = program_desc.load(Arc::clone(&device))?;
+        let (heap, scene_sets) = (None, scene_set_layouts);
+        Self::with_program(device, render_pass, program_desc, program, parameters, render_state, heap, scene_sets)
+    }
+
+    /// Like `new`, but loads the program's bindless variant, which reads its textures from `heap` at set 1. Set 0 only
+    /// holds the parameter block and the scene sets start at set 2. The heap must outlive the template.
+    ///
+    /// The vertex and fragment stages share one push constant range, see `push_constant_stages`. It starts with the
+    /// `BINDLESS_HANDLE_OFFSET` bytes of the vertex shader's transform, followed by the heap index of each texture as a
+    /// `uint`, in declaration order.
+    pub fn new_bindless(device : Arc<Device>,
+                        render_pass : &RenderPass,
+                        program_desc : ProgramDesc,
+                        parameters : Vec<(String, ParameterValue)>,
+                        render_state : RenderState,
+                        heap : &BindlessHeap,
+                        scene_set_layouts : &[&DescriptorSetLayout]) -> Result<Self, MaterialError> {
+        let program = program_desc.load_bindless(Arc::clone(&device))?;
+        let (heap, scene_sets) = (Some(heap), scene_set_layouts);
+        Self::with_program(device, render_pass, program_desc, program, parameters, render_state, heap, scene_sets)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn with_program(device : Arc<Device>,
+                    render_pass : &RenderPass,
+                    program_desc : ProgramDesc,
+                    program : Material,
+                    parameters : Vec<(String, ParameterValue)>,
+                    render_state : RenderState,
+                    heap : Option<&BindlessHeap>,
+                    scene_set_layouts : &[&DescriptorSetLayout]) -> Result<Self, MaterialError> {
+        let mut slots = Vec::with_capacity(parameters.len());
+        let mut block_size = 0;
+        let mut texture_binding = 1;
+        let mut handle_count = 0;
+        for (name, default) in parameters {
+            let location = match default.parameter_type() {
+                ParameterType::Scalar => {
+                    block_size += 4;
+                    block_size - 4
+                }
+                ParameterType::Color => {
+                    block_size = align_up(block_size, 16) + 16;
+                    block_size - 16
+                }
+                ParameterType::Texture if heap.is_some() => {
+                    handle_count += 1;
+                    handle_count - 1
+                }
+                ParameterType::Texture => {
+                    texture_binding += 2;
+                    texture_binding - 2
+                }
+            };
+            slots.push(ParameterSlot { name, location, default });
+        }
+        // The size of a uniform block is rounded up to the alignment of a vec4.
+        let block_size = align_up(block_size, 16);
+
+        let stages = vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT;
+        let mut layout_builder = DescriptorSetLayoutBuilder::new(Arc::clone(&device));
+        if block_size > 0 {
+            layout_builder = layout_builder.binding(0, vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC, 1, stages);
+        }
+        if heap.is_none() {
+            for slot in slots.iter().filter(|slot| slot.default.parameter_type() == ParameterType::Texture) {
+                layout_builder = layout_builder
+                    .sampled_image(slot.location, stages)
+                    .sampler(slot.location + 1, stages);
+            }
+        }
+        let set_layout = layout_builder.build();
+
+        let mut builder = render_state
+            .apply(PipelineBuilder::new(Arc::clone(&device)))
+            .descriptor_set_layout(&set_layout);
+        if let Some(heap) = heap {
+            builder = builder
+                .descriptor_set_layout(heap.set_layout())
+                .push_constant_range(stages, 0, BINDLESS_HANDLE_OFFSET + 4 * handle_count);
+        }
+        for scene_set_layout in scene_set_layouts {
+            builder = builder.descriptor_set_layout(scene_set_layout);
+        }
+        let pipeline = builder.build_graphics(render_pass, &program)?;
+
+        Ok(Self {
+            device,
+            program_desc,
+            render_state,
+            parameters: slots,
+            block_size,
+            set_layout,
+            heap_set: heap.map(BindlessHeap::set),
+            pipeline,
+            _program: program,
+        })
+    }
+
+    /// Creates a template from a material description, whose parameter values become the defaults.
+    pub fn from_desc(device : Arc<Device>,
+                     render_pass : &RenderPass,
+                     desc : &MaterialDesc,
+                     scene_set_layouts : &[&DescriptorSetLayout]) -> Result<Self, MaterialError> {
+        Self::new(
+            device,
+            render_pass,
+            desc.program.clone(),
+            desc.parameters.clone(),
+            desc.render_state,
+            scene_set_layouts)
+    }
+
+    /// Describes the template, with the default value of each parameter.
+    pub fn desc(&self) -> MaterialDesc {
+        MaterialDesc {
+            program: self.program_desc.clone(),
+            render_state: self.render_state,
+            parameters: self.parameters
+                .iter()
+                .map(|slot| (slot.name.clone(), slot.default.clone()))
+                .collect(),
+        }
+    }
+
+    pub fn device(&self) -> &Arc<Device> {
+        &self.device
+    }
+
+    /// Returns the pipeline shared by every instance of the template.
+    pub fn pipeline(&self) -> &Pipeline {
+        &self.pipeline
+    }
+
+    pub fn is_bindless(&self) -> bool {
+        self.heap_set.is_some()
+    }
+
+    /// Returns the stages to push the transform to, which include the fragment stage for bindless templates since it
+    /// shares their push constant range.
+    pub fn push_constant_stages(&self) -> vk::ShaderStageFlags {
+        if self.is_bindless() {
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT
+        } else {
+            vk::ShaderStageFlags::VERTEX
+        }
+    }
+
+    /// Binds the pipeline, along with the heap if the template is bindless. Instances of the template may then be
+    /// drawn one after another with `MaterialInstance::bind_parameters`.
+    pub fn bind(&self, cmd_buffer : &mut CmdBuffer) -> Result<(), MaterialError> {
+        cmd_buffer.bind_pipeline(&self.pipeline)?;
+        if let Some(heap_set) = self.heap_set {
+            cmd_buffer.bind_descriptor_sets(&self.pipeline, 1, &[heap_set], &[])?;
+        }
+        Ok(())
+    }
+
+    /// Returns the layout of descriptor set 0, which holds the parameters.
+    pub fn set_layout(&self) -> &DescriptorSetLayout {
+        &self.set_layout
+    }
+
+    pub fn render_state(&self) -> &RenderState {
+        &self.render_state
+    }
+
+    /// Returns the size in bytes of the block holding the scalar and color parameters, which is 0 if there are none.
+    pub fn block_size(&self) -> u32 {
+        self.block_size
+    }
+
+    /// Returns the index of the named parameter.
+    pub fn parameter_index(&self, name : &str) -> Option<usize> {
+        self.parameters.iter().position(|slot| slot.name == name)
+    }
+
+    /// Returns the name and type of each parameter, in declaration order.
+    pub fn parameters(&self) -> impl Iterator<Item = (&str, ParameterType)> {
+        self.parameters
+            .iter()
+            .map(|slot| (slot.name.as_str(), slot.default.parameter_type()))
+    }
+
+    pub(super) fn parameter_name(&self, index : usize) -> &str {
+        &self.parameters[index].name
+    }
+
+    pub(super) fn parameter_location(&self, index : usize) -> u32 {
+        self.parameters[index].location
+    }
+
+    /// Returns the number of texture parameters, which is the number of handles a bindless instance pushes.
+    pub(super) fn texture_count(&self) -> usize {
+        self.parameters
+            .iter()
+            .filter(|slot| slot.default.parameter_type() == ParameterType::Texture)
+            .count()
+    }
+
+    pub(super) fn default_values(&self) -> Vec<ParameterValue> {
+        self.parameters.iter().map(|slot| slot.default.clone()).collect()
+    }
+}
+
+/// A material which multiplies the vertex color by a uniform `color`.
+pub struct ColoredMaterial;
+
+impl ColoredMaterial {
+    pub const COLOR : &'static str = "color";
+
+    /// Describes a colored material with the given default color.
+    pub fn desc(color : Vector4<f32>) -> MaterialDesc {
+        MaterialDesc {
+            program: ProgramDesc::Colored,
+            render_state: RenderState::default(),
+            parameters: vec![(Self::COLOR.to_string(), ParameterValue::Color(color))],
+        }
+    }
+
+    /// Creates the template shared by colored materials drawn in `render_pass`, defaulting to white.
+    pub fn template(device : Arc<Device>, render_pass : &RenderPass) -> Result<MaterialTemplate, MaterialError> {
+        MaterialTemplate::from_desc(device, render_pass, &Self::desc(Vector4::new(1.0, 1.0, 1.0, 1.0)), &[])
+    }
+}
+
+/// A material which multiplies the vertex color by an `albedo` texture and a uniform `tint`.
+pub struct TexturedMaterial;
+
+impl TexturedMaterial {
+    pub const ALBEDO : &'static str = "albedo";
+    pub const TINT : &'static str = "tint";
+
+    /// Describes a textured material with the given default texture and tint.
+    pub fn desc(albedo : Option<PathBuf>, tint : Vector4<f32>) -> MaterialDesc {
+        MaterialDesc {
+            program: ProgramDesc::Textured,
+            render_state: RenderState::default(),
+            parameters: vec![
+                (Self::TINT.to_string(), ParameterValue::Color(tint)),
+                (Self::ALBEDO.to_string(), ParameterValue::Texture(albedo)),
+            ],
+        }
+    }
+
+    /// Creates the template shared by textured materials drawn in `render_pass`, with no default texture and a white
+    /// tint.
+    pub fn template(device : Arc<Device>, render_pass : &RenderPass) -> Result<MaterialTemplate, MaterialError> {
+        MaterialTemplate::from_desc(device, render_pass, &Self::desc(None, Vector4::new(1.0, 1.0, 1.0, 1.0)), &[])
+    }
+
+    /// Like `template`, but the textures are read from `heap`.
+    pub fn bindless_template(device : Arc<Device>, render_pass : &RenderPass, heap : &BindlessHeap)
+        -> Result<MaterialTemplate, MaterialError> {
+        let desc = Self::desc(None, Vector4::new(1.0, 1.0, 1.0, 1.0));
+        MaterialTemplate::new_bindless(device, render_pass, desc.program, desc.parameters, desc.render_state, heap, &[])
+    }
+}
+
+fn align_up(value : u32, alignment : u32) -> u32 {
+    value.div_ceil(alignment) * alignment
+}
@@ -0,0 +1,338 @@
+use std::sync::Arc;
+use ash::vk;
+use nalgebra::{Matrix4, Vector2, Vector3};
+use super::{CmdBuffer, CmdRecordingError, Device, Queue};
+use super::buffer::{BufferCreationError, IndexBuffer, VertexBuffer};
+use super::material::{PbrVertex, Vertex};
+
+mod obj;
+
+pub use self::obj::{ObjError, ObjMaterial, ObjModel};
+
+/// A vertex which meshes can be built from.
+pub trait MeshVertex : Copy {
+    fn position(&self) -> Vector3<f32>;
+}
+
+impl MeshVertex for Vertex {
+    fn position(&self) -> Vector3<f32> {
+        self.position
+    }
+}
+
+impl MeshVertex for PbrVertex {
+    fn position(&self) -> Vector3<f32> {
+        self.position
+    }
+}
+
+/// An axis-aligned bounding box. A box containing nothing has its minimum above its maximum, so it can be the start
+/// of a union.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingBox {
+    pub min : Vector3<f32>,
+    pub max : Vector3<f32>,
+}
+
+impl BoundingBox {
+    /// Returns a box containing nothing.
+    pub fn empty() -> Self {
+        Self {
+            min: Vector3::repeat(f32::INFINITY),
+            max: Vector3::repeat(f32::NEG_INFINITY),
+        }
+    }
+
+    /// Returns the smallest box containing every point, which is empty if there are none.
+    pub fn from_points(points : impl IntoIterator<Item = Vector3<f32>>) -> Self {
+        points.into_iter().fold(Self::empty(), |bounds, point| Self {
+            min: bounds.min.inf(&point),
+            max: bounds.max.sup(&point),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    /// Returns the smallest box containing both boxes.
+    pub fn union(&self, other : &Self) -> Self {
+        Self { min: self.min.inf(&other.min), max: self.max.sup(&other.max) }
+    }
+
+    pub fn center(&self) -> Vector3<f32> {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vector3<f32> {
+        self.max - self.min
+    }
+
+    /// Returns the smallest box containing this box after it is transformed, such as the bounds of a mesh placed in
+    /// the world by its model matrix.
+    pub fn transformed(&self, transform : &Matrix4<f32>) -> Self {
+        if self.is_empty() {
+            return *self;
+        }
+        let corners = (0..8).map(|corner| {
+            let point = Vector3::new(
+                if corner & 1 == 0 { self.min.x } else { self.max.x },
+                if corner & 2 == 0 { self.min.y } else { self.max.y },
+                if corner & 4 == 0 { self.min.z } else { self.max.z });
+            transform.transform_point(&point.into()).coords
+        });
+        Self::from_points(corners)
+    }
+}
+
+/// A range of a mesh's triangles which are drawn with the same material.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Submesh {
+    pub first_index : u32,
+    pub index_count : u32,
+    /// The material of the submesh, as an index into the materials of the model it was loaded from.
+    pub material : Option<usize>,
+    pub bounds : BoundingBox,
+}
+
+/// The vertices and triangle list indices of a mesh on the CPU, split into submeshes which cover every index.
+#[derive(Clone, Debug)]
+pub struct MeshData<V> {
+    pub vertices : Vec<V>,
+    pub indices : Vec<u32>,
+    pub submeshes : Vec<Submesh>,
+}
+
+impl<V> Default for MeshData<V> {
+    fn default() -> Self {
+        Self { vertices: Vec::new(), indices: Vec::new(), submeshes: Vec::new() }
+    }
+}
+
+impl<V : MeshVertex> MeshData<V> {
+    /// Appends a submesh of triangles indexing into `vertices`, computing its bounds. Every index must refer to a
+    /// vertex which has already been added, which loaders check before building the mesh.
+    pub fn push_submesh(&mut self, indices : &[u32], material : Option<usize>) {
+        debug_assert!(indices.len().is_multiple_of(3),
+                      "Submesh has {} indices, which is not a whole number of triangles", indices.len());
+        debug_assert!(indices.iter().all(|index| (*index as usize) < self.vertices.len()),
+                      "Submesh indexes past the {} vertices of the mesh", self.vertices.len());
+        let bounds = BoundingBox::from_points(indices.iter().map(|index| self.vertices[*index as usize].position()));
+        self.submeshes.push(Submesh {
+            first_index: self.indices.len() as u32,
+            index_count: indices.len() as u32,
+            material,
+            bounds,
+        });
+        self.indices.extend_from_slice(indices);
+    }
+
+    /// Returns the bounds of every submesh.
+    pub fn bounds(&self) -> BoundingBox {
+        self.submeshes
+            .iter()
+            .fold(BoundingBox::empty(), |bounds, submesh| bounds.union(&submesh.bounds))
+    }
+}
+
+impl MeshData<PbrVertex> {
+    /// Replaces every normal with the average of the normals of the triangles using the vertex, weighted by their
+    /// area. Vertices split along texture seams are not smoothed across the seam.
+    pub fn compute_normals(&mut self) {
+        let mut normals = vec![Vector3::zeros(); self.vertices.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+            let (p0, p1, p2) = (self.vertices[a].position, self.vertices[b].position, self.vertices[c].position);
+            // The length of the cross product is twice the area of the triangle.
+            let normal = (p1 - p0).cross(&(p2 - p0));
+            for index in &[a, b, c] {
+                normals[*index] += normal;
+            }
+        }
+        for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+            vertex.normal = normal.try_normalize(f32::EPSILON).unwrap_or_else(Vector3::z);
+        }
+    }
+
+    /// Computes the tangent of every vertex from the texture coordinates of the triangles using it, orthogonal to the
+    /// vertex normal. The tangent points along increasing u, and its w is chosen so `cross(normal, tangent) * w`
+    /// points up the texture, towards decreasing v, as expected by normal maps whose green channel points up. Normals
+    /// must already be set.
+    pub fn compute_tangents(&mut self) {
+        let mut tangents = vec![Vector3::zeros(); self.vertices.len()];
+        let mut bitangents = vec![Vector3::zeros(); self.vertices.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+            let (p0, p1, p2) = (self.vertices[a].position, self.vertices[b].position, self.vertices[c].position);
+            // V is flipped so the bitangent points up the texture.
+            let flip = |uv : Vector2<f32>| Vector2::new(uv.x, -uv.y);
+            let uv0 = flip(self.vertices[a].texture_coord);
+            let (uv1, uv2) = (flip(self.vertices[b].texture_coord) - uv0, flip(self.vertices[c].texture_coord) - uv0);
+            let (edge1, edge2) = (p1 - p0, p2 - p0);
+            let determinant = uv1.x * uv2.y - uv2.x * uv1.y;
+            if determinant.abs() <= f32::EPSILON {
+                continue;
+            }
+            let tangent = (edge1 * uv2.y - edge2 * uv1.y) / determinant;
+            let bitangent = (edge2 * uv1.x - edge1 * uv2.x) / determinant;
+            for index in &[a, b, c] {
+                tangents[*index] += tangent;
+                bitangents[*index] += bitangent;
+            }
+        }
+        for ((vertex, tangent), bitangent) in self.vertices.iter_mut().zip(tangents).zip(bitangents) {
+            let normal = vertex.normal;
+            // Vertices without texture coordinates get any tangent perpendicular to the normal.
+            let tangent = (tangent - normal * normal.dot(&tangent))
+                .try_normalize(f32::EPSILON)
+                .unwrap_or_else(|| {
+                    let axis = if normal.x.abs() < 0.9 { Vector3::x() } else { Vector3::y() };
+                    normal.cross(&axis).normalize()
+                });
+            let handedness = if normal.cross(&tangent).dot(&bitangent) < 0.0 { -1.0 } else { 1.0 };
+            vertex.tangent = tangent.push(handedness);
+        }
+    }
+}
+
+/// Vertices and indices in device local buffers, drawn as triangle lists split into submeshes.
+///
+/// The vertices are bound at binding 0, so the mesh's vertex type must match the vertex input of the pipelines it is
+/// drawn with, such as `Vertex` for colored and textured materials or `PbrVertex` for PBR materials.
+pub struct Mesh {
+    vertex_buffer : VertexBuffer,
+    index_buffer : IndexBuffer,
+    vertex_count : u32,
+    index_count : u32,
+    submeshes : Vec<Submesh>,
+    bounds : BoundingBox,
+}
+
+impl Drop for Mesh {
+    fn drop(&mut self) {
+        info!("Dropped Mesh")
+    }
+}
+
+impl Mesh {
+    /// Uploads the vertices and indices through the transfer queue. Blocks until the upload is complete.
+    pub fn new<V : MeshVertex>(device : Arc<Device>,
+                               transfer_queue : &Queue,
+                               graphics_queue : &Queue,
+                               data : &MeshData<V>) -> Result<Self, BufferCreationError> {
+        let vertex_buffer = VertexBuffer::from_vertices(
+            Arc::clone(&device),
+            transfer_queue,
+            graphics_queue,
+            &data.vertices)?;
+        let index_buffer = IndexBuffer::from_indices(device, transfer_queue, graphics_queue, &data.indices)?;
+        Ok(Self {
+            vertex_buffer,
+            index_buffer,
+            vertex_count: data.vertices.len() as u32,
+            index_count: data.indices.len() as u32,
+            submeshes: data.submeshes.clone(),
+            bounds: data.bounds(),
+        })
+    }
+
+    /// Binds the vertex buffer at binding 0 and the index buffer.
+    pub fn bind(&self, cmd_buffer : &mut CmdBuffer) -> Result<(), CmdRecordingError> {
+        cmd_buffer.bind_vertex_buffers(0, &[&self.vertex_buffer])?;
+        cmd_buffer.bind_index_buffer(&self.index_buffer, vk::IndexType::UINT32)
+    }
+
+    /// Binds the mesh and draws every submesh with the bound pipeline.
+    pub fn draw(&self, cmd_buffer : &mut CmdBuffer) -> Result<(), CmdRecordingError> {
+        self.bind(cmd_buffer)?;
+        cmd_buffer.draw_indexed(self.index_count, 1, 0, 0, 0)
+    }
+
+    /// Draws a single submesh with the bound pipeline, usually after binding its material. The mesh must already be
+    /// bound.
+    pub fn draw_submesh(&self, cmd_buffer : &mut CmdBuffer, index : usize) -> Result<(), CmdRecordingError> {
+        let submesh = &self.submeshes[index];
+        cmd_buffer.draw_indexed(submesh.index_count, 1, submesh.first_index, 0, 0)
+    }
+
+    pub fn submeshes(&self) -> &[Submesh] {
+        &self.submeshes
+    }
+
+    /// Returns the bounds of every submesh, in the space of the vertices.
+    pub fn bounds(&self) -> &BoundingBox {
+        &self.bounds
+    }
+
+    pub fn vertex_count(&self) -> u32 {
+        self.vertex_count
+    }
+
+    pub fn index_count(&self) -> u32 {
+        self.index_count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector4;
+    use super::*;
+
+    /// A unit quad in the XY plane facing +Z, with the top of the texture at +Y.
+    fn quad() -> MeshData<PbrVertex> {
+        let corners = [((0.0, 0.0), (0.0, 1.0)), ((1.0, 0.0), (1.0, 1.0)), ((1.0, 1.0), (1.0, 0.0)),
+                       ((0.0, 1.0), (0.0, 0.0))];
+        let vertices = corners
+            .iter()
+            .map(|((x, y), (u, v))| PbrVertex {
+                position: Vector3::new(*x, *y, 0.0),
+                normal: Vector3::zeros(),
+                tangent: Vector4::zeros(),
+                texture_coord: Vector2::new(*u, *v),
+            })
+            .collect();
+        let mut data = MeshData { vertices, ..Default::default() };
+        data.push_submesh(&[0, 1, 2, 0, 2, 3], None);
+        data
+    }
+
+    #[test]
+    fn computes_normals_and_tangents_of_a_quad() {
+        let mut data = quad();
+        data.compute_normals();
+        data.compute_tangents();
+        for vertex in &data.vertices {
+            assert!((vertex.normal - Vector3::z()).norm() < 1e-6, "{:?}", vertex.normal);
+            assert!((vertex.tangent - Vector4::new(1.0, 0.0, 0.0, 1.0)).norm() < 1e-6, "{:?}", vertex.tangent);
+        }
+    }
+
+    #[test]
+    fn mirrored_texture_coordinates_flip_the_tangent_handedness() {
+        let mut data = quad();
+        for vertex in &mut data.vertices {
+            vertex.texture_coord.x = 1.0 - vertex.texture_coord.x;
+        }
+        data.compute_normals();
+        data.compute_tangents();
+        for vertex in &data.vertices {
+            assert!((vertex.tangent - Vector4::new(-1.0, 0.0, 0.0, -1.0)).norm() < 1e-6, "{:?}", vertex.tangent);
+        }
+    }
+
+    #[test]
+    fn submeshes_cover_their_indices() {
+        let mut data = quad();
+        data.push_submesh(&[2, 1, 0], Some(1));
+        assert_eq!(data.submeshes[1].first_index, 6);
+        assert_eq!(data.submeshes[1].index_count, 3);
+        assert_eq!(data.submeshes[1].bounds, BoundingBox::from_points(data.vertices[..3].iter().map(|v| v.position)));
+        assert_eq!(data.bounds(), BoundingBox { min: Vector3::zeros(), max: Vector3::new(1.0, 1.0, 0.0) });
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_indices_past_the_vertices() {
+        quad().push_submesh(&[0, 1, 4], None);
+    }
+}
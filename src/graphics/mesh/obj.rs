@@ -0,0 +1,564 @@
+use std::{collections::HashMap, fmt, fs, io, path::{Path, PathBuf}, str::FromStr};
+use nalgebra::{Vector2, Vector3, Vector4};
+use super::MeshData;
+use super::super::{BlendMode, DepthStencilState};
+use super::super::material::{MaterialDesc, ParameterValue, PbrMaterial, PbrVertex, Vertex};
+
+/// Describes why an OBJ model or one of its material libraries could not be read.
+#[derive(Debug)]
+pub enum ObjError {
+    Io(PathBuf, io::Error),
+    /// The line of the model, counted from 1, could not be parsed.
+    Syntax { line : usize, message : &'static str },
+    /// The line of a material library, counted from 1, could not be parsed.
+    MaterialSyntax { path : PathBuf, line : usize, message : &'static str },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io(path, error) => write!(f, "{}: {}", path.display(), error),
+            ObjError::Syntax { line, message } => write!(f, "line {}: {}", line, message),
+            ObjError::MaterialSyntax { path, line, message } =>
+                write!(f, "{}: line {}: {}", path.display(), line, message),
+        }
+    }
+}
+
+/// A material from an MTL library. Paths are resolved against the directory of the library when it is loaded by
+/// `ObjModel::load`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjMaterial {
+    pub name : String,
+    /// `Kd`
+    pub diffuse : Vector3<f32>,
+    /// `Ke`
+    pub emissive : Vector3<f32>,
+    /// `Ns`, the exponent of the Blinn-Phong specular highlight.
+    pub shininess : f32,
+    /// `d`, or one minus `Tr`.
+    pub opacity : f32,
+    /// `Pr`, from the PBR extension to MTL.
+    pub roughness : Option<f32>,
+    /// `Pm`, from the PBR extension to MTL.
+    pub metallic : Option<f32>,
+    /// `map_Kd`
+    pub diffuse_map : Option<PathBuf>,
+    /// `norm`, or `map_Bump` and `bump`, which are assumed to hold tangent space normals rather than heights.
+    pub normal_map : Option<PathBuf>,
+    /// `map_Ke`
+    pub emissive_map : Option<PathBuf>,
+}
+
+impl ObjMaterial {
+    fn new(name : String) -> Self {
+        Self {
+            name,
+            diffuse: Vector3::new(1.0, 1.0, 1.0),
+            emissive: Vector3::zeros(),
+            shininess: 0.0,
+            opacity: 1.0,
+            roughness: None,
+            metallic: None,
+            diffuse_map: None,
+            normal_map: None,
+            emissive_map: None,
+        }
+    }
+
+    /// Describes a `PbrMaterial` which approximates the material. Without `Pr`, the roughness is derived from the
+    /// shininess. Materials which are not opaque are alpha blended without writing depth.
+    pub fn desc(&self) -> MaterialDesc {
+        // Converts the Blinn-Phong exponent to a Beckmann slope, whose square root is the perceptual roughness.
+        let roughness = self.roughness.unwrap_or_else(|| (2.0 / (self.shininess.max(0.0) + 2.0)).sqrt().sqrt());
+        let mut desc = PbrMaterial::desc(self.diffuse.push(self.opacity), self.metallic.unwrap_or(0.0), roughness);
+        // The emissive color scales the emissive map, which should show even if the library leaves `Ke` black.
+        let emissive = if self.emissive_map.is_some() && self.emissive == Vector3::zeros() {
+            Vector3::new(1.0, 1.0, 1.0)
+        } else {
+            self.emissive
+        };
+        desc.set_parameter(PbrMaterial::EMISSIVE, ParameterValue::Color(emissive.push(1.0)));
+        desc.set_parameter(PbrMaterial::BASE_COLOR_MAP, ParameterValue::Texture(self.diffuse_map.clone()));
+        desc.set_parameter(PbrMaterial::NORMAL_MAP, ParameterValue::Texture(self.normal_map.clone()));
+        desc.set_parameter(PbrMaterial::EMISSIVE_MAP, ParameterValue::Texture(self.emissive_map.clone()));
+        if self.opacity < 1.0 {
+            desc.render_state.blend = BlendMode::Alpha;
+            desc.render_state.depth = Some(DepthStencilState::read_only());
+        }
+        desc
+    }
+
+    /// Parses every material in an MTL library. Unknown statements are ignored.
+    fn parse_library(text : &str) -> Result<Vec<Self>, (usize, &'static str)> {
+        let mut materials : Vec<Self> = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let syntax = |message| (index + 1, message);
+            let line = line.split('#').next().unwrap().trim();
+            let mut words = line.split_whitespace();
+            let keyword = match words.next() {
+                Some(keyword) => keyword,
+                None => continue,
+            };
+            let words : Vec<&str> = words.collect();
+            if keyword == "newmtl" {
+                if words.is_empty() {
+                    return Err(syntax("material has no name"));
+                }
+                materials.push(Self::new(words.join(" ")));
+                continue;
+            }
+            let material = match materials.last_mut() {
+                Some(material) => material,
+                None => return Err(syntax("statement before the first `newmtl`")),
+            };
+            match keyword {
+                "Kd" => material.diffuse = parse_vector3(&words).ok_or_else(|| syntax("invalid color"))?,
+                "Ke" => material.emissive = parse_vector3(&words).ok_or_else(|| syntax("invalid color"))?,
+                "Ns" => material.shininess = parse_scalar(&words).ok_or_else(|| syntax("invalid shininess"))?,
+                "d" => material.opacity = parse_scalar(&words).ok_or_else(|| syntax("invalid opacity"))?,
+                "Tr" => material.opacity = 1.0 - parse_scalar(&words).ok_or_else(|| syntax("invalid transparency"))?,
+                "Pr" => material.roughness = Some(parse_scalar(&words).ok_or_else(|| syntax("invalid roughness"))?),
+                "Pm" => material.metallic = Some(parse_scalar(&words).ok_or_else(|| syntax("invalid metalness"))?),
+                "map_Kd" => material.diffuse_map = Some(parse_map(&words).ok_or_else(|| syntax("missing map path"))?),
+                "norm" | "map_Bump" | "map_bump" | "bump" =>
+                    material.normal_map = Some(parse_map(&words).ok_or_else(|| syntax("missing map path"))?),
+                "map_Ke" => material.emissive_map = Some(parse_map(&words).ok_or_else(|| syntax("missing map path"))?),
+                _ => {}
+            }
+        }
+        Ok(materials)
+    }
+
+    fn resolve_paths(&mut self, directory : &Path) {
+        let mut maps = [&mut self.diffuse_map, &mut self.normal_map, &mut self.emissive_map];
+        for path in maps.iter_mut().filter_map(|path| path.as_mut()) {
+            if path.is_relative() {
+                *path = directory.join(&*path);
+            }
+        }
+    }
+}
+
+/// A corner of a face, as indices into the attributes of the model.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct Corner {
+    position : usize,
+    texture_coord : Option<usize>,
+    normal : Option<usize>,
+}
+
+/// The triangles drawn with a material.
+#[derive(Debug)]
+struct FaceGroup {
+    material : Option<String>,
+    /// Three corners for each triangle.
+    corners : Vec<Corner>,
+}
+
+/// A Wavefront OBJ model, along with the materials of its MTL libraries.
+///
+/// Polygons are split into triangle fans, and faces are grouped by their material, in the order each material is
+/// first used. Lines, points, curves and smoothing groups are ignored. Vertex colors following the position of a `v`
+/// statement are read, which is a common extension.
+///
+/// Texture coordinates are flipped vertically when converted to mesh data, since OBJ places the origin at the bottom
+/// of the image while Vulkan places it at the top.
+#[derive(Debug)]
+pub struct ObjModel {
+    positions : Vec<Vector3<f32>>,
+    /// The color of each position, or `None` if the model has no vertex colors.
+    colors : Option<Vec<Vector3<f32>>>,
+    texture_coords : Vec<Vector2<f32>>,
+    normals : Vec<Vector3<f32>>,
+    groups : Vec<FaceGroup>,
+    material_libraries : Vec<PathBuf>,
+    materials : Vec<ObjMaterial>,
+}
+
+impl ObjModel {
+    /// Reads an OBJ file and the MTL libraries it refers to, which are resolved against the directory containing it.
+    /// Libraries which cannot be found are skipped with a warning, leaving their materials undefined.
+    pub fn load(path : &Path) -> Result<Self, ObjError> {
+        let text = fs::read_to_string(path).map_err(|error| ObjError::Io(path.to_path_buf(), error))?;
+        let mut model : Self = text.parse()?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        for library in &mut model.material_libraries {
+            if library.is_relative() {
+                *library = directory.join(&*library);
+            }
+        }
+        for library in &model.material_libraries {
+            let text = match fs::read_to_string(library) {
+                Ok(text) => text,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                    warn!("Material library {} not found", library.display());
+                    continue;
+                }
+                Err(error) => return Err(ObjError::Io(library.clone(), error)),
+            };
+            let mut materials = ObjMaterial::parse_library(&text)
+                .map_err(|(line, message)| ObjError::MaterialSyntax { path: library.clone(), line, message })?;
+            let library_directory = library.parent().unwrap_or_else(|| Path::new(""));
+            for material in &mut materials {
+                material.resolve_paths(library_directory);
+            }
+            model.materials.extend(materials);
+        }
+        Ok(model)
+    }
+
+    /// Returns the MTL libraries named by the model.
+    pub fn material_libraries(&self) -> &[PathBuf] {
+        &self.material_libraries
+    }
+
+    /// Returns the materials of every library which was loaded. Submeshes refer to materials by their index here.
+    pub fn materials(&self) -> &[ObjMaterial] {
+        &self.materials
+    }
+
+    /// Returns the index of the material each face group is drawn with, if it is defined.
+    fn group_materials(&self) -> Vec<Option<usize>> {
+        self.groups
+            .iter()
+            .map(|group| group.material
+                .as_ref()
+                .and_then(|name| self.materials.iter().position(|material| material.name == *name)))
+            .collect()
+    }
+
+    /// Builds mesh data for PBR materials, with one submesh per material. Corners which share a position, texture
+    /// coordinate and normal become a single vertex. Corners without a normal get the area weighted average of the
+    /// normals of the faces around their position, and tangents are always computed.
+    pub fn pbr_mesh_data(&self) -> MeshData<PbrVertex> {
+        let smooth_normals = self.smooth_normals();
+        let mut data = MeshData::default();
+        let mut vertices = HashMap::new();
+        let mut corner_indices = Vec::new();
+        for (group, material) in self.groups.iter().zip(self.group_materials()) {
+            corner_indices.clear();
+            for corner in &group.corners {
+                let index = *vertices.entry(*corner).or_insert_with(|| {
+                    data.vertices.push(PbrVertex {
+                        position: self.positions[corner.position],
+                        normal: corner.normal.map_or(smooth_normals[corner.position], |normal| self.normals[normal]),
+                        tangent: Vector4::zeros(),
+                        texture_coord: self.texture_coord(corner),
+                    });
+                    data.vertices.len() as u32 - 1
+                });
+                corner_indices.push(index);
+            }
+            data.push_submesh(&corner_indices, material);
+        }
+        data.compute_tangents();
+        data
+    }
+
+    /// Builds mesh data for colored and textured materials, with one submesh per material. Each vertex is colored
+    /// by the diffuse color and opacity of its material, multiplied by its vertex color if the model has them.
+    pub fn mesh_data(&self) -> MeshData<Vertex> {
+        let mut data = MeshData::default();
+        let mut vertices = HashMap::new();
+        let mut corner_indices = Vec::new();
+        for (group, material) in self.groups.iter().zip(self.group_materials()) {
+            let material_color = material.map_or(Vector4::new(1.0, 1.0, 1.0, 1.0), |index| {
+                let material = &self.materials[index];
+                material.diffuse.push(material.opacity)
+            });
+            corner_indices.clear();
+            for corner in &group.corners {
+                // Corners are only shared within a material, since the material decides their color.
+                let index = *vertices.entry((*corner, material)).or_insert_with(|| {
+                    let vertex_color = self.colors
+                        .as_ref()
+                        .map_or(Vector3::new(1.0, 1.0, 1.0), |colors| colors[corner.position]);
+                    data.vertices.push(Vertex::new(
+                        self.positions[corner.position],
+                        material_color.component_mul(&vertex_color.push(1.0)),
+                        self.texture_coord(corner)));
+                    data.vertices.len() as u32 - 1
+                });
+                corner_indices.push(index);
+            }
+            data.push_submesh(&corner_indices, material);
+        }
+        data
+    }
+
+    fn texture_coord(&self, corner : &Corner) -> Vector2<f32> {
+        corner.texture_coord.map_or(Vector2::zeros(), |index| {
+            let texture_coord = self.texture_coords[index];
+            Vector2::new(texture_coord.x, 1.0 - texture_coord.y)
+        })
+    }
+
+    /// Returns the area weighted average normal of the faces around each position.
+    fn smooth_normals(&self) -> Vec<Vector3<f32>> {
+        let mut normals = vec![Vector3::zeros(); self.positions.len()];
+        for triangle in self.groups.iter().flat_map(|group| group.corners.chunks_exact(3)) {
+            let [a, b, c] = [triangle[0].position, triangle[1].position, triangle[2].position];
+            let normal = (self.positions[b] - self.positions[a]).cross(&(self.positions[c] - self.positions[a]));
+            for index in &[a, b, c] {
+                normals[*index] += normal;
+            }
+        }
+        normals
+            .into_iter()
+            .map(|normal| normal.try_normalize(f32::EPSILON).unwrap_or_else(Vector3::z))
+            .collect()
+    }
+}
+
+impl FromStr for ObjModel {
+    type Err = ObjError;
+
+    /// Parses an OBJ model. Material libraries are named but not loaded, so the model has no materials.
+    fn from_str(text : &str) -> Result<Self, ObjError> {
+        let mut model = ObjModel {
+            positions: Vec::new(),
+            colors: None,
+            texture_coords: Vec::new(),
+            normals: Vec::new(),
+            groups: Vec::new(),
+            material_libraries: Vec::new(),
+            materials: Vec::new(),
+        };
+        let mut colors = Vec::new();
+        let mut group_index = HashMap::new();
+        let mut current_group = None;
+        let mut polygon = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let syntax = |message| ObjError::Syntax { line: index + 1, message };
+            let line = line.split('#').next().unwrap().trim();
+            let mut words = line.split_whitespace();
+            let keyword = match words.next() {
+                Some(keyword) => keyword,
+                None => continue,
+            };
+            let words : Vec<&str> = words.collect();
+            match keyword {
+                "v" => {
+                    let position = parse_vector3(&words[..words.len().min(3)])
+                        .ok_or_else(|| syntax("invalid position"))?;
+                    model.positions.push(position);
+                    // Four values are a position with a weight, while six or seven carry a color.
+                    if words.len() >= 6 {
+                        colors.resize(model.positions.len() - 1, Vector3::new(1.0, 1.0, 1.0));
+                        colors.push(parse_vector3(&words[3..6]).ok_or_else(|| syntax("invalid vertex color"))?);
+                    }
+                }
+                "vt" => {
+                    let u = words.first()
+                        .and_then(|word| word.parse().ok())
+                        .ok_or_else(|| syntax("invalid texture coordinate"))?;
+                    let v = match words.get(1) {
+                        Some(word) => word.parse().map_err(|_| syntax("invalid texture coordinate"))?,
+                        None => 0.0,
+                    };
+                    model.texture_coords.push(Vector2::new(u, v));
+                }
+                "vn" => model.normals.push(parse_vector3(&words).ok_or_else(|| syntax("invalid normal"))?),
+                "f" => {
+                    if words.len() < 3 {
+                        return Err(syntax("face has fewer than three corners"));
+                    }
+                    polygon.clear();
+                    for word in &words {
+                        polygon.push(model.parse_corner(word).ok_or_else(|| syntax("invalid face corner"))?);
+                    }
+                    let group = *current_group.get_or_insert_with(|| {
+                        *group_index.entry(None).or_insert_with(|| {
+                            model.groups.push(FaceGroup { material: None, corners: Vec::new() });
+                            model.groups.len() - 1
+                        })
+                    });
+                    let corners = &mut model.groups[group].corners;
+                    for corner in 1..polygon.len() - 1 {
+                        corners.extend_from_slice(&[polygon[0], polygon[corner], polygon[corner + 1]]);
+                    }
+                }
+                "usemtl" => {
+                    let name = if words.is_empty() { None } else { Some(words.join(" ")) };
+                    let groups = &mut model.groups;
+                    current_group = Some(*group_index.entry(name.clone()).or_insert_with(|| {
+                        groups.push(FaceGroup { material: name, corners: Vec::new() });
+                        groups.len() - 1
+                    }));
+                }
+                "mtllib" => {
+                    // The path is the rest of the line, so it may contain spaces.
+                    if words.is_empty() {
+                        return Err(syntax("missing material library path"));
+                    }
+                    model.material_libraries.push(PathBuf::from(line[keyword.len()..].trim()));
+                }
+                _ => {}
+            }
+        }
+        if !colors.is_empty() {
+            colors.resize(model.positions.len(), Vector3::new(1.0, 1.0, 1.0));
+            model.colors = Some(colors);
+        }
+        model.groups.retain(|group| !group.corners.is_empty());
+        Ok(model)
+    }
+}
+
+impl ObjModel {
+    /// Parses a face corner of the form `v`, `v/vt`, `v//vn` or `v/vt/vn`. Indices count from 1, or back from the
+    /// latest attribute when negative.
+    fn parse_corner(&self, word : &str) -> Option<Corner> {
+        let mut parts = word.split('/');
+        let resolve = |part : &str, count : usize| -> Option<usize> {
+            let index : i64 = part.parse().ok()?;
+            let index = if index < 0 { count as i64 + index } else { index - 1 };
+            if index >= 0 && (index as usize) < count { Some(index as usize) } else { None }
+        };
+        let position = resolve(parts.next()?, self.positions.len())?;
+        let texture_coord = match parts.next() {
+            None | Some("") => None,
+            Some(part) => Some(resolve(part, self.texture_coords.len())?),
+        };
+        let normal = match parts.next() {
+            None | Some("") => None,
+            Some(part) => Some(resolve(part, self.normals.len())?),
+        };
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Corner { position, texture_coord, normal })
+    }
+}
+
+fn parse_scalar(words : &[&str]) -> Option<f32> {
+    match words {
+        [word] => word.parse().ok(),
+        _ => None,
+    }
+}
+
+fn parse_vector3(words : &[&str]) -> Option<Vector3<f32>> {
+    match words {
+        [x, y, z] => Some(Vector3::new(x.parse().ok()?, y.parse().ok()?, z.parse().ok()?)),
+        _ => None,
+    }
+}
+
+/// Returns the path of a texture map statement, skipping any options before it.
+fn parse_map(words : &[&str]) -> Option<PathBuf> {
+    let mut index = 0;
+    while index < words.len() && words[index].starts_with('-') {
+        // Options take one value, except for offsets, scales and turbulence which take up to three numbers.
+        let max_values = match words[index] {
+            "-o" | "-s" | "-t" => 3,
+            "-mm" => 2,
+            _ => 1,
+        };
+        index += 1;
+        let mut values = 0;
+        while values < max_values && index < words.len()
+            && (values == 0 || words[index].parse::<f32>().is_ok()) {
+            index += 1;
+            values += 1;
+        }
+    }
+    if index < words.len() {
+        Some(PathBuf::from(words[index..].join(" ")))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use super::*;
+
+    const QUAD : &str = "\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+";
+
+    #[test]
+    fn resolves_negative_indices() {
+        let relative : ObjModel = format!("{}f -4/-4/-1 -3/-3/-1 -2/-2/-1 -1/-1/-1\n", QUAD).parse().unwrap();
+        let absolute : ObjModel = format!("{}f 1/1/1 2/2/1 3/3/1 4/4/1\n", QUAD).parse().unwrap();
+        assert_eq!(relative.groups[0].corners, absolute.groups[0].corners);
+        assert!("v 0 0 0\nf -2 -1 1\n".parse::<ObjModel>().is_err());
+    }
+
+    #[test]
+    fn reads_corners_without_texture_coordinates() {
+        let model : ObjModel = format!("{}f 1//1 2//1 3//1\n", QUAD).parse().unwrap();
+        let corner = model.groups[0].corners[1];
+        assert_eq!(corner, Corner { position: 1, texture_coord: None, normal: Some(0) });
+        let data = model.pbr_mesh_data();
+        assert_eq!(data.vertices[1].normal, Vector3::z());
+        assert_eq!(data.vertices[1].texture_coord, Vector2::zeros());
+        assert!("v 0 0 0\nf 1/1 1 1\n".parse::<ObjModel>().is_err());
+    }
+
+    #[test]
+    fn splits_polygons_into_fans() {
+        let model : ObjModel = "v 0 0 0\nv 1 0 0\nv 2 1 0\nv 1 2 0\nv 0 1 0\nf 1 2 3 4 5\n".parse().unwrap();
+        let data = model.mesh_data();
+        assert_eq!(data.indices, vec![0, 1, 2, 0, 2, 3, 0, 3, 4]);
+        assert_eq!(data.submeshes.len(), 1);
+        assert_eq!(data.submeshes[0].index_count, 9);
+    }
+
+    #[test]
+    fn shares_vertices_between_identical_corners() {
+        let model : ObjModel = format!("{}f 1/1/1 2/2/1 3/3/1\nf 1/1/1 3/3/1 4/4/1\nf 1/4/1 3/3/1 4/4/1\n", QUAD)
+            .parse()
+            .unwrap();
+        let data = model.pbr_mesh_data();
+        // The last face refers to the first position with other texture coordinates, which is a new vertex.
+        assert_eq!(data.vertices.len(), 5);
+        assert_eq!(data.indices, vec![0, 1, 2, 0, 2, 3, 4, 2, 3]);
+        // Texture coordinates are flipped so the top of the image is at v = 0.
+        assert_eq!(data.vertices[2].texture_coord, Vector2::new(1.0, 0.0));
+    }
+
+    #[test]
+    fn groups_faces_by_material() {
+        let faces = "usemtl red\nf 1 2 3\nusemtl blue\nf 1 3 4\nusemtl red\nf 2 3 4\n";
+        let text = format!("{}mtllib my materials.mtl\n{}", QUAD, faces);
+        let model : ObjModel = text.parse().unwrap();
+        assert_eq!(model.material_libraries(), &[PathBuf::from("my materials.mtl")]);
+        assert_eq!(model.groups.len(), 2);
+        assert_eq!(model.groups[0].corners.len(), 6);
+        assert_eq!(model.groups[1].material.as_deref(), Some("blue"));
+    }
+
+    #[test]
+    fn loads_material_libraries_with_spaces_in_their_names() {
+        let directory = env::temp_dir().join("halogen obj test");
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("my materials.mtl"),
+                  "newmtl red paint\nKd 1 0 0\nd 0.5\nmap_Kd -s 1 1 1 textures/red paint.png\n").unwrap();
+        fs::write(directory.join("model.obj"),
+                  format!("{}mtllib my materials.mtl\nusemtl red paint\nf 1 2 3 4\n", QUAD)).unwrap();
+
+        let model = ObjModel::load(&directory.join("model.obj")).unwrap();
+        assert_eq!(model.materials().len(), 1);
+        let material = &model.materials()[0];
+        assert_eq!(material.name, "red paint");
+        assert_eq!(material.diffuse, Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(material.diffuse_map, Some(directory.join("textures/red paint.png")));
+        assert_eq!(material.desc().render_state.blend, BlendMode::Alpha);
+        let data = model.mesh_data();
+        assert_eq!(data.submeshes[0].material, Some(0));
+        assert_eq!(data.vertices[0].color, Vector4::new(1.0, 0.0, 0.0, 0.5));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
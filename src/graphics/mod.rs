@@ -1,31 +1,83 @@
+/// A global heap of textures and buffers indexed by handles, for devices which support descriptor indexing.
+pub mod bindless;
 pub mod buffer;
 pub mod cmd;
 pub mod debug;
+/// Creates descriptor set layouts, allocates sets from growable pools and writes resources into them.
+pub mod descriptors;
+/// Defers the destruction of Vulkan objects until the frames using them have retired.
+pub mod deletion;
 pub mod device;
+/// Generates the irradiance, prefiltered specular and BRDF lookup maps used for image based lighting with compute
+/// shaders.
+pub mod environment;
 pub mod framebuffer;
+/// Imports glTF 2.0 assets into scenes, meshes and PBR materials, and draws them once uploaded.
+pub mod gltf;
+/// Composes passes from the resources they read and write, ordering them and computing their barriers.
+pub mod graph;
+/// Decodes PNG, JPEG and Radiance HDR images into pixel data which can be uploaded to textures.
+pub mod image;
 pub mod instance;
-/// Defines the appearance of a renderable object. Currently provides basic options for a `ColoredMaterial` or a `TexturedMaterial`.
+/// Directional, point and spot lights, passed to lit materials along with the view and the environment map.
+pub mod lighting;
+/// Defines the appearance of a renderable object through a shader program, typed parameters and render state, with
+/// a `ColoredMaterial`, a `TexturedMaterial` and a metallic-roughness `PbrMaterial` built in. Materials can be saved to
+/// and loaded from a text format.
 pub mod material;
+/// Meshes of vertex and index buffers split into submeshes, with a loader for Wavefront OBJ models and their MTL
+/// materials.
+pub mod mesh;
+/// Records secondary command buffers on several threads with rayon.
+pub mod parallel;
+/// Simulates particles with a compute shader and draws them on the graphics queue.
+pub mod particles;
 pub mod pass;
 pub mod pipeline;
+/// Persists compiled pipelines to disk so they are not compiled again on the next run.
+pub mod pipeline_cache;
+/// Deduplicates pipelines with identical state, optionally compiling them in the background.
+pub mod pipeline_manager;
 /// Platform-specific helper functions.
 pub mod platform;
+/// Reflects SPIR-V modules to find their descriptor bindings, push constants and vertex inputs.
+pub mod reflect;
 /// Operations for a queue, such as submitting graphics, compute, or transfer operations for execution by the GPU.
 pub mod queue;
+/// Loads and validates SPIR-V into shader modules.
+pub mod shader;
+/// Compiles GLSL shaders to SPIR-V at runtime, resolving includes and defines.
+#[cfg(feature = "shader-compiler")]
+pub mod shader_compiler;
+/// Watches shader sources for changes so they can be reloaded while running.
+#[cfg(feature = "shader-compiler")]
+pub mod shader_watcher;
 /// Manages a Vulkan surface and swapchain, presenting the acquired images to the screen.
 pub mod swapchain;
 pub mod renderer;
+/// Node hierarchies with cameras, skins and keyframed animations, such as those imported from glTF.
+pub mod scene;
+/// Shares samplers with identical descriptions, since devices limit how many may exist at once.
+pub mod sampler_cache;
+/// Hands out per-frame allocations from a persistently mapped buffer, for uniform and storage data.
+pub mod ring_buffer;
+/// Sampled images loaded from disk, uploaded through the transfer queue with generated mipmaps.
+pub mod texture;
+/// Tracks how resources are accessed and computes the pipeline barriers between accesses.
+pub mod sync;
 /// Utilities for common functionality used in Vulkan.
 pub mod util;
 
 pub use self::renderer::Renderer;
 use self::buffer::VertexBuffer;
-use self::cmd::{CmdBuffer, CmdPool, CmdState};
+use self::cmd::{CmdBuffer, CmdPool, CmdRecordingError, CmdState, DescriptorSet};
 use self::device::{Device, DeviceCreationError};
 use self::framebuffer::{Framebuffer, FramebufferBuilder};
 use self::instance::Instance;
 use self::material::{Material, Vertex};
+use self::particles::ParticleSystem;
 use self::pass::{RenderPass, RenderPassBuilder};
-use self::pipeline::{Pipeline, PipelineBuilder};
+use self::pipeline::{BlendMode, DepthBias, DepthStencilState, Pipeline, PipelineBuilder};
+use self::pipeline_manager::PipelineManager;
 use self::queue::Queue;
 use self::swapchain::{Swapchain, SwapchainCreationError};
@@ -0,0 +1,80 @@
+use std::sync::Arc;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use rayon::prelude::*;
+use super::{CmdBuffer, CmdPool, CmdRecordingError, Device, Framebuffer, Queue, RenderPass};
+
+/// Records secondary command buffers in parallel. Each worker thread owns its own `CmdPool`, since command pools are
+/// externally synchronized and cannot be shared by threads which are recording at the same time. Each chunk of work is
+/// recorded with its own pool, so pools are only locked while command buffers are allocated from them.
+pub struct ParallelRecorder {
+    device : Arc<Device>,
+    thread_pool : ThreadPool,
+    cmd_pools : Vec<Arc<CmdPool>>,
+}
+
+impl ParallelRecorder {
+    /// Creates a worker thread for each logical CPU, each with a command pool for the family of `queue`.
+    pub fn new(device : Arc<Device>, queue : &Queue) -> Self {
+        Self::with_threads(device, queue, num_cpus::get())
+    }
+
+    pub fn with_threads(device : Arc<Device>, queue : &Queue, thread_count : usize) -> Self {
+        let thread_pool = ThreadPoolBuilder::new()
+            .num_threads(thread_count)
+            .thread_name(|index| format!("halogen-record-{}", index))
+            .build()
+            .expect("Failed to create recording threads");
+        let cmd_pools = (0..thread_count)
+            .map(|_| Arc::new(CmdPool::new(Arc::clone(&device), queue)))
+            .collect();
+        Self { device, thread_pool, cmd_pools }
+    }
+
+    pub fn thread_count(&self) -> usize {
+        self.cmd_pools.len()
+    }
+
+    /// Splits `items` into at most `thread_count` chunks and records each chunk into its own secondary command
+    /// buffer on a worker thread. The buffers continue `subpass` of the render pass and are returned in the order of
+    /// the chunks, ready to be passed to `CmdBuffer::execute_commands` inside a render pass begun with
+    /// `begin_render_pass_secondary`.
+    ///
+    /// Takes `&mut self` so that no two recordings share a pool, which lets `record` run nested parallel work without
+    /// holding any lock.
+    pub fn record_secondary<T, F>(&mut self,
+                                  render_pass : &RenderPass,
+                                  subpass : u32,
+                                  framebuffer : &Framebuffer,
+                                  items : &[T],
+                                  record : F) -> Result<Vec<CmdBuffer>, CmdRecordingError>
+        where T : Sync,
+              F : Fn(&mut CmdBuffer, &[T]) -> Result<(), CmdRecordingError> + Sync {
+        if items.is_empty() {
+            return Ok(Vec::new());
+        }
+        let chunk_size = items.len().div_ceil(self.thread_count());
+        let device = &self.device;
+        let cmd_pools = &self.cmd_pools;
+        self.thread_pool.install(|| {
+            items
+                .par_chunks(chunk_size)
+                .enumerate()
+                .map(|(index, chunk)| {
+                    // There are at most as many chunks as pools, so the pool is not used by any other chunk.
+                    let mut cmd_buffer = CmdBuffer::new_secondary(Arc::clone(device), Arc::clone(&cmd_pools[index]));
+                    cmd_buffer.begin_secondary(render_pass, subpass, framebuffer)?;
+                    record(&mut cmd_buffer, chunk)?;
+                    cmd_buffer.end()?;
+                    Ok(cmd_buffer)
+                })
+                .collect()
+        })
+    }
+
+    /// Runs a closure on the recording threads, i.e. for other parallel work between frames.
+    pub fn install<R, F>(&self, work : F) -> R
+        where R : Send,
+              F : FnOnce() -> R + Send {
+        self.thread_pool.install(work)
+    }
+}
@@ -0,0 +1,202 @@
+use std::{f32::consts::PI, mem::size_of, slice, sync::Arc};
+use ash::{vk, version::DeviceV1_0};
+use super::{BlendMode, CmdBuffer, CmdPool, CmdRecordingError, DescriptorSet, Device, Material, Pipeline,
+            PipelineBuilder, Queue, RenderPass};
+use super::buffer::Buffer;
+use super::deletion::DeferredObject;
+use super::descriptors::{DescriptorAllocator, DescriptorSetLayout, DescriptorSetLayoutBuilder, DescriptorWriter};
+
+/// Number of invocations in a workgroup of the update shader.
+const WORKGROUP_SIZE : u32 = 64;
+
+/// A particle as laid out in the storage buffer shared by the update and draw shaders.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Particle {
+    pub position : [f32; 2],
+    pub velocity : [f32; 2],
+}
+
+/// The push constants of the update shader.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct UpdateConstants {
+    delta : f32,
+    count : u32,
+}
+
+/// Simulates particles with a compute shader on the compute queue, and draws them as points on the graphics queue.
+///
+/// Each call to `update` must be followed by exactly one graphics submission which waits on `update_semaphore` before
+/// the vertex shader, and signals `draw_semaphore`. The next update waits on the draw so it does not overwrite
+/// particles which are still being read.
+pub struct ParticleSystem {
+    device : Arc<Device>,
+    count : u32,
+    /// The particle storage, which is only referenced through the descriptor set.
+    _particles : Buffer,
+    _set_layout : DescriptorSetLayout,
+    _descriptor_allocator : DescriptorAllocator,
+    descriptor_set : DescriptorSet,
+    update_pipeline : Pipeline,
+    draw_pipeline : Pipeline,
+    cmd_buffer : CmdBuffer,
+    update_fence : vk::Fence,
+    update_semaphore : vk::Semaphore,
+    draw_semaphore : vk::Semaphore,
+    drawn : bool,
+}
+
+impl Drop for ParticleSystem {
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .ash_device()
+                .wait_for_fences(&[self.update_fence], true, u64::MAX)
+                .expect("Failed to wait for fence");
+        }
+        self.device.destroy_deferred(DeferredObject::Fence(self.update_fence));
+        self.device.destroy_deferred(DeferredObject::Semaphore(self.update_semaphore));
+        self.device.destroy_deferred(DeferredObject::Semaphore(self.draw_semaphore));
+        info!("Dropped ParticleSystem")
+    }
+}
+
+impl ParticleSystem {
+    /// Creates `count` particles bursting out from the center of the screen. The particles are drawn within the first
+    /// subpass of `render_pass`.
+    pub fn new(device : Arc<Device>,
+               compute_queue : &Queue,
+               graphics_queue : &Queue,
+               render_pass : &RenderPass,
+               count : u32) -> Self {
+        let initial : Vec<Particle> = (0..count)
+            .map(|i| {
+                let angle = 2.0 * PI * i as f32 / count as f32;
+                let speed = 0.2 + 0.8 * ((i * 7919) % count) as f32 / count as f32;
+                Particle { position: [0.0, 0.0], velocity: [angle.cos() * speed, angle.sin() * speed] }
+            })
+            .collect();
+        let bytes = unsafe {
+            slice::from_raw_parts(initial.as_ptr() as *const u8, initial.len() * size_of::<Particle>())
+        };
+
+        // Shared between both queues, so no ownership transfers are needed between the update and the draw.
+        let particles = Buffer::new(
+            Arc::clone(&device),
+            bytes.len() as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            &[compute_queue.family_index(), graphics_queue.family_index()])
+            .expect("Failed to create particle buffer");
+        particles.write(0, bytes);
+
+        let set_layout = DescriptorSetLayoutBuilder::new(Arc::clone(&device))
+            .storage_buffer(0, vk::ShaderStageFlags::COMPUTE | vk::ShaderStageFlags::VERTEX)
+            .build();
+        let mut descriptor_allocator = DescriptorAllocator::with_pool_sizes(
+            Arc::clone(&device),
+            vec![(vk::DescriptorType::STORAGE_BUFFER, 1)]);
+        let descriptor_set = descriptor_allocator.allocate(&set_layout);
+        DescriptorWriter::new()
+            .storage_buffer(0, &particles, 0, vk::WHOLE_SIZE)
+            .update(&device, descriptor_set);
+
+        let update_pipeline = PipelineBuilder::new(Arc::clone(&device))
+            .descriptor_set_layout(&set_layout)
+            .push_constant_range(vk::ShaderStageFlags::COMPUTE, 0, size_of::<UpdateConstants>() as u32)
+            .build_compute(include_bytes!("../assets/shaders/particles_comp.spv"))
+            .expect("Failed to create particle update pipeline");
+
+        let material = Material::from_spirv(
+            Arc::clone(&device),
+            include_bytes!("../assets/shaders/particles_vert.spv"),
+            include_bytes!("../assets/shaders/particles_frag.spv"))
+            .expect("Failed to load particle shaders");
+        let draw_pipeline = PipelineBuilder::new(Arc::clone(&device))
+            .topology(vk::PrimitiveTopology::POINT_LIST, false)
+            .blend(BlendMode::Additive)
+            .descriptor_set_layout(&set_layout)
+            .build_graphics(render_pass, &material)
+            .expect("Failed to create particle draw pipeline");
+
+        let cmd_pool = Arc::new(CmdPool::new(Arc::clone(&device), compute_queue));
+        let cmd_buffer = CmdBuffer::new(Arc::clone(&device), cmd_pool);
+
+        // Signalled so the first update does not wait.
+        let fence_info = vk::FenceCreateInfo::builder()
+            .flags(vk::FenceCreateFlags::SIGNALED);
+        let semaphore_info = vk::SemaphoreCreateInfo::builder();
+        let (update_fence, update_semaphore, draw_semaphore) = unsafe {
+            (device.ash_device().create_fence(&fence_info, None).expect("Failed to create fence"),
+             device.ash_device().create_semaphore(&semaphore_info, None).expect("Failed to create semaphore"),
+             device.ash_device().create_semaphore(&semaphore_info, None).expect("Failed to create semaphore"))
+        };
+
+        Self { device,
+            count,
+            _particles: particles,
+            _set_layout: set_layout,
+            _descriptor_allocator: descriptor_allocator,
+            descriptor_set,
+            update_pipeline,
+            draw_pipeline,
+            cmd_buffer,
+            update_fence,
+            update_semaphore,
+            draw_semaphore,
+            drawn: false,
+        }
+    }
+
+    /// Advances the simulation by `delta` seconds on the compute queue.
+    pub fn update(&mut self, compute_queue : &Queue, delta : f32) {
+        self.cmd_buffer.reset_after(self.update_fence);
+        unsafe {
+            self.device
+                .ash_device()
+                .reset_fences(&[self.update_fence])
+                .expect("Failed to reset fence");
+        }
+
+        let constants = UpdateConstants { delta, count: self.count };
+        self.cmd_buffer.begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)
+            .and_then(|_| self.cmd_buffer.bind_pipeline(&self.update_pipeline))
+            .and_then(|_| self.cmd_buffer.bind_descriptor_sets(&self.update_pipeline, 0, &[self.descriptor_set], &[]))
+            .and_then(|_| self.cmd_buffer.push_constants(
+                &self.update_pipeline,
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                &constants))
+            .and_then(|_| self.cmd_buffer.dispatch(self.count.div_ceil(WORKGROUP_SIZE), 1, 1))
+            .and_then(|_| self.cmd_buffer.end())
+            .expect("Failed to record particle update");
+
+        // The previous draw has to finish reading the particles before they are overwritten.
+        let waits = if self.drawn {
+            vec![(self.draw_semaphore, vk::PipelineStageFlags::COMPUTE_SHADER)]
+        } else {
+            Vec::new()
+        };
+        compute_queue.submit_with(&[&self.cmd_buffer], &waits, &[self.update_semaphore], Some(self.update_fence));
+        self.drawn = true;
+    }
+
+    /// Records drawing the particles as points. Must be recorded inside the render pass the system was created with,
+    /// after the viewport and scissor have been set.
+    pub fn draw(&self, cmd_buffer : &mut CmdBuffer) -> Result<(), CmdRecordingError> {
+        cmd_buffer.bind_pipeline(&self.draw_pipeline)?;
+        cmd_buffer.bind_descriptor_sets(&self.draw_pipeline, 0, &[self.descriptor_set], &[])?;
+        cmd_buffer.draw(self.count, 1, 0, 0)
+    }
+
+    /// Signalled once the last update has completed. The graphics submission drawing the particles must wait on it.
+    pub fn update_semaphore(&self) -> vk::Semaphore {
+        self.update_semaphore
+    }
+
+    /// Must be signalled by the graphics submission which draws the particles.
+    pub fn draw_semaphore(&self) -> vk::Semaphore {
+        self.draw_semaphore
+    }
+}
@@ -1,21 +1,29 @@
-use std::{cell::RefCell, default::Default, rc::Rc};
+use std::{default::Default, sync::Arc};
 use ash::version::DeviceV1_0;
 use ash::vk;
 use super::Device;
+use super::deletion::DeferredObject;
 
 /// Represents how the begin to end state for rendering should occur.
 // TODO: Create builder for this object due to somewhat complicated state.
 pub struct RenderPass {
-    device : Rc<RefCell<Device>>,
+    device : Arc<Device>,
     render_pass : vk::RenderPass,
+    color_attachment_count : u32,
+    compatibility : RenderPassCompatibility,
+}
+
+/// The properties of a render pass which determine whether a pipeline created for it can be used with another. Render
+/// passes with equal compatibility can share pipelines.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RenderPassCompatibility {
+    color_attachments : Vec<(vk::Format, vk::SampleCountFlags)>,
+    depth_stencil_attachment : Option<(vk::Format, vk::SampleCountFlags)>,
 }
 
 impl Drop for RenderPass {
     fn drop(&mut self) {
-        unsafe {
-            self.device.borrow().ash_device().device_wait_idle().unwrap();
-            self.device.borrow().ash_device().destroy_render_pass(self.render_pass, None);
-        }
+        self.device.destroy_deferred(DeferredObject::RenderPass(self.render_pass));
         info!("Dropped RenderPass")
     }
 }
@@ -24,17 +32,26 @@ impl RenderPass {
     pub fn render_pass_raw(&self) -> vk::RenderPass {
         self.render_pass
     }
+
+    /// Returns the number of color attachments written by the subpass, which pipelines need a blend state for.
+    pub fn color_attachment_count(&self) -> u32 {
+        self.color_attachment_count
+    }
+
+    pub fn compatibility(&self) -> &RenderPassCompatibility {
+        &self.compatibility
+    }
 }
 
 pub struct RenderPassBuilder {
-    device : Rc<RefCell<Device>>,
+    device : Arc<Device>,
     color_attachments : Vec<vk::AttachmentDescription>,
     color_references : Vec<vk::AttachmentReference>,
     depth_stencil_attachment : Option<vk::AttachmentDescription>,
 }
 
 impl RenderPassBuilder {
-    pub fn new(device : Rc<RefCell<Device>>) -> Self {
+    pub fn new(device : Arc<Device>) -> Self {
         Self { device,
             color_attachments: Vec::new(),
             color_references: Vec::new(),
@@ -56,6 +73,24 @@ impl RenderPassBuilder {
         self
     }
 
+    /// Adds a color attachment which stays in `layout` before and after the render pass, for attachments whose layout
+    /// transitions are recorded outside of it, i.e. by a `RenderGraph`.
+    pub fn add_color_attachment_in_layout(mut self, format : vk::Format, layout : vk::ImageLayout) -> Self {
+        self.color_attachments.push(vk::AttachmentDescription::builder()
+            .format(format)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .initial_layout(layout)
+            .final_layout(layout)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .build());
+        self.color_references.push(vk::AttachmentReference::builder()
+            .attachment((self.color_attachments.len() - 1) as u32)
+            .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .build());
+        self
+    }
+
     /// Adds a depth stencil attachment to the renderpass. There can only be a single depth-stencil attachment.
     pub fn add_depth_attachment(mut self, format : vk::Format) -> Self {
         self.depth_stencil_attachment = Some(vk::AttachmentDescription::builder()
@@ -91,11 +126,21 @@ impl RenderPassBuilder {
             .build();
         let render_pass = unsafe {
             self.device
-                .borrow()
                 .ash_device()
                 .create_render_pass(&render_pass_info, None)
                 .expect("Failed to create render pass")
         };
-        RenderPass { device: Rc::clone(&self.device), render_pass }
+        let compatibility = RenderPassCompatibility {
+            color_attachments: self.color_attachments
+                .iter()
+                .map(|attachment| (attachment.format, attachment.samples))
+                .collect(),
+            depth_stencil_attachment: self.depth_stencil_attachment
+                .map(|attachment| (attachment.format, attachment.samples)),
+        };
+        RenderPass { device: Arc::clone(&self.device),
+            render_pass,
+            color_attachment_count: self.color_references.len() as u32,
+            compatibility }
     }
 }
\ No newline at end of file
@@ -1,23 +1,61 @@
-use std::{cell::RefCell, ffi::CString, rc::Rc};
+use std::{collections::hash_map::DefaultHasher, ffi::CString, fmt, hash::{Hash, Hasher}, sync::Arc};
 use ash::{vk, version::DeviceV1_0};
 use super::{Device, Material, RenderPass};
+use super::descriptors::DescriptorSetLayout;
+use super::pass::RenderPassCompatibility;
+use super::reflect::{PipelineReflection, ShaderReflection};
+use super::shader::{ShaderModule, ShaderModuleError};
+use super::deletion::DeferredObject;
+
+/// Describes why a pipeline could not be created.
+#[derive(Debug)]
+pub enum PipelineError {
+    /// The compute shader code could not be loaded.
+    Shader(ShaderModuleError),
+    /// The driver failed to create the pipeline, its layout or a reflected descriptor set layout.
+    Vulkan(vk::Result),
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineError::Shader(error) => write!(f, "failed to load compute shader: {}", error),
+            PipelineError::Vulkan(result) => write!(f, "failed to create pipeline: {:?}", result),
+        }
+    }
+}
+
+impl From<ShaderModuleError> for PipelineError {
+    fn from(error : ShaderModuleError) -> Self {
+        PipelineError::Shader(error)
+    }
+}
+
+impl From<vk::Result> for PipelineError {
+    fn from(result : vk::Result) -> Self {
+        PipelineError::Vulkan(result)
+    }
+}
 
 /// Represents the flow of the graphics pipeline from the vertex to fragment stage.
 pub struct Pipeline {
-    device : Rc<RefCell<Device>>,
+    device : Arc<Device>,
     pipeline : vk::Pipeline,
     layout : vk::PipelineLayout,
+    /// Descriptor set layouts generated from the shaders, which are owned by the pipeline.
+    reflected_set_layouts : Vec<vk::DescriptorSetLayout>,
+    push_constant_ranges : Vec<vk::PushConstantRange>,
     supports_compute : bool,
     supports_graphics : bool,
 }
 
 impl Drop for Pipeline {
     fn drop(&mut self) {
-        unsafe {
-            self.device.borrow().ash_device().device_wait_idle().unwrap();
-            self.device.borrow().ash_device().destroy_pipeline_layout(self.layout, None);
-            self.device.borrow().ash_device().destroy_pipeline(self.pipeline, None);
+        self.device.destroy_deferred(DeferredObject::PipelineLayout(self.layout));
+        for set_layout in self.reflected_set_layouts.drain(..) {
+            self.device.destroy_deferred(DeferredObject::DescriptorSetLayout(set_layout));
         }
+        self.device.destroy_deferred(DeferredObject::Pipeline(self.pipeline));
         info!("Dropped GraphicsPipeline")
     }
 }
@@ -29,6 +67,15 @@ impl Pipeline {
     pub fn layout_raw(&self) -> vk::PipelineLayout {
          self.layout
     }
+    /// Returns the descriptor set layouts which were generated from the shaders, indexed by set. Empty if the layouts
+    /// were given to the builder instead.
+    pub fn reflected_set_layouts(&self) -> &[vk::DescriptorSetLayout] {
+        &self.reflected_set_layouts
+    }
+    /// Returns the push constant ranges of the layout, whether given to the builder or reflected from the shaders.
+    pub fn push_constant_ranges(&self) -> &[vk::PushConstantRange] {
+        &self.push_constant_ranges
+    }
     pub fn supports_compute(&self) -> bool {
         self.supports_compute
     }
@@ -37,58 +84,453 @@ impl Pipeline {
     }
 }
 
+/// Describes how the output of the fragment shader is combined with the contents of a color attachment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    /// Overwrites the attachment.
+    Opaque,
+    /// Interpolates between the attachment and the output using the output alpha.
+    Alpha,
+    /// Adds the output, weighted by its alpha, to the attachment.
+    Additive,
+    /// Like `Alpha`, but the output color has already been multiplied by its alpha.
+    Premultiplied,
+}
+
+impl BlendMode {
+    /// Returns the attachment blend state for this mode, writing to every component.
+    pub fn attachment_state(self) -> vk::PipelineColorBlendAttachmentState {
+        let builder = vk::PipelineColorBlendAttachmentState::builder()
+            .color_write_mask(vk::ColorComponentFlags::R | vk::ColorComponentFlags::G |
+                vk::ColorComponentFlags::B | vk::ColorComponentFlags::A);
+        let (src_color, dst_color, src_alpha, dst_alpha) = match self {
+            BlendMode::Opaque => return builder.build(),
+            BlendMode::Alpha => (
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA),
+            BlendMode::Additive => (
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE),
+            BlendMode::Premultiplied => (
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA),
+        };
+        builder
+            .blend_enable(true)
+            .src_color_blend_factor(src_color)
+            .dst_color_blend_factor(dst_color)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(src_alpha)
+            .dst_alpha_blend_factor(dst_alpha)
+            .alpha_blend_op(vk::BlendOp::ADD)
+            .build()
+    }
+}
+
+/// Constant and slope scaled offsets applied to the depth of each fragment, typically used for shadow maps and decals.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DepthBias {
+    pub constant_factor : f32,
+    pub clamp : f32,
+    pub slope_factor : f32,
+}
+
+/// Configures the depth and stencil tests. Only used when the render pass has a depth attachment.
+#[derive(Clone, Copy, Debug)]
+pub struct DepthStencilState {
+    pub depth_test : bool,
+    pub depth_write : bool,
+    pub compare_op : vk::CompareOp,
+    pub stencil_test : bool,
+    pub front : vk::StencilOpState,
+    pub back : vk::StencilOpState,
+}
+
+impl Default for DepthStencilState {
+    /// Depth testing and writing with a `LESS` comparison, and no stencil test.
+    fn default() -> Self {
+        Self {
+            depth_test: true,
+            depth_write: true,
+            compare_op: vk::CompareOp::LESS,
+            stencil_test: false,
+            front: vk::StencilOpState::default(),
+            back: vk::StencilOpState::default(),
+        }
+    }
+}
+
+impl DepthStencilState {
+    /// Tests against the depth buffer without writing to it, as is usual for transparent geometry.
+    pub fn read_only() -> Self {
+        Self { depth_write: false, ..Self::default() }
+    }
+
+    /// Disables both depth testing and writing, as is usual for UI.
+    pub fn disabled() -> Self {
+        Self { depth_test: false, depth_write: false, ..Self::default() }
+    }
+}
+
+/// Everything which determines the pipeline a `PipelineBuilder` creates. Builders with equal keys create
+/// interchangeable pipelines, which lets the `PipelineManager` deduplicate them.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+    shaders : Vec<(vk::ShaderStageFlags, vk::ShaderModule)>,
+    /// Hash of the SPIR-V code of a compute shader, which is compiled by the builder rather than owned by a material.
+    compute_code : Option<u64>,
+    vertex_bindings : Vec<(u32, u32, vk::VertexInputRate)>,
+    vertex_attributes : Vec<(u32, u32, vk::Format, u32)>,
+    render_pass : Option<RenderPassCompatibility>,
+    topology : vk::PrimitiveTopology,
+    primitive_restart : bool,
+    polygon_mode : vk::PolygonMode,
+    line_width : u32,
+    cull_mode : vk::CullModeFlags,
+    front_face : vk::FrontFace,
+    depth_bias : Option<(u32, u32, u32)>,
+    depth_clamp : bool,
+    samples : vk::SampleCountFlags,
+    blend_modes : Vec<BlendMode>,
+    depth_stencil : Option<(bool, bool, vk::CompareOp, bool, [StencilKey; 2])>,
+    dynamic_states : Vec<vk::DynamicState>,
+    /// The bindings of each descriptor set layout rather than its handle, which may be reused once the layout is
+    /// destroyed. Identically defined set layouts are compatible, so pipelines are shared between them.
+    descriptor_set_layouts : Vec<Vec<BindingKey>>,
+    push_constant_ranges : Vec<(vk::ShaderStageFlags, u32, u32)>,
+}
+
+type StencilKey = (vk::StencilOp, vk::StencilOp, vk::StencilOp, vk::CompareOp, u32, u32, u32);
+
+fn stencil_key(state : &vk::StencilOpState) -> StencilKey {
+    (state.fail_op, state.pass_op, state.depth_fail_op, state.compare_op, state.compare_mask, state.write_mask,
+     state.reference)
+}
+
+type BindingKey = (u32, vk::DescriptorType, u32, vk::ShaderStageFlags);
+
+fn binding_key(binding : &vk::DescriptorSetLayoutBinding) -> BindingKey {
+    (binding.binding, binding.descriptor_type, binding.descriptor_count, binding.stage_flags)
+}
+
 pub struct PipelineBuilder {
-    device : Rc<RefCell<Device>>,
+    device : Arc<Device>,
+    topology : vk::PrimitiveTopology,
+    primitive_restart : bool,
+    polygon_mode : vk::PolygonMode,
+    line_width : f32,
+    cull_mode : vk::CullModeFlags,
+    front_face : vk::FrontFace,
+    depth_bias : Option<DepthBias>,
+    depth_clamp : bool,
+    samples : vk::SampleCountFlags,
+    default_blend : BlendMode,
+    blend_modes : Vec<(u32, BlendMode)>,
+    depth_stencil : Option<DepthStencilState>,
+    dynamic_states : Vec<vk::DynamicState>,
+    descriptor_set_layouts : Vec<vk::DescriptorSetLayout>,
+    descriptor_set_bindings : Vec<Vec<BindingKey>>,
+    push_constant_ranges : Vec<vk::PushConstantRange>,
 }
 
 impl PipelineBuilder {
     /// Creates a new pipeline using the initial shader. This would be either a compute shader, or a vertex shader.
-    pub fn new(device : Rc<RefCell<Device>>) -> Self {
-        Self { device, }
+    /// Graphics state defaults to filled, unculled, opaque triangle lists without depth testing. The viewport and
+    /// scissor are always dynamic, so they must be set on the command buffer before drawing.
+    pub fn new(device : Arc<Device>) -> Self {
+        Self { device,
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            primitive_restart: false,
+            polygon_mode: vk::PolygonMode::FILL,
+            line_width: 1.0,
+            cull_mode: vk::CullModeFlags::NONE,
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+            depth_bias: None,
+            depth_clamp: false,
+            samples: vk::SampleCountFlags::TYPE_1,
+            default_blend: BlendMode::Opaque,
+            blend_modes: Vec::new(),
+            depth_stencil: None,
+            dynamic_states: vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR],
+            descriptor_set_layouts: Vec::new(),
+            descriptor_set_bindings: Vec::new(),
+            push_constant_ranges: Vec::new(),
+        }
+    }
+
+    /// Sets the primitive topology. Primitive restart is only valid for strip and fan topologies.
+    pub fn topology(mut self, topology : vk::PrimitiveTopology, primitive_restart : bool) -> Self {
+        self.topology = topology;
+        self.primitive_restart = primitive_restart;
+        self
+    }
+
+    /// Sets how polygons are rasterized, e.g. `LINE` for wireframe rendering.
+    pub fn polygon_mode(mut self, polygon_mode : vk::PolygonMode) -> Self {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+
+    /// Sets the width of rasterized lines. Widths other than 1.0 require the `wide_lines` feature.
+    pub fn line_width(mut self, line_width : f32) -> Self {
+        self.line_width = line_width;
+        self
+    }
+
+    /// Sets which faces are culled, and which winding order is considered front facing.
+    pub fn cull_mode(mut self, cull_mode : vk::CullModeFlags, front_face : vk::FrontFace) -> Self {
+        self.cull_mode = cull_mode;
+        self.front_face = front_face;
+        self
+    }
+
+    pub fn depth_bias(mut self, depth_bias : DepthBias) -> Self {
+        self.depth_bias = Some(depth_bias);
+        self
+    }
+
+    /// Clamps fragment depth instead of clipping against the near and far planes. Requires the `depth_clamp` feature.
+    pub fn depth_clamp(mut self, depth_clamp : bool) -> Self {
+        self.depth_clamp = depth_clamp;
+        self
+    }
+
+    /// Sets the sample count, which must match the attachments of the render pass.
+    pub fn samples(mut self, samples : vk::SampleCountFlags) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    /// Sets the blend mode of every color attachment which has not been given one with `attachment_blend`.
+    pub fn blend(mut self, blend : BlendMode) -> Self {
+        self.default_blend = blend;
+        self
+    }
+
+    /// Sets the blend mode of a single color attachment.
+    pub fn attachment_blend(mut self, attachment : u32, blend : BlendMode) -> Self {
+        self.blend_modes.retain(|(index, _)| *index != attachment);
+        self.blend_modes.push((attachment, blend));
+        self
+    }
+
+    /// Enables depth and stencil testing with the given state. The render pass must have a depth attachment.
+    pub fn depth_stencil(mut self, depth_stencil : DepthStencilState) -> Self {
+        self.depth_stencil = Some(depth_stencil);
+        self
+    }
+
+    /// Marks additional state as dynamic, ignoring the value given to the builder in favour of the one set on the
+    /// command buffer, e.g. `DEPTH_BIAS` or `STENCIL_REFERENCE`.
+    pub fn dynamic_state(mut self, state : vk::DynamicState) -> Self {
+        if !self.dynamic_states.contains(&state) {
+            self.dynamic_states.push(state);
+        }
+        self
+    }
+
+    /// Appends a descriptor set layout to the pipeline layout. Sets are numbered in the order they are added.
+    pub fn descriptor_set_layout(mut self, layout : &DescriptorSetLayout) -> Self {
+        self.descriptor_set_layouts.push(layout.layout_raw());
+        self.descriptor_set_bindings.push(layout.bindings().iter().map(binding_key).collect());
+        self
+    }
+
+    /// Declares a range of push constants accessible from `stages`.
+    pub fn push_constant_range(mut self, stages : vk::ShaderStageFlags, offset : u32, size : u32) -> Self {
+        self.push_constant_ranges.push(vk::PushConstantRange::builder()
+            .stage_flags(stages)
+            .offset(offset)
+            .size(size)
+            .build());
+        self
+    }
+
+    /// Returns the key of the graphics pipeline this builder would create for `render_pass` and `material`.
+    pub fn graphics_key(&self, render_pass : &RenderPass, material : &Material) -> PipelineKey {
+        PipelineKey {
+            shaders: material.shader_modules(),
+            compute_code: None,
+            vertex_bindings: material.vertex_bindings()
+                .iter()
+                .map(|binding| (binding.binding, binding.stride, binding.input_rate))
+                .collect(),
+            vertex_attributes: material.vertex_attributes()
+                .iter()
+                .map(|attribute| (attribute.location, attribute.binding, attribute.format, attribute.offset))
+                .collect(),
+            render_pass: Some(render_pass.compatibility().clone()),
+            blend_modes: self.color_blend_modes(render_pass),
+            ..self.layout_key()
+        }
+    }
+
+    /// Returns the key of the compute pipeline this builder would create from `code`.
+    pub fn compute_key(&self, code : &[u8]) -> PipelineKey {
+        let mut hasher = DefaultHasher::new();
+        code.hash(&mut hasher);
+        PipelineKey { compute_code: Some(hasher.finish()), ..self.layout_key() }
+    }
+
+    /// Returns a key containing the builder state, without any shaders or render pass.
+    fn layout_key(&self) -> PipelineKey {
+        PipelineKey {
+            shaders: Vec::new(),
+            compute_code: None,
+            vertex_bindings: Vec::new(),
+            vertex_attributes: Vec::new(),
+            render_pass: None,
+            topology: self.topology,
+            primitive_restart: self.primitive_restart,
+            polygon_mode: self.polygon_mode,
+            line_width: self.line_width.to_bits(),
+            cull_mode: self.cull_mode,
+            front_face: self.front_face,
+            depth_bias: self.depth_bias.map(|bias|
+                (bias.constant_factor.to_bits(), bias.clamp.to_bits(), bias.slope_factor.to_bits())),
+            depth_clamp: self.depth_clamp,
+            samples: self.samples,
+            blend_modes: Vec::new(),
+            depth_stencil: self.depth_stencil.map(|state|
+                (state.depth_test, state.depth_write, state.compare_op, state.stencil_test,
+                 [stencil_key(&state.front), stencil_key(&state.back)])),
+            dynamic_states: self.dynamic_states.clone(),
+            descriptor_set_layouts: self.descriptor_set_bindings.clone(),
+            push_constant_ranges: self.push_constant_ranges
+                .iter()
+                .map(|range| (range.stage_flags, range.offset, range.size))
+                .collect(),
+        }
+    }
+
+    /// Returns the blend mode of each color attachment of `render_pass`.
+    fn color_blend_modes(&self, render_pass : &RenderPass) -> Vec<BlendMode> {
+        (0..render_pass.color_attachment_count())
+            .map(|attachment| self.blend_modes
+                .iter()
+                .find(|(index, _)| *index == attachment)
+                .map_or(self.default_blend, |(_, blend)| *blend))
+            .collect()
+    }
+
+    /// Creates the pipeline layout. Descriptor set layouts and push constant ranges which were not given to the
+    /// builder are generated from the reflected shaders, in which case the set layouts are returned to be owned by the
+    /// pipeline.
+    fn create_layout(&self, reflection : Option<&PipelineReflection>)
+        -> Result<(vk::PipelineLayout, Vec<vk::DescriptorSetLayout>, Vec<vk::PushConstantRange>), vk::Result> {
+        let mut reflected_set_layouts = Vec::new();
+        if let Some(reflection) = reflection.filter(|_| self.descriptor_set_layouts.is_empty()) {
+            for bindings in reflection.set_layout_bindings() {
+                let set_layout_info = vk::DescriptorSetLayoutCreateInfo::builder()
+                    .bindings(bindings.as_slice());
+                let set_layout = unsafe {
+                    self.device
+                        .ash_device()
+                        .create_descriptor_set_layout(&set_layout_info, None)
+                };
+                match set_layout {
+                    Ok(set_layout) => reflected_set_layouts.push(set_layout),
+                    Err(result) => {
+                        self.destroy_set_layouts(reflected_set_layouts);
+                        return Err(result);
+                    }
+                }
+            }
+        }
+        let set_layouts = if reflected_set_layouts.is_empty() {
+            &self.descriptor_set_layouts
+        } else {
+            &reflected_set_layouts
+        };
+        let push_constant_ranges = match reflection {
+            Some(reflection) if self.push_constant_ranges.is_empty() => &reflection.push_constant_ranges,
+            _ => &self.push_constant_ranges,
+        };
+
+        let layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(set_layouts.as_slice())
+            .push_constant_ranges(push_constant_ranges.as_slice());
+        let layout = unsafe {
+            self.device
+                .ash_device()
+                .create_pipeline_layout(&layout_info, None)
+        };
+        match layout {
+            Ok(layout) => Ok((layout, reflected_set_layouts, push_constant_ranges.clone())),
+            Err(result) => {
+                self.destroy_set_layouts(reflected_set_layouts);
+                Err(result)
+            }
+        }
+    }
+
+    /// Destroys what `create_layout` created when the pipeline itself could not be created.
+    fn destroy_set_layouts(&self, set_layouts : Vec<vk::DescriptorSetLayout>) {
+        for set_layout in set_layouts {
+            self.device.destroy_deferred(DeferredObject::DescriptorSetLayout(set_layout));
+        }
     }
 
     /// Builds a graphics pipeline.
-    pub fn build_graphics(self, render_pass : &RenderPass, material : &Material, extent : vk::Extent2D) -> Pipeline {
-        let color_blend_attachments = vec![
-            vk::PipelineColorBlendAttachmentState::builder()
-                .color_write_mask(vk::ColorComponentFlags::R | vk::ColorComponentFlags::G |
-                    vk::ColorComponentFlags::B | vk::ColorComponentFlags::A).build()];
+    pub fn build_graphics(self, render_pass : &RenderPass, material : &Material) -> Result<Pipeline, PipelineError> {
+        let color_blend_attachments : Vec<vk::PipelineColorBlendAttachmentState> = self
+            .color_blend_modes(render_pass)
+            .into_iter()
+            .map(BlendMode::attachment_state)
+            .collect();
 
         let color_blend_info = vk::PipelineColorBlendStateCreateInfo::builder()
             .attachments(color_blend_attachments.as_slice());
 
         let input_assembly_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
-            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+            .topology(self.topology)
+            .primitive_restart_enable(self.primitive_restart);
 
         let multisample_info = vk::PipelineMultisampleStateCreateInfo::builder()
-            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+            .rasterization_samples(self.samples);
 
+        let depth_bias = self.depth_bias.unwrap_or_default();
         let rasterizer_info = vk::PipelineRasterizationStateCreateInfo::builder()
-            .line_width(1.0);
-
-        let viewports = vec![
-            vk::Viewport::builder()
-                .width(extent.width as _)
-                .height(extent.height as _)
-                .max_depth(1.0).build()];
-        let scissors = vec![
-            vk::Rect2D::builder()
-                .extent(extent).build()];
+            .polygon_mode(self.polygon_mode)
+            .line_width(self.line_width)
+            .cull_mode(self.cull_mode)
+            .front_face(self.front_face)
+            .depth_clamp_enable(self.depth_clamp)
+            .depth_bias_enable(self.depth_bias.is_some())
+            .depth_bias_constant_factor(depth_bias.constant_factor)
+            .depth_bias_clamp(depth_bias.clamp)
+            .depth_bias_slope_factor(depth_bias.slope_factor);
+
+        let depth_stencil_info = self.depth_stencil.map(|state|
+            vk::PipelineDepthStencilStateCreateInfo::builder()
+                .depth_test_enable(state.depth_test)
+                .depth_write_enable(state.depth_write)
+                .depth_compare_op(state.compare_op)
+                .stencil_test_enable(state.stencil_test)
+                .front(state.front)
+                .back(state.back)
+                .max_depth_bounds(1.0)
+                .build());
+
+        // The viewport and scissor are dynamic, so only their count is baked into the pipeline.
         let viewport_info = vk::PipelineViewportStateCreateInfo::builder()
-            .viewports(viewports.as_slice())
-            .scissors(scissors.as_slice());
+            .viewport_count(1)
+            .scissor_count(1);
 
-        // Currently we are not using this for anything, but in the future this will hold
-        // information relating to bindings. A layout is required for creating a pipeline.
-        let layout_info = vk::PipelineLayoutCreateInfo::builder();
+        let dynamic_info = vk::PipelineDynamicStateCreateInfo::builder()
+            .dynamic_states(self.dynamic_states.as_slice());
 
-        let layout = unsafe {
-            self.device
-                .borrow()
-                .ash_device()
-                .create_pipeline_layout(&layout_info, None)
-                .unwrap()
-        };
+        let reflection = material.reflection();
+        if let Some(Err(error)) = reflection.map(|reflection| reflection.verify_vertex_attributes(material.vertex_attributes())) {
+            error!("Vertex layout of material does not match its shaders: {:?}", error);
+        }
+        let (layout, reflected_set_layouts, push_constant_ranges) = self.create_layout(reflection)?;
 
         let stages = material.pipeline_shader_stages();
         let vertex_input_stage = material.pipeline_vertex_input_state();
@@ -104,25 +546,77 @@ impl PipelineBuilder {
             .stages(stages.as_slice())
             .vertex_input_state(&vertex_input_stage)
             .viewport_state(&viewport_info)
-            .build();
+            .dynamic_state(&dynamic_info);
+        let pipeline_info = match depth_stencil_info.as_ref() {
+            Some(depth_stencil_info) => pipeline_info.depth_stencil_state(depth_stencil_info),
+            None => pipeline_info,
+        }.build();
 
         // Create pipeline and destroy unneeded shader modules.
         let pipeline = unsafe {
             self.device
-                .borrow()
                 .ash_device()
-                .create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_info], None)
-                .expect("Failed to create pipeline").remove(0)
+                .create_graphics_pipelines(self.device.pipeline_cache_raw(), &[pipeline_info], None)
         };
-        Pipeline { device: self.device,
+        let pipeline = match pipeline {
+            Ok(mut pipelines) => pipelines.remove(0),
+            Err((_, result)) => {
+                self.device.destroy_deferred(DeferredObject::PipelineLayout(layout));
+                self.destroy_set_layouts(reflected_set_layouts);
+                return Err(PipelineError::Vulkan(result));
+            }
+        };
+        Ok(Pipeline { device: self.device,
             pipeline,
             layout,
+            reflected_set_layouts,
+            push_constant_ranges,
             supports_graphics: true,
             supports_compute: false,
-        }
+        })
     }
 
-    pub fn build_compute(self) {
+    /// Builds a compute pipeline from SPIR-V code, using `main` as the entry point.
+    pub fn build_compute(self, code : &[u8]) -> Result<Pipeline, PipelineError> {
+        let module = ShaderModule::from_bytes(Arc::clone(&self.device), code)?;
 
+        // Reflection is best effort, so a module which cannot be reflected still gets a layout from the builder.
+        let reflection = ShaderReflection::new(module.code())
+            .and_then(|reflection| PipelineReflection::merge(&[&reflection]))
+            .map_err(|error| warn!("Failed to reflect compute shader: {:?}", error))
+            .ok();
+        let (layout, reflected_set_layouts, push_constant_ranges) = self.create_layout(reflection.as_ref())?;
+        let entry_point = CString::new("main").unwrap();
+        let stage = vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::COMPUTE)
+            .module(module.module_raw())
+            .name(entry_point.as_c_str())
+            .build();
+        let pipeline_info = vk::ComputePipelineCreateInfo::builder()
+            .stage(stage)
+            .layout(layout)
+            .build();
+
+        let pipeline = unsafe {
+            self.device
+                .ash_device()
+                .create_compute_pipelines(self.device.pipeline_cache_raw(), &[pipeline_info], None)
+        };
+        let pipeline = match pipeline {
+            Ok(mut pipelines) => pipelines.remove(0),
+            Err((_, result)) => {
+                self.device.destroy_deferred(DeferredObject::PipelineLayout(layout));
+                self.destroy_set_layouts(reflected_set_layouts);
+                return Err(PipelineError::Vulkan(result));
+            }
+        };
+        Ok(Pipeline { device: self.device,
+            pipeline,
+            layout,
+            reflected_set_layouts,
+            push_constant_ranges,
+            supports_graphics: false,
+            supports_compute: true,
+        })
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,217 @@
+use std::{env, fs, io, path::{Path, PathBuf}};
+use ash::version::DeviceV1_0;
+use ash::vk;
+
+/// The name of the file the pipeline cache is loaded from on startup and written to on shutdown.
+const PIPELINE_CACHE_FILE : &str = "pipeline_cache.bin";
+
+/// Returns where the pipeline cache is stored, which is the `halogen` directory in the user's cache directory, i.e.
+/// `$XDG_CACHE_HOME` or `~/.cache` on Linux, `~/Library/Caches` on macOS and `%LOCALAPPDATA%` on Windows. If that
+/// cannot be determined the cache is stored next to the executable, and as a last resort in the working directory.
+pub fn pipeline_cache_path() -> PathBuf {
+    let env_dir = |name| env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+    let user_cache_dir = if cfg!(windows) {
+        env_dir("LOCALAPPDATA")
+    } else if cfg!(target_os = "macos") {
+        env_dir("HOME").map(|home| home.join("Library").join("Caches"))
+    } else {
+        env_dir("XDG_CACHE_HOME").or_else(|| env_dir("HOME").map(|home| home.join(".cache")))
+    };
+    match user_cache_dir {
+        Some(dir) => dir.join("halogen").join(PIPELINE_CACHE_FILE),
+        None => env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(|dir| dir.join(PIPELINE_CACHE_FILE)))
+            .unwrap_or_else(|| PathBuf::from(PIPELINE_CACHE_FILE)),
+    }
+}
+
+/// Size of the header written by `VK_PIPELINE_CACHE_HEADER_VERSION_ONE`.
+const HEADER_SIZE : usize = 16 + vk::UUID_SIZE;
+
+/// Reasons why the data in a cache file cannot be used by the current device.
+#[derive(Debug)]
+pub enum PipelineCacheError {
+    Truncated,
+    UnsupportedVersion(u32),
+    MismatchedVendor(u32),
+    MismatchedDevice(u32),
+    MismatchedUuid,
+}
+
+/// A `VkPipelineCache` which persists between runs, so pipelines compiled in a previous run are not compiled again.
+pub struct PipelineCache {
+    cache : vk::PipelineCache,
+    path : PathBuf,
+}
+
+impl PipelineCache {
+    /// Creates a pipeline cache seeded with the contents of `path`. Files which are missing, corrupt or were written by
+    /// a different device or driver are ignored, and an empty cache is created instead.
+    pub fn load(device : &ash::Device, properties : &vk::PhysicalDeviceProperties, path : &Path) -> Self {
+        let data = match fs::read(path) {
+            Ok(data) => match validate_header(&data, properties) {
+                Ok(()) => data,
+                Err(error) => {
+                    warn!("Discarding pipeline cache {}: {:?}", path.display(), error);
+                    Vec::new()
+                }
+            },
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(error) => {
+                warn!("Failed to read pipeline cache {}: {}", path.display(), error);
+                Vec::new()
+            }
+        };
+
+        let cache_info = vk::PipelineCacheCreateInfo::builder()
+            .initial_data(data.as_slice());
+        let cache = unsafe {
+            // The header is only validated, so a driver may still reject the data itself.
+            device
+                .create_pipeline_cache(&cache_info, None)
+                .or_else(|_| device.create_pipeline_cache(&vk::PipelineCacheCreateInfo::default(), None))
+                .expect("Failed to create pipeline cache")
+        };
+        info!("Loaded pipeline cache with {} bytes", data.len());
+        Self { cache, path: path.to_path_buf() }
+    }
+
+    pub fn cache_raw(&self) -> vk::PipelineCache {
+        self.cache
+    }
+
+    /// Writes the cache back to its file. The data is written to a temporary file first, so that an interrupted write
+    /// does not leave a corrupt cache behind.
+    pub fn save(&self, device : &ash::Device) -> io::Result<()> {
+        let data = unsafe {
+            device
+                .get_pipeline_cache_data(self.cache)
+                .map_err(|error| io::Error::other(format!("{:?}", error)))?
+        };
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let temporary = self.path.with_extension("tmp");
+        fs::write(&temporary, &data)?;
+        fs::rename(&temporary, &self.path)?;
+        info!("Saved pipeline cache with {} bytes", data.len());
+        Ok(())
+    }
+
+    /// Saves and destroys the cache. This is called by the `Device` before it is destroyed.
+    pub fn destroy(&self, device : &ash::Device) {
+        if let Err(error) = self.save(device) {
+            warn!("Failed to save pipeline cache {}: {}", self.path.display(), error);
+        }
+        unsafe {
+            device.destroy_pipeline_cache(self.cache, None);
+        }
+    }
+}
+
+/// Checks the header of cache data against the device, as the data is only usable by the device which produced it.
+fn validate_header(data : &[u8], properties : &vk::PhysicalDeviceProperties) -> Result<(), PipelineCacheError> {
+    if data.len() < HEADER_SIZE {
+        return Err(PipelineCacheError::Truncated);
+    }
+    let read_u32 = |offset : usize| {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(&data[offset..offset + 4]);
+        u32::from_le_bytes(bytes)
+    };
+    let header_size = read_u32(0);
+    if (header_size as usize) < HEADER_SIZE || header_size as usize > data.len() {
+        return Err(PipelineCacheError::Truncated);
+    }
+    let version = read_u32(4);
+    if version != vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32 {
+        return Err(PipelineCacheError::UnsupportedVersion(version));
+    }
+    let vendor_id = read_u32(8);
+    if vendor_id != properties.vendor_id {
+        return Err(PipelineCacheError::MismatchedVendor(vendor_id));
+    }
+    let device_id = read_u32(12);
+    if device_id != properties.device_id {
+        return Err(PipelineCacheError::MismatchedDevice(device_id));
+    }
+    if data[16..HEADER_SIZE] != properties.pipeline_cache_uuid {
+        return Err(PipelineCacheError::MismatchedUuid);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties() -> vk::PhysicalDeviceProperties {
+        vk::PhysicalDeviceProperties {
+            vendor_id: 0x10de,
+            device_id: 0x1b80,
+            pipeline_cache_uuid: [7; vk::UUID_SIZE],
+            ..Default::default()
+        }
+    }
+
+    /// Returns a header written by the device described by `properties`, followed by some pipeline data.
+    fn cache_data(properties : &vk::PhysicalDeviceProperties) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+        data.extend_from_slice(&(vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32).to_le_bytes());
+        data.extend_from_slice(&properties.vendor_id.to_le_bytes());
+        data.extend_from_slice(&properties.device_id.to_le_bytes());
+        data.extend_from_slice(&properties.pipeline_cache_uuid);
+        data.extend_from_slice(&[1, 2, 3, 4]);
+        data
+    }
+
+    #[test]
+    fn accepts_data_from_the_same_device() {
+        let properties = properties();
+        assert!(validate_header(&cache_data(&properties), &properties).is_ok());
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        let properties = properties();
+        let data = cache_data(&properties);
+        assert!(matches!(validate_header(&[], &properties), Err(PipelineCacheError::Truncated)));
+        assert!(matches!(validate_header(&data[..HEADER_SIZE - 1], &properties), Err(PipelineCacheError::Truncated)));
+
+        let mut oversized = data.clone();
+        oversized[0..4].copy_from_slice(&(data.len() as u32 + 1).to_le_bytes());
+        assert!(matches!(validate_header(&oversized, &properties), Err(PipelineCacheError::Truncated)));
+        let mut undersized = data;
+        undersized[0..4].copy_from_slice(&8u32.to_le_bytes());
+        assert!(matches!(validate_header(&undersized, &properties), Err(PipelineCacheError::Truncated)));
+    }
+
+    #[test]
+    fn rejects_other_header_versions() {
+        let properties = properties();
+        let mut data = cache_data(&properties);
+        data[4..8].copy_from_slice(&2u32.to_le_bytes());
+        assert!(matches!(validate_header(&data, &properties), Err(PipelineCacheError::UnsupportedVersion(2))));
+    }
+
+    #[test]
+    fn rejects_data_from_other_devices() {
+        let properties = properties();
+        let data = cache_data(&properties);
+
+        let other_vendor = vk::PhysicalDeviceProperties { vendor_id: 0x1002, ..properties };
+        assert!(matches!(
+            validate_header(&data, &other_vendor),
+            Err(PipelineCacheError::MismatchedVendor(0x10de))
+        ));
+        let other_device = vk::PhysicalDeviceProperties { device_id: 0x1b81, ..properties };
+        assert!(matches!(
+            validate_header(&data, &other_device),
+            Err(PipelineCacheError::MismatchedDevice(0x1b80))
+        ));
+        let other_driver = vk::PhysicalDeviceProperties { pipeline_cache_uuid: [8; vk::UUID_SIZE], ..properties };
+        assert!(matches!(validate_header(&data, &other_driver), Err(PipelineCacheError::MismatchedUuid)));
+    }
+}
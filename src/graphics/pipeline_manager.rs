@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex};
+use super::{Material, Pipeline, PipelineBuilder, RenderPass};
+use super::pipeline::{PipelineError, PipelineKey};
+
+/// The outcome of building a pipeline, shared by every request with the same key.
+pub type PipelineResult = Result<Arc<Pipeline>, Arc<PipelineError>>;
+
+/// Holds a pipeline which may still be compiling, or the error it failed to compile with.
+struct PipelineSlot {
+    pipeline : Mutex<Option<PipelineResult>>,
+    ready : Condvar,
+}
+
+impl PipelineSlot {
+    fn new() -> Self {
+        Self { pipeline: Mutex::new(None), ready: Condvar::new() }
+    }
+
+    fn fill(&self, pipeline : Result<Pipeline, PipelineError>) {
+        *self.pipeline.lock().unwrap() = Some(pipeline.map(Arc::new).map_err(Arc::new));
+        self.ready.notify_all();
+    }
+}
+
+/// A pipeline requested from the `PipelineManager`, which may still be compiling in the background.
+#[derive(Clone)]
+pub struct PipelineHandle {
+    slot : Arc<PipelineSlot>,
+}
+
+impl PipelineHandle {
+    /// Returns the pipeline, or why it could not be created, once it has finished compiling.
+    pub fn try_get(&self) -> Option<PipelineResult> {
+        self.slot.pipeline.lock().unwrap().clone()
+    }
+
+    /// Blocks until the pipeline has finished compiling.
+    pub fn wait(&self) -> PipelineResult {
+        let mut pipeline = self.slot.pipeline.lock().unwrap();
+        loop {
+            if let Some(pipeline) = pipeline.as_ref() {
+                return pipeline.clone();
+            }
+            pipeline = self.slot.ready.wait(pipeline).unwrap();
+        }
+    }
+
+    /// Returns true once compiling has finished, whether or not it succeeded.
+    pub fn is_ready(&self) -> bool {
+        self.slot.pipeline.lock().unwrap().is_some()
+    }
+}
+
+struct PipelineEntry {
+    handle : PipelineHandle,
+    /// Keeps the material alive, since its shader modules are part of the key and a destroyed module's handle could be
+    /// reused by a different shader.
+    _material : Option<Arc<Material>>,
+}
+
+/// Deduplicates pipelines by their `PipelineKey`, so identical requests share one `Pipeline`. Pipelines are created
+/// lazily on first request, either on the calling thread or in the background on rayon's global thread pool. A pipeline
+/// which fails to compile keeps its error until `clear` is called, since the same request would fail again.
+pub struct PipelineManager {
+    pipelines : Mutex<HashMap<PipelineKey, PipelineEntry>>,
+}
+
+impl Default for PipelineManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PipelineManager {
+    pub fn new() -> Self {
+        Self { pipelines: Mutex::new(HashMap::new()) }
+    }
+
+    /// Returns the graphics pipeline for the builder's state, creating it on this thread if it does not exist yet.
+    pub fn graphics(&self,
+                    builder : PipelineBuilder,
+                    render_pass : &Arc<RenderPass>,
+                    material : &Arc<Material>) -> PipelineResult {
+        let key = builder.graphics_key(render_pass, material);
+        let (handle, created) = self.lookup(key, Some(material));
+        if created {
+            handle.slot.fill(builder.build_graphics(render_pass, material));
+        }
+        handle.wait()
+    }
+
+    /// Returns a handle to the graphics pipeline for the builder's state. If it does not exist yet it is compiled on a
+    /// rayon thread, so it may not be ready for a few frames.
+    pub fn graphics_async(&self,
+                          builder : PipelineBuilder,
+                          render_pass : &Arc<RenderPass>,
+                          material : &Arc<Material>) -> PipelineHandle {
+        let key = builder.graphics_key(render_pass, material);
+        let (handle, created) = self.lookup(key, Some(material));
+        if created {
+            let slot = Arc::clone(&handle.slot);
+            let render_pass = Arc::clone(render_pass);
+            let material = Arc::clone(material);
+            rayon::spawn(move || slot.fill(builder.build_graphics(&render_pass, &material)));
+        }
+        handle
+    }
+
+    /// Returns the compute pipeline for the builder's state and `code`, creating it on this thread if it does not exist
+    /// yet.
+    pub fn compute(&self, builder : PipelineBuilder, code : &[u8]) -> PipelineResult {
+        let key = builder.compute_key(code);
+        let (handle, created) = self.lookup(key, None);
+        if created {
+            handle.slot.fill(builder.build_compute(code));
+        }
+        handle.wait()
+    }
+
+    /// Returns the number of distinct pipelines which have been requested.
+    pub fn len(&self) -> usize {
+        self.pipelines.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops every pipeline held by the manager. Pipelines still referenced elsewhere stay alive until those references
+    /// are dropped.
+    pub fn clear(&self) {
+        self.pipelines.lock().unwrap().clear();
+    }
+
+    /// Finds the entry for `key`, inserting an empty one if there is none. Returns whether the caller inserted it and so
+    /// is responsible for filling it.
+    fn lookup(&self, key : PipelineKey, material : Option<&Arc<Material>>) -> (PipelineHandle, bool) {
+        let mut pipelines = self.pipelines.lock().unwrap();
+        if let Some(entry) = pipelines.get(&key) {
+            return (entry.handle.clone(), false);
+        }
+        let handle = PipelineHandle { slot: Arc::new(PipelineSlot::new()) };
+        pipelines.insert(key, PipelineEntry { handle: handle.clone(), _material: material.cloned() });
+        (handle, true)
+    }
+}
@@ -1,9 +1,10 @@
-use std::{cell::RefCell, rc::Rc};
+use std::sync::Arc;
 use ash::{vk, version::DeviceV1_0};
 use super::{CmdBuffer, Device};
+use super::deletion::DeferredObject;
 
 pub struct Queue {
-    device : Rc<RefCell<Device>>,
+    device : Arc<Device>,
     queue : vk::Queue,
     family_index : u32,
     submit_semaphore : vk::Semaphore,
@@ -11,19 +12,15 @@ pub struct Queue {
 
 impl Drop for Queue {
     fn drop(&mut self) {
-        unsafe {
-            self.device.borrow().ash_device().queue_wait_idle(self.queue).unwrap();
-            self.device.borrow().ash_device().destroy_semaphore(self.submit_semaphore, None);
-        }
+        self.device.destroy_deferred(DeferredObject::Semaphore(self.submit_semaphore));
         info!("Dropped Queue")
     }
 }
 
 impl Queue {
-    pub fn new(device : Rc<RefCell<Device>>, family_index : u32) -> Self {
+    pub fn new(device : Arc<Device>, family_index : u32) -> Self {
         let queue = unsafe {
             device
-                .borrow()
                 .ash_device()
                 .get_device_queue(family_index, 0)
         };
@@ -31,7 +28,6 @@ impl Queue {
         let semaphore_info = vk::SemaphoreCreateInfo::builder();
         let submit_semaphore = unsafe {
             device
-                .borrow()
                 .ash_device()
                 .create_semaphore(&semaphore_info, None)
                 .expect("Failed to create semaphore")
@@ -43,37 +39,43 @@ impl Queue {
     /// If the queue needs to wait for some work to be done, use `signal_semaphore`. If you need the CPU to wait for the queue to finish,
     /// i.e acquiring images on the swapchain, use `signal_fence`.
     pub fn submit(&self, cmd_buffer : &CmdBuffer, wait_semaphore : Option<vk::Semaphore>, signal_fence : Option<vk::Fence>) {
-        let submit_info = match wait_semaphore {
-            Some(semaphore) => vk::SubmitInfo::builder()
-                .command_buffers(&[cmd_buffer.cmd_buffer_raw()])
-                // Signal this semaphore when submission is completed.
-                .signal_semaphores(&[self.submit_semaphore])
-                // Wait on the image to be acquired before submitting the command buffer.
-                .wait_semaphores(&[semaphore])
-                .wait_dst_stage_mask(&[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT])
-                .build(),
-            None => vk::SubmitInfo::builder()
-                .command_buffers(&[cmd_buffer.cmd_buffer_raw()])
-                // Signal this semaphore when submission is completed.
-                .signal_semaphores(&[self.submit_semaphore])
-                .wait_dst_stage_mask(&[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT])
-                .build()
-        };
+        // Wait on the image to be acquired before writing to it.
+        let waits : Vec<(vk::Semaphore, vk::PipelineStageFlags)> = wait_semaphore
+            .map(|semaphore| (semaphore, vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT))
+            .into_iter()
+            .collect();
+        self.submit_with(&[cmd_buffer], &waits, &[self.submit_semaphore], signal_fence);
+    }
+
+    /// Submits command buffers which wait on each semaphore at the paired stage, and signal `signal_semaphores` and
+    /// `signal_fence` once complete. This is used to order work between queues, e.g. compute work which is consumed by
+    /// the graphics queue.
+    pub fn submit_with(&self,
+                       cmd_buffers : &[&CmdBuffer],
+                       wait_semaphores : &[(vk::Semaphore, vk::PipelineStageFlags)],
+                       signal_semaphores : &[vk::Semaphore],
+                       signal_fence : Option<vk::Fence>) {
+        // The submit info points into these, so they have to outlive the submission.
+        let cmd_buffers : Vec<vk::CommandBuffer> = cmd_buffers
+            .iter()
+            .map(|cmd_buffer| cmd_buffer.cmd_buffer_raw())
+            .collect();
+        let (semaphores, stages) : (Vec<vk::Semaphore>, Vec<vk::PipelineStageFlags>) = wait_semaphores
+            .iter()
+            .cloned()
+            .unzip();
+        let submit_info = vk::SubmitInfo::builder()
+            .command_buffers(cmd_buffers.as_slice())
+            .wait_semaphores(semaphores.as_slice())
+            .wait_dst_stage_mask(stages.as_slice())
+            .signal_semaphores(signal_semaphores)
+            .build();
+        let _lock = self.device.lock_queue(self.family_index);
         unsafe {
-            match signal_fence {
-                Some(fence) => self.device
-                    .borrow()
-                    .ash_device()
-                    // Submits to the queue with the specified semaphores and a acquire fence to signal.
-                    .queue_submit(self.queue, &[submit_info], fence)
-                    .expect("Failed to submit command buffer."),
-                None => self.device
-                    .borrow()
-                    .ash_device()
-                    // Submits to the queue with the specified semaphores and a acquire fence to signal.
-                    .queue_submit(self.queue, &[submit_info], vk::Fence::null())
-                    .expect("Failed to submit command buffer."),
-            }
+            self.device
+                .ash_device()
+                .queue_submit(self.queue, &[submit_info], signal_fence.unwrap_or_else(vk::Fence::null))
+                .expect("Failed to submit command buffer.");
         }
     }
 
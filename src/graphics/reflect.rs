@@ -0,0 +1,637 @@
+use std::collections::{BTreeMap, HashMap};
+use ash::vk;
+use super::shader::MAGIC_NUMBER;
+
+// Opcodes.
+const OP_ENTRY_POINT : u32 = 15;
+const OP_TYPE_BOOL : u32 = 20;
+const OP_TYPE_INT : u32 = 21;
+const OP_TYPE_FLOAT : u32 = 22;
+const OP_TYPE_VECTOR : u32 = 23;
+const OP_TYPE_MATRIX : u32 = 24;
+const OP_TYPE_IMAGE : u32 = 25;
+const OP_TYPE_SAMPLER : u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE : u32 = 27;
+const OP_TYPE_ARRAY : u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY : u32 = 29;
+const OP_TYPE_STRUCT : u32 = 30;
+const OP_TYPE_POINTER : u32 = 32;
+const OP_CONSTANT : u32 = 43;
+const OP_SPEC_CONSTANT_TRUE : u32 = 48;
+const OP_SPEC_CONSTANT_FALSE : u32 = 49;
+const OP_SPEC_CONSTANT : u32 = 50;
+const OP_VARIABLE : u32 = 59;
+const OP_DECORATE : u32 = 71;
+const OP_MEMBER_DECORATE : u32 = 72;
+
+// Decorations.
+const DECORATION_SPEC_ID : u32 = 1;
+const DECORATION_BLOCK : u32 = 2;
+const DECORATION_BUFFER_BLOCK : u32 = 3;
+const DECORATION_ARRAY_STRIDE : u32 = 6;
+const DECORATION_MATRIX_STRIDE : u32 = 7;
+const DECORATION_BUILT_IN : u32 = 11;
+const DECORATION_LOCATION : u32 = 30;
+const DECORATION_BINDING : u32 = 33;
+const DECORATION_DESCRIPTOR_SET : u32 = 34;
+const DECORATION_OFFSET : u32 = 35;
+
+// Storage classes.
+const STORAGE_UNIFORM_CONSTANT : u32 = 0;
+const STORAGE_INPUT : u32 = 1;
+const STORAGE_UNIFORM : u32 = 2;
+const STORAGE_PUSH_CONSTANT : u32 = 9;
+const STORAGE_STORAGE_BUFFER : u32 = 12;
+
+// Execution models.
+const EXECUTION_MODEL_VERTEX : u32 = 0;
+const EXECUTION_MODEL_FRAGMENT : u32 = 4;
+const EXECUTION_MODEL_GL_COMPUTE : u32 = 5;
+
+// Image dimensions.
+const DIM_BUFFER : u32 = 5;
+const DIM_SUBPASS_DATA : u32 = 6;
+
+/// Provides a brief overview of why a module could not be reflected, or why reflected modules do not fit together.
+#[derive(Debug)]
+pub enum ReflectionError {
+    /// The code is shorter than the header, or does not start with the SPIR-V magic number.
+    InvalidHeader,
+    /// An instruction extends past the end of the code or has a word count of zero.
+    MalformedInstruction(usize),
+    /// The module has no entry point of a supported stage.
+    MissingEntryPoint,
+    /// Two stages declare the same binding with different descriptor types.
+    ConflictingBinding { set : u32, binding : u32 },
+    /// A vertex shader input has no attribute at its location.
+    MissingVertexAttribute(u32),
+    /// A vertex shader input's attribute has a format which does not match the input's type.
+    MismatchedVertexAttribute { location : u32, expected : vk::Format, found : vk::Format },
+}
+
+#[derive(Clone, Debug)]
+enum Type {
+    Bool,
+    Int { width : u32, signed : bool },
+    Float { width : u32 },
+    Vector { component : u32, count : u32 },
+    Matrix { column : u32, count : u32 },
+    Image { dim : u32, sampled : u32 },
+    Sampler,
+    SampledImage,
+    Array { element : u32, length : u32 },
+    RuntimeArray { element : u32 },
+    Struct { members : Vec<u32> },
+    Pointer { pointee : u32 },
+}
+
+/// A descriptor binding used by one or more stages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DescriptorBinding {
+    pub binding : u32,
+    pub descriptor_type : vk::DescriptorType,
+    /// The number of descriptors, or 0 for a runtime sized array.
+    pub count : u32,
+    pub stages : vk::ShaderStageFlags,
+}
+
+/// A vertex shader input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VertexInput {
+    pub location : u32,
+    pub format : vk::Format,
+}
+
+/// A specialization constant and its default value, as raw bits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpecializationConstant {
+    pub constant_id : u32,
+    pub size : u32,
+    pub default : u32,
+}
+
+/// The interface of a single shader module, extracted from its SPIR-V.
+#[derive(Clone, Debug)]
+pub struct ShaderReflection {
+    pub stage : vk::ShaderStageFlags,
+    pub entry_point : String,
+    /// Descriptor bindings, by set and then by binding.
+    pub descriptor_sets : BTreeMap<u32, BTreeMap<u32, DescriptorBinding>>,
+    /// The range of push constants used by the module, if any.
+    pub push_constants : Option<vk::PushConstantRange>,
+    /// Inputs of a vertex shader, sorted by location. Empty for other stages.
+    pub vertex_inputs : Vec<VertexInput>,
+    pub specialization_constants : Vec<SpecializationConstant>,
+}
+
+#[derive(Default)]
+struct Decorations {
+    set : Option<u32>,
+    binding : Option<u32>,
+    location : Option<u32>,
+    spec_id : Option<u32>,
+    array_stride : Option<u32>,
+    built_in : bool,
+    block : bool,
+    buffer_block : bool,
+}
+
+impl ShaderReflection {
+    /// Reflects the first entry point of the module.
+    pub fn new(words : &[u32]) -> Result<Self, ReflectionError> {
+        if words.len() < 5 || words[0] != MAGIC_NUMBER {
+            return Err(ReflectionError::InvalidHeader);
+        }
+
+        let mut entry_point = None;
+        let mut types = HashMap::new();
+        let mut constants = HashMap::new();
+        let mut spec_constants = Vec::new();
+        let mut variables = Vec::new();
+        let mut decorations : HashMap<u32, Decorations> = HashMap::new();
+        let mut member_offsets : HashMap<(u32, u32), u32> = HashMap::new();
+        let mut member_matrix_strides : HashMap<(u32, u32), u32> = HashMap::new();
+
+        let mut offset = 5;
+        while offset < words.len() {
+            let word_count = (words[offset] >> 16) as usize;
+            let opcode = words[offset] & 0xffff;
+            if word_count == 0 || offset + word_count > words.len() {
+                return Err(ReflectionError::MalformedInstruction(offset));
+            }
+            let operands = &words[offset + 1..offset + word_count];
+            // Operands are checked for length before use, so a truncated instruction is skipped rather than panicking.
+            match (opcode, operands) {
+                (OP_ENTRY_POINT, [model, id, rest @ ..]) if entry_point.is_none() => {
+                    let (name, name_words) = read_string(rest);
+                    let interface = rest[name_words.min(rest.len())..].to_vec();
+                    entry_point = Some((*model, *id, name, interface));
+                }
+                (OP_TYPE_BOOL, [id]) => { types.insert(*id, Type::Bool); }
+                (OP_TYPE_INT, [id, width, signed]) => {
+                    types.insert(*id, Type::Int { width: *width, signed: *signed != 0 });
+                }
+                (OP_TYPE_FLOAT, [id, width, ..]) => { types.insert(*id, Type::Float { width: *width }); }
+                (OP_TYPE_VECTOR, [id, component, count]) => {
+                    types.insert(*id, Type::Vector { component: *component, count: *count });
+                }
+                (OP_TYPE_MATRIX, [id, column, count]) => {
+                    types.insert(*id, Type::Matrix { column: *column, count: *count });
+                }
+                (OP_TYPE_IMAGE, [id, _, dim, _, _, _, sampled, ..]) => {
+                    types.insert(*id, Type::Image { dim: *dim, sampled: *sampled });
+                }
+                (OP_TYPE_SAMPLER, [id]) => { types.insert(*id, Type::Sampler); }
+                (OP_TYPE_SAMPLED_IMAGE, [id, _]) => { types.insert(*id, Type::SampledImage); }
+                (OP_TYPE_ARRAY, [id, element, length]) => {
+                    types.insert(*id, Type::Array { element: *element, length: *length });
+                }
+                (OP_TYPE_RUNTIME_ARRAY, [id, element]) => {
+                    types.insert(*id, Type::RuntimeArray { element: *element });
+                }
+                (OP_TYPE_STRUCT, [id, members @ ..]) => {
+                    types.insert(*id, Type::Struct { members: members.to_vec() });
+                }
+                (OP_TYPE_POINTER, [id, _, pointee]) => { types.insert(*id, Type::Pointer { pointee: *pointee }); }
+                (OP_CONSTANT, [_, id, value, ..]) => { constants.insert(*id, *value); }
+                (OP_SPEC_CONSTANT_TRUE, [ty, id]) => spec_constants.push((*id, *ty, 1)),
+                (OP_SPEC_CONSTANT_FALSE, [ty, id]) => spec_constants.push((*id, *ty, 0)),
+                (OP_SPEC_CONSTANT, [ty, id, value, ..]) => spec_constants.push((*id, *ty, *value)),
+                (OP_VARIABLE, [ty, id, storage_class, ..]) => variables.push((*id, *ty, *storage_class)),
+                (OP_DECORATE, [target, decoration, rest @ ..]) => {
+                    let entry = decorations.entry(*target).or_default();
+                    let value = rest.first().cloned();
+                    match *decoration {
+                        DECORATION_DESCRIPTOR_SET => entry.set = value,
+                        DECORATION_BINDING => entry.binding = value,
+                        DECORATION_LOCATION => entry.location = value,
+                        DECORATION_SPEC_ID => entry.spec_id = value,
+                        DECORATION_ARRAY_STRIDE => entry.array_stride = value,
+                        DECORATION_BUILT_IN => entry.built_in = true,
+                        DECORATION_BLOCK => entry.block = true,
+                        DECORATION_BUFFER_BLOCK => entry.buffer_block = true,
+                        _ => (),
+                    }
+                }
+                (OP_MEMBER_DECORATE, [target, member, decoration, value, ..]) => match *decoration {
+                    DECORATION_OFFSET => { member_offsets.insert((*target, *member), *value); }
+                    DECORATION_MATRIX_STRIDE => { member_matrix_strides.insert((*target, *member), *value); }
+                    DECORATION_BUILT_IN => decorations.entry(*target).or_default().built_in = true,
+                    _ => (),
+                },
+                _ => (),
+            }
+            offset += word_count;
+        }
+
+        let (model, _, entry_name, interface) = entry_point.ok_or(ReflectionError::MissingEntryPoint)?;
+        let stage = match model {
+            EXECUTION_MODEL_VERTEX => vk::ShaderStageFlags::VERTEX,
+            EXECUTION_MODEL_FRAGMENT => vk::ShaderStageFlags::FRAGMENT,
+            EXECUTION_MODEL_GL_COMPUTE => vk::ShaderStageFlags::COMPUTE,
+            _ => return Err(ReflectionError::MissingEntryPoint),
+        };
+
+        let module = Module { types, constants, decorations, member_offsets, member_matrix_strides };
+        let mut reflection = ShaderReflection {
+            stage,
+            entry_point: entry_name,
+            descriptor_sets: BTreeMap::new(),
+            push_constants: None,
+            vertex_inputs: Vec::new(),
+            specialization_constants: Vec::new(),
+        };
+
+        for (id, ty, storage_class) in variables {
+            let pointee = match module.types.get(&ty) {
+                Some(Type::Pointer { pointee }) => *pointee,
+                _ => continue,
+            };
+            let decoration = module.decorations.get(&id);
+            match storage_class {
+                STORAGE_UNIFORM_CONSTANT | STORAGE_UNIFORM | STORAGE_STORAGE_BUFFER => {
+                    let (set, binding) = match decoration.map(|d| (d.set, d.binding)) {
+                        Some((Some(set), Some(binding))) => (set, binding),
+                        _ => continue,
+                    };
+                    let (element, count) = module.strip_arrays(pointee);
+                    if let Some(descriptor_type) = module.descriptor_type(element, storage_class) {
+                        reflection.descriptor_sets
+                            .entry(set)
+                            .or_default()
+                            .insert(binding, DescriptorBinding { binding, descriptor_type, count, stages: stage });
+                    }
+                }
+                STORAGE_PUSH_CONSTANT => {
+                    if let Some((start, end)) = module.struct_extent(pointee) {
+                        reflection.push_constants = Some(vk::PushConstantRange::builder()
+                            .stage_flags(stage)
+                            .offset(start)
+                            .size(end - start)
+                            .build());
+                    }
+                }
+                STORAGE_INPUT if stage == vk::ShaderStageFlags::VERTEX && interface.contains(&id) => {
+                    let location = match decoration {
+                        Some(decoration) if !decoration.built_in => decoration.location,
+                        _ => None,
+                    };
+                    if let (Some(location), Some(format)) = (location, module.vertex_format(pointee)) {
+                        reflection.vertex_inputs.push(VertexInput { location, format });
+                    }
+                }
+                _ => (),
+            }
+        }
+        reflection.vertex_inputs.sort_by_key(|input| input.location);
+
+        for (id, ty, default) in spec_constants {
+            if let Some(constant_id) = module.decorations.get(&id).and_then(|d| d.spec_id) {
+                let size = module.size_of(ty).unwrap_or(4);
+                reflection.specialization_constants.push(SpecializationConstant { constant_id, size, default });
+            }
+        }
+        reflection.specialization_constants.sort_by_key(|constant| constant.constant_id);
+        Ok(reflection)
+    }
+}
+
+struct Module {
+    types : HashMap<u32, Type>,
+    constants : HashMap<u32, u32>,
+    decorations : HashMap<u32, Decorations>,
+    member_offsets : HashMap<(u32, u32), u32>,
+    member_matrix_strides : HashMap<(u32, u32), u32>,
+}
+
+impl Module {
+    /// Strips arrays of descriptors, returning the element type and the number of descriptors.
+    fn strip_arrays(&self, mut ty : u32) -> (u32, u32) {
+        let mut count = 1;
+        loop {
+            match self.types.get(&ty) {
+                Some(Type::Array { element, length }) => {
+                    count *= self.constants.get(length).cloned().unwrap_or(1);
+                    ty = *element;
+                }
+                Some(Type::RuntimeArray { element }) => {
+                    count = 0;
+                    ty = *element;
+                }
+                _ => return (ty, count),
+            }
+        }
+    }
+
+    fn descriptor_type(&self, ty : u32, storage_class : u32) -> Option<vk::DescriptorType> {
+        match self.types.get(&ty)? {
+            Type::Sampler => Some(vk::DescriptorType::SAMPLER),
+            Type::SampledImage => Some(vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+            Type::Image { dim: DIM_SUBPASS_DATA, .. } => Some(vk::DescriptorType::INPUT_ATTACHMENT),
+            Type::Image { dim: DIM_BUFFER, sampled: 2 } => Some(vk::DescriptorType::STORAGE_TEXEL_BUFFER),
+            Type::Image { dim: DIM_BUFFER, .. } => Some(vk::DescriptorType::UNIFORM_TEXEL_BUFFER),
+            Type::Image { sampled: 2, .. } => Some(vk::DescriptorType::STORAGE_IMAGE),
+            Type::Image { .. } => Some(vk::DescriptorType::SAMPLED_IMAGE),
+            Type::Struct { .. } => {
+                let decoration = self.decorations.get(&ty);
+                if storage_class == STORAGE_STORAGE_BUFFER || decoration.is_some_and(|d| d.buffer_block) {
+                    Some(vk::DescriptorType::STORAGE_BUFFER)
+                } else {
+                    Some(vk::DescriptorType::UNIFORM_BUFFER)
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the offset of the first member of a struct and the end of its last member.
+    fn struct_extent(&self, ty : u32) -> Option<(u32, u32)> {
+        let members = match self.types.get(&ty)? {
+            Type::Struct { members } => members,
+            _ => return None,
+        };
+        let mut start = u32::MAX;
+        let mut end = 0;
+        for (index, member) in members.iter().enumerate() {
+            let offset = self.member_offsets.get(&(ty, index as u32)).cloned().unwrap_or(0);
+            let size = match self.types.get(member) {
+                Some(Type::Matrix { count, .. }) => self.member_matrix_strides
+                    .get(&(ty, index as u32))
+                    .map(|stride| stride * count)
+                    .or_else(|| self.size_of(*member))?,
+                _ => self.size_of(*member)?,
+            };
+            start = start.min(offset);
+            end = end.max(offset + size);
+        }
+        if members.is_empty() { None } else { Some((start, end)) }
+    }
+
+    fn size_of(&self, ty : u32) -> Option<u32> {
+        match self.types.get(&ty)? {
+            Type::Bool => Some(4),
+            Type::Int { width, .. } | Type::Float { width } => Some(width / 8),
+            Type::Vector { component, count } => Some(self.size_of(*component)? * count),
+            Type::Matrix { column, count } => Some(self.size_of(*column)? * count),
+            Type::Array { element, length } => {
+                let length = self.constants.get(length).cloned()?;
+                let stride = self.decorations
+                    .get(&ty)
+                    .and_then(|d| d.array_stride)
+                    .or_else(|| self.size_of(*element))?;
+                Some(stride * length)
+            }
+            Type::Struct { .. } => self.struct_extent(ty).map(|(_, end)| end),
+            _ => None,
+        }
+    }
+
+    fn vertex_format(&self, ty : u32) -> Option<vk::Format> {
+        let (component, count) = match self.types.get(&ty)? {
+            Type::Vector { component, count } => (*component, *count),
+            _ => (ty, 1),
+        };
+        let formats = match self.types.get(&component)? {
+            Type::Float { width: 32 } => [
+                vk::Format::R32_SFLOAT, vk::Format::R32G32_SFLOAT,
+                vk::Format::R32G32B32_SFLOAT, vk::Format::R32G32B32A32_SFLOAT],
+            Type::Int { width: 32, signed: true } => [
+                vk::Format::R32_SINT, vk::Format::R32G32_SINT,
+                vk::Format::R32G32B32_SINT, vk::Format::R32G32B32A32_SINT],
+            Type::Int { width: 32, signed: false } => [
+                vk::Format::R32_UINT, vk::Format::R32G32_UINT,
+                vk::Format::R32G32B32_UINT, vk::Format::R32G32B32A32_UINT],
+            _ => return None,
+        };
+        formats.get(count.checked_sub(1)? as usize).cloned()
+    }
+}
+
+/// Reads a nul terminated string packed into words, returning it and the number of words it occupied.
+fn read_string(words : &[u32]) -> (String, usize) {
+    let mut bytes = Vec::new();
+    for (index, word) in words.iter().enumerate() {
+        for byte in word.to_le_bytes().iter() {
+            if *byte == 0 {
+                return (String::from_utf8_lossy(&bytes).into_owned(), index + 1);
+            }
+            bytes.push(*byte);
+        }
+    }
+    (String::from_utf8_lossy(&bytes).into_owned(), words.len())
+}
+
+/// The combined interface of every stage of a pipeline.
+#[derive(Clone, Debug, Default)]
+pub struct PipelineReflection {
+    pub descriptor_sets : BTreeMap<u32, BTreeMap<u32, DescriptorBinding>>,
+    /// One range per stage which uses push constants.
+    pub push_constant_ranges : Vec<vk::PushConstantRange>,
+    pub vertex_inputs : Vec<VertexInput>,
+    pub specialization_constants : Vec<SpecializationConstant>,
+}
+
+impl PipelineReflection {
+    /// Merges the interfaces of each stage. Bindings used by several stages are visible to all of them.
+    pub fn merge(stages : &[&ShaderReflection]) -> Result<Self, ReflectionError> {
+        let mut merged = Self::default();
+        for stage in stages {
+            for (set, bindings) in &stage.descriptor_sets {
+                let merged_set = merged.descriptor_sets.entry(*set).or_default();
+                for (binding, descriptor) in bindings {
+                    match merged_set.get_mut(binding) {
+                        Some(existing) if existing.descriptor_type != descriptor.descriptor_type =>
+                            return Err(ReflectionError::ConflictingBinding { set: *set, binding: *binding }),
+                        Some(existing) => {
+                            existing.stages |= descriptor.stages;
+                            existing.count = existing.count.max(descriptor.count);
+                        }
+                        None => { merged_set.insert(*binding, *descriptor); }
+                    }
+                }
+            }
+            merged.push_constant_ranges.extend(stage.push_constants);
+            if stage.stage == vk::ShaderStageFlags::VERTEX {
+                merged.vertex_inputs = stage.vertex_inputs.clone();
+            }
+            for constant in &stage.specialization_constants {
+                if !merged.specialization_constants.iter().any(|c| c.constant_id == constant.constant_id) {
+                    merged.specialization_constants.push(*constant);
+                }
+            }
+        }
+        Ok(merged)
+    }
+
+    /// Checks that every vertex input has an attribute at its location with a matching format.
+    pub fn verify_vertex_attributes(&self, attributes : &[vk::VertexInputAttributeDescription])
+        -> Result<(), ReflectionError> {
+        for input in &self.vertex_inputs {
+            let attribute = attributes
+                .iter()
+                .find(|attribute| attribute.location == input.location)
+                .ok_or(ReflectionError::MissingVertexAttribute(input.location))?;
+            if attribute.format != input.format {
+                return Err(ReflectionError::MismatchedVertexAttribute {
+                    location: input.location,
+                    expected: input.format,
+                    found: attribute.format,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the bindings of each set from 0 to the highest set used, so that the set numbers match the shaders.
+    /// Unused sets are empty.
+    pub fn set_layout_bindings(&self) -> Vec<Vec<vk::DescriptorSetLayoutBinding>> {
+        let set_count = self.descriptor_sets.keys().next_back().map_or(0, |set| set + 1);
+        (0..set_count)
+            .map(|set| self.descriptor_sets
+                .get(&set)
+                .map(|bindings| bindings
+                    .values()
+                    .map(|binding| vk::DescriptorSetLayoutBinding::builder()
+                        .binding(binding.binding)
+                        .descriptor_type(binding.descriptor_type)
+                        .descriptor_count(binding.count.max(1))
+                        .stage_flags(binding.stages)
+                        .build())
+                    .collect())
+                .unwrap_or_default())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::shader::read_spirv;
+
+    fn reflect(code : &[u8]) -> ShaderReflection {
+        ShaderReflection::new(&read_spirv(code).unwrap()).unwrap()
+    }
+
+    /// Returns the type and stages of each binding of `set`, in binding order.
+    fn bindings(sets : &BTreeMap<u32, BTreeMap<u32, DescriptorBinding>>, set : u32)
+        -> Vec<(u32, vk::DescriptorType, u32, vk::ShaderStageFlags)> {
+        sets[&set]
+            .values()
+            .map(|binding| (binding.binding, binding.descriptor_type, binding.count, binding.stages))
+            .collect()
+    }
+
+    fn push_constants(range : &vk::PushConstantRange) -> (vk::ShaderStageFlags, u32, u32) {
+        (range.stage_flags, range.offset, range.size)
+    }
+
+    #[test]
+    fn reflects_vertex_shaders() {
+        let pbr = reflect(include_bytes!("../assets/shaders/pbr_vert.spv"));
+        assert_eq!(pbr.stage, vk::ShaderStageFlags::VERTEX);
+        assert_eq!(pbr.entry_point, "main");
+        assert_eq!(pbr.descriptor_sets.keys().copied().collect::<Vec<u32>>(), vec![1]);
+        assert_eq!(bindings(&pbr.descriptor_sets, 1),
+                   vec![(0, vk::DescriptorType::UNIFORM_BUFFER, 1, vk::ShaderStageFlags::VERTEX)]);
+        assert_eq!(pbr.push_constants.as_ref().map(push_constants), Some((vk::ShaderStageFlags::VERTEX, 0, 128)));
+        assert_eq!(pbr.vertex_inputs, vec![
+            VertexInput { location: 0, format: vk::Format::R32G32B32_SFLOAT },
+            VertexInput { location: 1, format: vk::Format::R32G32B32_SFLOAT },
+            VertexInput { location: 2, format: vk::Format::R32G32B32A32_SFLOAT },
+            VertexInput { location: 3, format: vk::Format::R32G32_SFLOAT },
+        ]);
+
+        let material = reflect(include_bytes!("../assets/shaders/material_vert.spv"));
+        assert!(material.descriptor_sets.is_empty());
+        assert_eq!(material.push_constants.as_ref().map(push_constants),
+                   Some((vk::ShaderStageFlags::VERTEX, 0, 64)));
+        assert_eq!(material.vertex_inputs, vec![
+            VertexInput { location: 0, format: vk::Format::R32G32B32_SFLOAT },
+            VertexInput { location: 1, format: vk::Format::R32G32B32A32_SFLOAT },
+            VertexInput { location: 2, format: vk::Format::R32G32_SFLOAT },
+        ]);
+
+        // The particles are read from a storage buffer by vertex index, so there are no vertex inputs.
+        let particles = reflect(include_bytes!("../assets/shaders/particles_vert.spv"));
+        assert_eq!(bindings(&particles.descriptor_sets, 0),
+                   vec![(0, vk::DescriptorType::STORAGE_BUFFER, 1, vk::ShaderStageFlags::VERTEX)]);
+        assert!(particles.push_constants.is_none());
+        assert!(particles.vertex_inputs.is_empty());
+    }
+
+    #[test]
+    fn reflects_fragment_shaders() {
+        let stage = vk::ShaderStageFlags::FRAGMENT;
+        let pbr = reflect(include_bytes!("../assets/shaders/pbr_frag.spv"));
+        assert_eq!(pbr.stage, stage);
+        let mut material_bindings = vec![(0, vk::DescriptorType::UNIFORM_BUFFER, 1, stage)];
+        for binding in (1..11).step_by(2) {
+            material_bindings.push((binding, vk::DescriptorType::SAMPLED_IMAGE, 1, stage));
+            material_bindings.push((binding + 1, vk::DescriptorType::SAMPLER, 1, stage));
+        }
+        assert_eq!(bindings(&pbr.descriptor_sets, 0), material_bindings);
+        assert_eq!(bindings(&pbr.descriptor_sets, 1), vec![
+            (0, vk::DescriptorType::UNIFORM_BUFFER, 1, stage),
+            (1, vk::DescriptorType::SAMPLED_IMAGE, 1, stage),
+            (2, vk::DescriptorType::SAMPLED_IMAGE, 1, stage),
+            (3, vk::DescriptorType::SAMPLED_IMAGE, 1, stage),
+            (4, vk::DescriptorType::SAMPLER, 1, stage),
+        ]);
+        assert!(pbr.push_constants.is_none());
+        assert!(pbr.vertex_inputs.is_empty());
+
+        let textured = reflect(include_bytes!("../assets/shaders/textured_frag.spv"));
+        assert_eq!(bindings(&textured.descriptor_sets, 0), vec![
+            (0, vk::DescriptorType::UNIFORM_BUFFER, 1, stage),
+            (1, vk::DescriptorType::SAMPLED_IMAGE, 1, stage),
+            (2, vk::DescriptorType::SAMPLER, 1, stage),
+        ]);
+
+        // The bindless variant reads its texture from the heap, with the handle pushed after the vertex transform.
+        let bindless = reflect(include_bytes!("../assets/shaders/textured_bindless_frag.spv"));
+        assert_eq!(bindings(&bindless.descriptor_sets, 0), vec![(0, vk::DescriptorType::UNIFORM_BUFFER, 1, stage)]);
+        assert_eq!(bindings(&bindless.descriptor_sets, 1), vec![
+            (0, vk::DescriptorType::SAMPLED_IMAGE, 4096, stage),
+            (2, vk::DescriptorType::SAMPLER, 4096, stage),
+        ]);
+        assert_eq!(bindless.push_constants.as_ref().map(push_constants), Some((stage, 0, 68)));
+    }
+
+    #[test]
+    fn reflects_compute_shaders() {
+        let stage = vk::ShaderStageFlags::COMPUTE;
+        let particles = reflect(include_bytes!("../assets/shaders/particles_comp.spv"));
+        assert_eq!(particles.stage, stage);
+        assert_eq!(bindings(&particles.descriptor_sets, 0),
+                   vec![(0, vk::DescriptorType::STORAGE_BUFFER, 1, stage)]);
+        assert_eq!(particles.push_constants.as_ref().map(push_constants), Some((stage, 0, 8)));
+
+        let prefilter = reflect(include_bytes!("../assets/shaders/ibl_prefilter_comp.spv"));
+        assert_eq!(bindings(&prefilter.descriptor_sets, 0), vec![
+            (0, vk::DescriptorType::SAMPLED_IMAGE, 1, stage),
+            (1, vk::DescriptorType::SAMPLER, 1, stage),
+            (2, vk::DescriptorType::STORAGE_IMAGE, 1, stage),
+        ]);
+        assert_eq!(prefilter.push_constants.as_ref().map(push_constants), Some((stage, 0, 4)));
+    }
+
+    #[test]
+    fn merges_the_stages_of_a_pipeline() {
+        let vertex = reflect(include_bytes!("../assets/shaders/pbr_vert.spv"));
+        let fragment = reflect(include_bytes!("../assets/shaders/pbr_frag.spv"));
+        let pipeline = PipelineReflection::merge(&[&vertex, &fragment]).unwrap();
+        assert_eq!(pipeline.descriptor_sets[&1][&0].stages,
+                   vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT);
+        assert_eq!(pipeline.push_constant_ranges.iter().map(push_constants).collect::<Vec<_>>(),
+                   vec![(vk::ShaderStageFlags::VERTEX, 0, 128)]);
+        assert_eq!(pipeline.vertex_inputs, vertex.vertex_inputs);
+        let set_sizes : Vec<usize> = pipeline.set_layout_bindings().iter().map(Vec::len).collect();
+        assert_eq!(set_sizes, vec![11, 5]);
+    }
+
+    #[test]
+    fn rejects_code_without_a_spirv_header() {
+        assert!(matches!(ShaderReflection::new(&[0; 5]), Err(ReflectionError::InvalidHeader)));
+        assert!(matches!(ShaderReflection::new(&[MAGIC_NUMBER]), Err(ReflectionError::InvalidHeader)));
+    }
+}
@@ -1,37 +1,187 @@
-use std::{cell::RefCell, iter, rc::Rc};
-use std::sync::{Arc, Mutex};
+use std::{iter, sync::Arc, time::Instant};
 use winit::dpi::{LogicalPosition, LogicalSize};
 use winit::window::Window;
-use super::{Material, CmdBuffer, CmdPool, CmdState, Device, Framebuffer, FramebufferBuilder, Instance, Pipeline,
-            PipelineBuilder, RenderPass, RenderPassBuilder, Swapchain, Queue};
+use super::{Material, CmdBuffer, CmdPool, Device, Framebuffer, FramebufferBuilder, Instance, Pipeline,
+            ParticleSystem, PipelineBuilder, PipelineManager, RenderPass, RenderPassBuilder, Swapchain, Queue, Vertex};
+use super::bindless::BindlessHeap;
+use super::descriptors::DescriptorAllocator;
+use super::graph::{GraphResources, ImageDesc, RenderGraph};
+use super::image::ImageData;
+use super::material::{MaterialError, MaterialInstance, TexturedMaterial};
+use super::mesh::{Mesh, MeshData};
+use super::parallel::ParallelRecorder;
+use super::ring_buffer::RingBuffer;
+use super::sampler_cache::SamplerDesc;
+use super::sync::ImageAccess;
+use super::texture::{Texture, TextureOptions};
+use ash::vk;
+use nalgebra::{Matrix4, Vector2, Vector3, Vector4};
+#[cfg(feature = "shader-compiler")]
+use std::{path::Path, time::Duration};
+#[cfg(feature = "shader-compiler")]
+use super::{pipeline_manager::PipelineHandle,
+            shader_compiler::ShaderCompiler,
+            shader_watcher::{rebuild_changed, ShaderWatcher}};
 use crate::util::CapturedEvent;
 
+/// Directory the shader sources are compiled and reloaded from.
+#[cfg(feature = "shader-compiler")]
+const SHADER_DIR : &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/assets/shaders");
+
+/// The number of textures and storage buffers the bindless heap is created for, if the device supports it. Bindless
+/// shaders declare their texture arrays with this many elements.
+const BINDLESS_TEXTURES : u32 = 4096;
+const BINDLESS_BUFFERS : u32 = 1024;
+
+/// The draws of the scene pass, which are recorded into secondary command buffers in parallel.
+#[derive(Clone, Copy)]
+enum SceneDraw {
+    Triangle,
+    Particles,
+    BindlessQuads,
+}
+
+/// A quad in each corner of the screen, each with its own instance of a bindless textured material. The instances
+/// share the pipeline and the heap, so those are bound once and each quad only binds its parameter block and pushes its
+/// transform and texture handle.
+struct BindlessQuads {
+    mesh : Mesh,
+    materials : Vec<MaterialInstance>,
+    ring_buffer : RingBuffer,
+    /// Kept alive for the descriptor sets of the materials.
+    _allocator : DescriptorAllocator,
+}
+
+impl BindlessQuads {
+    /// The color of each quad's texture, which is a checkerboard of the color and white.
+    const COLORS : [[u8; 4]; 4] = [[230, 60, 60, 255], [60, 200, 90, 255], [60, 110, 230, 255], [240, 200, 50, 255]];
+
+    fn new(device : Arc<Device>,
+           transfer_queue : &Queue,
+           graphics_queue : &Queue,
+           render_pass : &RenderPass,
+           heap : &mut BindlessHeap) -> Result<Self, MaterialError> {
+        // Instances share their template through an `Arc`, even though templates stay on one thread.
+        #[allow(clippy::arc_with_non_send_sync)]
+        let template = Arc::new(TexturedMaterial::bindless_template(Arc::clone(&device), render_pass, heap)?);
+        let mut allocator = DescriptorAllocator::new(Arc::clone(&device));
+        let ring_buffer = RingBuffer::new(Arc::clone(&device), 64 * 1024, vk::BufferUsageFlags::UNIFORM_BUFFER)
+            .expect("Failed to create the ring buffer of the bindless quads");
+
+        let mut materials = Vec::with_capacity(Self::COLORS.len());
+        for color in Self::COLORS.iter() {
+            let white = [255; 4];
+            let data = ImageData {
+                width: 2,
+                height: 2,
+                format: vk::Format::R8G8B8A8_UNORM,
+                levels: vec![[*color, white, white, *color].concat()],
+            };
+            let options = TextureOptions {
+                generate_mipmaps: false,
+                sampler: SamplerDesc::nearest(),
+                ..TextureOptions::default()
+            };
+            let texture = Texture::from_image_data(Arc::clone(&device), transfer_queue, graphics_queue, data, options)?;
+            let mut material = MaterialInstance::new(Arc::clone(&template), &mut allocator, &ring_buffer);
+            material.set_bindless_texture(TexturedMaterial::ALBEDO, Arc::new(texture), heap)?;
+            materials.push(material);
+        }
+
+        let white = Vector4::new(1.0, 1.0, 1.0, 1.0);
+        let corners = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)];
+        let vertices = corners
+            .iter()
+            .map(|&(x, y)| Vertex::new(Vector3::new(x, y, 0.0), white, Vector2::new((x + 1.0) / 2.0, (y + 1.0) / 2.0)))
+            .collect();
+        let mut data = MeshData { vertices, ..MeshData::default() };
+        data.push_submesh(&[0, 1, 2, 2, 3, 0], None);
+        let mesh = Mesh::new(device, transfer_queue, graphics_queue, &data)
+            .expect("Failed to create the mesh of the bindless quads");
+        Ok(Self { mesh, materials, ring_buffer, _allocator: allocator })
+    }
+
+    /// Writes the parameters of each quad for this frame, returning their dynamic offsets.
+    fn write_parameters(&mut self) -> Result<Vec<Vec<u32>>, MaterialError> {
+        let ring_buffer = &mut self.ring_buffer;
+        self.materials.iter().map(|material| material.write_parameters(ring_buffer)).collect()
+    }
+
+    /// Draws the quads spinning by `rotation`, with the dynamic offsets returned by `write_parameters`.
+    fn draw(&self, cmd_buffer : &mut CmdBuffer, rotation : f32, dynamic_offsets : &[Vec<u32>])
+        -> Result<(), MaterialError> {
+        let template = self.materials[0].template();
+        template.bind(cmd_buffer)?;
+        self.mesh.bind(cmd_buffer)?;
+        let corners = [(-0.7, -0.7), (0.7, -0.7), (0.7, 0.7), (-0.7, 0.7)];
+        for ((material, offsets), (x, y)) in self.materials.iter().zip(dynamic_offsets).zip(corners.iter()) {
+            let transform = Matrix4::new_translation(&Vector3::new(*x, *y, 0.0))
+                * Matrix4::new_rotation(Vector3::z() * -rotation)
+                * Matrix4::new_scaling(0.2);
+            material.bind_parameters(cmd_buffer, offsets)?;
+            cmd_buffer.push_constants(template.pipeline(), template.push_constant_stages(), 0, &transform)?;
+            self.mesh.draw_submesh(cmd_buffer, 0)?;
+        }
+        Ok(())
+    }
+}
+
 /// The highest level of the graphics module, the `Renderer` manages all render state.
 pub struct Renderer {
-    instance : Option<Rc<RefCell<Instance>>>,
-    device : Option<Rc<RefCell<Device>>>,
-    compute_queue : Option<Rc<RefCell<Queue>>>,
-    graphics_queue : Option<Rc<RefCell<Queue>>>,
-    transfer_queue : Option<Rc<RefCell<Queue>>>,
+    instance : Option<Arc<Instance>>,
+    device : Option<Arc<Device>>,
+    compute_queue : Option<Arc<Queue>>,
+    graphics_queue : Option<Arc<Queue>>,
+    transfer_queue : Option<Arc<Queue>>,
     swapchain : Option<Swapchain>,
-    render_pass: Option<Rc<RefCell<RenderPass>>>,
-    colored_graphics_pipeline : Option<Pipeline>,
+    render_pass: Option<Arc<RenderPass>>,
+    pipelines : Option<PipelineManager>,
+    colored_graphics_pipeline : Option<Arc<Pipeline>>,
     framebuffers : Option<Vec<Framebuffer>>,
-    graphics_pool : Option<Rc<RefCell<CmdPool>>>,
+    /// The physical resources of the frame graph, which only change when the swapchain is recreated.
+    graph_resources : Option<GraphResources>,
+    graphics_pool : Option<Arc<CmdPool>>,
     graphics_buffer : Option<CmdBuffer>,
-    material : Option<Material>,
+    recorder : Option<ParallelRecorder>,
+    material : Option<Arc<Material>>,
+    particles : Option<ParticleSystem>,
+    bindless : Option<BindlessHeap>,
+    /// Quads drawn through the bindless heap, if the device supports it.
+    bindless_quads : Option<BindlessQuads>,
+    last_frame : Instant,
+    /// Rotation of the triangle about the view axis in radians, which is pushed as its transform.
+    rotation : f32,
+    #[cfg(feature = "shader-compiler")]
+    shader_compiler : ShaderCompiler,
+    #[cfg(feature = "shader-compiler")]
+    shader_watcher : ShaderWatcher,
+    /// The pipeline of recompiled shaders while it is being built, along with their material.
+    #[cfg(feature = "shader-compiler")]
+    reloaded : Option<(PipelineHandle, Arc<Material>)>,
 }
 
 impl Drop for Renderer {
     fn drop(&mut self) {
+        self.graph_resources.take();
+        debug_assert!(self.graph_resources.is_none());
+        self.bindless_quads.take();
+        debug_assert!(self.bindless_quads.is_none());
+        self.bindless.take();
+        debug_assert!(self.bindless.is_none());
+        self.particles.take();
+        debug_assert!(self.particles.is_none());
         self.material.take();
         debug_assert!(self.material.is_none());
         self.graphics_buffer.take();
         debug_assert!(self.graphics_buffer.is_none());
+        self.recorder.take();
+        debug_assert!(self.recorder.is_none());
         self.graphics_pool.take();
         debug_assert!(self.graphics_pool.is_none());
         self.framebuffers.take();
         debug_assert!(self.framebuffers.is_none());
+        self.pipelines.take();
+        debug_assert!(self.pipelines.is_none());
         self.colored_graphics_pipeline.take();
         debug_assert!(self.colored_graphics_pipeline.is_none());
         self.render_pass.take();
@@ -56,11 +206,12 @@ impl CapturedEvent for Renderer {
     /// When this event is captured, the swapchain is recreated, and regenerates all framebuffers from the swapchain images.
     fn on_resize(&mut self, _size : LogicalSize<f32>) {
         self.swapchain.as_mut().unwrap().recreate();
+        self.graph_resources.take();
         self.framebuffers.as_mut().unwrap().clear();
         for image in self.swapchain.as_ref().unwrap().images() {
             self.framebuffers.as_mut().unwrap().push(FramebufferBuilder::new(
-                Rc::clone(&self.device.clone().unwrap()),
-                Rc::clone(&self.render_pass.clone().unwrap()),
+                Arc::clone(&self.device.clone().unwrap()),
+                Arc::clone(&self.render_pass.clone().unwrap()),
                 image,
                 self.swapchain.as_ref().unwrap().surface_format().format,
                 self.swapchain.as_ref().unwrap().capabilities().current_extent)
@@ -75,63 +226,103 @@ impl Renderer {
         info!("Initializing Renderer.");
         // TODO: Properly handle errors here and present them to the output.
 
-        let instance = Rc::new(RefCell::new(Instance::new()
+        let instance = Arc::new(Instance::new()
             .ok()
-            .unwrap()));
+            .unwrap());
 
-        let device = Rc::new(RefCell::new(Device::new(&instance.borrow())
+        let device = Arc::new(Device::new(&instance)
             .ok()
-            .unwrap()));
+            .unwrap());
 
         // Create our queues.
-        let compute_queue = Rc::new(RefCell::new(Queue::new(
-            Rc::clone(&device),
-            device.borrow().compute_queue_index())));
-        let graphics_queue = Rc::new(RefCell::new(Queue::new(
-            Rc::clone(&device),
-            device.borrow().graphics_queue_index())));
-        let transfer_queue = Rc::new(RefCell::new(Queue::new(
-            Rc::clone(&device),
-            device.borrow().transfer_queue_index())));
+        let compute_queue = Arc::new(Queue::new(
+            Arc::clone(&device),
+            device.compute_queue_index()));
+        let graphics_queue = Arc::new(Queue::new(
+            Arc::clone(&device),
+            device.graphics_queue_index()));
+        let transfer_queue = Arc::new(Queue::new(
+            Arc::clone(&device),
+            device.transfer_queue_index()));
 
         // Create the swapchain.
         let swapchain = Swapchain::new(
-            Rc::clone(&instance),
-            Rc::clone(&device),
-            Rc::clone(&graphics_queue),
+            Arc::clone(&instance),
+            Arc::clone(&device),
+            Arc::clone(&graphics_queue),
             window,
             2).ok()
             .unwrap();
 
-        let render_pass = Rc::new(RefCell::new(RenderPassBuilder::new(
-            Rc::clone(&device))
-            .add_color_attachment(swapchain.surface_format().format)
-            .build()));
+        // The frame graph transitions the swapchain images for rendering and presentation.
+        let render_pass = Arc::new(RenderPassBuilder::new(
+            Arc::clone(&device))
+            .add_color_attachment_in_layout(
+                swapchain.surface_format().format,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .build());
 
-        let material = Material::new(Rc::clone(&device));
+        #[cfg(feature = "shader-compiler")]
+        let shader_compiler = ShaderCompiler::new().include_dir(SHADER_DIR);
+        #[cfg(feature = "shader-compiler")]
+        let material = Material::from_glsl(
+            Arc::clone(&device),
+            &shader_compiler,
+            &Path::new(SHADER_DIR).join("default.vert"),
+            &Path::new(SHADER_DIR).join("default.frag"))
+            .unwrap_or_else(|error| {
+                error!("Failed to compile shaders, using the precompiled shaders instead:\n{}", error);
+                Material::new(Arc::clone(&device))
+            });
+        #[cfg(not(feature = "shader-compiler"))]
+        let material = Material::new(Arc::clone(&device));
 
-        let colored_graphics_pipeline = PipelineBuilder::new(Rc::clone(&device))
-            .build_graphics(&render_pass.borrow(), &material, swapchain.capabilities().current_extent);
+        let material = Arc::new(material);
+        let pipelines = PipelineManager::new();
+        let colored_graphics_pipeline = pipelines
+            .graphics(PipelineBuilder::new(Arc::clone(&device)), &render_pass, &material)
+            .expect("Failed to create graphics pipeline");
 
         // Grab the swapchain images to create the framebuffers.
         let mut framebuffers = Vec::<Framebuffer>::new();
         for image in swapchain.images() {
             framebuffers.push(FramebufferBuilder::new(
-                Rc::clone(&device),
-                Rc::clone(&render_pass),
+                Arc::clone(&device),
+                Arc::clone(&render_pass),
                 image,
                 swapchain.surface_format().format,
                 swapchain.capabilities().current_extent
             ).build());
         }
 
-        let graphics_pool = Rc::new(RefCell::new(CmdPool::new(
-            Rc::clone(&device),
-            &graphics_queue.borrow())));
+        let graphics_pool = Arc::new(CmdPool::new(
+            Arc::clone(&device),
+            &graphics_queue));
 
         let graphics_buffer = CmdBuffer::new(
-            Rc::clone(&device),
-            Rc::clone(&graphics_pool));
+            Arc::clone(&device),
+            Arc::clone(&graphics_pool));
+
+        let recorder = ParallelRecorder::new(Arc::clone(&device), &graphics_queue);
+
+        let particles = ParticleSystem::new(
+            Arc::clone(&device),
+            &compute_queue,
+            &graphics_queue,
+            &render_pass,
+            4096);
+
+        let mut bindless = BindlessHeap::new(Arc::clone(&device), BINDLESS_TEXTURES, BINDLESS_BUFFERS);
+        if bindless.is_none() {
+            info!("Descriptor indexing is not supported, bindless resources are disabled");
+        }
+        // The shader of the quads declares as many textures as the heap is created for.
+        let full_heap = bindless.as_mut().filter(|heap| heap.texture_capacity() == BINDLESS_TEXTURES);
+        let bindless_quads = full_heap.and_then(|heap| {
+            BindlessQuads::new(Arc::clone(&device), &transfer_queue, &graphics_queue, &render_pass, heap)
+                .map_err(|error| error!("Failed to create the bindless quads: {}", error))
+                .ok()
+        });
 
         info!("Renderer has been initialized.");
         Self {
@@ -142,38 +333,176 @@ impl Renderer {
             transfer_queue: Some(transfer_queue),
             swapchain: Some(swapchain),
             render_pass: Some(render_pass),
+            pipelines: Some(pipelines),
             colored_graphics_pipeline : Some(colored_graphics_pipeline),
             framebuffers: Some(framebuffers),
+            graph_resources: None,
             graphics_pool: Some(graphics_pool),
             graphics_buffer: Some(graphics_buffer),
-            material: Some(material)
+            recorder: Some(recorder),
+            material: Some(material),
+            particles: Some(particles),
+            bindless,
+            bindless_quads,
+            last_frame: Instant::now(),
+            rotation: 0.0,
+            #[cfg(feature = "shader-compiler")]
+            shader_compiler,
+            #[cfg(feature = "shader-compiler")]
+            shader_watcher: ShaderWatcher::new(SHADER_DIR, Duration::from_millis(500)),
+            #[cfg(feature = "shader-compiler")]
+            reloaded: None,
+        }
+    }
+
+    /// Returns the heap of textures and buffers shared by bindless materials, if the device supports descriptor
+    /// indexing.
+    pub fn bindless_heap(&mut self) -> Option<&mut BindlessHeap> {
+        self.bindless.as_mut()
+    }
+
+    /// Recompiles the material if any of its shader sources changed, and rebuilds its pipeline in the background. The
+    /// current pipeline is used until the new one is ready, and the replaced objects are destroyed once the frames
+    /// using them have retired. If compilation fails the error is logged and the last good shaders are kept.
+    #[cfg(feature = "shader-compiler")]
+    fn reload_shaders(&mut self) {
+        self.swap_reloaded_pipeline();
+        let changed = self.shader_watcher.poll();
+        let material = self.material.as_ref().unwrap();
+        let compiler = &self.shader_compiler;
+        if let Some(material) = rebuild_changed(&changed, material.dependencies(), || material.recompile(compiler)) {
+            let material = Arc::new(material);
+            let handle = self.pipelines.as_ref().unwrap().graphics_async(
+                PipelineBuilder::new(Arc::clone(self.device.as_ref().unwrap())),
+                self.render_pass.as_ref().unwrap(),
+                &material);
+            self.reloaded = Some((handle, material));
+        }
+    }
+
+    /// Starts using the pipeline of the reloaded shaders once it has been built.
+    #[cfg(feature = "shader-compiler")]
+    fn swap_reloaded_pipeline(&mut self) {
+        let result = match self.reloaded.as_ref() {
+            Some((handle, _)) => handle.try_get(),
+            None => return,
+        };
+        match result {
+            Some(Ok(pipeline)) => {
+                let (_, material) = self.reloaded.take().unwrap();
+                // The manager still holds the pipelines of earlier versions of the shaders.
+                self.pipelines.as_ref().unwrap().clear();
+                self.colored_graphics_pipeline = Some(pipeline);
+                self.material = Some(material);
+                info!("Reloaded shaders");
+            }
+            Some(Err(error)) => {
+                self.reloaded = None;
+                error!("Failed to rebuild the pipeline, keeping the last good version:\n{}", error);
+            }
+            None => (),
         }
     }
 
     pub fn draw_frame(&mut self) {
-        let next_image = &self.swapchain.as_mut().unwrap().acquire_next_image();
-        let cmd_state = CmdState {
-            format: self.swapchain.as_ref().unwrap().surface_format().format,
-            extent: self.swapchain.as_ref().unwrap().capabilities().current_extent
+        #[cfg(feature = "shader-compiler")]
+        self.reload_shaders();
+
+        let now = Instant::now();
+        let delta = now.duration_since(self.last_frame).as_secs_f32();
+        self.last_frame = now;
+        self.rotation = (self.rotation + delta) % (2.0 * std::f32::consts::PI);
+        let transform = Matrix4::new_rotation(Vector3::z() * self.rotation);
+
+        // The graphics commands were last submitted with the current acquire fence, which the swapchain waits on and
+        // resets when the next image is acquired, so they are reset before that.
+        let fence = self.swapchain.as_ref().unwrap().current_acquire_fence();
+        self.graphics_buffer.as_mut().unwrap().reset_after(fence);
+
+        // Particles are simulated on the compute queue while the image is being acquired.
+        self.particles.as_mut().unwrap().update(self.compute_queue.as_ref().unwrap(), delta);
+        let particles = self.particles.as_ref().unwrap();
+
+        let quad_offsets = match self.bindless_quads.as_mut() {
+            Some(quads) => quads.write_parameters().expect("Failed to write the parameters of the bindless quads"),
+            None => Vec::new(),
         };
+        let quads = self.bindless_quads.as_ref();
+        let rotation = self.rotation;
+
+        let next_image = self.swapchain.as_mut().unwrap().acquire_next_image();
+        let swapchain = self.swapchain.as_ref().unwrap();
+        let extent = swapchain.capabilities().current_extent;
+        let image = swapchain.images()[next_image as usize];
+        let render_pass = self.render_pass.as_ref().unwrap();
+        let framebuffer = self.framebuffers.as_ref().unwrap().get(next_image as usize).unwrap();
+        let pipeline = self.colored_graphics_pipeline.as_ref().unwrap();
+        let recorder = self.recorder.as_mut().unwrap();
+
+        // The acquired image is only written once the acquire semaphore has been waited on, and is presented after.
+        let mut graph = RenderGraph::new();
+        let desc = ImageDesc::new(swapchain.surface_format().format, extent);
+        let backbuffer = graph.import_image("backbuffer", desc, None);
+        graph.wait_for_stages(backbuffer, vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT);
+        graph.export_image(backbuffer, Some(ImageAccess::Present));
+        graph.add_pass("scene")
+            .write_image(backbuffer, ImageAccess::ColorAttachmentWrite)
+            .execute(|context| {
+                let cmd_buffer = &mut *context.cmd_buffer;
+                let clear_values = [
+                    vk::ClearValue { color: vk::ClearColorValue { float32: [0.39, 0.58, 0.94, 1.0] } }];
+                let draws = [SceneDraw::Triangle, SceneDraw::BindlessQuads, SceneDraw::Particles];
+                let record = |cmd_buffer : &mut CmdBuffer, draws : &[SceneDraw]| {
+                    cmd_buffer.set_viewport_extent(extent)?;
+                    for draw in draws {
+                        match draw {
+                            SceneDraw::Triangle => {
+                                cmd_buffer.bind_pipeline(pipeline)?;
+                                cmd_buffer.push_constants(pipeline, vk::ShaderStageFlags::VERTEX, 0, &transform)?;
+                                cmd_buffer.draw(3, 1, 0, 0)?;
+                            }
+                            SceneDraw::Particles => particles.draw(cmd_buffer)?,
+                            SceneDraw::BindlessQuads => if let Some(quads) = quads {
+                                quads.draw(cmd_buffer, rotation, &quad_offsets).map_err(|error| match error {
+                                    MaterialError::Recording(error) => error,
+                                    error => panic!("Failed to draw the bindless quads: {}", error),
+                                })?;
+                            },
+                        }
+                    }
+                    Ok(())
+                };
+                let secondary_buffers = recorder.record_secondary(render_pass, 0, framebuffer, &draws, record)?;
+                cmd_buffer.begin_render_pass_secondary(render_pass, framebuffer, extent, &clear_values)?;
+                cmd_buffer.execute_commands(&secondary_buffers)?;
+                cmd_buffer.end_render_pass()
+            });
+        let compiled = graph.compile().expect("Failed to compile the frame graph");
+        let device = self.device.as_ref().unwrap();
+        let graph_resources = self.graph_resources.get_or_insert_with(|| {
+            GraphResources::new(Arc::clone(device), &compiled).expect("Failed to create the frame graph resources")
+        });
+        graph_resources.bind_image(backbuffer, image, framebuffer.color_view_raw());
+
+        let cmd_buffer = self.graphics_buffer.as_mut().unwrap();
+        cmd_buffer.begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)
+            .expect("Failed to begin graphics commands");
+        graph.execute(&compiled, cmd_buffer, graph_resources)
+            .expect("Failed to record the frame graph");
+        cmd_buffer.end()
+            .expect("Failed to record graphics commands");
 
-        self.graphics_buffer
-            .as_mut()
-            .unwrap()
-            .record_graphics(
-                cmd_state,
-                &self.render_pass.as_ref().unwrap().borrow(),
-                self.framebuffers.as_ref().unwrap().get(next_image.clone() as usize).unwrap(),
-                self.colored_graphics_pipeline.as_ref().unwrap());
-
-        // Queue needs to submit our draw calls, but has to wait for the image to be acquired.
-        self.graphics_queue
-            .as_ref()
-            .unwrap()
-            .borrow()
-            .submit(self.graphics_buffer.as_ref().unwrap(),
-                    Some(self.swapchain.as_ref().unwrap().current_acquire_semaphore()),
-                    Some(self.swapchain.as_ref().unwrap().current_acquire_fence()));
-        self.swapchain.as_ref().unwrap().present();
+        // Queue needs to submit our draw calls, but has to wait for the image to be acquired and the particles to be
+        // updated.
+        let graphics_queue = self.graphics_queue.as_ref().unwrap();
+        let swapchain = self.swapchain.as_ref().unwrap();
+        graphics_queue.submit_with(
+            &[cmd_buffer],
+            &[(swapchain.current_acquire_semaphore(), vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT),
+              (particles.update_semaphore(), vk::PipelineStageFlags::VERTEX_SHADER)],
+            &[graphics_queue.submit_semaphore_raw(), particles.draw_semaphore()],
+            Some(swapchain.current_acquire_fence()));
+        swapchain.present();
+        self.device.as_ref().unwrap().advance_frame();
     }
 }
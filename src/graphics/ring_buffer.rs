@@ -0,0 +1,212 @@
+use std::{collections::VecDeque, mem::size_of, ptr, slice, sync::Arc};
+use ash::version::DeviceV1_0;
+use ash::vk;
+use super::Device;
+use super::buffer::{Buffer, BufferCreationError};
+
+/// Provides a brief overview of why an allocation could not be made from a `RingBuffer`.
+#[derive(Debug)]
+pub enum RingBufferError {
+    /// The allocation is larger than the whole ring.
+    TooLarge(vk::DeviceSize),
+    /// Frames which have not retired yet are using the space, so the ring needs to be larger.
+    Full(vk::DeviceSize),
+}
+
+/// A region of a `RingBuffer` which has been written to.
+#[derive(Clone, Copy, Debug)]
+pub struct RingAllocation {
+    pub offset : vk::DeviceSize,
+    pub size : vk::DeviceSize,
+}
+
+impl RingAllocation {
+    /// Returns the offset to pass as a dynamic offset when binding a descriptor set which refers to the ring buffer
+    /// from offset 0.
+    pub fn dynamic_offset(&self) -> u32 {
+        self.offset as u32
+    }
+}
+
+/// A persistently mapped buffer for data which changes every frame, such as camera matrices, lights and per-object
+/// data. Allocations are made one after another and wrap around to the start of the buffer, and the space used by a
+/// frame is reclaimed once that frame has retired.
+///
+/// Allocations are aligned to the device's minimum offset alignment, so they can be bound with dynamic descriptor
+/// offsets. Describe the ring with `DescriptorWriter::uniform_buffer_dynamic` at offset 0 with the range of the block,
+/// then bind with `RingAllocation::dynamic_offset`. Use `allocate_in_range` for data smaller than the range.
+pub struct RingBuffer {
+    device : Arc<Device>,
+    buffer : Buffer,
+    mapped : *mut u8,
+    size : vk::DeviceSize,
+    alignment : vk::DeviceSize,
+    /// Total bytes allocated since creation. The next allocation starts at `head % size`.
+    head : vk::DeviceSize,
+    /// Total bytes reclaimed since creation. Everything between the tail and the head is in use.
+    tail : vk::DeviceSize,
+    /// Each frame which allocated from the ring and has not retired yet, along with the head when it began.
+    frames : VecDeque<(u64, vk::DeviceSize)>,
+}
+
+// The mapped pointer is only written through `&mut self`.
+unsafe impl Send for RingBuffer {}
+unsafe impl Sync for RingBuffer {}
+
+impl RingBuffer {
+    /// Creates a ring of `size` bytes in host visible memory with the given usage, which should include
+    /// `UNIFORM_BUFFER`, `STORAGE_BUFFER` or both. The size is rounded up to the alignment, so allocations stay aligned
+    /// after wrapping around.
+    pub fn new(device : Arc<Device>, size : vk::DeviceSize, usage : vk::BufferUsageFlags)
+        -> Result<Self, BufferCreationError> {
+        let limits = device.limits();
+        let mut alignment = 4;
+        if usage.contains(vk::BufferUsageFlags::UNIFORM_BUFFER) {
+            alignment = alignment.max(limits.min_uniform_buffer_offset_alignment);
+        }
+        if usage.contains(vk::BufferUsageFlags::STORAGE_BUFFER) {
+            alignment = alignment.max(limits.min_storage_buffer_offset_alignment);
+        }
+        let size = align_up(size, alignment);
+
+        let buffer = Buffer::new(
+            Arc::clone(&device),
+            size,
+            usage,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            &[])?;
+        // Freeing the memory unmaps it, so the mapping lives as long as the buffer.
+        let mapped = unsafe {
+            device
+                .ash_device()
+                .map_memory(buffer.memory_raw(), 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty())
+                .expect("Failed to map ring buffer memory") as *mut u8
+        };
+        Ok(Self { device, buffer, mapped, size, alignment, head: 0, tail: 0, frames: VecDeque::new() })
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+
+    pub fn size(&self) -> vk::DeviceSize {
+        self.size
+    }
+
+    /// Returns the alignment of every allocation.
+    pub fn alignment(&self) -> vk::DeviceSize {
+        self.alignment
+    }
+
+    /// Copies `value` into the ring. `T` should be `#[repr(C)]` and match the layout of the shader's block.
+    pub fn write<T : Copy>(&mut self, value : &T) -> Result<RingAllocation, RingBufferError> {
+        let bytes = unsafe { slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) };
+        self.write_bytes(bytes)
+    }
+
+    /// Copies `data` into the ring, valid until the current frame has retired.
+    pub fn write_bytes(&mut self, data : &[u8]) -> Result<RingAllocation, RingBufferError> {
+        let allocation = self.allocate(data.len() as vk::DeviceSize)?;
+        unsafe {
+            ptr::copy_nonoverlapping(data.as_ptr(), self.mapped.add(allocation.offset as usize), data.len());
+        }
+        Ok(allocation)
+    }
+
+    /// Reserves `size` bytes without writing to them.
+    pub fn allocate(&mut self, size : vk::DeviceSize) -> Result<RingAllocation, RingBufferError> {
+        self.allocate_in_range(size, size)
+    }
+
+    /// Reserves `size` bytes which are bound through a dynamic descriptor of `range` bytes. Enough space is kept after
+    /// the allocation for the whole range to fit before the end of the ring.
+    pub fn allocate_in_range(&mut self, size : vk::DeviceSize, range : vk::DeviceSize)
+        -> Result<RingAllocation, RingBufferError> {
+        let reserved = size.max(range);
+        if reserved > self.size {
+            return Err(RingBufferError::TooLarge(reserved));
+        }
+        self.reclaim();
+
+        let frame = self.device.current_frame();
+        if self.frames.back().map(|(begun, _)| *begun) != Some(frame) {
+            self.frames.push_back((frame, self.head));
+        }
+
+        let start = place(self.head, self.tail, self.size, self.alignment, reserved)
+            .ok_or(RingBufferError::Full(size))?;
+        self.head = start + reserved;
+        Ok(RingAllocation { offset: start % self.size, size })
+    }
+
+    /// Reclaims the space used by frames which have retired.
+    fn reclaim(&mut self) {
+        let retired = match self.device.retired_frame() {
+            Some(retired) => retired,
+            None => return,
+        };
+        while let Some(&(frame, _)) = self.frames.front() {
+            if frame > retired {
+                break;
+            }
+            self.frames.pop_front();
+        }
+        self.tail = self.frames.front().map_or(self.head, |(_, begun)| *begun);
+    }
+}
+
+/// Returns where an allocation of `size` bytes starts in the ever increasing space of the ring, or `None` if it would
+/// overlap the space in use from `tail`. The start is aligned, and skips to the start of the ring if the allocation
+/// would run past its end. `ring_size` must be a multiple of `alignment`.
+fn place(head : vk::DeviceSize,
+         tail : vk::DeviceSize,
+         ring_size : vk::DeviceSize,
+         alignment : vk::DeviceSize,
+         size : vk::DeviceSize) -> Option<vk::DeviceSize> {
+    let mut start = align_up(head, alignment);
+    let offset = start % ring_size;
+    if offset + size > ring_size {
+        start += ring_size - offset;
+    }
+    if start + size - tail > ring_size {
+        return None;
+    }
+    Some(start)
+}
+
+fn align_up(value : vk::DeviceSize, alignment : vk::DeviceSize) -> vk::DeviceSize {
+    value.div_ceil(alignment) * alignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stays_aligned_after_wrapping() {
+        let ring_size = align_up(1000, 256);
+        assert_eq!(ring_size, 1024);
+        let mut head = 0;
+        let mut tail = 0;
+        for _ in 0..20 {
+            let start = place(head, tail, ring_size, 256, 300).unwrap();
+            assert_eq!(start % ring_size % 256, 0);
+            assert!(start % ring_size + 300 <= ring_size);
+            head = start + 300;
+            tail = start;
+        }
+    }
+
+    #[test]
+    fn wraps_when_the_end_is_too_small() {
+        assert_eq!(place(700, 700, 1024, 256, 300), Some(1024));
+        assert_eq!(place(700, 700, 1024, 256, 256), Some(768));
+    }
+
+    #[test]
+    fn rejects_allocations_over_space_in_use() {
+        assert_eq!(place(768, 0, 1024, 256, 256), Some(768));
+        assert_eq!(place(1024, 0, 1024, 256, 256), None);
+        assert_eq!(place(700, 200, 1024, 256, 300), None);
+    }
+}
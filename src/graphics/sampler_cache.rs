@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use ash::version::DeviceV1_0;
+use ash::vk;
+
+/// Describes a sampler. Samplers with equal descriptions are interchangeable, so the description is used as the key of
+/// the `SamplerCache`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SamplerDesc {
+    pub mag_filter : vk::Filter,
+    pub min_filter : vk::Filter,
+    pub mipmap_mode : vk::SamplerMipmapMode,
+    pub address_mode_u : vk::SamplerAddressMode,
+    pub address_mode_v : vk::SamplerAddressMode,
+    pub address_mode_w : vk::SamplerAddressMode,
+    /// The maximum anisotropy, which is clamped to the device limit. Anisotropic filtering is disabled when this is 1
+    /// or less.
+    pub max_anisotropy : u32,
+    /// Enables depth comparison with this operation, for sampling shadow maps.
+    pub compare_op : Option<vk::CompareOp>,
+    /// The color returned outside the image when an address mode is `CLAMP_TO_BORDER`.
+    pub border_color : vk::BorderColor,
+}
+
+impl Default for SamplerDesc {
+    /// Trilinear filtering with repeating addressing and no anisotropy.
+    fn default() -> Self {
+        Self {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            address_mode_u: vk::SamplerAddressMode::REPEAT,
+            address_mode_v: vk::SamplerAddressMode::REPEAT,
+            address_mode_w: vk::SamplerAddressMode::REPEAT,
+            max_anisotropy: 1,
+            compare_op: None,
+            border_color: vk::BorderColor::FLOAT_TRANSPARENT_BLACK,
+        }
+    }
+}
+
+impl SamplerDesc {
+    /// Nearest filtering between texels and mip levels, for pixel art and lookup tables.
+    pub fn nearest() -> Self {
+        Self {
+            mag_filter: vk::Filter::NEAREST,
+            min_filter: vk::Filter::NEAREST,
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+            ..Default::default()
+        }
+    }
+
+    /// Linear filtering which clamps coordinates to the edge, with a depth comparison, for sampling shadow maps.
+    pub fn shadow(compare_op : vk::CompareOp) -> Self {
+        Self::default()
+            .address_mode(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .compare_op(compare_op)
+    }
+
+    pub fn filter(mut self, filter : vk::Filter) -> Self {
+        self.mag_filter = filter;
+        self.min_filter = filter;
+        self
+    }
+
+    pub fn mipmap_mode(mut self, mipmap_mode : vk::SamplerMipmapMode) -> Self {
+        self.mipmap_mode = mipmap_mode;
+        self
+    }
+
+    /// Sets the address mode of every coordinate.
+    pub fn address_mode(mut self, address_mode : vk::SamplerAddressMode) -> Self {
+        self.address_mode_u = address_mode;
+        self.address_mode_v = address_mode;
+        self.address_mode_w = address_mode;
+        self
+    }
+
+    pub fn anisotropy(mut self, max_anisotropy : u32) -> Self {
+        self.max_anisotropy = max_anisotropy;
+        self
+    }
+
+    pub fn compare_op(mut self, compare_op : vk::CompareOp) -> Self {
+        self.compare_op = Some(compare_op);
+        self
+    }
+
+    pub fn border_color(mut self, border_color : vk::BorderColor) -> Self {
+        self.border_color = border_color;
+        self
+    }
+
+    /// Clamps the anisotropy to what the device supports, so descriptions which only differ beyond the limit share a
+    /// sampler.
+    fn clamped(mut self, limits : &vk::PhysicalDeviceLimits) -> Self {
+        self.max_anisotropy = self.max_anisotropy.clamp(1, (limits.max_sampler_anisotropy as u32).max(1));
+        self
+    }
+}
+
+/// Creates each distinct sampler once and hands out the same handle for every request with an equal description.
+/// Devices limit how many samplers may exist at once through `maxSamplerAllocationCount`, which can be as low as 4000,
+/// so sharing them keeps textures and materials from running into it.
+///
+/// The samplers live as long as the `Device` which owns the cache, so the returned handles never need destroying.
+pub struct SamplerCache {
+    samplers : Mutex<HashMap<SamplerDesc, vk::Sampler>>,
+}
+
+impl Default for SamplerCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SamplerCache {
+    pub fn new() -> Self {
+        Self { samplers: Mutex::new(HashMap::new()) }
+    }
+
+    /// Returns the sampler for `desc`, creating it if no equal sampler exists yet.
+    pub fn get(&self, device : &ash::Device, limits : &vk::PhysicalDeviceLimits, desc : &SamplerDesc) -> vk::Sampler {
+        let desc = desc.clamped(limits);
+        let mut samplers = self.samplers.lock().unwrap();
+        *samplers.entry(desc).or_insert_with(|| {
+            let sampler_info = vk::SamplerCreateInfo::builder()
+                .mag_filter(desc.mag_filter)
+                .min_filter(desc.min_filter)
+                .mipmap_mode(desc.mipmap_mode)
+                .address_mode_u(desc.address_mode_u)
+                .address_mode_v(desc.address_mode_v)
+                .address_mode_w(desc.address_mode_w)
+                .anisotropy_enable(desc.max_anisotropy > 1)
+                .max_anisotropy(desc.max_anisotropy as f32)
+                .compare_enable(desc.compare_op.is_some())
+                .compare_op(desc.compare_op.unwrap_or(vk::CompareOp::ALWAYS))
+                .min_lod(0.0)
+                .max_lod(vk::LOD_CLAMP_NONE)
+                .border_color(desc.border_color);
+            unsafe {
+                device
+                    .create_sampler(&sampler_info, None)
+                    .expect("Failed to create sampler")
+            }
+        })
+    }
+
+    /// Returns the number of distinct samplers which have been created.
+    pub fn len(&self) -> usize {
+        self.samplers.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Destroys every sampler. This is called by the `Device` before it is destroyed.
+    pub fn destroy(&self, device : &ash::Device) {
+        for (_, sampler) in self.samplers.lock().unwrap().drain() {
+            unsafe {
+                device.destroy_sampler(sampler, None);
+            }
+        }
+    }
+}
@@ -0,0 +1,319 @@
+use nalgebra::{Matrix3, Matrix4, Quaternion, Rotation3, U1, U3, UnitQuaternion, Vector3, Vector4};
+
+/// The placement of a node relative to its parent, applied as scale, then rotation, then translation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation : Vector3<f32>,
+    pub rotation : UnitQuaternion<f32>,
+    pub scale : Vector3<f32>,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Self {
+            translation: Vector3::zeros(),
+            rotation: UnitQuaternion::identity(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    /// Decomposes an affine matrix without shear. A matrix which mirrors is given a negative scale along x.
+    pub fn from_matrix(matrix : &Matrix4<f32>) -> Self {
+        let linear : Matrix3<f32> = matrix.fixed_slice::<U3, U3>(0, 0).clone_owned();
+        let mut scale = Vector3::new(linear.column(0).norm(), linear.column(1).norm(), linear.column(2).norm());
+        if linear.determinant() < 0.0 {
+            scale.x = -scale.x;
+        }
+        let mut rotation = linear;
+        for (axis, scale) in scale.iter().enumerate() {
+            if *scale != 0.0 {
+                rotation.column_mut(axis).scale_mut(1.0 / scale);
+            }
+        }
+        Self {
+            translation: matrix.fixed_slice::<U3, U1>(0, 3).clone_owned(),
+            rotation: UnitQuaternion::from_rotation_matrix(&Rotation3::from_matrix_unchecked(rotation)),
+            scale,
+        }
+    }
+
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::new_translation(&self.translation)
+            * self.rotation.to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&self.scale)
+    }
+}
+
+/// A node of a scene's hierarchy, which may place a mesh or a camera.
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub name : Option<String>,
+    pub transform : Transform,
+    pub parent : Option<usize>,
+    pub children : Vec<usize>,
+    /// The mesh drawn at the node, as an index into the meshes the scene was loaded with.
+    pub mesh : Option<usize>,
+    /// An index into the cameras of the scene.
+    pub camera : Option<usize>,
+    /// The skin deforming the node's mesh, as an index into the skins of the scene.
+    pub skin : Option<usize>,
+}
+
+/// How a camera projects the view space, which looks down -z with +y up, into Vulkan's clip space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    /// A perspective projection with a vertical field of view in radians. Without an aspect ratio, the ratio of the
+    /// viewport is used, and without a far plane, the projection is infinite.
+    Perspective {
+        y_fov : f32,
+        aspect_ratio : Option<f32>,
+        z_near : f32,
+        z_far : Option<f32>,
+    },
+    /// An orthographic projection, where the magnifications are half the width and height of the view.
+    Orthographic {
+        x_mag : f32,
+        y_mag : f32,
+        z_near : f32,
+        z_far : f32,
+    },
+}
+
+impl Projection {
+    /// Returns the projection matrix for a viewport with the given aspect ratio, mapping depth from 0 at the near
+    /// plane to 1 at the far plane and flipping y to point down.
+    pub fn matrix(&self, viewport_aspect_ratio : f32) -> Matrix4<f32> {
+        match *self {
+            Projection::Perspective { y_fov, aspect_ratio, z_near, z_far } => {
+                let focal_length = 1.0 / (y_fov * 0.5).tan();
+                let aspect_ratio = aspect_ratio.unwrap_or(viewport_aspect_ratio);
+                let (depth_scale, depth_offset) = match z_far {
+                    Some(z_far) => (z_far / (z_near - z_far), z_near * z_far / (z_near - z_far)),
+                    None => (-1.0, -z_near),
+                };
+                Matrix4::new(
+                    focal_length / aspect_ratio, 0.0, 0.0, 0.0,
+                    0.0, -focal_length, 0.0, 0.0,
+                    0.0, 0.0, depth_scale, depth_offset,
+                    0.0, 0.0, -1.0, 0.0)
+            }
+            Projection::Orthographic { x_mag, y_mag, z_near, z_far } => Matrix4::new(
+                1.0 / x_mag, 0.0, 0.0, 0.0,
+                0.0, -1.0 / y_mag, 0.0, 0.0,
+                0.0, 0.0, 1.0 / (z_near - z_far), z_near / (z_near - z_far),
+                0.0, 0.0, 0.0, 1.0),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Camera {
+    pub name : Option<String>,
+    pub projection : Projection,
+}
+
+/// The joints deforming a skinned mesh.
+#[derive(Clone, Debug)]
+pub struct Skin {
+    pub name : Option<String>,
+    /// The node of each joint, in the order the joint indices of the vertices refer to them.
+    pub joints : Vec<usize>,
+    /// The matrix of each joint which moves the mesh into the joint's space in the bind pose.
+    pub inverse_bind_matrices : Vec<Matrix4<f32>>,
+    /// The common root of the joints, if one is given.
+    pub skeleton : Option<usize>,
+}
+
+impl Skin {
+    /// Returns the matrix of each joint, which moves a vertex from the bind pose into world space given the world
+    /// transforms returned by `Scene::world_transforms`. Skinned vertices are in world space, so the transform of the
+    /// node with the mesh is not applied.
+    pub fn joint_matrices(&self, world_transforms : &[Matrix4<f32>]) -> Vec<Matrix4<f32>> {
+        self.joints
+            .iter()
+            .zip(&self.inverse_bind_matrices)
+            .map(|(joint, inverse_bind_matrix)| world_transforms[*joint] * inverse_bind_matrix)
+            .collect()
+    }
+}
+
+/// How values are interpolated between the keyframes of an animation channel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Each value is held until the next keyframe.
+    Step,
+    /// Values are interpolated linearly, and rotations spherically.
+    Linear,
+    /// Values are interpolated along a cubic Hermite spline. Each keyframe has three values: the in tangent, the
+    /// value and the out tangent.
+    CubicSpline,
+}
+
+/// The keyframe values of an animation channel, along with the property of the node they animate.
+#[derive(Clone, Debug)]
+pub enum ChannelValues {
+    Translation(Vec<Vector3<f32>>),
+    /// Rotations as quaternions, which are only unit quaternions for values rather than spline tangents.
+    Rotation(Vec<Quaternion<f32>>),
+    Scale(Vec<Vector3<f32>>),
+}
+
+/// Animates a property of a node through keyframes.
+#[derive(Clone, Debug)]
+pub struct AnimationChannel {
+    pub node : usize,
+    pub interpolation : Interpolation,
+    /// The time of each keyframe in seconds, in increasing order.
+    pub times : Vec<f32>,
+    pub values : ChannelValues,
+}
+
+impl AnimationChannel {
+    /// Returns the keyframes surrounding `time`, and how far between them it is. Times outside of the keyframes are
+    /// clamped to the first or last keyframe.
+    fn keyframes(&self, time : f32) -> (usize, usize, f32) {
+        let last = self.times.len() - 1;
+        let next = self.times.iter().position(|keyframe_time| *keyframe_time > time).unwrap_or(last + 1);
+        if next == 0 {
+            return (0, 0, 0.0);
+        } else if next > last {
+            return (last, last, 0.0);
+        }
+        let previous = next - 1;
+        let duration = self.times[next] - self.times[previous];
+        (previous, next, (time - self.times[previous]) / duration)
+    }
+
+    /// Samples a channel whose values are 4D vectors at `time`.
+    fn sample(&self, values : &[Vector4<f32>], time : f32, spherical : bool) -> Vector4<f32> {
+        let (previous, next, t) = self.keyframes(time);
+        match self.interpolation {
+            Interpolation::Step => values[previous],
+            Interpolation::Linear if spherical => slerp(&values[previous], &values[next], t),
+            Interpolation::Linear => values[previous].lerp(&values[next], t),
+            Interpolation::CubicSpline => {
+                // The value of keyframe k is at 3k + 1, between its in tangent and its out tangent.
+                let duration = self.times[next] - self.times[previous];
+                let (t2, t3) = (t * t, t * t * t);
+                values[previous * 3 + 1] * (2.0 * t3 - 3.0 * t2 + 1.0)
+                    + values[previous * 3 + 2] * (duration * (t3 - 2.0 * t2 + t))
+                    + values[next * 3 + 1] * (-2.0 * t3 + 3.0 * t2)
+                    + values[next * 3] * (duration * (t3 - t2))
+            }
+        }
+    }
+}
+
+/// Interpolates spherically between two unit quaternions along the shortest path.
+fn slerp(from : &Vector4<f32>, to : &Vector4<f32>, t : f32) -> Vector4<f32> {
+    let mut cos_angle = from.dot(to);
+    let to = if cos_angle < 0.0 {
+        cos_angle = -cos_angle;
+        -to
+    } else {
+        *to
+    };
+    // Nearly parallel quaternions are interpolated linearly, since the sine of their angle vanishes.
+    if cos_angle > 0.9995 {
+        return from.lerp(&to, t).normalize();
+    }
+    let angle = cos_angle.acos();
+    (from * ((1.0 - t) * angle).sin() + to * (t * angle).sin()) / angle.sin()
+}
+
+/// Keyframed changes to the transforms of nodes.
+#[derive(Clone, Debug)]
+pub struct Animation {
+    pub name : Option<String>,
+    pub channels : Vec<AnimationChannel>,
+}
+
+impl Animation {
+    /// Returns the time of the last keyframe of any channel.
+    pub fn duration(&self) -> f32 {
+        self.channels
+            .iter()
+            .filter_map(|channel| channel.times.last())
+            .fold(0.0, |duration, time| duration.max(*time))
+    }
+
+    /// Sets the transforms of the animated nodes to their values `time` seconds into the animation. Loop the
+    /// animation by passing the time modulo the `duration`.
+    pub fn apply(&self, time : f32, nodes : &mut [Node]) {
+        for channel in self.channels.iter().filter(|channel| !channel.times.is_empty()) {
+            let transform = &mut nodes[channel.node].transform;
+            match &channel.values {
+                ChannelValues::Translation(values) => {
+                    let values : Vec<_> = values.iter().map(|value| value.push(0.0)).collect();
+                    transform.translation = channel.sample(&values, time, false).xyz();
+                }
+                ChannelValues::Rotation(values) => {
+                    let values : Vec<_> = values.iter().map(|value| value.coords).collect();
+                    let rotation = Quaternion::from(channel.sample(&values, time, true));
+                    transform.rotation = UnitQuaternion::new_normalize(rotation);
+                }
+                ChannelValues::Scale(values) => {
+                    let values : Vec<_> = values.iter().map(|value| value.push(0.0)).collect();
+                    transform.scale = channel.sample(&values, time, false).xyz();
+                }
+            }
+        }
+    }
+}
+
+/// A hierarchy of nodes with the cameras, skins and animations referring to them.
+#[derive(Clone, Debug, Default)]
+pub struct Scene {
+    pub name : Option<String>,
+    pub nodes : Vec<Node>,
+    /// The nodes at the top of the hierarchy which are shown.
+    pub roots : Vec<usize>,
+    pub cameras : Vec<Camera>,
+    pub skins : Vec<Skin>,
+    pub animations : Vec<Animation>,
+}
+
+impl Scene {
+    /// Returns the transform of every node from its space into world space. Nodes outside the hierarchy of the roots
+    /// are still placed relative to their parents.
+    pub fn world_transforms(&self) -> Vec<Matrix4<f32>> {
+        let mut world_transforms = vec![Matrix4::identity(); self.nodes.len()];
+        let mut stack : Vec<(usize, Matrix4<f32>)> = self.nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| node.parent.is_none())
+            .map(|(index, _)| (index, Matrix4::identity()))
+            .collect();
+        while let Some((index, parent_transform)) = stack.pop() {
+            let node = &self.nodes[index];
+            let world_transform = parent_transform * node.transform.matrix();
+            world_transforms[index] = world_transform;
+            stack.extend(node.children.iter().map(|child| (*child, world_transform)));
+        }
+        world_transforms
+    }
+
+    /// Returns every node in the hierarchy of the roots, parents before their children.
+    pub fn visible_nodes(&self) -> Vec<usize> {
+        let mut nodes = Vec::new();
+        let mut stack : Vec<usize> = self.roots.iter().rev().copied().collect();
+        while let Some(index) = stack.pop() {
+            nodes.push(index);
+            stack.extend(self.nodes[index].children.iter().rev());
+        }
+        nodes
+    }
+
+    /// Returns the view matrix of a camera node, which is the inverse of its world transform without scale.
+    pub fn view_matrix(&self, node : usize, world_transforms : &[Matrix4<f32>]) -> Matrix4<f32> {
+        let world = Transform::from_matrix(&world_transforms[node]);
+        let unscaled = Transform { scale: Vector3::new(1.0, 1.0, 1.0), ..world };
+        unscaled.matrix().try_inverse().unwrap_or_else(Matrix4::identity)
+    }
+}
@@ -0,0 +1,147 @@
+use std::{fmt, fs, io, path::{Path, PathBuf}, sync::Arc};
+use ash::version::DeviceV1_0;
+use ash::vk;
+use super::Device;
+use super::deletion::DeferredObject;
+
+/// The first word of every SPIR-V module, in the byte order it was written in.
+pub const MAGIC_NUMBER : u32 = 0x0723_0203;
+/// The number of words in the header which precedes the instructions.
+const HEADER_WORDS : usize = 5;
+/// The newest SPIR-V version accepted, which is 1.6.
+const MAX_VERSION : (u32, u32) = (1, 6);
+
+/// Provides a brief overview of why SPIR-V could not be loaded.
+#[derive(Debug)]
+pub enum ShaderModuleError {
+    Io(PathBuf, io::Error),
+    /// The number of bytes is not a multiple of the word size.
+    UnalignedLength(usize),
+    /// The code is shorter than the header.
+    Truncated(usize),
+    /// The first word is not the SPIR-V magic number in either byte order.
+    InvalidMagicNumber(u32),
+    /// The version is malformed or newer than this loader understands.
+    UnsupportedVersion(u32),
+    /// The instruction at the given word has a word count of zero or extends past the end of the code.
+    MalformedInstruction(usize),
+    Vulkan(vk::Result),
+}
+
+impl fmt::Display for ShaderModuleError {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderModuleError::Io(path, error) => write!(f, "{}: {}", path.display(), error),
+            ShaderModuleError::UnalignedLength(length) =>
+                write!(f, "SPIR-V length of {} bytes is not a multiple of 4", length),
+            ShaderModuleError::Truncated(words) =>
+                write!(f, "SPIR-V has {} words, which is too short for its {} word header", words, HEADER_WORDS),
+            ShaderModuleError::InvalidMagicNumber(magic) =>
+                write!(f, "SPIR-V magic number is {:#010x}, expected {:#010x}", magic, MAGIC_NUMBER),
+            ShaderModuleError::UnsupportedVersion(version) => write!(f,
+                "SPIR-V version {}.{} is not supported, the newest supported is {}.{}",
+                version >> 16 & 0xff, version >> 8 & 0xff, MAX_VERSION.0, MAX_VERSION.1),
+            ShaderModuleError::MalformedInstruction(offset) =>
+                write!(f, "SPIR-V instruction at word {} has an invalid word count", offset),
+            ShaderModuleError::Vulkan(result) => write!(f, "failed to create shader module: {}", result),
+        }
+    }
+}
+
+/// Converts SPIR-V in bytes to aligned words and validates its header and instruction word counts. Code in either byte
+/// order is accepted and is converted to the native byte order.
+pub fn read_spirv(bytes : &[u8]) -> Result<Vec<u32>, ShaderModuleError> {
+    if !bytes.len().is_multiple_of(4) {
+        return Err(ShaderModuleError::UnalignedLength(bytes.len()));
+    }
+    // Copying word by word means the bytes need not be aligned, as is the case for `include_bytes!`.
+    let mut words : Vec<u32> = bytes
+        .chunks_exact(4)
+        .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect();
+    if words.first() == Some(&MAGIC_NUMBER.swap_bytes()) {
+        words.iter_mut().for_each(|word| *word = word.swap_bytes());
+    }
+    validate_spirv(&words)?;
+    Ok(words)
+}
+
+/// Validates the header and instruction word counts of SPIR-V words in the native byte order.
+pub fn validate_spirv(words : &[u32]) -> Result<(), ShaderModuleError> {
+    if words.len() < HEADER_WORDS {
+        return Err(ShaderModuleError::Truncated(words.len()));
+    }
+    if words[0] != MAGIC_NUMBER {
+        return Err(ShaderModuleError::InvalidMagicNumber(words[0]));
+    }
+    // The version is 0x00MMmm00, so the outer bytes must be zero.
+    let version = words[1];
+    let (major, minor) = (version >> 16 & 0xff, version >> 8 & 0xff);
+    if version & 0xff00_00ff != 0 || major != MAX_VERSION.0 || minor > MAX_VERSION.1 {
+        return Err(ShaderModuleError::UnsupportedVersion(version));
+    }
+
+    let mut offset = HEADER_WORDS;
+    while offset < words.len() {
+        let word_count = (words[offset] >> 16) as usize;
+        if word_count == 0 || offset + word_count > words.len() {
+            return Err(ShaderModuleError::MalformedInstruction(offset));
+        }
+        offset += word_count;
+    }
+    Ok(())
+}
+
+/// A `VkShaderModule` along with the validated SPIR-V it was created from, which is kept for reflection.
+pub struct ShaderModule {
+    device : Arc<Device>,
+    module : vk::ShaderModule,
+    code : Vec<u32>,
+}
+
+impl Drop for ShaderModule {
+    fn drop(&mut self) {
+        self.device.destroy_deferred(DeferredObject::ShaderModule(self.module));
+        info!("Dropped ShaderModule")
+    }
+}
+
+impl ShaderModule {
+    /// Creates a shader module from SPIR-V in bytes, such as an asset embedded with `include_bytes!`.
+    pub fn from_bytes(device : Arc<Device>, bytes : &[u8]) -> Result<Self, ShaderModuleError> {
+        Self::create(device, read_spirv(bytes)?)
+    }
+
+    /// Creates a shader module from the SPIR-V file at `path`.
+    pub fn from_file(device : Arc<Device>, path : &Path) -> Result<Self, ShaderModuleError> {
+        let bytes = fs::read(path).map_err(|error| ShaderModuleError::Io(path.to_path_buf(), error))?;
+        Self::from_bytes(device, &bytes)
+    }
+
+    /// Creates a shader module from SPIR-V words, such as those produced by the `ShaderCompiler`.
+    pub fn from_words(device : Arc<Device>, words : Vec<u32>) -> Result<Self, ShaderModuleError> {
+        validate_spirv(&words)?;
+        Self::create(device, words)
+    }
+
+    fn create(device : Arc<Device>, code : Vec<u32>) -> Result<Self, ShaderModuleError> {
+        let module_info = vk::ShaderModuleCreateInfo::builder()
+            .code(code.as_slice());
+        let module = unsafe {
+            device
+                .ash_device()
+                .create_shader_module(&module_info, None)
+                .map_err(ShaderModuleError::Vulkan)?
+        };
+        Ok(Self { device, module, code })
+    }
+
+    pub fn module_raw(&self) -> vk::ShaderModule {
+        self.module
+    }
+
+    /// Returns the validated SPIR-V words.
+    pub fn code(&self) -> &[u32] {
+        &self.code
+    }
+}
@@ -0,0 +1,668 @@
+use std::{collections::{HashMap, HashSet}, fmt, fs, io, path::{Path, PathBuf}};
+use ash::vk;
+use super::shader::ShaderModuleError;
+
+/// The pipeline stage a shader is compiled for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+    Compute,
+}
+
+impl ShaderStage {
+    /// Determines the stage from the extension of `path`, i.e. `.vert`, `.frag` or `.comp`.
+    pub fn from_path(path : &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "vert" => Some(ShaderStage::Vertex),
+            "frag" => Some(ShaderStage::Fragment),
+            "comp" => Some(ShaderStage::Compute),
+            _ => None,
+        }
+    }
+
+    pub fn stage_flags(self) -> vk::ShaderStageFlags {
+        match self {
+            ShaderStage::Vertex => vk::ShaderStageFlags::VERTEX,
+            ShaderStage::Fragment => vk::ShaderStageFlags::FRAGMENT,
+            ShaderStage::Compute => vk::ShaderStageFlags::COMPUTE,
+        }
+    }
+
+    fn naga_stage(self) -> naga::ShaderStage {
+        match self {
+            ShaderStage::Vertex => naga::ShaderStage::Vertex,
+            ShaderStage::Fragment => naga::ShaderStage::Fragment,
+            ShaderStage::Compute => naga::ShaderStage::Compute,
+        }
+    }
+}
+
+/// A compile error located in the original source file, after includes have been resolved.
+#[derive(Debug)]
+pub struct ShaderDiagnostic {
+    pub file : PathBuf,
+    pub line : u32,
+    pub column : u32,
+    pub message : String,
+}
+
+impl fmt::Display for ShaderDiagnostic {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}: {}", self.file.display(), self.line, self.column, self.message)
+    }
+}
+
+/// Provides a brief overview of why a shader failed to compile.
+#[derive(Debug)]
+pub enum ShaderCompileError {
+    Io(PathBuf, io::Error),
+    /// The stage could not be determined from the file extension.
+    UnknownStage(PathBuf),
+    /// The shader is written in HLSL, which cannot be compiled at runtime. HLSL shaders need to be compiled to SPIR-V
+    /// ahead of time, i.e. with `dxc -spirv`, and loaded as shader modules.
+    UnsupportedLanguage(PathBuf),
+    /// An `#include` could not be found relative to the including file or in any include directory.
+    IncludeNotFound(ShaderDiagnostic),
+    /// A file includes itself, directly or through other files.
+    IncludeCycle(ShaderDiagnostic),
+    Parse(Vec<ShaderDiagnostic>),
+    Validation(String),
+    Codegen(String),
+    /// The compiled code could not be loaded into a shader module.
+    Module(ShaderModuleError),
+}
+
+impl fmt::Display for ShaderCompileError {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderCompileError::Io(path, error) => write!(f, "{}: {}", path.display(), error),
+            ShaderCompileError::UnknownStage(path) => write!(f, "{}: unknown shader stage", path.display()),
+            ShaderCompileError::UnsupportedLanguage(path) => {
+                write!(f, "{}: HLSL cannot be compiled at runtime, compile it to SPIR-V instead", path.display())
+            }
+            ShaderCompileError::IncludeNotFound(diagnostic) => write!(f, "{}", diagnostic),
+            ShaderCompileError::IncludeCycle(diagnostic) => write!(f, "{}", diagnostic),
+            ShaderCompileError::Parse(diagnostics) => {
+                for diagnostic in diagnostics {
+                    writeln!(f, "{}", diagnostic)?;
+                }
+                Ok(())
+            }
+            ShaderCompileError::Validation(message) => write!(f, "validation failed: {}", message),
+            ShaderCompileError::Codegen(message) => write!(f, "SPIR-V generation failed: {}", message),
+            ShaderCompileError::Module(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+/// Source with its includes expanded, along with the file and line each line of it came from.
+struct ExpandedSource {
+    source : String,
+    lines : Vec<(PathBuf, u32)>,
+}
+
+impl ExpandedSource {
+    /// Maps a byte offset in the expanded source back to the file, line and column it came from.
+    fn locate(&self, offset : usize) -> (PathBuf, u32, u32) {
+        let offset = offset.min(self.source.len());
+        let prefix = &self.source[..offset];
+        let line = prefix.matches('\n').count();
+        let column = prefix.len() - prefix.rfind('\n').map_or(0, |position| position + 1) + 1;
+        match self.lines.get(line) {
+            Some((file, line)) => (file.clone(), *line, column as u32),
+            None => (PathBuf::new(), line as u32 + 1, column as u32),
+        }
+    }
+}
+
+/// An `#if`, `#ifdef` or `#ifndef` group which is open while includes are expanded.
+#[derive(Clone, Copy)]
+struct ConditionalGroup {
+    /// Whether the lines of the current branch are compiled.
+    active : bool,
+    /// Whether a branch of the group has been taken, after which the remaining `#elif` and `#else` branches are not.
+    taken : bool,
+    /// Whether the lines around the group are compiled.
+    parent_active : bool,
+}
+
+/// Compiles GLSL and WGSL shaders to SPIR-V at runtime. `#include "file"` directives in GLSL are resolved relative to
+/// the including file and then to each include directory, and each file is only included once. Includes in comments or
+/// in branches of `#if`, `#ifdef` and `#ifndef` which are not compiled are not followed. Macros can be defined to
+/// compile permutations of a GLSL shader. WGSL has no preprocessor, so neither includes nor macros apply to it.
+#[derive(Clone, Debug, Default)]
+pub struct ShaderCompiler {
+    include_dirs : Vec<PathBuf>,
+    defines : Vec<(String, String)>,
+}
+
+impl ShaderCompiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a directory which is searched for included files.
+    pub fn include_dir<P : Into<PathBuf>>(mut self, dir : P) -> Self {
+        self.include_dirs.push(dir.into());
+        self
+    }
+
+    /// Defines a macro, as if by `#define name value`, in every shader compiled.
+    pub fn define<N : Into<String>, V : Into<String>>(mut self, name : N, value : V) -> Self {
+        let name = name.into();
+        self.defines.retain(|(defined, _)| *defined != name);
+        self.defines.push((name, value.into()));
+        self
+    }
+
+    /// Compiles the shader at `path`. GLSL shaders have their stage determined from the file extension, while `.wgsl`
+    /// files are compiled with every entry point they declare. `.hlsl` files are rejected with `UnsupportedLanguage`.
+    pub fn compile_file(&self, path : &Path) -> Result<Vec<u32>, ShaderCompileError> {
+        self.compile_file_with_dependencies(path).map(|(code, _)| code)
+    }
+
+    /// Compiles the shader at `path`, also returning every file it was compiled from, including itself. The shader
+    /// needs to be recompiled when any of these change.
+    pub fn compile_file_with_dependencies(&self, path : &Path) -> Result<(Vec<u32>, Vec<PathBuf>), ShaderCompileError> {
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        if extension == Some("hlsl") {
+            return Err(ShaderCompileError::UnsupportedLanguage(path.to_path_buf()));
+        }
+        let stage = ShaderStage::from_path(path);
+        if extension != Some("wgsl") && stage.is_none() {
+            return Err(ShaderCompileError::UnknownStage(path.to_path_buf()));
+        }
+        let source = fs::read_to_string(path)
+            .map_err(|error| ShaderCompileError::Io(path.to_path_buf(), error))?;
+        match stage {
+            Some(stage) => self.compile(path, &source, stage),
+            None => {
+                let code = self.compile_wgsl(path, &source)?;
+                Ok((code, vec![path.canonicalize().unwrap_or_else(|_| path.to_path_buf())]))
+            }
+        }
+    }
+
+    /// Compiles `source` as though it were read from `path`, which is used to resolve includes and report errors.
+    pub fn compile_source(&self, path : &Path, source : &str, stage : ShaderStage)
+        -> Result<Vec<u32>, ShaderCompileError> {
+        self.compile(path, source, stage).map(|(code, _)| code)
+    }
+
+    /// Compiles WGSL `source` with every entry point it declares, as though it were read from `path`, which is used to
+    /// report errors. Pipelines which load a stage by name expect its entry point to be called `main`.
+    pub fn compile_wgsl(&self, path : &Path, source : &str) -> Result<Vec<u32>, ShaderCompileError> {
+        let module = naga::front::wgsl::parse_str(source).map_err(|error| {
+            let (line, column) = error
+                .location(source)
+                .map_or((0, 0), |location| (location.line_number, location.line_position));
+            let message = error.message().to_string();
+            ShaderCompileError::Parse(vec![ShaderDiagnostic { file: path.to_path_buf(), line, column, message }])
+        })?;
+        write_spirv(&module)
+    }
+
+    fn compile(&self, path : &Path, source : &str, stage : ShaderStage)
+        -> Result<(Vec<u32>, Vec<PathBuf>), ShaderCompileError> {
+        let mut expanded = ExpandedSource { source: String::new(), lines: Vec::new() };
+        let mut included = HashSet::new();
+        let mut stack = Vec::new();
+        let mut defines = self.defines.iter().cloned().collect();
+        self.expand(path, source, &mut expanded, &mut included, &mut stack, &mut defines)?;
+
+        let mut options = naga::front::glsl::Options::from(stage.naga_stage());
+        options.defines.extend(self.defines.iter().cloned());
+        let module = naga::front::glsl::Frontend::default()
+            .parse(&options, &expanded.source)
+            .map_err(|errors| ShaderCompileError::Parse(errors
+                .into_iter()
+                .map(|error| {
+                    let offset = error.meta.to_range().map_or(0, |range| range.start);
+                    let (file, line, column) = expanded.locate(offset);
+                    ShaderDiagnostic { file, line, column, message: error.kind.to_string() }
+                })
+                .collect()))?;
+
+        Ok((write_spirv(&module)?, included.into_iter().collect()))
+    }
+
+    /// Appends `source` to `expanded`, replacing includes with the contents of the included file. Conditional
+    /// directives are kept for the compiler, but are also tracked along with `defines` so that includes are only
+    /// followed where they will be compiled.
+    fn expand(&self,
+              path : &Path,
+              source : &str,
+              expanded : &mut ExpandedSource,
+              included : &mut HashSet<PathBuf>,
+              stack : &mut Vec<PathBuf>,
+              defines : &mut HashMap<String, String>) -> Result<(), ShaderCompileError> {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        included.insert(canonical.clone());
+        stack.push(canonical);
+        let mut groups : Vec<ConditionalGroup> = Vec::new();
+        let mut in_comment = false;
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index as u32 + 1;
+            let starts_in_comment = in_comment;
+            in_comment = ends_in_comment(line, in_comment);
+            let directive = line.trim_start().strip_prefix('#').filter(|_| !starts_in_comment);
+            let (name, arguments) = match directive {
+                Some(directive) => split_directive(directive),
+                None => {
+                    push_line(expanded, line, path, line_number);
+                    continue;
+                }
+            };
+            let active = groups.last().is_none_or(|group| group.active);
+            match name {
+                "if" | "ifdef" | "ifndef" => {
+                    let condition = active && match name {
+                        "ifdef" => defines.contains_key(arguments),
+                        "ifndef" => !defines.contains_key(arguments),
+                        _ => evaluate_condition(arguments, defines),
+                    };
+                    groups.push(ConditionalGroup { active: condition, taken: condition, parent_active: active });
+                }
+                "elif" => if let Some(group) = groups.last_mut() {
+                    group.active = group.parent_active && !group.taken && evaluate_condition(arguments, defines);
+                    group.taken |= group.active;
+                },
+                "else" => if let Some(group) = groups.last_mut() {
+                    group.active = group.parent_active && !group.taken;
+                    group.taken = true;
+                },
+                "endif" => {
+                    groups.pop();
+                }
+                "define" if active => {
+                    let name_end = arguments
+                        .find(|c : char| !(c.is_ascii_alphanumeric() || c == '_'))
+                        .unwrap_or(arguments.len());
+                    defines.insert(arguments[..name_end].to_string(), arguments[name_end..].trim().to_string());
+                }
+                "undef" if active => {
+                    defines.remove(arguments);
+                }
+                _ => (),
+            }
+            // Lines which are removed are kept as blank lines, so columns and line numbers still line up.
+            if name == "pragma" && arguments == "once"
+                || name == "extension" && arguments.starts_with("GL_GOOGLE_include_directive")
+                || name == "include" && !active {
+                push_line(expanded, "", path, line_number);
+                continue;
+            }
+            if name != "include" {
+                push_line(expanded, line, path, line_number);
+                continue;
+            }
+            let include = arguments.trim_matches(|c| c == '"' || c == '<' || c == '>');
+
+            let diagnostic = |message : String| ShaderDiagnostic {
+                file: path.to_path_buf(),
+                line: line_number,
+                column: 1,
+                message,
+            };
+            let include_path = self.resolve_include(path, include)
+                .ok_or_else(|| ShaderCompileError::IncludeNotFound(diagnostic(format!("cannot find {}", include))))?;
+            let canonical = include_path.canonicalize().unwrap_or_else(|_| include_path.clone());
+            if stack.contains(&canonical) {
+                return Err(ShaderCompileError::IncludeCycle(diagnostic(format!("{} includes itself", include))));
+            }
+            if included.contains(&canonical) {
+                push_line(expanded, "", path, line_number);
+                continue;
+            }
+            let include_source = fs::read_to_string(&include_path)
+                .map_err(|error| ShaderCompileError::Io(include_path.clone(), error))?;
+            self.expand(&include_path, &include_source, expanded, included, stack, defines)?;
+        }
+        stack.pop();
+        Ok(())
+    }
+
+    fn resolve_include(&self, path : &Path, include : &str) -> Option<PathBuf> {
+        path.parent()
+            .into_iter()
+            .chain(self.include_dirs.iter().map(PathBuf::as_path))
+            .map(|dir| dir.join(include))
+            .find(|candidate| candidate.is_file())
+    }
+}
+
+/// Returns whether a block comment is still open at the end of `line`.
+fn ends_in_comment(line : &str, mut in_comment : bool) -> bool {
+    let mut rest = line;
+    loop {
+        if in_comment {
+            match rest.find("*/") {
+                Some(end) => {
+                    rest = &rest[end + 2..];
+                    in_comment = false;
+                }
+                None => return true,
+            }
+        } else {
+            match (rest.find("//"), rest.find("/*")) {
+                (Some(line_comment), Some(start)) if line_comment < start => return false,
+                (_, Some(start)) => {
+                    rest = &rest[start + 2..];
+                    in_comment = true;
+                }
+                _ => return false,
+            }
+        }
+    }
+}
+
+/// Splits the text after the `#` of a directive into its name and arguments, without any trailing comment.
+fn split_directive(directive : &str) -> (&str, &str) {
+    let directive = directive.trim_start();
+    let end = [directive.find("//"), directive.find("/*")].iter().flatten().min().copied().unwrap_or(directive.len());
+    let directive = &directive[..end];
+    let name_end = directive.find(|c : char| !c.is_ascii_alphabetic()).unwrap_or(directive.len());
+    (&directive[..name_end], directive[name_end..].trim())
+}
+
+/// Evaluates the expression of an `#if` or `#elif` directive. Expressions which cannot be evaluated, i.e. because
+/// they use function-like macros, are assumed to be true so that their includes are still followed.
+fn evaluate_condition(expression : &str, defines : &HashMap<String, String>) -> bool {
+    let mut parser = ConditionParser { tokens: tokenize(expression), position: 0, defines, depth: 0 };
+    match parser.or() {
+        Some(value) if parser.position == parser.tokens.len() => value != 0,
+        _ => true,
+    }
+}
+
+/// Splits a preprocessor expression into identifiers, numbers and operators.
+fn tokenize(expression : &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let mut end = start + c.len_utf8();
+        if c.is_ascii_alphanumeric() || c == '_' {
+            while let Some(&(index, next)) = chars.peek() {
+                if !(next.is_ascii_alphanumeric() || next == '_') {
+                    break;
+                }
+                end = index + next.len_utf8();
+                chars.next();
+            }
+        } else if let Some(&(index, next)) = chars.peek() {
+            if matches!((c, next), ('&', '&') | ('|', '|') | ('=', '=') | ('!', '=') | ('<', '=') | ('>', '=')) {
+                end = index + 1;
+                chars.next();
+            }
+        }
+        tokens.push(expression[start..end].to_string());
+    }
+    tokens
+}
+
+/// A recursive descent parser for the expressions of `#if` directives, which evaluates them as it goes. Returns
+/// `None` for anything it does not understand.
+struct ConditionParser<'a> {
+    tokens : Vec<String>,
+    position : usize,
+    defines : &'a HashMap<String, String>,
+    /// How many macros are being expanded, which stops self-referential macros.
+    depth : u32,
+}
+
+impl ConditionParser<'_> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(String::as_str)
+    }
+
+    fn accept(&mut self, token : &str) -> bool {
+        let accepted = self.peek() == Some(token);
+        if accepted {
+            self.position += 1;
+        }
+        accepted
+    }
+
+    fn or(&mut self) -> Option<i64> {
+        let mut value = self.and()?;
+        while self.accept("||") {
+            let right = self.and()?;
+            value = (value != 0 || right != 0) as i64;
+        }
+        Some(value)
+    }
+
+    fn and(&mut self) -> Option<i64> {
+        let mut value = self.comparison()?;
+        while self.accept("&&") {
+            let right = self.comparison()?;
+            value = (value != 0 && right != 0) as i64;
+        }
+        Some(value)
+    }
+
+    fn comparison(&mut self) -> Option<i64> {
+        let mut value = self.additive()?;
+        loop {
+            let operator = match self.peek() {
+                Some(operator @ ("==" | "!=" | "<" | ">" | "<=" | ">=")) => operator.to_string(),
+                _ => return Some(value),
+            };
+            self.position += 1;
+            let right = self.additive()?;
+            value = match operator.as_str() {
+                "==" => value == right,
+                "!=" => value != right,
+                "<" => value < right,
+                ">" => value > right,
+                "<=" => value <= right,
+                _ => value >= right,
+            } as i64;
+        }
+    }
+
+    fn additive(&mut self) -> Option<i64> {
+        let mut value = self.unary()?;
+        loop {
+            if self.accept("+") {
+                value = value.checked_add(self.unary()?)?;
+            } else if self.accept("-") {
+                value = value.checked_sub(self.unary()?)?;
+            } else {
+                return Some(value);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Option<i64> {
+        if self.accept("!") {
+            return Some((self.unary()? == 0) as i64);
+        }
+        if self.accept("-") {
+            return self.unary()?.checked_neg();
+        }
+        if self.accept("(") {
+            let value = self.or()?;
+            return if self.accept(")") { Some(value) } else { None };
+        }
+        let token = self.peek()?.to_string();
+        self.position += 1;
+        if token == "defined" {
+            let parenthesized = self.accept("(");
+            let name = self.peek()?.to_string();
+            self.position += 1;
+            if parenthesized && !self.accept(")") {
+                return None;
+            }
+            return Some(self.defines.contains_key(&name) as i64);
+        }
+        if token.starts_with(|c : char| c.is_ascii_digit()) {
+            let digits = token.trim_end_matches(['u', 'U']);
+            return match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+                Some(hex) => i64::from_str_radix(hex, 16).ok(),
+                None => digits.parse().ok(),
+            };
+        }
+        if !token.starts_with(|c : char| c.is_ascii_alphabetic() || c == '_') || self.peek() == Some("(") {
+            return None;
+        }
+        // Undefined identifiers evaluate to zero, and defined ones to their expanded value.
+        match self.defines.get(&token) {
+            None => Some(0),
+            Some(_) if self.depth >= 16 => None,
+            Some(value) => {
+                let mut parser = ConditionParser {
+                    tokens: tokenize(value),
+                    position: 0,
+                    defines: self.defines,
+                    depth: self.depth + 1,
+                };
+                let value = parser.or()?;
+                if parser.position == parser.tokens.len() { Some(value) } else { None }
+            }
+        }
+    }
+}
+
+/// Validates a parsed module and generates SPIR-V for it.
+fn write_spirv(module : &naga::Module) -> Result<Vec<u32>, ShaderCompileError> {
+    let info = naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+        .validate(module)
+        .map_err(|error| ShaderCompileError::Validation(format!("{:?}", error.into_inner())))?;
+    naga::back::spv::write_vec(module, &info, &naga::back::spv::Options::default(), None)
+        .map_err(|error| ShaderCompileError::Codegen(error.to_string()))
+}
+
+fn push_line(expanded : &mut ExpandedSource, line : &str, path : &Path, line_number : u32) {
+    expanded.source.push_str(line);
+    expanded.source.push('\n');
+    expanded.lines.push((path.to_path_buf(), line_number));
+}
+
+/// Converts SPIR-V words to bytes, for APIs which take shader code as bytes.
+pub fn words_to_bytes(words : &[u32]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_le_bytes().to_vec()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Expands `source` as though it were in a directory containing `a.glsl` and `b.glsl`, but not `missing.glsl`.
+    fn expand(compiler : &ShaderCompiler, name : &str, source : &str) -> Result<String, ShaderCompileError> {
+        let dir = std::env::temp_dir().join(format!("halogen-includes-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.glsl"), "float a;").unwrap();
+        fs::write(dir.join("b.glsl"), "float b;").unwrap();
+        let mut expanded = ExpandedSource { source: String::new(), lines: Vec::new() };
+        let mut defines = compiler.defines.iter().cloned().collect();
+        let result = compiler.expand(
+            &dir.join("main.vert"),
+            source,
+            &mut expanded,
+            &mut HashSet::new(),
+            &mut Vec::new(),
+            &mut defines);
+        fs::remove_dir_all(&dir).unwrap();
+        result.map(|_| expanded.source)
+    }
+
+    #[test]
+    fn skips_includes_in_comments() {
+        let source = "// #include \"missing.glsl\"\n\
+                      /* #include \"missing.glsl\" */\n\
+                      /*\n\
+                      #include \"missing.glsl\"\n\
+                      */\n\
+                      #include \"a.glsl\" // the first include\n";
+        let expanded = expand(&ShaderCompiler::new(), "comments", source).unwrap();
+        assert!(expanded.contains("float a;"));
+        assert_eq!(expanded.lines().count(), 6);
+    }
+
+    #[test]
+    fn skips_includes_in_inactive_groups() {
+        let source = "#ifdef UNDEFINED\n\
+                      #include \"missing.glsl\"\n\
+                      #elif 1\n\
+                      #include \"a.glsl\"\n\
+                      #else\n\
+                      #include \"missing.glsl\"\n\
+                      #endif\n\
+                      #if 0\n\
+                      #if 1\n\
+                      #include \"missing.glsl\"\n\
+                      #endif\n\
+                      #else\n\
+                      #include \"b.glsl\"\n\
+                      #endif\n";
+        let expanded = expand(&ShaderCompiler::new(), "groups", source).unwrap();
+        assert!(expanded.contains("float a;"));
+        assert!(expanded.contains("float b;"));
+        assert!(expanded.contains("#ifdef UNDEFINED"));
+    }
+
+    #[test]
+    fn follows_includes_enabled_by_defines() {
+        let compiler = ShaderCompiler::new().define("USE_A", "1");
+        let source = "#define LEVEL 2\n\
+                      #if defined(USE_A) && LEVEL >= 2\n\
+                      #include \"a.glsl\"\n\
+                      #endif\n\
+                      #ifndef USE_A\n\
+                      #include \"missing.glsl\"\n\
+                      #endif\n\
+                      #undef LEVEL\n\
+                      #if LEVEL\n\
+                      #include \"missing.glsl\"\n\
+                      #endif\n";
+        let expanded = expand(&compiler, "defines", source).unwrap();
+        assert!(expanded.contains("float a;"));
+    }
+
+    #[test]
+    fn evaluates_conditions() {
+        let defines : HashMap<String, String> = vec![
+            ("THREE".to_string(), "3".to_string()),
+            ("ALIAS".to_string(), "(THREE + 1)".to_string()),
+            ("LOOP".to_string(), "LOOP".to_string()),
+        ].into_iter().collect();
+        assert!(evaluate_condition("defined(THREE) && !defined UNDEFINED", &defines));
+        assert!(evaluate_condition("THREE == 3 && ALIAS == 4", &defines));
+        assert!(evaluate_condition("0x10 > 15u || 0", &defines));
+        assert!(!evaluate_condition("UNDEFINED", &defines));
+        assert!(!evaluate_condition("(THREE - 3)", &defines));
+        // Conditions which cannot be evaluated are assumed to be true.
+        assert!(evaluate_condition("MAX(1, 2)", &defines));
+        assert!(evaluate_condition("LOOP", &defines));
+    }
+
+    #[test]
+    fn compiles_wgsl() {
+        let source = "@vertex\n\
+                      fn main(@builtin(vertex_index) index : u32) -> @builtin(position) vec4<f32> {\n\
+                      \x20   return vec4<f32>(f32(index), 0.0, 0.0, 1.0);\n\
+                      }\n";
+        let code = ShaderCompiler::new().compile_wgsl(Path::new("shader.wgsl"), source).unwrap();
+        assert_eq!(code[0], 0x0723_0203);
+
+        let error = ShaderCompiler::new().compile_wgsl(Path::new("shader.wgsl"), "fn main( {}").unwrap_err();
+        match error {
+            ShaderCompileError::Parse(diagnostics) => {
+                assert_eq!((diagnostics[0].line, diagnostics[0].column), (1, 10));
+                assert_eq!(diagnostics[0].file, Path::new("shader.wgsl"));
+            }
+            error => panic!("unexpected error: {}", error),
+        }
+    }
+
+    #[test]
+    fn rejects_hlsl() {
+        let result = ShaderCompiler::new().compile_file(Path::new("missing.hlsl"));
+        assert!(matches!(result, Err(ShaderCompileError::UnsupportedLanguage(_))));
+    }
+}
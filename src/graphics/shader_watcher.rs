@@ -0,0 +1,151 @@
+use std::{collections::{HashMap, HashSet}, fmt, fs, io, path::{Path, PathBuf}};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Watches a directory of shader sources for changes by polling the modification time of each file. Polling is done
+/// at most once per interval, so it is cheap enough to call every frame.
+pub struct ShaderWatcher {
+    root : PathBuf,
+    interval : Duration,
+    last_poll : Instant,
+    modified : HashMap<PathBuf, SystemTime>,
+}
+
+impl ShaderWatcher {
+    /// Starts watching every file below `root`. Files which exist now are not reported as changed.
+    pub fn new<P : Into<PathBuf>>(root : P, interval : Duration) -> Self {
+        let root = root.into();
+        let mut modified = HashMap::new();
+        if let Err(error) = scan(&root, &mut modified) {
+            warn!("Failed to watch shaders in {}: {}", root.display(), error);
+        }
+        Self { root, interval, last_poll: Instant::now(), modified }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Returns the files which were created or modified since the last poll. Returns nothing if the interval has not
+    /// elapsed yet.
+    pub fn poll(&mut self) -> HashSet<PathBuf> {
+        if self.last_poll.elapsed() < self.interval {
+            return HashSet::new();
+        }
+        self.last_poll = Instant::now();
+
+        let mut modified = HashMap::new();
+        if let Err(error) = scan(&self.root, &mut modified) {
+            warn!("Failed to poll shaders in {}: {}", self.root.display(), error);
+            return HashSet::new();
+        }
+        let changed = changed_files(&self.modified, &modified);
+        self.modified = modified;
+        changed
+    }
+}
+
+/// Returns the files in `current` which are not in `previous` or have a different modification time. Deleted files are
+/// not reported, as there is nothing left to compile.
+fn changed_files(previous : &HashMap<PathBuf, SystemTime>,
+                 current : &HashMap<PathBuf, SystemTime>) -> HashSet<PathBuf> {
+    current
+        .iter()
+        .filter(|(path, time)| previous.get(*path) != Some(time))
+        .map(|(path, _)| path.clone())
+        .collect()
+}
+
+/// Rebuilds something compiled from `dependencies`, such as a material, if any of them are in `changed`. Returns `None`
+/// if nothing needs rebuilding or the rebuild failed, in which case the error is logged and the caller keeps the last
+/// good version.
+pub fn rebuild_changed<T, E, F>(changed : &HashSet<PathBuf>, dependencies : &[PathBuf], rebuild : F) -> Option<T>
+    where E : fmt::Display,
+          F : FnOnce() -> Option<Result<T, E>> {
+    if !dependencies.iter().any(|dependency| changed.contains(dependency)) {
+        return None;
+    }
+    match rebuild()? {
+        Ok(rebuilt) => Some(rebuilt),
+        Err(error) => {
+            error!("Failed to reload shaders, keeping the last good version:\n{}", error);
+            None
+        }
+    }
+}
+
+/// Records the modification time of every file below `dir`, keyed by canonical path so they can be compared with the
+/// dependencies reported by the `ShaderCompiler`.
+fn scan(dir : &Path, modified : &mut HashMap<PathBuf, SystemTime>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            scan(&path, modified)?;
+        } else if let Ok(time) = fs::metadata(&path).and_then(|metadata| metadata.modified()) {
+            modified.insert(path.canonicalize().unwrap_or(path), time);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn times(files : &[(&str, u64)]) -> HashMap<PathBuf, SystemTime> {
+        files
+            .iter()
+            .map(|(path, seconds)| (PathBuf::from(path), SystemTime::UNIX_EPOCH + Duration::from_secs(*seconds)))
+            .collect()
+    }
+
+    fn paths(paths : &[&str]) -> HashSet<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn reports_created_and_modified_files() {
+        let previous = times(&[("a.vert", 1), ("b.glsl", 1), ("deleted.frag", 1)]);
+        let current = times(&[("a.vert", 1), ("b.glsl", 2), ("c.frag", 1)]);
+        assert_eq!(changed_files(&previous, &current), paths(&["b.glsl", "c.frag"]));
+        assert!(changed_files(&current, &current).is_empty());
+    }
+
+    #[test]
+    fn polls_the_watched_directory() {
+        let dir = std::env::temp_dir().join(format!("halogen-watcher-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("old.vert"), "").unwrap();
+        let mut watcher = ShaderWatcher::new(&dir, Duration::from_secs(0));
+        fs::write(dir.join("new.frag"), "").unwrap();
+        let changed = watcher.poll();
+        let expected = dir.join("new.frag").canonicalize().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(changed, [expected].iter().cloned().collect());
+    }
+
+    #[test]
+    fn only_rebuilds_when_a_dependency_changed() {
+        let dependencies = [PathBuf::from("a.vert"), PathBuf::from("common.glsl")];
+        let rebuilt = rebuild_changed(&paths(&["other.frag"]), &dependencies, || -> Option<Result<u32, String>> {
+            panic!("rebuilt without a change")
+        });
+        assert_eq!(rebuilt, None);
+        assert_eq!(rebuild_changed(&paths(&["common.glsl"]), &dependencies, || Some(Ok::<_, String>(2))), Some(2));
+    }
+
+    #[test]
+    fn keeps_the_last_good_version_when_rebuilding_fails() {
+        let dependencies = [PathBuf::from("a.vert")];
+        let mut current = 1;
+        let failed = rebuild_changed(&paths(&["a.vert"]), &dependencies, || Some(Err("syntax error")));
+        if let Some(rebuilt) = failed {
+            current = rebuilt;
+        }
+        assert_eq!(current, 1);
+        let fixed = rebuild_changed(&paths(&["a.vert"]), &dependencies, || Some(Ok::<_, &str>(2)));
+        if let Some(rebuilt) = fixed {
+            current = rebuilt;
+        }
+        assert_eq!(current, 2);
+    }
+}
@@ -1,8 +1,9 @@
-use std::{cell::RefCell, iter, rc::Rc};
+use std::{iter, sync::Arc};
 use ash::extensions::{khr::Surface as SurfaceLoader, khr::Swapchain as SwapchainLoader};
 use ash::version::DeviceV1_0;
 use ash::vk::{self, Result as VkResult};
 use winit::window::Window;
+use super::deletion::DeferredObject;
 use super::{Device, Instance, Queue, platform::create_surface, platform::get_required_instance_extensions, util::select_color_format};
 
 /// Provides a brief overview of why a swapchain failed to be created.
@@ -13,9 +14,9 @@ pub enum SwapchainCreationError {
 }
 
 pub struct Swapchain {
-    instance : Rc<RefCell<Instance>>,
-    device : Rc<RefCell<Device>>,
-    present_queue : Rc<RefCell<Queue>>,
+    instance : Arc<Instance>,
+    device : Arc<Device>,
+    present_queue : Arc<Queue>,
     surface_loader : SurfaceLoader,
     surface : vk::SurfaceKHR,
     surface_format : vk::SurfaceFormatKHR,
@@ -26,6 +27,8 @@ pub struct Swapchain {
     swapchain : vk::SwapchainKHR,
     acquire_semaphores : Vec<vk::Semaphore>,
     acquire_fences : Vec<vk::Fence>,
+    /// The device frame which last signalled each acquire fence, retired once that fence has been waited on.
+    fence_frames : Vec<Option<u64>>,
     images : Vec<vk::Image>,
     image_count : u32,
     current_frame : u32,
@@ -35,12 +38,13 @@ pub struct Swapchain {
 impl Drop for Swapchain {
     fn drop(&mut self) {
         unsafe {
-            self.device.borrow().ash_device().device_wait_idle().unwrap();
-            for semaphore in self.acquire_semaphores.clone() {
-                self.device.borrow().ash_device().destroy_semaphore(semaphore, None);
+            // Only the work submitted against this swapchain has to finish before it can be destroyed.
+            self.wait_for_frames();
+            for semaphore in self.acquire_semaphores.drain(..) {
+                self.device.destroy_deferred(DeferredObject::Semaphore(semaphore));
             }
-            for fence in self.acquire_fences.clone() {
-                self.device.borrow().ash_device().destroy_fence(fence, None);
+            for fence in self.acquire_fences.drain(..) {
+                self.device.destroy_deferred(DeferredObject::Fence(fence));
             }
             self.swapchain_loader.destroy_swapchain(self.swapchain, None);
             self.surface_loader.destroy_surface(self.surface, None);
@@ -52,22 +56,22 @@ impl Drop for Swapchain {
 impl Swapchain {
     /// Creates a new swapchain with the given surface. This function will only need to be called once.
     /// Any events that break the existing swapchain `should` call `recreate`.
-    pub fn new(instance : Rc<RefCell<Instance>>,
-               device : Rc<RefCell<Device>>,
-               present_queue : Rc<RefCell<Queue>>,
+    pub fn new(instance : Arc<Instance>,
+               device : Arc<Device>,
+               present_queue : Arc<Queue>,
                window : &Window,
                image_count : u32) -> Result<Self,SwapchainCreationError> {
         // Initializes surface entry points and creates one.
         let surface_loader = SurfaceLoader::new(
-            instance.borrow().ash_entry(),
-            instance.borrow().ash_instance());
+            instance.ash_entry(),
+            instance.ash_instance());
         let surface = create_surface(
-            instance.borrow().ash_entry(),
-            instance.borrow().ash_instance(), window);
+            instance.ash_entry(),
+            instance.ash_instance(), window);
 
         let supports_present = unsafe {
             surface_loader.get_physical_device_surface_support(
-                device.borrow().physical_device(),
+                device.physical_device(),
                 0,
                 surface)
         };
@@ -81,25 +85,25 @@ impl Swapchain {
         let (capabilities, formats, present_modes) = unsafe {
             let capabilities = surface_loader
                 .get_physical_device_surface_capabilities(
-                    device.borrow().physical_device(),
+                    device.physical_device(),
                     surface)
                 .unwrap();
             let formats = surface_loader
                 .get_physical_device_surface_formats(
-                    device.borrow().physical_device(),
+                    device.physical_device(),
                     surface)
                 .unwrap();
             let present_modes = surface_loader
                 .get_physical_device_surface_present_modes(
-                    device.borrow().physical_device(),
+                    device.physical_device(),
                     surface)
                 .unwrap();
             (capabilities, formats, present_modes)
         };
 
         let swapchain_loader = SwapchainLoader::new(
-            instance.borrow().ash_instance(),
-            device.borrow().ash_device());
+            instance.ash_instance(),
+            device.ash_device());
 
         let surface_format = select_color_format(
             formats.clone(),
@@ -128,7 +132,6 @@ impl Swapchain {
         let acquire_semaphores = iter::repeat_with(||
             unsafe {
                 device
-                    .borrow()
                     .ash_device()
                     .create_semaphore(&semaphore_info, None)
                     .expect("Failed to create semaphore")
@@ -141,7 +144,6 @@ impl Swapchain {
         let acquire_fences = iter::repeat_with(||
             unsafe {
                 device
-                    .borrow()
                     .ash_device()
                     .create_fence(&fence_info, None)
                     .expect("Failed to create fence")
@@ -168,6 +170,7 @@ impl Swapchain {
             swapchain,
             acquire_semaphores,
             acquire_fences,
+            fence_frames: vec![None; image_count as usize],
             images,
             image_count,
             current_frame: 0,
@@ -181,15 +184,17 @@ impl Swapchain {
         let acquire_result = unsafe {
             // Wait for these fences to be signalled then reset them to a non-signalled state.
             self.device
-                .borrow()
                 .ash_device()
                 .wait_for_fences(&[self.acquire_fences.get(self.current_frame as usize).unwrap().clone()], true, u64::max_value())
                 .unwrap();
             self.device
-                .borrow()
                 .ash_device()
                 .reset_fences(&[self.acquire_fences.get(self.current_frame as usize).unwrap().clone()])
                 .unwrap();
+            // The frame which last used this fence has finished, so anything dropped during it can be destroyed.
+            if let Some(frame) = self.fence_frames[self.current_frame as usize].replace(self.device.current_frame()) {
+                self.device.retire_frame(frame);
+            }
             // Attempt to acquire the next image from the swapchain.
             self.swapchain_loader
                 .acquire_next_image(
@@ -218,12 +223,13 @@ impl Swapchain {
             .image_indices(&[self.current_image])
             .swapchains(&[self.swapchain])
             // Wait on submission to be completed before presenting.
-            .wait_semaphores(&[self.present_queue.borrow().submit_semaphore_raw()])
+            .wait_semaphores(&[self.present_queue.submit_semaphore_raw()])
             .build();
         // TODO: Use value to validate present status.
+        let _lock = self.device.lock_queue(self.present_queue.family_index());
         let present_status = unsafe {
             self.swapchain_loader.queue_present(
-                self.present_queue.borrow().queue_raw(),
+                self.present_queue.queue_raw(),
                 &present_info)
         };
         match present_status {
@@ -237,17 +243,17 @@ impl Swapchain {
         unsafe {
             self.capabilities = self.surface_loader
                 .get_physical_device_surface_capabilities(
-                    self.device.borrow().physical_device(),
+                    self.device.physical_device(),
                     self.surface)
                 .unwrap();
             self.formats = self.surface_loader
                 .get_physical_device_surface_formats(
-                    self.device.borrow().physical_device(),
+                    self.device.physical_device(),
                     self.surface)
                 .unwrap();
             self.present_modes = self.surface_loader
                 .get_physical_device_surface_present_modes(
-                    self.device.borrow().physical_device(),
+                    self.device.physical_device(),
                     self.surface)
                 .unwrap();
         }
@@ -268,6 +274,8 @@ impl Swapchain {
             .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
             .min_image_count(self.image_count)
             .clipped(true);
+        // The old swapchain images may still be referenced by frames in flight.
+        self.wait_for_frames();
         self.swapchain = unsafe {
             let new_swapchain = self.swapchain_loader
                 .create_swapchain(&swapchain_info, None)
@@ -284,6 +292,19 @@ impl Swapchain {
         info!("Recreated Swapchain")
     }
 
+    /// Waits for every frame submitted against this swapchain to complete.
+    fn wait_for_frames(&self) {
+        if self.acquire_fences.is_empty() {
+            return;
+        }
+        unsafe {
+            self.device
+                .ash_device()
+                .wait_for_fences(&self.acquire_fences, true, u64::max_value())
+                .expect("Failed to wait for fences");
+        }
+    }
+
     /// Returns the images associated with this Swapchain, used in the creation of a Framebuffer.
     pub fn images(&self) -> Vec<vk::Image> {
         self.images.clone()
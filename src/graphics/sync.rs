@@ -0,0 +1,608 @@
+use std::collections::HashMap;
+use ash::version::DeviceV1_0;
+use ash::vk;
+use super::Device;
+
+/// The pipeline stage, access mask and image layout which make up a single usage of a resource.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AccessInfo {
+    pub stage : vk::PipelineStageFlags,
+    pub access_mask : vk::AccessFlags,
+    pub layout : vk::ImageLayout,
+    pub write : bool,
+}
+
+/// The ways in which an image may be used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ImageAccess {
+    ColorAttachmentWrite,
+    DepthStencilWrite,
+    DepthStencilRead,
+    VertexShaderRead,
+    FragmentShaderRead,
+    ComputeShaderRead,
+    ComputeShaderWrite,
+    TransferRead,
+    TransferWrite,
+    Present,
+}
+
+impl ImageAccess {
+    pub fn is_write(self) -> bool {
+        matches!(self, ImageAccess::ColorAttachmentWrite
+            | ImageAccess::DepthStencilWrite
+            | ImageAccess::ComputeShaderWrite
+            | ImageAccess::TransferWrite)
+    }
+
+    pub fn info(self) -> AccessInfo {
+        let (stage, access_mask, layout) = match self {
+            ImageAccess::ColorAttachmentWrite => (
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL),
+            ImageAccess::DepthStencilWrite => (
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL),
+            ImageAccess::DepthStencilRead => (
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ,
+                vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL),
+            ImageAccess::VertexShaderRead => (
+                vk::PipelineStageFlags::VERTEX_SHADER,
+                vk::AccessFlags::SHADER_READ,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            ImageAccess::FragmentShaderRead => (
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::AccessFlags::SHADER_READ,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            ImageAccess::ComputeShaderRead => (
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::AccessFlags::SHADER_READ,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            ImageAccess::ComputeShaderWrite => (
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+                vk::ImageLayout::GENERAL),
+            ImageAccess::TransferRead => (
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_READ,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL),
+            ImageAccess::TransferWrite => (
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL),
+            ImageAccess::Present => (
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::AccessFlags::empty(),
+                vk::ImageLayout::PRESENT_SRC_KHR),
+        };
+        AccessInfo { stage, access_mask, layout, write: self.is_write() }
+    }
+
+    /// The usage flags an image needs to be created with to support this access.
+    pub fn usage(self) -> vk::ImageUsageFlags {
+        match self {
+            ImageAccess::ColorAttachmentWrite => vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            ImageAccess::DepthStencilWrite | ImageAccess::DepthStencilRead =>
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            ImageAccess::VertexShaderRead | ImageAccess::FragmentShaderRead | ImageAccess::ComputeShaderRead =>
+                vk::ImageUsageFlags::SAMPLED,
+            ImageAccess::ComputeShaderWrite => vk::ImageUsageFlags::STORAGE,
+            ImageAccess::TransferRead => vk::ImageUsageFlags::TRANSFER_SRC,
+            ImageAccess::TransferWrite => vk::ImageUsageFlags::TRANSFER_DST,
+            ImageAccess::Present => vk::ImageUsageFlags::empty(),
+        }
+    }
+}
+
+/// The ways in which a buffer may be used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BufferAccess {
+    VertexBuffer,
+    IndexBuffer,
+    IndirectBuffer,
+    UniformRead,
+    ShaderRead,
+    ComputeShaderWrite,
+    TransferRead,
+    TransferWrite,
+}
+
+impl BufferAccess {
+    pub fn is_write(self) -> bool {
+        matches!(self, BufferAccess::ComputeShaderWrite | BufferAccess::TransferWrite)
+    }
+
+    pub fn info(self) -> AccessInfo {
+        let (stage, access_mask) = match self {
+            BufferAccess::VertexBuffer => (vk::PipelineStageFlags::VERTEX_INPUT, vk::AccessFlags::VERTEX_ATTRIBUTE_READ),
+            BufferAccess::IndexBuffer => (vk::PipelineStageFlags::VERTEX_INPUT, vk::AccessFlags::INDEX_READ),
+            BufferAccess::IndirectBuffer => (vk::PipelineStageFlags::DRAW_INDIRECT, vk::AccessFlags::INDIRECT_COMMAND_READ),
+            BufferAccess::UniformRead => (
+                vk::PipelineStageFlags::VERTEX_SHADER
+                    | vk::PipelineStageFlags::FRAGMENT_SHADER
+                    | vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::AccessFlags::UNIFORM_READ),
+            BufferAccess::ShaderRead => (
+                vk::PipelineStageFlags::VERTEX_SHADER
+                    | vk::PipelineStageFlags::FRAGMENT_SHADER
+                    | vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::AccessFlags::SHADER_READ),
+            BufferAccess::ComputeShaderWrite => (
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE),
+            BufferAccess::TransferRead => (vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_READ),
+            BufferAccess::TransferWrite => (vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_WRITE),
+        };
+        AccessInfo { stage, access_mask, layout: vk::ImageLayout::UNDEFINED, write: self.is_write() }
+    }
+
+    /// The usage flags a buffer needs to be created with to support this access.
+    pub fn usage(self) -> vk::BufferUsageFlags {
+        match self {
+            BufferAccess::VertexBuffer => vk::BufferUsageFlags::VERTEX_BUFFER,
+            BufferAccess::IndexBuffer => vk::BufferUsageFlags::INDEX_BUFFER,
+            BufferAccess::IndirectBuffer => vk::BufferUsageFlags::INDIRECT_BUFFER,
+            BufferAccess::UniformRead => vk::BufferUsageFlags::UNIFORM_BUFFER,
+            BufferAccess::ShaderRead | BufferAccess::ComputeShaderWrite => vk::BufferUsageFlags::STORAGE_BUFFER,
+            BufferAccess::TransferRead => vk::BufferUsageFlags::TRANSFER_SRC,
+            BufferAccess::TransferWrite => vk::BufferUsageFlags::TRANSFER_DST,
+        }
+    }
+}
+
+/// The source and destination scopes of a single barrier.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Transition {
+    pub src_stage : vk::PipelineStageFlags,
+    pub dst_stage : vk::PipelineStageFlags,
+    pub src_access : vk::AccessFlags,
+    pub dst_access : vk::AccessFlags,
+    pub old_layout : vk::ImageLayout,
+    pub new_layout : vk::ImageLayout,
+}
+
+/// Tracks the last writer and readers of a resource, producing the barrier required for each new access.
+#[derive(Clone, Copy, Debug)]
+pub struct AccessState {
+    layout : vk::ImageLayout,
+    write_stage : vk::PipelineStageFlags,
+    write_access : vk::AccessFlags,
+    read_stages : vk::PipelineStageFlags,
+    visible_stages : vk::PipelineStageFlags,
+    visible_access : vk::AccessFlags,
+}
+
+impl AccessState {
+    /// A resource whose contents are undefined and which has no pending accesses.
+    pub fn undefined() -> Self {
+        Self {
+            layout: vk::ImageLayout::UNDEFINED,
+            write_stage: vk::PipelineStageFlags::empty(),
+            write_access: vk::AccessFlags::empty(),
+            read_stages: vk::PipelineStageFlags::empty(),
+            visible_stages: vk::PipelineStageFlags::empty(),
+            visible_access: vk::AccessFlags::empty(),
+        }
+    }
+
+    /// A resource which was last used with the provided access.
+    pub fn from_access(access : AccessInfo) -> Self {
+        let mut state = Self::undefined();
+        state.layout = access.layout;
+        if access.write {
+            state.write_stage = access.stage;
+            state.write_access = access.access_mask;
+        } else {
+            state.read_stages = access.stage;
+        }
+        state
+    }
+
+    /// Makes the next access wait for the provided stages, i.e. when memory is reused by an aliased resource.
+    pub fn wait_for_stages(&mut self, stages : vk::PipelineStageFlags) {
+        self.read_stages |= stages;
+    }
+
+    pub fn layout(&self) -> vk::ImageLayout {
+        self.layout
+    }
+
+    /// Returns every stage which a following barrier has to wait on.
+    pub fn pending_stages(&self) -> vk::PipelineStageFlags {
+        self.write_stage | self.read_stages
+    }
+
+    pub fn pending_access(&self) -> vk::AccessFlags {
+        self.write_access
+    }
+
+    /// Moves to the new access, returning a transition if a barrier is required. Reads which follow a read in the
+    /// same layout do not require a barrier.
+    pub fn transition(&mut self, access : AccessInfo) -> Option<Transition> {
+        let layout_changed = access.layout != self.layout;
+        let transition = if access.write || layout_changed {
+            // Writes and layout transitions wait on every previous reader and writer.
+            let src_stage = self.pending_stages();
+            let transition = Transition {
+                src_stage: if src_stage.is_empty() { vk::PipelineStageFlags::TOP_OF_PIPE } else { src_stage },
+                dst_stage: access.stage,
+                src_access: self.write_access,
+                dst_access: access.access_mask,
+                old_layout: self.layout,
+                new_layout: access.layout,
+            };
+            if access.write {
+                self.write_stage = access.stage;
+                self.write_access = access.access_mask;
+                self.read_stages = vk::PipelineStageFlags::empty();
+                self.visible_stages = vk::PipelineStageFlags::empty();
+                self.visible_access = vk::AccessFlags::empty();
+            } else {
+                self.read_stages = access.stage;
+                self.visible_stages = access.stage;
+                self.visible_access = access.access_mask;
+            }
+            Some(transition)
+        } else if !(self.write_access.is_empty()
+            || self.visible_stages.contains(access.stage) && self.visible_access.contains(access.access_mask)) {
+            // The previous write has not yet been made visible to this reader.
+            let transition = Transition {
+                src_stage: self.write_stage,
+                dst_stage: access.stage,
+                src_access: self.write_access,
+                dst_access: access.access_mask,
+                old_layout: access.layout,
+                new_layout: access.layout,
+            };
+            self.read_stages |= access.stage;
+            self.visible_stages |= access.stage;
+            self.visible_access |= access.access_mask;
+            Some(transition)
+        } else {
+            self.read_stages |= access.stage;
+            None
+        };
+        self.layout = access.layout;
+        transition
+    }
+}
+
+struct PendingImageBarrier {
+    image : vk::Image,
+    range : vk::ImageSubresourceRange,
+    transition : Transition,
+    src_family : u32,
+    dst_family : u32,
+}
+
+struct PendingBufferBarrier {
+    buffer : vk::Buffer,
+    transition : Transition,
+    src_family : u32,
+    dst_family : u32,
+}
+
+/// Barriers which are recorded together with a single `cmd_pipeline_barrier`, and so are not ordered between each
+/// other.
+#[derive(Default)]
+struct PendingBatch {
+    image_barriers : Vec<PendingImageBarrier>,
+    buffer_barriers : Vec<PendingBufferBarrier>,
+    src_stage : vk::PipelineStageFlags,
+    dst_stage : vk::PipelineStageFlags,
+}
+
+impl PendingBatch {
+    fn is_empty(&self) -> bool {
+        self.image_barriers.is_empty() && self.buffer_barriers.is_empty()
+    }
+}
+
+struct TrackedImage {
+    state : AccessState,
+    range : vk::ImageSubresourceRange,
+}
+
+/// Records the current access and layout of every image and buffer used by a command buffer, and batches the barriers
+/// required when a new usage is declared until they are flushed with a single `cmd_pipeline_barrier`. A resource which
+/// is used again before a flush starts a new batch, so its second barrier is recorded after the first.
+pub struct ResourceTracker {
+    images : HashMap<vk::Image, TrackedImage>,
+    buffers : HashMap<vk::Buffer, AccessState>,
+    batches : Vec<PendingBatch>,
+}
+
+impl Default for ResourceTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ResourceTracker {
+    pub fn new() -> Self {
+        Self {
+            images: HashMap::new(),
+            buffers: HashMap::new(),
+            batches: Vec::new(),
+        }
+    }
+
+    /// Sets the known state of an image without recording a barrier, i.e. for images used by a previous submission.
+    /// `None` means the contents of the image can be discarded.
+    pub fn import_image(&mut self, image : vk::Image, range : vk::ImageSubresourceRange, access : Option<ImageAccess>) {
+        let state = access.map(|access| AccessState::from_access(access.info())).unwrap_or_else(AccessState::undefined);
+        self.images.insert(image, TrackedImage { state, range });
+    }
+
+    /// Sets the known state of a buffer without recording a barrier.
+    pub fn import_buffer(&mut self, buffer : vk::Buffer, access : Option<BufferAccess>) {
+        let state = access.map(|access| AccessState::from_access(access.info())).unwrap_or_else(AccessState::undefined);
+        self.buffers.insert(buffer, state);
+    }
+
+    /// Stops tracking an image, i.e. before it is destroyed.
+    pub fn forget_image(&mut self, image : vk::Image) {
+        self.images.remove(&image);
+    }
+
+    /// Stops tracking a buffer, i.e. before it is destroyed.
+    pub fn forget_buffer(&mut self, buffer : vk::Buffer) {
+        self.buffers.remove(&buffer);
+    }
+
+    /// Returns the layout the image is currently in, if it is being tracked.
+    pub fn image_layout(&self, image : vk::Image) -> Option<vk::ImageLayout> {
+        self.images.get(&image).map(|tracked| tracked.state.layout())
+    }
+
+    /// Returns the batch a barrier is added to, which is a new one if the last batch already has a barrier for the
+    /// same resource.
+    fn batch_for(&mut self, transition : Transition, pending : impl Fn(&PendingBatch) -> bool) -> &mut PendingBatch {
+        if self.batches.last().is_none_or(&pending) {
+            self.batches.push(PendingBatch::default());
+        }
+        let batch = self.batches.last_mut().unwrap();
+        batch.src_stage |= transition.src_stage;
+        batch.dst_stage |= transition.dst_stage;
+        batch
+    }
+
+    fn push_image_barrier(&mut self,
+                          image : vk::Image,
+                          range : vk::ImageSubresourceRange,
+                          transition : Transition,
+                          src_family : u32,
+                          dst_family : u32) {
+        self.batch_for(transition, |batch| batch.image_barriers.iter().any(|barrier| barrier.image == image))
+            .image_barriers
+            .push(PendingImageBarrier { image, range, transition, src_family, dst_family });
+    }
+
+    fn push_buffer_barrier(&mut self, buffer : vk::Buffer, transition : Transition, src_family : u32, dst_family : u32) {
+        self.batch_for(transition, |batch| batch.buffer_barriers.iter().any(|barrier| barrier.buffer == buffer))
+            .buffer_barriers
+            .push(PendingBufferBarrier { buffer, transition, src_family, dst_family });
+    }
+
+    /// Declares the next usage of an image. Images which were not imported are assumed to have undefined contents.
+    pub fn use_image(&mut self, image : vk::Image, range : vk::ImageSubresourceRange, access : ImageAccess) {
+        let tracked = self.images
+            .entry(image)
+            .or_insert_with(|| TrackedImage { state: AccessState::undefined(), range });
+        tracked.range = range;
+        if let Some(transition) = tracked.state.transition(access.info()) {
+            self.push_image_barrier(image, range, transition, vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED);
+        }
+    }
+
+    /// Declares the next usage of a buffer.
+    pub fn use_buffer(&mut self, buffer : vk::Buffer, access : BufferAccess) {
+        let state = self.buffers.entry(buffer).or_insert_with(AccessState::undefined);
+        if let Some(transition) = state.transition(access.info()) {
+            self.push_buffer_barrier(buffer, transition, vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED);
+        }
+    }
+
+    /// Releases ownership of an image to another queue family, transitioning it to the layout of `access`. The image
+    /// must then be acquired on the other queue with `acquire_image`, passing the returned layout and the same access.
+    /// Afterwards this tracker no longer knows about the image.
+    pub fn release_image(&mut self, image : vk::Image, src_family : u32, dst_family : u32, access : ImageAccess)
+        -> Option<vk::ImageLayout> {
+        let tracked = self.images.remove(&image)?;
+        let src_stage = tracked.state.pending_stages();
+        let transition = Transition {
+            src_stage: if src_stage.is_empty() { vk::PipelineStageFlags::TOP_OF_PIPE } else { src_stage },
+            dst_stage: vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            src_access: tracked.state.pending_access(),
+            dst_access: vk::AccessFlags::empty(),
+            old_layout: tracked.state.layout(),
+            new_layout: access.info().layout,
+        };
+        self.push_image_barrier(image, tracked.range, transition, src_family, dst_family);
+        Some(tracked.state.layout())
+    }
+
+    /// Acquires ownership of an image released by another queue family with `release_image`. `old_layout` is the
+    /// layout returned by the release, since both barriers have to describe the same transition.
+    pub fn acquire_image(&mut self,
+                         image : vk::Image,
+                         range : vk::ImageSubresourceRange,
+                         src_family : u32,
+                         dst_family : u32,
+                         old_layout : vk::ImageLayout,
+                         access : ImageAccess) {
+        let info = access.info();
+        let transition = Transition {
+            src_stage: vk::PipelineStageFlags::TOP_OF_PIPE,
+            dst_stage: info.stage,
+            src_access: vk::AccessFlags::empty(),
+            dst_access: info.access_mask,
+            old_layout,
+            new_layout: info.layout,
+        };
+        self.push_image_barrier(image, range, transition, src_family, dst_family);
+        self.images.insert(image, TrackedImage { state: AccessState::from_access(info), range });
+    }
+
+    /// Releases ownership of a buffer to another queue family.
+    pub fn release_buffer(&mut self, buffer : vk::Buffer, src_family : u32, dst_family : u32) {
+        if let Some(state) = self.buffers.remove(&buffer) {
+            let src_stage = state.pending_stages();
+            let transition = Transition {
+                src_stage: if src_stage.is_empty() { vk::PipelineStageFlags::TOP_OF_PIPE } else { src_stage },
+                dst_stage: vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                src_access: state.pending_access(),
+                dst_access: vk::AccessFlags::empty(),
+                old_layout: vk::ImageLayout::UNDEFINED,
+                new_layout: vk::ImageLayout::UNDEFINED,
+            };
+            self.push_buffer_barrier(buffer, transition, src_family, dst_family);
+        }
+    }
+
+    /// Acquires ownership of a buffer released by another queue family with `release_buffer`.
+    pub fn acquire_buffer(&mut self, buffer : vk::Buffer, src_family : u32, dst_family : u32, access : BufferAccess) {
+        let info = access.info();
+        let transition = Transition {
+            src_stage: vk::PipelineStageFlags::TOP_OF_PIPE,
+            dst_stage: info.stage,
+            src_access: vk::AccessFlags::empty(),
+            dst_access: info.access_mask,
+            old_layout: vk::ImageLayout::UNDEFINED,
+            new_layout: vk::ImageLayout::UNDEFINED,
+        };
+        self.push_buffer_barrier(buffer, transition, src_family, dst_family);
+        self.buffers.insert(buffer, AccessState::from_access(info));
+    }
+
+    /// Returns true if there are barriers waiting to be recorded.
+    pub fn has_pending_barriers(&self) -> bool {
+        self.batches.iter().any(|batch| !batch.is_empty())
+    }
+
+    /// Drops the barriers queued by a recording which was abandoned. The resources they would have transitioned are
+    /// forgotten, since the state they were tracked in was never reached.
+    pub fn discard_pending(&mut self) {
+        for batch in self.batches.drain(..) {
+            for barrier in batch.image_barriers {
+                self.images.remove(&barrier.image);
+            }
+            for barrier in batch.buffer_barriers {
+                self.buffers.remove(&barrier.buffer);
+            }
+        }
+    }
+
+    /// Records every pending barrier, with a `cmd_pipeline_barrier` for each batch in the order they were queued.
+    pub fn flush(&mut self, device : &Device, cmd_buffer : vk::CommandBuffer) {
+        for batch in self.batches.drain(..).filter(|batch| !batch.is_empty()) {
+            Self::record_batch(&batch, device, cmd_buffer);
+        }
+    }
+
+    fn record_batch(batch : &PendingBatch, device : &Device, cmd_buffer : vk::CommandBuffer) {
+        // Barriers are only built here, since the Vulkan structures hold pointers and are not thread safe.
+        let image_barriers : Vec<vk::ImageMemoryBarrier> = batch.image_barriers
+            .iter()
+            .map(|barrier| vk::ImageMemoryBarrier::builder()
+                .image(barrier.image)
+                .subresource_range(barrier.range)
+                .src_access_mask(barrier.transition.src_access)
+                .dst_access_mask(barrier.transition.dst_access)
+                .old_layout(barrier.transition.old_layout)
+                .new_layout(barrier.transition.new_layout)
+                .src_queue_family_index(barrier.src_family)
+                .dst_queue_family_index(barrier.dst_family)
+                .build())
+            .collect();
+        let buffer_barriers : Vec<vk::BufferMemoryBarrier> = batch.buffer_barriers
+            .iter()
+            .map(|barrier| vk::BufferMemoryBarrier::builder()
+                .buffer(barrier.buffer)
+                .size(vk::WHOLE_SIZE)
+                .src_access_mask(barrier.transition.src_access)
+                .dst_access_mask(barrier.transition.dst_access)
+                .src_queue_family_index(barrier.src_family)
+                .dst_queue_family_index(barrier.dst_family)
+                .build())
+            .collect();
+        unsafe {
+            device
+                .ash_device()
+                .cmd_pipeline_barrier(
+                    cmd_buffer,
+                    batch.src_stage,
+                    batch.dst_stage,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    buffer_barriers.as_slice(),
+                    image_barriers.as_slice());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk::Handle;
+    use super::*;
+
+    fn color_range() -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .level_count(1)
+            .layer_count(1)
+            .build()
+    }
+
+    #[test]
+    fn batches_barriers_of_different_resources() {
+        let mut tracker = ResourceTracker::new();
+        tracker.use_image(vk::Image::from_raw(1), color_range(), ImageAccess::TransferWrite);
+        tracker.use_image(vk::Image::from_raw(2), color_range(), ImageAccess::TransferWrite);
+        tracker.use_buffer(vk::Buffer::from_raw(1), BufferAccess::TransferWrite);
+        assert_eq!(tracker.batches.len(), 1);
+        assert_eq!(tracker.batches[0].image_barriers.len(), 2);
+        assert_eq!(tracker.batches[0].buffer_barriers.len(), 1);
+    }
+
+    #[test]
+    fn orders_barriers_of_a_resource_used_twice() {
+        let mut tracker = ResourceTracker::new();
+        let image = vk::Image::from_raw(1);
+        let buffer = vk::Buffer::from_raw(1);
+        tracker.use_image(image, color_range(), ImageAccess::TransferWrite);
+        tracker.use_buffer(buffer, BufferAccess::TransferWrite);
+        tracker.use_image(image, color_range(), ImageAccess::ComputeShaderRead);
+        tracker.use_buffer(buffer, BufferAccess::ShaderRead);
+        assert_eq!(tracker.batches.len(), 2);
+
+        let (first, second) = (&tracker.batches[0], &tracker.batches[1]);
+        assert_eq!(first.image_barriers[0].transition.new_layout, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+        assert_eq!(second.image_barriers[0].transition.old_layout, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+        assert_eq!(second.image_barriers[0].transition.new_layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        assert_eq!(second.src_stage, vk::PipelineStageFlags::TRANSFER);
+        assert_eq!(second.dst_stage, BufferAccess::ShaderRead.info().stage);
+        assert_eq!(second.buffer_barriers[0].transition.src_access, vk::AccessFlags::TRANSFER_WRITE);
+        assert!(tracker.has_pending_barriers());
+    }
+
+    #[test]
+    fn discards_barriers_and_forgets_their_resources() {
+        let mut tracker = ResourceTracker::new();
+        let (imported, used) = (vk::Image::from_raw(1), vk::Image::from_raw(2));
+        tracker.import_image(imported, color_range(), Some(ImageAccess::FragmentShaderRead));
+        tracker.use_image(used, color_range(), ImageAccess::TransferWrite);
+        tracker.use_buffer(vk::Buffer::from_raw(1), BufferAccess::TransferWrite);
+        tracker.discard_pending();
+        assert!(!tracker.has_pending_barriers());
+        assert_eq!(tracker.image_layout(imported), Some(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL));
+        assert_eq!(tracker.image_layout(used), None);
+        assert!(tracker.buffers.is_empty());
+
+        tracker.forget_image(imported);
+        assert_eq!(tracker.image_layout(imported), None);
+    }
+}
@@ -0,0 +1,400 @@
+use std::{fmt, path::{Path, PathBuf}, sync::Arc};
+use ash::version::DeviceV1_0;
+use ash::vk;
+use super::{CmdBuffer, CmdPool, CmdRecordingError, Device, Queue};
+use super::buffer::{Buffer, BufferCreationError};
+use super::deletion::DeferredObject;
+use super::image::{mip_level_count, srgb_format, ImageData, ImageError};
+use super::sampler_cache::SamplerDesc;
+use super::sync::ImageAccess;
+use super::util::find_memory_type_index;
+
+/// Describes why a texture could not be created.
+#[derive(Debug)]
+pub enum TextureError {
+    Image(ImageError),
+    Staging(BufferCreationError),
+    /// The device cannot sample images of this format with optimal tiling.
+    UnsupportedFormat(vk::Format),
+    /// The device cannot sample this block-compressed format, and it has no CPU fallback. This is the case for BC6H,
+    /// whose HDR values do not fit in 8-bit channels, and for the signed BC4, BC5 and EAC formats.
+    NoCpuFallback(vk::Format),
+    /// No device local memory could be allocated for the image.
+    AllocationFailed,
+    Recording(CmdRecordingError),
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextureError::Image(error) => write!(f, "{}", error),
+            TextureError::Staging(error) => write!(f, "failed to create staging buffer: {:?}", error),
+            TextureError::UnsupportedFormat(format) => write!(f, "{:?} cannot be sampled on this device", format),
+            TextureError::NoCpuFallback(format) => {
+                write!(f, "{:?} cannot be sampled on this device or decompressed on the CPU", format)
+            },
+            TextureError::AllocationFailed => write!(f, "failed to allocate image memory"),
+            TextureError::Recording(error) => write!(f, "failed to record upload: {:?}", error),
+        }
+    }
+}
+
+impl From<ImageError> for TextureError {
+    fn from(error : ImageError) -> Self {
+        TextureError::Image(error)
+    }
+}
+
+impl From<CmdRecordingError> for TextureError {
+    fn from(error : CmdRecordingError) -> Self {
+        TextureError::Recording(error)
+    }
+}
+
+/// Controls how image data is interpreted and sampled.
+#[derive(Clone, Copy, Debug)]
+pub struct TextureOptions {
+    /// Treats color data stored in a linear format with an sRGB equivalent as sRGB, so it is converted to linear when
+    /// sampled. Used for albedo and emissive maps, but not for normal or roughness maps. Formats which are already
+    /// sRGB are kept.
+    pub srgb : bool,
+    /// Generates a full mip chain when the data only has its first level. Block-compressed data is never filtered.
+    pub generate_mipmaps : bool,
+    /// The sampler the texture is sampled with, which is shared with every other texture using the same description.
+    pub sampler : SamplerDesc,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self { srgb: true, generate_mipmaps: true, sampler: SamplerDesc::default().anisotropy(16) }
+    }
+}
+
+/// A sampled image in device local memory, along with a view of every mip level and a sampler from the device's
+/// `SamplerCache`.
+///
+/// Textures are always in `SHADER_READ_ONLY_OPTIMAL` once created, so they can be written into descriptor sets with
+/// `DescriptorWriter::texture` or added to a `BindlessHeap` with `view_raw`, `layout` and `sampler_raw`.
+pub struct Texture {
+    device : Arc<Device>,
+    image : vk::Image,
+    memory : vk::DeviceMemory,
+    view : vk::ImageView,
+    sampler : vk::Sampler,
+    format : vk::Format,
+    extent : vk::Extent2D,
+    mip_levels : u32,
+    usage : vk::ImageUsageFlags,
+    /// The file the texture was loaded from, if any.
+    path : Option<PathBuf>,
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        self.device.destroy_deferred(DeferredObject::ImageView(self.view));
+        self.device.destroy_deferred(DeferredObject::Image(self.image));
+        self.device.destroy_deferred(DeferredObject::Memory(self.memory));
+        info!("Dropped Texture")
+    }
+}
+
+impl Texture {
+    /// Loads a PNG, JPEG or Radiance HDR image, or a KTX2 or DDS texture, from disk and uploads it.
+    pub fn from_file(device : Arc<Device>,
+                     transfer_queue : &Queue,
+                     graphics_queue : &Queue,
+                     path : &Path,
+                     options : TextureOptions) -> Result<Self, TextureError> {
+        let data = ImageData::load(path)?;
+        let mut texture = Self::from_image_data(device, transfer_queue, graphics_queue, data, options)?;
+        texture.path = Some(path.to_path_buf());
+        Ok(texture)
+    }
+
+    /// Uploads image data through a staging buffer on the transfer queue, then generates the remaining mip levels on
+    /// the graphics queue. Mipmaps are blitted on the GPU when the format supports linear blits, and are otherwise
+    /// filtered on the CPU before the upload. Block-compressed formats the device cannot sample are decompressed on
+    /// the CPU where possible. Blocks until the upload is complete.
+    pub fn from_image_data(device : Arc<Device>,
+                           transfer_queue : &Queue,
+                           graphics_queue : &Queue,
+                           mut data : ImageData,
+                           options : TextureOptions) -> Result<Self, TextureError> {
+        if options.srgb {
+            data.format = srgb_format(data.format);
+        }
+        let mut features = device.format_properties(data.format).optimal_tiling_features;
+        if !features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE) {
+            // Devices usually only support either the BCn formats or the ETC2 and ASTC formats.
+            if !data.is_compressed() {
+                return Err(TextureError::UnsupportedFormat(data.format));
+            }
+            let decompressed = data.decompress().ok_or(TextureError::NoCpuFallback(data.format))?;
+            info!("Decompressing {:?} texture since the device cannot sample it", data.format);
+            features = device.format_properties(decompressed.format).optimal_tiling_features;
+            if !features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE) {
+                return Err(TextureError::UnsupportedFormat(decompressed.format));
+            }
+            data = decompressed;
+        }
+        let format = data.format;
+
+        let full_chain = mip_level_count(data.width, data.height);
+        let blit_features = vk::FormatFeatureFlags::BLIT_SRC
+            | vk::FormatFeatureFlags::BLIT_DST
+            | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR;
+        let mut blit_mipmaps = false;
+        if options.generate_mipmaps && data.levels.len() == 1 && full_chain > 1 {
+            if features.contains(blit_features) {
+                blit_mipmaps = true;
+            } else if !data.is_compressed() && !data.generate_mipmaps() {
+                warn!("Cannot generate mipmaps for {:?}, only the first level will be used", format);
+            }
+        }
+        let mip_levels = if blit_mipmaps { full_chain } else { data.levels.len() as u32 };
+        let extent = vk::Extent2D { width: data.width, height: data.height };
+
+        let mut usage = vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED;
+        if blit_mipmaps {
+            usage |= vk::ImageUsageFlags::TRANSFER_SRC;
+        }
+        let (image, memory) = create_image(&device, format, extent, mip_levels, usage, transfer_queue, graphics_queue)?;
+        let sampler = device.sampler(&options.sampler);
+        let mut texture = Self::new(device, image, memory, sampler, format, extent, mip_levels);
+        texture.usage = usage;
+        texture.upload(transfer_queue, graphics_queue, &data.levels, blit_mipmaps)?;
+        Ok(texture)
+    }
+
+    /// Wraps an image in device local memory, creating its view. The texture takes ownership of the image and view,
+    /// while the sampler is owned by the device.
+    fn new(device : Arc<Device>,
+           image : vk::Image,
+           memory : vk::DeviceMemory,
+           sampler : vk::Sampler,
+           format : vk::Format,
+           extent : vk::Extent2D,
+           mip_levels : u32) -> Self {
+        let view_info = vk::ImageViewCreateInfo::builder()
+            .image(image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(format)
+            .subresource_range(subresource_range(mip_levels));
+        let view = unsafe {
+            device
+                .ash_device()
+                .create_image_view(&view_info, None)
+                .expect("Failed to create texture image view")
+        };
+        Self { device,
+            image,
+            memory,
+            view,
+            sampler,
+            format,
+            extent,
+            mip_levels,
+            usage: vk::ImageUsageFlags::empty(),
+            path: None }
+    }
+
+    /// Copies each level into the image on the transfer queue, and hands it to the graphics queue to generate mipmaps
+    /// and transition it for sampling. The image is shared concurrently between the queues, so only a semaphore is
+    /// needed between the submissions.
+    fn upload(&self,
+              transfer_queue : &Queue,
+              graphics_queue : &Queue,
+              levels : &[Vec<u8>],
+              blit_mipmaps : bool) -> Result<(), TextureError> {
+        // Offsets into the staging buffer must be a multiple of the texel size, which is at most 16 bytes.
+        let mut offsets = Vec::with_capacity(levels.len());
+        let mut size = 0;
+        for level in levels {
+            offsets.push(size);
+            size = (size + level.len() as vk::DeviceSize).div_ceil(16) * 16;
+        }
+        let staging = Buffer::new(
+            Arc::clone(&self.device),
+            size.max(16),
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            &[])
+            .map_err(TextureError::Staging)?;
+        for (level, offset) in levels.iter().zip(&offsets) {
+            staging.write(*offset, level);
+        }
+
+        let regions : Vec<vk::BufferImageCopy> = offsets
+            .iter()
+            .enumerate()
+            .map(|(level, offset)| vk::BufferImageCopy {
+                buffer_offset: *offset,
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: level as u32,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                },
+                image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+                image_extent: vk::Extent3D {
+                    width: (self.extent.width >> level).max(1),
+                    height: (self.extent.height >> level).max(1),
+                    depth: 1,
+                },
+            })
+            .collect();
+        let range = subresource_range(self.mip_levels);
+
+        let transfer_pool = Arc::new(CmdPool::new(Arc::clone(&self.device), transfer_queue));
+        let mut transfer_buffer = CmdBuffer::new(Arc::clone(&self.device), transfer_pool);
+        transfer_buffer.begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+        transfer_buffer.use_image(self.image, range, ImageAccess::TransferWrite);
+        transfer_buffer.copy_buffer_to_texture(&staging, self, regions.as_slice())?;
+        transfer_buffer.end()?;
+
+        let graphics_pool = Arc::new(CmdPool::new(Arc::clone(&self.device), graphics_queue));
+        let mut graphics_buffer = CmdBuffer::new(Arc::clone(&self.device), graphics_pool);
+        graphics_buffer.begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+        graphics_buffer
+            .tracker_mut()
+            .import_image(self.image, range, Some(ImageAccess::TransferWrite));
+        if blit_mipmaps {
+            graphics_buffer.generate_mipmaps(self.image, self.extent, self.mip_levels, ImageAccess::FragmentShaderRead)?;
+        } else {
+            graphics_buffer.use_image(self.image, range, ImageAccess::FragmentShaderRead);
+            graphics_buffer.flush_barriers();
+        }
+        graphics_buffer.end()?;
+
+        let semaphore_info = vk::SemaphoreCreateInfo::builder();
+        let fence_info = vk::FenceCreateInfo::builder();
+        let (semaphore, fence) = unsafe {
+            (self.device.ash_device().create_semaphore(&semaphore_info, None).expect("Failed to create semaphore"),
+             self.device.ash_device().create_fence(&fence_info, None).expect("Failed to create fence"))
+        };
+        transfer_queue.submit_with(&[&transfer_buffer], &[], &[semaphore], None);
+        graphics_queue.submit_with(
+            &[&graphics_buffer],
+            &[(semaphore, vk::PipelineStageFlags::TRANSFER)],
+            &[],
+            Some(fence));
+        unsafe {
+            self.device
+                .ash_device()
+                .wait_for_fences(&[fence], true, u64::MAX)
+                .expect("Failed to wait for texture upload");
+        }
+        self.device.destroy_deferred(DeferredObject::Fence(fence));
+        self.device.destroy_deferred(DeferredObject::Semaphore(semaphore));
+        Ok(())
+    }
+
+    pub fn image_raw(&self) -> vk::Image {
+        self.image
+    }
+
+    pub fn view_raw(&self) -> vk::ImageView {
+        self.view
+    }
+
+    pub fn sampler_raw(&self) -> vk::Sampler {
+        self.sampler
+    }
+
+    /// The layout the texture is in whenever it is sampled.
+    pub fn layout(&self) -> vk::ImageLayout {
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+    }
+
+    pub fn format(&self) -> vk::Format {
+        self.format
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+
+    /// Returns the file the texture was loaded from, or `None` if it was created from image data.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    /// The usage flags the image was created with.
+    pub fn usage(&self) -> vk::ImageUsageFlags {
+        self.usage
+    }
+}
+
+fn subresource_range(mip_levels : u32) -> vk::ImageSubresourceRange {
+    vk::ImageSubresourceRange {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        base_mip_level: 0,
+        level_count: mip_levels,
+        base_array_layer: 0,
+        layer_count: 1,
+    }
+}
+
+/// Creates a 2D image bound to its own device local allocation, shared concurrently between the transfer and graphics
+/// queue families when they differ.
+fn create_image(device : &Device,
+                format : vk::Format,
+                extent : vk::Extent2D,
+                mip_levels : u32,
+                usage : vk::ImageUsageFlags,
+                transfer_queue : &Queue,
+                graphics_queue : &Queue) -> Result<(vk::Image, vk::DeviceMemory), TextureError> {
+    let families = [transfer_queue.family_index(), graphics_queue.family_index()];
+    let image_info = vk::ImageCreateInfo::builder()
+        .image_type(vk::ImageType::TYPE_2D)
+        .format(format)
+        .extent(vk::Extent3D { width: extent.width, height: extent.height, depth: 1 })
+        .mip_levels(mip_levels)
+        .array_layers(1)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .usage(usage)
+        .initial_layout(vk::ImageLayout::UNDEFINED);
+    let image_info = if families[0] != families[1] {
+        image_info
+            .sharing_mode(vk::SharingMode::CONCURRENT)
+            .queue_family_indices(&families)
+    } else {
+        image_info.sharing_mode(vk::SharingMode::EXCLUSIVE)
+    };
+
+    let (image, memory_requirements) = unsafe {
+        let image = device
+            .ash_device()
+            .create_image(&image_info, None)
+            .expect("Failed to create texture image");
+        (image, device.ash_device().get_image_memory_requirements(image))
+    };
+    let memory_properties = device.memory_properties();
+    let memory = find_memory_type_index(&memory_requirements, &memory_properties, vk::MemoryPropertyFlags::DEVICE_LOCAL)
+        .and_then(|memory_index| {
+            let allocate_info = vk::MemoryAllocateInfo::builder()
+                .memory_type_index(memory_index)
+                .allocation_size(memory_requirements.size);
+            unsafe { device.ash_device().allocate_memory(&allocate_info, None).ok() }
+        });
+    match memory {
+        Some(memory) => unsafe {
+            device
+                .ash_device()
+                .bind_image_memory(image, memory, 0)
+                .expect("Failed to bind texture memory");
+            Ok((image, memory))
+        },
+        None => {
+            unsafe { device.ash_device().destroy_image(image, None) };
+            Err(TextureError::AllocationFailed)
+        }
+    }
+}
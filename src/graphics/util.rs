@@ -37,7 +37,7 @@ pub fn find_memory_type_index(memory_req: &vk::MemoryRequirements,
                              flags: vk::MemoryPropertyFlags) -> Option<u32> {
     let mut memory_type_bits = memory_req.memory_type_bits;
     for (index, ref memory_type) in memory_prop.memory_types.iter().enumerate() {
-        if memory_type_bits & 1 == 1 {
+        if memory_type_bits & 1 == 1 && memory_type.property_flags & flags == flags {
             return Some(index as u32);
         }
         memory_type_bits = memory_type_bits >> 1;
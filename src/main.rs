@@ -3,6 +3,7 @@ extern crate ash;
 extern crate lewton;
 #[macro_use] extern crate log;
 extern crate log4rs;
+#[cfg(feature = "shader-compiler")] extern crate naga;
 extern crate nalgebra;
 extern crate num_cpus;
 extern crate rayon;
@@ -48,6 +49,7 @@ fn main() {
         if let Event::WindowEvent { event, .. } = event {
             match event {
                 WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                WindowEvent::Resized(size) => renderer.on_resize(size.to_logical(window.scale_factor())),
                 _ => (),
             }
         }